@@ -1,7 +1,7 @@
 /* Linker script for nRF52840 with SoftDevice S140 v7.3.0
  *
  * The SoftDevice occupies the first 0x27000 bytes of flash and
- * the first 0x20000 bytes of RAM. Application code and data
+ * the first 0xA000 bytes of RAM. Application code and data
  * start after those regions.
  *
  * nRF52840 totals:
@@ -18,14 +18,18 @@ MEMORY
     FLASH : ORIGIN = 0x00027000, LENGTH = 868K
 
     /*
-     * RAM: starts after SoftDevice RAM reservation (0x2000_6000)
-     * Length: 256K - 24K (SoftDevice) = 232K
+     * RAM: starts after SoftDevice RAM reservation (0x2000_A000)
+     * Length: 256K - 40K (SoftDevice) = 216K
      *
-     * NOTE: If you get SoftDevice RAM errors at runtime, increase the
-     * origin here and decrease the length accordingly. The SoftDevice
-     * RAM requirement depends on the number of connections, MTU size,
-     * and enabled features. Current reservation (24 KB) is generous
-     * for 2 central connections with MTU 64.
+     * NOTE: The SoftDevice RAM requirement depends on the number of
+     * links, ATT MTU, event length and GATT table size set in
+     * softdevice_config() (main.rs). This reservation has NOT been
+     * measured: it is an estimate with margin, because too little RAM
+     * is fatal while too much only wastes it. At enable time
+     * nrf-softdevice panics naming the required RAM start when the
+     * origin is too low, and warns with the exact start when it is
+     * higher than needed; set the origin to that value (rounded up to
+     * 4 KB) and shrink the length to match.
      */
-    RAM : ORIGIN = 0x20006000, LENGTH = 232K
+    RAM : ORIGIN = 0x2000A000, LENGTH = 216K
 }
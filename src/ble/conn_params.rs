@@ -0,0 +1,334 @@
+//! Pure connection-parameter policy.
+//!
+//! Each link's interval and slave latency follow what the peer is used for: a
+//! mouse wants every 7.5 ms event, a keyboard is happy at 15 ms and can skip
+//! idle events (it still transmits at the next event once a key goes down), and
+//! a media remote only needs to be heard within a blink. While the USB host is
+//! suspended every link is relaxed regardless of role.
+//!
+//! Peripherals may also ask for their own parameters (typically a longer
+//! interval to save their battery). The SoftDevice accepts such a request as it
+//! stands; [`LinkPolicy`] notices the change and answers with the nearest
+//! parameters the role allows, so a mouse can't talk itself into a 100 ms
+//! interval. The SoftDevice calls live in [`crate::ble::multi_conn`].
+
+use crate::ble::coordinator::DeviceRole;
+use crate::power_logic::PeripheralPower;
+
+/// Connection parameters in SoftDevice units: intervals in 1.25 ms, the
+/// supervision timeout in 10 ms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnParams {
+    pub min_interval: u16,
+    pub max_interval: u16,
+    pub slave_latency: u16,
+    pub supervision_timeout: u16,
+}
+
+impl ConnParams {
+    /// Does a link running at `current` (whose interval is a single value
+    /// within our requested range) satisfy these parameters?
+    pub fn accepts(&self, current: &ConnParams) -> bool {
+        current.min_interval >= self.min_interval
+            && current.max_interval <= self.max_interval
+            && current.slave_latency == self.slave_latency
+            && current.supervision_timeout == self.supervision_timeout
+    }
+}
+
+/// Shortest interval the Core spec allows (7.5 ms).
+pub const MIN_INTERVAL: u16 = 6;
+/// Longest interval the Core spec allows (4 s).
+pub const MAX_INTERVAL: u16 = 3200;
+/// Longest supervision timeout the Core spec allows (32 s).
+pub const MAX_SUPERVISION_TIMEOUT: u16 = 3200;
+
+/// What a role gets while the host is awake, and how far a peer request may
+/// move it.
+struct RolePolicy {
+    params: ConnParams,
+    /// Longest interval a peer request is granted.
+    interval_ceiling: u16,
+    /// Most slave latency a peer request is granted.
+    latency_ceiling: u16,
+}
+
+const fn params(min: u16, max: u16, latency: u16, timeout: u16) -> ConnParams {
+    ConnParams {
+        min_interval: min,
+        max_interval: max,
+        slave_latency: latency,
+        supervision_timeout: timeout,
+    }
+}
+
+/// Mice: 7.5 ms, no latency. Motion is continuous, so every event counts.
+const POINTER: RolePolicy = RolePolicy {
+    params: params(6, 6, 0, 400),
+    interval_ceiling: 12,
+    latency_ceiling: 4,
+};
+
+/// Keyboards: 15 ms with latency 4, so an idle keyboard wakes its radio every
+/// 75 ms while a keypress still goes out at the next 15 ms event.
+const KEYBOARD: RolePolicy = RolePolicy {
+    params: params(12, 12, 4, 400),
+    interval_ceiling: 24,
+    latency_ceiling: 8,
+};
+
+/// Remotes and media keys: 50–100 ms.
+const CONSUMER: RolePolicy = RolePolicy {
+    params: params(40, 80, 4, 600),
+    interval_ceiling: 160,
+    latency_ceiling: 16,
+};
+
+/// Anything else: the low-latency HID defaults (7.5–15 ms).
+const OTHER: RolePolicy = RolePolicy {
+    params: params(6, 12, 0, 400),
+    interval_ceiling: 24,
+    latency_ceiling: 4,
+};
+
+/// Every link while the host is suspended: 100–200 ms with latency 4. A
+/// wake-up keypress still arrives well within a human blink.
+pub const SUSPENDED: ConnParams = params(80, 160, 4, 400);
+
+/// Shortest interval any role asks for; the SoftDevice event length is sized
+/// from it.
+pub const FASTEST_INTERVAL: u16 = POINTER.params.min_interval;
+
+fn policy(role: DeviceRole) -> &'static RolePolicy {
+    match role {
+        DeviceRole::Pointer => &POINTER,
+        DeviceRole::Keyboard => &KEYBOARD,
+        DeviceRole::Consumer => &CONSUMER,
+        DeviceRole::Other => &OTHER,
+    }
+}
+
+/// Raise `params.supervision_timeout` until the link survives its worst case
+/// of skipped events: the spec requires the timeout to exceed
+/// `(1 + latency) * max_interval * 2`.
+fn with_valid_timeout(mut params: ConnParams) -> ConnParams {
+    // 10 ms units vs 1.25 ms units: timeout * 8 > (1 + latency) * max * 2.
+    let needed = (1 + params.slave_latency as u32) * params.max_interval as u32 / 4 + 1;
+    let timeout = (params.supervision_timeout as u32).max(needed);
+    params.supervision_timeout = timeout.min(MAX_SUPERVISION_TIMEOUT as u32) as u16;
+    params
+}
+
+/// The parameters a link should run with. `peer` is what the peripheral last
+/// asked for, if anything; it is honoured as far as the role allows and
+/// ignored while suspended.
+pub fn target(role: DeviceRole, power: PeripheralPower, peer: Option<ConnParams>) -> ConnParams {
+    if power == PeripheralPower::Suspended {
+        return SUSPENDED;
+    }
+    let policy = policy(role);
+    let ours = policy.params;
+    let Some(peer) = peer else {
+        return ours;
+    };
+    let clamp = |interval: u16| interval.clamp(ours.min_interval, policy.interval_ceiling);
+    with_valid_timeout(ConnParams {
+        min_interval: clamp(peer.min_interval),
+        max_interval: clamp(peer.max_interval.max(peer.min_interval)),
+        slave_latency: peer.slave_latency.min(policy.latency_ceiling),
+        supervision_timeout: peer.supervision_timeout.max(ours.supervision_timeout),
+    })
+}
+
+/// Per-link policy state: the link's role and power, the peer's last request,
+/// and what the SoftDevice last took from us.
+///
+/// Methods that return parameters only propose them; the shell reports a
+/// request the SoftDevice accepted with [`LinkPolicy::confirm`], so one it
+/// refused is proposed again by [`LinkPolicy::pending`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkPolicy {
+    role: DeviceRole,
+    power: PeripheralPower,
+    peer: Option<ConnParams>,
+    applied: ConnParams,
+    /// Parameters the link was last seen running with.
+    seen: ConnParams,
+}
+
+impl LinkPolicy {
+    /// A link that was opened with `initial` (from [`target`] for the
+    /// advertised role, awake).
+    pub fn new(role: DeviceRole, initial: ConnParams) -> Self {
+        Self {
+            role,
+            power: PeripheralPower::Awake,
+            peer: None,
+            applied: initial,
+            seen: initial,
+        }
+    }
+
+    /// The parameters the SoftDevice last accepted a request for.
+    pub fn applied(&self) -> ConnParams {
+        self.applied
+    }
+
+    /// The SoftDevice accepted a request for `params`.
+    pub fn confirm(&mut self, params: ConnParams) {
+        self.applied = params;
+    }
+
+    /// The parameters the link should move to but no accepted request has
+    /// asked for yet (the last request failed), if any.
+    pub fn pending(&self) -> Option<ConnParams> {
+        let next = target(self.role, self.power, self.peer);
+        (next != self.applied).then_some(next)
+    }
+
+    /// The link's role became known (from the Report Map). Returns the
+    /// parameters to request, if they change.
+    pub fn set_role(&mut self, role: DeviceRole) -> Option<ConnParams> {
+        self.role = role;
+        self.pending()
+    }
+
+    /// The host suspended or resumed. Returns the parameters to request, if
+    /// they change.
+    pub fn set_power(&mut self, power: PeripheralPower) -> Option<ConnParams> {
+        self.power = power;
+        self.pending()
+    }
+
+    /// The link is running with `current`. A change we didn't ask for is the
+    /// peer's own update; returns the parameters to answer it with, if they
+    /// differ from what the peer got.
+    pub fn observe(&mut self, current: ConnParams) -> Option<ConnParams> {
+        if current == self.seen {
+            return None;
+        }
+        self.seen = current;
+        if self.applied.accepts(&current) {
+            // Our own request landed.
+            return None;
+        }
+        self.peer = Some(current);
+        let next = target(self.role, self.power, self.peer);
+        if next.accepts(&current) {
+            // Nothing to request: what the peer got is what we'd ask for.
+            self.applied = next;
+            return None;
+        }
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWAKE: PeripheralPower = PeripheralPower::Awake;
+
+    fn running(interval: u16, latency: u16, timeout: u16) -> ConnParams {
+        params(interval, interval, latency, timeout)
+    }
+
+    #[test]
+    fn roles_get_their_own_parameters() {
+        let mouse = target(DeviceRole::Pointer, AWAKE, None);
+        assert_eq!((mouse.max_interval, mouse.slave_latency), (6, 0));
+        let keyboard = target(DeviceRole::Keyboard, AWAKE, None);
+        assert_eq!((keyboard.max_interval, keyboard.slave_latency), (12, 4));
+        let remote = target(DeviceRole::Consumer, AWAKE, None);
+        assert!(remote.min_interval >= 40);
+        assert_eq!(FASTEST_INTERVAL, MIN_INTERVAL);
+    }
+
+    #[test]
+    fn suspend_relaxes_every_role() {
+        for role in [
+            DeviceRole::Pointer,
+            DeviceRole::Keyboard,
+            DeviceRole::Consumer,
+            DeviceRole::Other,
+        ] {
+            let peer = Some(running(6, 0, 100));
+            assert_eq!(target(role, PeripheralPower::Suspended, peer), SUSPENDED);
+        }
+    }
+
+    #[test]
+    fn peer_request_is_clamped_to_the_role() {
+        // A mouse asking for 100 ms gets the 15 ms ceiling.
+        let answer = target(DeviceRole::Pointer, AWAKE, Some(running(80, 20, 600)));
+        assert_eq!(answer, params(12, 12, 4, 600));
+        // A keyboard asking for 30 ms and latency 2 gets exactly that.
+        let answer = target(DeviceRole::Keyboard, AWAKE, Some(running(24, 2, 400)));
+        assert_eq!(answer, params(24, 24, 2, 400));
+    }
+
+    #[test]
+    fn timeout_covers_skipped_events() {
+        let answer = target(DeviceRole::Consumer, AWAKE, Some(running(160, 16, 100)));
+        // (1 + 16) * 200 ms * 2 = 6.8 s.
+        assert!(answer.supervision_timeout as u32 * 10 > 6800);
+        assert!(SUSPENDED.supervision_timeout as u32 * 8 > 5 * 160 * 2);
+    }
+
+    #[test]
+    fn link_answers_a_peer_update_once() {
+        let initial = target(DeviceRole::Other, AWAKE, None);
+        let mut link = LinkPolicy::new(DeviceRole::Other, initial);
+        assert_eq!(link.set_role(DeviceRole::Pointer), Some(POINTER.params));
+        link.confirm(POINTER.params);
+        // Our request landing is not a peer update.
+        assert_eq!(link.observe(running(6, 0, 400)), None);
+        // The peer moves to 50 ms; answer with the mouse ceiling.
+        let answer = link.observe(running(40, 0, 400)).unwrap();
+        assert_eq!(answer.max_interval, 12);
+        link.confirm(answer);
+        // Still 50 ms while our answer is pending: nothing new.
+        assert_eq!(link.observe(running(40, 0, 400)), None);
+        assert_eq!(link.observe(running(12, 0, 400)), None);
+    }
+
+    #[test]
+    fn acceptable_peer_update_is_left_alone() {
+        let mut link = LinkPolicy::new(DeviceRole::Keyboard, KEYBOARD.params);
+        assert_eq!(link.observe(running(24, 4, 400)), None);
+        assert_eq!(link.applied(), params(24, 24, 4, 400));
+    }
+
+    #[test]
+    fn resume_restores_the_peer_adjusted_parameters() {
+        let mut link = LinkPolicy::new(DeviceRole::Keyboard, KEYBOARD.params);
+        link.observe(running(24, 4, 400));
+        assert_eq!(link.set_power(PeripheralPower::Suspended), Some(SUSPENDED));
+        link.confirm(SUSPENDED);
+        assert_eq!(link.set_power(PeripheralPower::Suspended), None);
+        assert_eq!(
+            link.set_power(AWAKE),
+            Some(params(24, 24, 4, 400)),
+            "the peer's accepted request survives a suspend"
+        );
+    }
+
+    #[test]
+    fn refused_request_is_not_taken_as_applied() {
+        let mut link = LinkPolicy::new(DeviceRole::Keyboard, KEYBOARD.params);
+        assert_eq!(link.set_power(PeripheralPower::Suspended), Some(SUSPENDED));
+        // The SoftDevice refused it: the link still aims for the old
+        // parameters, and the next check asks again.
+        assert_eq!(link.applied(), KEYBOARD.params);
+        assert_eq!(link.observe(KEYBOARD.params), None);
+        assert_eq!(link.pending(), Some(SUSPENDED));
+        link.confirm(SUSPENDED);
+        assert_eq!(link.pending(), None);
+        // A refused answer to a peer update is retried the same way.
+        let mut link = LinkPolicy::new(DeviceRole::Pointer, POINTER.params);
+        let answer = link.observe(running(40, 0, 400)).unwrap();
+        assert_eq!(link.observe(running(40, 0, 400)), None);
+        assert_eq!(link.pending(), Some(answer));
+    }
+}
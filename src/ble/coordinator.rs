@@ -12,11 +12,39 @@
 //! BLE address type so tests can substitute a trivial stand-in for
 //! `nrf_softdevice::ble::Address`.
 
+use crate::ble::adv_parser::DeviceIcon;
+use crate::ble::link_stats::LinkStats;
+use crate::ble::reconnect::ReconnectTarget;
+use crate::hid::battery::became_low;
+use crate::hid::report_protocol::HidDescriptor;
 use core::fmt::Write;
 use heapless::{String, Vec};
 
-/// Maximum simultaneous BLE connections.
-pub const MAX_CONNECTIONS: usize = 2;
+/// Simultaneous BLE connections the firmware is built for (keyboard, mouse,
+/// numpad, presenter). The coordinator itself is generic over the slot count;
+/// this is the default, and the SoftDevice link configuration and slot task
+/// pool are derived from it.
+pub const MAX_CONNECTIONS: usize = 4;
+
+/// Shortest SoftDevice connection event (in 1.25 ms units; 2.5 ms).
+pub const MIN_CONN_EVENT_LENGTH: u16 = 2;
+
+/// SoftDevice connection event length (in 1.25 ms units) that lets `links`
+/// connections at `interval` share the radio: an equal share of the interval,
+/// but never below [`MIN_CONN_EVENT_LENGTH`] (past that, the scheduler skips
+/// some events rather than shortening them further).
+pub const fn conn_event_length(interval: u16, links: usize) -> u16 {
+    let share = if links == 0 {
+        interval
+    } else {
+        interval / links as u16
+    };
+    if share < MIN_CONN_EVENT_LENGTH {
+        MIN_CONN_EVENT_LENGTH
+    } else {
+        share
+    }
+}
 
 /// Lightweight error tag surfaced to the UI (no dynamic allocation).
 ///
@@ -45,6 +73,60 @@ pub struct DeviceInfo<A> {
     pub address: A,
     pub name: String<32>,
     pub rssi: i8,
+    /// Device-list icon, from the advertised GAP Appearance.
+    pub icon: DeviceIcon,
+    /// The address matched an existing bond (directly or by resolving an RPA).
+    pub bonded: bool,
+}
+
+/// What a connected peripheral is used as, for deciding which slot a new
+/// device should take over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceRole {
+    Keyboard,
+    Pointer,
+    Consumer,
+    #[default]
+    Other,
+}
+
+impl DeviceRole {
+    /// Role from the peer's Report Maps, one per HID service instance. A combo
+    /// device is classed by its most significant collection across all of
+    /// them (a keyboard with a touchpad is a keyboard).
+    pub fn from_descriptors(descriptors: &[Option<HidDescriptor>]) -> Self {
+        let any = |has: fn(&HidDescriptor) -> bool| descriptors.iter().flatten().any(has);
+        if any(|d| d.has_keyboard) {
+            DeviceRole::Keyboard
+        } else if any(|d| d.has_mouse) {
+            DeviceRole::Pointer
+        } else if any(|d| d.has_consumer) {
+            DeviceRole::Consumer
+        } else {
+            DeviceRole::Other
+        }
+    }
+
+    /// Best guess before the Report Map has been read: the advertised
+    /// appearance.
+    pub fn from_icon(icon: DeviceIcon) -> Self {
+        match icon {
+            DeviceIcon::Keyboard => DeviceRole::Keyboard,
+            DeviceIcon::Mouse => DeviceRole::Pointer,
+            DeviceIcon::Gamepad | DeviceIcon::Other => DeviceRole::Other,
+        }
+    }
+
+    /// Single-character marker for the slot list.
+    pub fn glyph(self) -> char {
+        match self {
+            DeviceRole::Keyboard => 'K',
+            DeviceRole::Pointer => 'M',
+            DeviceRole::Consumer => 'C',
+            DeviceRole::Other => '?',
+        }
+    }
 }
 
 /// One connection slot.
@@ -54,6 +136,15 @@ pub struct Slot<A> {
     name: String<32>,
     connected: bool,
     connecting: bool,
+    /// Last Battery Service level reported by the peer (percent).
+    battery: Option<u8>,
+    role: DeviceRole,
+    /// [`ConnManager`] activity clock value when the peer last sent input (or
+    /// was assigned to the slot).
+    last_active: u32,
+    /// The slot was handed to a new device while the old link was still up;
+    /// the old link's closing event must not clear the reservation.
+    replacing: bool,
 }
 
 impl<A> Slot<A> {
@@ -63,6 +154,10 @@ impl<A> Slot<A> {
             name: String::new(),
             connected: false,
             connecting: false,
+            battery: None,
+            role: DeviceRole::Other,
+            last_active: 0,
+            replacing: false,
         }
     }
 
@@ -71,21 +166,37 @@ impl<A> Slot<A> {
     }
 }
 
-/// The connection-slot state machine.
-pub struct ConnManager<A> {
-    slots: [Slot<A>; MAX_CONNECTIONS],
+/// Role and name of an occupied slot, for the UI's slot list.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlotSummary {
+    pub role: DeviceRole,
+    pub name: String<32>,
+}
+
+/// The connection-slot state machine, for `N` slots.
+pub struct ConnManager<A, const N: usize = MAX_CONNECTIONS> {
+    slots: [Slot<A>; N],
+    /// Link quality per slot. Kept across disconnects (the loss count is the
+    /// interesting part), restarted when a link comes up.
+    links: [LinkStats; N],
+    /// Logical clock behind `Slot::last_active`: ticks on every activity, so
+    /// ordering needs no wall time.
+    clock: u32,
 }
 
-impl<A: Clone + PartialEq> Default for ConnManager<A> {
+impl<A: Clone + PartialEq, const N: usize> Default for ConnManager<A, N> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<A: Clone + PartialEq> ConnManager<A> {
+impl<A: Clone + PartialEq, const N: usize> ConnManager<A, N> {
     pub const fn new() -> Self {
         Self {
-            slots: [Slot::empty(), Slot::empty()],
+            slots: [const { Slot::empty() }; N],
+            links: [const { LinkStats::new() }; N],
+            clock: 0,
         }
     }
 
@@ -106,7 +217,7 @@ impl<A: Clone + PartialEq> ConnManager<A> {
 
     /// Is the given slot index connected or mid-connect?
     pub fn is_slot_occupied(&self, slot: usize) -> bool {
-        slot < MAX_CONNECTIONS && self.slots[slot].is_occupied()
+        slot < N && self.slots[slot].is_occupied()
     }
 
     /// Is this address already in use by an occupied slot?
@@ -116,39 +227,146 @@ impl<A: Clone + PartialEq> ConnManager<A> {
             .any(|s| s.is_occupied() && s.address.as_ref() == Some(address))
     }
 
-    /// Mark a slot as connecting (reserved) for the given device.
+    fn tick(&mut self) -> u32 {
+        self.clock = self.clock.wrapping_add(1);
+        self.clock
+    }
+
+    /// Mark a slot as connecting (reserved) for the given device. Reserving
+    /// an occupied slot replaces its link (see [`plan_connect`]).
     pub fn reserve_slot(&mut self, slot: usize, device: &DeviceInfo<A>) {
-        if slot < MAX_CONNECTIONS {
+        if slot < N {
+            let replacing = self.slots[slot].is_occupied();
             self.slots[slot] = Slot {
                 address: Some(device.address.clone()),
                 name: device.name.clone(),
                 connected: false,
                 connecting: true,
+                battery: None,
+                role: DeviceRole::from_icon(device.icon),
+                last_active: self.tick(),
+                replacing,
             };
         }
     }
 
     /// Mark a slot as fully connected for the given device.
     pub fn connect_slot(&mut self, slot: usize, device: &DeviceInfo<A>) {
-        if slot < MAX_CONNECTIONS {
+        if slot < N {
+            self.links[slot].restart();
             self.slots[slot] = Slot {
                 address: Some(device.address.clone()),
                 name: device.name.clone(),
                 connected: true,
                 connecting: false,
+                battery: None,
+                role: DeviceRole::from_icon(device.icon),
+                last_active: self.tick(),
+                replacing: false,
             };
         }
     }
 
     /// Clear a slot.
     pub fn disconnect_slot(&mut self, slot: usize) {
-        if slot < MAX_CONNECTIONS {
+        if slot < N {
             self.slots[slot] = Slot::empty();
         }
     }
 
+    /// A slot's role (`Other` when empty).
+    pub fn role(&self, slot: usize) -> DeviceRole {
+        self.slots.get(slot).map_or(DeviceRole::Other, |s| s.role)
+    }
+
+    /// Record a slot's role once its Report Map is known.
+    pub fn set_role(&mut self, slot: usize, role: DeviceRole) {
+        if let Some(s) = self.slots.get_mut(slot).filter(|s| s.is_occupied()) {
+            s.role = role;
+        }
+    }
+
+    /// Record that a slot's peer just sent input.
+    pub fn note_activity(&mut self, slot: usize) {
+        if self.is_slot_occupied(slot) {
+            self.slots[slot].last_active = self.tick();
+        }
+    }
+
+    /// The slot a new device with `role` should take: a free one if any, else
+    /// the least recently active slot of the same role, else the least
+    /// recently active slot overall. `None` only with zero slots.
+    pub fn slot_for(&self, role: DeviceRole) -> Option<usize> {
+        if let Some(slot) = self.find_empty_slot() {
+            return Some(slot);
+        }
+        let least_recent = |same_role: bool| {
+            (0..N)
+                .filter(|&i| !same_role || self.slots[i].role == role)
+                .min_by_key(|&i| self.slots[i].last_active)
+        };
+        // An unknown role says nothing about which device it supersedes.
+        let same_role = if role == DeviceRole::Other {
+            None
+        } else {
+            least_recent(true)
+        };
+        same_role.or_else(|| least_recent(false))
+    }
+
+    /// Link quality statistics of a slot.
+    pub fn link_stats(&self, slot: usize) -> Option<&LinkStats> {
+        self.links.get(slot)
+    }
+
+    /// A slot's link closed without us asking for it.
+    pub fn record_link_loss(&mut self, slot: usize) {
+        if let Some(link) = self.links.get_mut(slot) {
+            link.record_link_loss();
+        }
+    }
+
+    /// Signal-strength bars of every connected slot, indexed by slot.
+    pub fn signal_levels(&self) -> [Option<u8>; N] {
+        core::array::from_fn(|i| {
+            if self.slots[i].connected {
+                self.links[i].bars()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Role and name of every occupied slot, indexed by slot.
+    pub fn slot_summaries(&self) -> [Option<SlotSummary>; N] {
+        core::array::from_fn(|i| {
+            let s = &self.slots[i];
+            s.is_occupied().then(|| SlotSummary {
+                role: s.role,
+                name: s.name.clone(),
+            })
+        })
+    }
+
+    /// Last known battery level of a slot's peer.
+    pub fn battery(&self, slot: usize) -> Option<u8> {
+        self.slots.get(slot).and_then(|s| s.battery)
+    }
+
+    /// Record a slot's battery level (ignored for an unoccupied slot).
+    pub fn set_battery(&mut self, slot: usize, level: Option<u8>) {
+        if let Some(s) = self.slots.get_mut(slot).filter(|s| s.is_occupied()) {
+            s.battery = level;
+        }
+    }
+
+    /// Battery level of every slot, indexed by slot.
+    pub fn battery_levels(&self) -> [Option<u8>; N] {
+        core::array::from_fn(|i| self.slots[i].battery)
+    }
+
     /// Names of all connected (not merely connecting) devices.
-    pub fn get_connected_names(&self) -> Vec<String<32>, MAX_CONNECTIONS> {
+    pub fn get_connected_names(&self) -> Vec<String<32>, N> {
         let mut names = Vec::new();
         for slot in &self.slots {
             if slot.connected {
@@ -160,7 +378,9 @@ impl<A: Clone + PartialEq> ConnManager<A> {
 }
 
 /// A short human-readable summary of the current connections for the UI.
-pub fn connection_summary<A: Clone + PartialEq>(manager: &ConnManager<A>) -> String<32> {
+pub fn connection_summary<A: Clone + PartialEq, const N: usize>(
+    manager: &ConnManager<A, N>,
+) -> String<32> {
     let names = manager.get_connected_names();
     match names.len() {
         0 => {
@@ -177,25 +397,42 @@ pub fn connection_summary<A: Clone + PartialEq>(manager: &ConnManager<A>) -> Str
     }
 }
 
-/// UI-facing events the coordinator wants emitted.
+/// UI-facing events the coordinator wants emitted (for `N` slots).
 #[derive(Clone, PartialEq, Debug)]
-pub enum UiEvent {
+pub enum UiEvent<const N: usize = MAX_CONNECTIONS> {
     Connected(String<32>),
     Disconnected,
     Error(ErrorTag),
+    /// Battery level of every slot changed (indexed by slot).
+    Battery([Option<u8>; N]),
+    /// The set of occupied slots changed (indexed by slot).
+    Slots([Option<SlotSummary>; N]),
+    /// Signal-strength bars (0–4) of every slot changed (indexed by slot).
+    Signal([Option<u8>; N]),
+    /// The named device's link just degraded.
+    WeakLink(String<32>),
+    /// The named device's battery just dropped into the low range.
+    LowBattery(String<32>),
 }
 
 /// Side effects the imperative shell must perform, as data.
 #[derive(Clone, PartialEq, Debug)]
-pub enum Action<A> {
+pub enum Action<A, const N: usize = MAX_CONNECTIONS> {
     /// Tell a slot worker to disconnect.
     DisconnectSlot(usize),
     /// Tell a slot worker to connect to a device.
     ConnectSlot { slot: usize, device: DeviceInfo<A> },
-    /// Persist a newly connected device (+ its bond) to flash.
-    PersistDevice(DeviceInfo<A>),
+    /// Persist a newly connected device (+ its bond) to flash and count the
+    /// connection towards its recency.
+    PersistDevice {
+        device: DeviceInfo<A>,
+        role: DeviceRole,
+    },
     /// Emit a UI event.
-    Emit(UiEvent),
+    Emit(UiEvent<N>),
+    /// Expose a slot's battery level (percent) to the USB host; `None`
+    /// withdraws it once the link is gone.
+    PublishBattery { slot: usize, level: Option<u8> },
 }
 
 // ─── Reducers ──────────────────────────────────────────────────────────────
@@ -203,29 +440,18 @@ pub enum Action<A> {
 // Each takes the current manager state (sometimes mutating it the same way the
 // live system would) and returns the actions the shell should execute.
 
-/// Decide what must happen before a new scan starts: if every slot is busy we
-/// free them all so the user can pick a fresh device.
-pub fn plan_start_scan<A: Clone + PartialEq>(
-    manager: &ConnManager<A>,
-) -> Vec<Action<A>, MAX_CONNECTIONS> {
-    let mut actions = Vec::new();
-    if manager.occupied_count() >= MAX_CONNECTIONS {
-        for slot in 0..MAX_CONNECTIONS {
-            if manager.is_slot_occupied(slot) {
-                let _ = actions.push(Action::DisconnectSlot(slot));
-            }
-        }
-    }
-    actions
-}
-
 /// Decide how to handle a connect request for `devices[index]`, reserving a
 /// slot on success.
-pub fn plan_connect<A: Clone + PartialEq>(
-    manager: &mut ConnManager<A>,
+///
+/// With every slot busy the new device replaces one link (see
+/// [`ConnManager::slot_for`]) rather than all of them: the slot worker drops
+/// the old peer when it receives the new `ConnectSlot`. The replaced peer's
+/// battery level is withdrawn first.
+pub fn plan_connect<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
     devices: &[DeviceInfo<A>],
     index: usize,
-) -> Vec<Action<A>, 1> {
+) -> Vec<Action<A, N>, 3> {
     let mut actions = Vec::new();
 
     let Some(device) = devices.get(index) else {
@@ -238,12 +464,16 @@ pub fn plan_connect<A: Clone + PartialEq>(
         return actions;
     }
 
-    let Some(slot) = manager.find_empty_slot() else {
+    let Some(slot) = manager.slot_for(DeviceRole::from_icon(device.icon)) else {
         let _ = actions.push(Action::Emit(UiEvent::Error(ErrorTag::ConnectFailed)));
         return actions;
     };
 
+    let withdrawn = withdraw_battery(manager, slot, &mut actions);
     manager.reserve_slot(slot, device);
+    if withdrawn {
+        let _ = actions.push(Action::Emit(UiEvent::Battery(manager.battery_levels())));
+    }
     let _ = actions.push(Action::ConnectSlot {
         slot,
         device: device.clone(),
@@ -251,12 +481,27 @@ pub fn plan_connect<A: Clone + PartialEq>(
     actions
 }
 
+/// Withdraw the battery level published for `slot` before the slot is handed
+/// to another device: the slot forgets it on reserving, so the old link's own
+/// `None` would find nothing to change. Returns whether there was one.
+fn withdraw_battery<A: Clone + PartialEq, const N: usize, const M: usize>(
+    manager: &ConnManager<A, N>,
+    slot: usize,
+    actions: &mut Vec<Action<A, N>, M>,
+) -> bool {
+    if manager.battery(slot).is_none() {
+        return false;
+    }
+    let _ = actions.push(Action::PublishBattery { slot, level: None });
+    true
+}
+
 /// Disconnect every occupied slot (user pressed "disconnect").
-pub fn plan_disconnect<A: Clone + PartialEq>(
-    manager: &ConnManager<A>,
-) -> Vec<Action<A>, MAX_CONNECTIONS> {
+pub fn plan_disconnect<A: Clone + PartialEq, const N: usize>(
+    manager: &ConnManager<A, N>,
+) -> Vec<Action<A, N>, N> {
     let mut actions = Vec::new();
-    for slot in 0..MAX_CONNECTIONS {
+    for slot in 0..N {
         if manager.is_slot_occupied(slot) {
             let _ = actions.push(Action::DisconnectSlot(slot));
         }
@@ -264,52 +509,175 @@ pub fn plan_disconnect<A: Clone + PartialEq>(
     actions
 }
 
-/// A slot worker reported a successful connection.
-pub fn on_slot_connected<A: Clone + PartialEq>(
-    manager: &mut ConnManager<A>,
+/// Disconnect a single slot, leaving the others up (user picked it from the
+/// slot list).
+pub fn plan_disconnect_slot<A: Clone + PartialEq, const N: usize>(
+    manager: &ConnManager<A, N>,
+    slot: usize,
+) -> Vec<Action<A, N>, 1> {
+    let mut actions = Vec::new();
+    if manager.is_slot_occupied(slot) {
+        let _ = actions.push(Action::DisconnectSlot(slot));
+    }
+    actions
+}
+
+/// Reconnect the active profile's stored devices (`peers`, most recently used
+/// first) after boot or a profile switch, one slot each in order. `targets`
+/// pair them with the devices of the scan just run (see
+/// [`resolve_reconnect_targets`](crate::ble::reconnect::resolve_reconnect_targets)):
+/// a peer seen in the scan is connected at its live address, keeping its
+/// stored name, one that wasn't at the stored address. A slot still holding
+/// the previous profile's link is replaced, its battery level withdrawn.
+pub fn plan_reconnect<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
+    peers: &[DeviceInfo<A>],
+    scanned: &[DeviceInfo<A>],
+    targets: &[ReconnectTarget],
+) -> Vec<Action<A, N>, { 2 * MAX_CONNECTIONS + 1 }> {
+    let mut actions = Vec::new();
+    let mut withdrawn = false;
+    for (slot, target) in targets.iter().enumerate().take(N) {
+        let Some(stored) = peers.get(target.peer) else {
+            continue;
+        };
+        let device = match target.scanned.and_then(|i| scanned.get(i)) {
+            Some(live) => DeviceInfo {
+                name: stored.name.clone(),
+                bonded: true,
+                ..live.clone()
+            },
+            None => stored.clone(),
+        };
+        withdrawn |= withdraw_battery(manager, slot, &mut actions);
+        manager.reserve_slot(slot, &device);
+        let _ = actions.push(Action::ConnectSlot { slot, device });
+    }
+    if withdrawn {
+        let _ = actions.push(Action::Emit(UiEvent::Battery(manager.battery_levels())));
+    }
+    actions
+}
+
+/// A slot worker reported a successful connection; `role` comes from the
+/// peer's Report Map.
+pub fn on_slot_connected<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
     slot: usize,
     device: &DeviceInfo<A>,
-) -> Vec<Action<A>, 2> {
+    role: DeviceRole,
+) -> Vec<Action<A, N>, 3> {
     let mut actions = Vec::new();
     manager.connect_slot(slot, device);
-    let _ = actions.push(Action::PersistDevice(device.clone()));
+    manager.set_role(slot, role);
+    let _ = actions.push(Action::PersistDevice {
+        device: device.clone(),
+        role,
+    });
     let _ = actions.push(Action::Emit(UiEvent::Connected(connection_summary(
         manager,
     ))));
+    let _ = actions.push(Action::Emit(UiEvent::Slots(manager.slot_summaries())));
     actions
 }
 
-/// A slot worker reported a disconnection.
-pub fn on_slot_disconnected<A: Clone + PartialEq>(
-    manager: &mut ConnManager<A>,
+/// Clear a slot whose link ended, unless it has already been handed to a
+/// replacement device (the ending link was the one being replaced).
+fn release_slot<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
     slot: usize,
-) -> Vec<Action<A>, 1> {
-    let mut actions = Vec::new();
-    manager.disconnect_slot(slot);
-    let event = if manager.active_count() == 0 {
+) -> bool {
+    match manager.slots.get_mut(slot) {
+        Some(s) if s.replacing => {
+            s.replacing = false;
+            false
+        }
+        _ => {
+            manager.disconnect_slot(slot);
+            true
+        }
+    }
+}
+
+fn link_status<A: Clone + PartialEq, const N: usize>(manager: &ConnManager<A, N>) -> UiEvent<N> {
+    if manager.active_count() == 0 {
         UiEvent::Disconnected
     } else {
         UiEvent::Connected(connection_summary(manager))
-    };
-    let _ = actions.push(Action::Emit(event));
+    }
+}
+
+/// A slot worker reported a disconnection.
+pub fn on_slot_disconnected<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
+    slot: usize,
+) -> Vec<Action<A, N>, 2> {
+    let mut actions = Vec::new();
+    release_slot(manager, slot);
+    let _ = actions.push(Action::Emit(link_status(manager)));
+    let _ = actions.push(Action::Emit(UiEvent::Slots(manager.slot_summaries())));
     actions
 }
 
-/// A slot worker reported an error.
-pub fn on_slot_error<A: Clone + PartialEq>(
-    manager: &mut ConnManager<A>,
+/// A slot worker reported an error. An error from a link that was being
+/// replaced anyway isn't shown.
+pub fn on_slot_error<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
     slot: usize,
     tag: ErrorTag,
-) -> Vec<Action<A>, 2> {
+) -> Vec<Action<A, N>, 3> {
     let mut actions = Vec::new();
-    manager.disconnect_slot(slot);
-    let _ = actions.push(Action::Emit(UiEvent::Error(tag)));
-    let event = if manager.active_count() == 0 {
-        UiEvent::Disconnected
-    } else {
-        UiEvent::Connected(connection_summary(manager))
-    };
-    let _ = actions.push(Action::Emit(event));
+    if release_slot(manager, slot) {
+        let _ = actions.push(Action::Emit(UiEvent::Error(tag)));
+    }
+    let _ = actions.push(Action::Emit(link_status(manager)));
+    let _ = actions.push(Action::Emit(UiEvent::Slots(manager.slot_summaries())));
+    actions
+}
+
+/// A slot worker reported its peer's Battery Service level (`None` once the
+/// link is gone).
+pub fn on_slot_battery<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
+    slot: usize,
+    level: Option<u8>,
+) -> Vec<Action<A, N>, 3> {
+    let mut actions = Vec::new();
+    let previous = manager.battery(slot);
+    if !manager.is_slot_occupied(slot) || previous == level {
+        return actions;
+    }
+    manager.set_battery(slot, level);
+
+    let _ = actions.push(Action::PublishBattery { slot, level });
+    if let Some(level) = level {
+        if became_low(previous, level) {
+            let name = manager.slots[slot].name.clone();
+            let _ = actions.push(Action::Emit(UiEvent::LowBattery(name)));
+        }
+    }
+    let _ = actions.push(Action::Emit(UiEvent::Battery(manager.battery_levels())));
+    actions
+}
+
+/// A slot worker sampled its link's RSSI (dBm).
+pub fn on_slot_rssi<A: Clone + PartialEq, const N: usize>(
+    manager: &mut ConnManager<A, N>,
+    slot: usize,
+    rssi: i8,
+) -> Vec<Action<A, N>, 2> {
+    let mut actions = Vec::new();
+    if slot >= N || !manager.slots[slot].connected {
+        return actions;
+    }
+    let update = manager.links[slot].record_rssi(rssi);
+    if update.bars_changed {
+        let _ = actions.push(Action::Emit(UiEvent::Signal(manager.signal_levels())));
+    }
+    if update.became_weak {
+        let name = manager.slots[slot].name.clone();
+        let _ = actions.push(Action::Emit(UiEvent::WeakLink(name)));
+    }
     actions
 }
 
@@ -1,4 +1,5 @@
 use super::*;
+use crate::ble::reconnect::ReconnectTarget;
 
 // Trivial stand-in for the embedded `Address` type.
 type Addr = u8;
@@ -10,10 +11,20 @@ fn dev(address: Addr, name: &str) -> DeviceInfo<Addr> {
         address,
         name: n,
         rssi: -50,
+        icon: DeviceIcon::Other,
+        bonded: false,
     }
 }
 
-fn mgr() -> ConnManager<Addr> {
+fn typed(address: Addr, name: &str, icon: DeviceIcon) -> DeviceInfo<Addr> {
+    DeviceInfo {
+        icon,
+        ..dev(address, name)
+    }
+}
+
+// The slot-count edge cases below are written for two slots.
+fn mgr() -> ConnManager<Addr, 2> {
     ConnManager::new()
 }
 
@@ -100,26 +111,52 @@ fn summary_reflects_connection_count() {
     assert_eq!(connection_summary(&m).as_str(), "2 devices");
 }
 
-// ── Reducers ────────────────────────────────────────────────────────────
+#[test]
+fn default_slot_count_is_max_connections() {
+    let mut m: ConnManager<Addr> = ConnManager::new();
+    for slot in 0..MAX_CONNECTIONS {
+        assert_eq!(m.find_empty_slot(), Some(slot));
+        m.connect_slot(slot, &dev(slot as Addr, "d"));
+    }
+    assert_eq!(m.find_empty_slot(), None);
+    assert_eq!(m.battery_levels().len(), MAX_CONNECTIONS);
+}
 
 #[test]
-fn plan_start_scan_noop_when_not_full() {
-    let mut m = mgr();
-    m.connect_slot(0, &dev(1, "kb")); // one slot busy, one free
-    assert!(plan_start_scan(&m).is_empty());
+fn four_slots_hold_four_devices() {
+    let mut m: ConnManager<Addr, 4> = ConnManager::new();
+    let devices = [
+        dev(1, "Keyboard"),
+        dev(2, "Mouse"),
+        dev(3, "Numpad"),
+        dev(4, "Presenter"),
+    ];
+    for (i, device) in devices.iter().enumerate() {
+        let acts = plan_connect(&mut m, &devices, i);
+        assert_eq!(
+            acts[0],
+            Action::ConnectSlot {
+                slot: i,
+                device: device.clone()
+            }
+        );
+        on_slot_connected(&mut m, i, device, DeviceRole::Other);
+    }
+    assert_eq!(connection_summary(&m).as_str(), "4 devices");
+    assert_eq!(plan_disconnect(&m).len(), 4);
 }
 
 #[test]
-fn plan_start_scan_disconnects_all_when_full() {
-    let mut m = mgr();
-    m.connect_slot(0, &dev(1, "kb"));
-    m.connect_slot(1, &dev(2, "mouse"));
-    let acts = plan_start_scan(&m);
-    assert_eq!(acts.len(), 2);
-    assert_eq!(acts[0], Action::DisconnectSlot(0));
-    assert_eq!(acts[1], Action::DisconnectSlot(1));
+fn event_length_shares_the_interval() {
+    assert_eq!(conn_event_length(6, 1), 6);
+    assert_eq!(conn_event_length(6, 2), 3);
+    // Four links at 7.5 ms can't each get 1.875 ms; clamp to the minimum.
+    assert_eq!(conn_event_length(6, 4), MIN_CONN_EVENT_LENGTH);
+    assert_eq!(conn_event_length(12, 0), 12);
 }
 
+// ── Reducers ────────────────────────────────────────────────────────────
+
 #[test]
 fn plan_connect_out_of_range_errors() {
     let mut m = mgr();
@@ -158,16 +195,150 @@ fn plan_connect_already_connected_is_noop() {
 }
 
 #[test]
-fn plan_connect_no_free_slot_errors() {
+fn plan_connect_when_full_replaces_least_recently_active() {
     let mut m = mgr();
     m.connect_slot(0, &dev(1, "a"));
     m.connect_slot(1, &dev(2, "b"));
+    m.note_activity(0);
     let devices = [dev(3, "c")];
     let acts = plan_connect(&mut m, &devices, 0);
+    assert_eq!(
+        acts.as_slice(),
+        &[Action::ConnectSlot {
+            slot: 1,
+            device: dev(3, "c")
+        }]
+    );
+    // Slot 0 is untouched; slot 1 now waits for the new device.
+    assert_eq!(m.active_count(), 1);
+    assert!(m.is_connected_address(&3));
+    assert!(!m.is_connected_address(&2));
+}
+
+#[test]
+fn plan_connect_when_full_replaces_same_role() {
+    let mut m = mgr();
+    let mouse = typed(2, "Old mouse", DeviceIcon::Mouse);
+    on_slot_connected(&mut m, 0, &dev(1, "Keyboard"), DeviceRole::Keyboard);
+    on_slot_connected(&mut m, 1, &mouse, DeviceRole::Pointer);
+    // The mouse is busier than the keyboard, but it's the one being replaced.
+    m.note_activity(1);
+    let devices = [typed(3, "New mouse", DeviceIcon::Mouse)];
+    let acts = plan_connect(&mut m, &devices, 0);
+    assert!(matches!(acts[0], Action::ConnectSlot { slot: 1, .. }));
+    assert_eq!(m.role(0), DeviceRole::Keyboard);
+    assert_eq!(m.role(1), DeviceRole::Pointer);
+}
+
+#[test]
+fn plan_connect_withdraws_replaced_battery() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "a"));
+    m.connect_slot(1, &dev(2, "b"));
+    on_slot_battery(&mut m, 1, Some(70));
+    m.note_activity(0);
+    let acts = plan_connect(&mut m, &[dev(3, "c")], 0);
+    assert_eq!(
+        acts.as_slice(),
+        &[
+            Action::PublishBattery {
+                slot: 1,
+                level: None
+            },
+            Action::Emit(UiEvent::Battery([None, None])),
+            Action::ConnectSlot {
+                slot: 1,
+                device: dev(3, "c")
+            }
+        ]
+    );
+    // The old worker's own report of the lost level has nothing left to do.
+    assert!(on_slot_battery(&mut m, 1, None).is_empty());
+}
+
+#[test]
+fn replaced_link_closing_keeps_the_reservation() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "a"));
+    m.connect_slot(1, &dev(2, "b"));
+    plan_connect(&mut m, &[dev(3, "c")], 0);
+    // The old peer's link on slot 0 ends as the worker switches over.
+    let acts = on_slot_disconnected(&mut m, 0);
+    assert!(m.is_connected_address(&3));
+    assert!(matches!(acts[0], Action::Emit(UiEvent::Connected(_))));
+    // The new device's own failure does free the slot.
+    let acts = on_slot_error(&mut m, 0, ErrorTag::ConnectFailed);
     assert_eq!(
         acts[0],
         Action::Emit(UiEvent::Error(ErrorTag::ConnectFailed))
     );
+    assert!(!m.is_slot_occupied(0));
+}
+
+#[test]
+fn replaced_link_error_is_not_shown() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "a"));
+    m.connect_slot(1, &dev(2, "b"));
+    plan_connect(&mut m, &[dev(3, "c")], 0);
+    let acts = on_slot_error(&mut m, 0, ErrorTag::NotifyFailed);
+    assert!(!acts
+        .iter()
+        .any(|a| matches!(a, Action::Emit(UiEvent::Error(_)))));
+    assert!(m.is_connected_address(&3));
+}
+
+#[test]
+fn role_comes_from_report_map() {
+    let desc = |keyboard, mouse, consumer| HidDescriptor {
+        has_keyboard: keyboard,
+        has_mouse: mouse,
+        has_consumer: consumer,
+        keyboard_report_id: None,
+        mouse_report_id: None,
+        consumer_report_id: None,
+        complete: true,
+    };
+    let role = |d| DeviceRole::from_descriptors(&[Some(d)]);
+    assert_eq!(role(desc(true, true, true)), DeviceRole::Keyboard);
+    assert_eq!(role(desc(false, true, false)), DeviceRole::Pointer);
+    assert_eq!(role(desc(false, false, true)), DeviceRole::Consumer);
+    assert_eq!(role(desc(false, false, false)), DeviceRole::Other);
+    assert_eq!(DeviceRole::from_descriptors(&[None]), DeviceRole::Other);
+    // A touchpad service next to a keyboard service is still a keyboard.
+    let combo = [
+        Some(desc(false, true, false)),
+        Some(desc(true, false, false)),
+    ];
+    assert_eq!(DeviceRole::from_descriptors(&combo), DeviceRole::Keyboard);
+}
+
+#[test]
+fn plan_disconnect_slot_leaves_others_up() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "kb"));
+    m.connect_slot(1, &dev(2, "mouse"));
+    assert_eq!(
+        plan_disconnect_slot(&m, 1).as_slice(),
+        &[Action::DisconnectSlot(1)]
+    );
+    m.disconnect_slot(1);
+    assert!(plan_disconnect_slot(&m, 1).is_empty());
+}
+
+#[test]
+fn slot_summaries_list_occupied_slots() {
+    let mut m = mgr();
+    on_slot_connected(&mut m, 1, &dev(2, "Mouse"), DeviceRole::Pointer);
+    let summaries = m.slot_summaries();
+    assert_eq!(summaries[0], None);
+    assert_eq!(
+        summaries[1],
+        Some(SlotSummary {
+            role: DeviceRole::Pointer,
+            name: dev(2, "Mouse").name,
+        })
+    );
 }
 
 #[test]
@@ -179,13 +350,90 @@ fn plan_disconnect_targets_occupied_slots() {
     assert_eq!(acts[0], Action::DisconnectSlot(1));
 }
 
+#[test]
+fn plan_reconnect_prefers_the_live_address_and_keeps_the_stored_name() {
+    let mut m = mgr();
+    let peers = [
+        typed(1, "Keyboard", DeviceIcon::Keyboard),
+        dev(2, "Mouse"),
+        dev(3, "Remote"),
+    ];
+    // The keyboard advertises under a rotated address; the mouse wasn't seen.
+    let scanned = [dev(9, "Unnamed"), typed(7, "KB-1234", DeviceIcon::Keyboard)];
+    let targets = [
+        ReconnectTarget {
+            peer: 0,
+            scanned: Some(1),
+        },
+        ReconnectTarget {
+            peer: 1,
+            scanned: None,
+        },
+    ];
+    let acts = plan_reconnect(&mut m, &peers, &scanned, &targets);
+    assert_eq!(
+        acts.as_slice(),
+        &[
+            Action::ConnectSlot {
+                slot: 0,
+                device: DeviceInfo {
+                    bonded: true,
+                    ..typed(7, "Keyboard", DeviceIcon::Keyboard)
+                },
+            },
+            Action::ConnectSlot {
+                slot: 1,
+                device: dev(2, "Mouse"),
+            },
+        ]
+    );
+    assert!(m.is_connected_address(&7) && m.is_connected_address(&2));
+}
+
+#[test]
+fn plan_reconnect_replaces_the_previous_profiles_links() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "Desk keyboard"));
+    m.connect_slot(1, &dev(2, "Desk mouse"));
+    on_slot_battery(&mut m, 0, Some(40));
+    let acts = plan_disconnect(&m);
+    assert_eq!(acts.len(), 2);
+
+    let target = ReconnectTarget {
+        peer: 0,
+        scanned: None,
+    };
+    let acts = plan_reconnect(&mut m, &[dev(5, "Presenter")], &[], &[target]);
+    // The keyboard's level goes with it.
+    assert_eq!(
+        acts[0],
+        Action::PublishBattery {
+            slot: 0,
+            level: None
+        }
+    );
+    assert_eq!(m.battery(0), None);
+    // The old link on slot 0 closing doesn't free the new reservation; the
+    // one on slot 1 does.
+    on_slot_disconnected(&mut m, 0);
+    on_slot_disconnected(&mut m, 1);
+    assert!(m.is_connected_address(&5));
+    assert_eq!(m.occupied_count(), 1);
+}
+
 #[test]
 fn on_slot_connected_persists_and_emits_summary() {
     let mut m = mgr();
     let kb = dev(1, "Keyboard");
-    let acts = on_slot_connected(&mut m, 0, &kb);
-    assert_eq!(acts.len(), 2);
-    assert!(matches!(acts[0], Action::PersistDevice(_)));
+    let acts = on_slot_connected(&mut m, 0, &kb, DeviceRole::Keyboard);
+    assert_eq!(acts.len(), 3);
+    assert_eq!(
+        acts[0],
+        Action::PersistDevice {
+            device: kb.clone(),
+            role: DeviceRole::Keyboard,
+        }
+    );
     assert_eq!(
         acts[1],
         Action::Emit(UiEvent::Connected({
@@ -227,7 +475,7 @@ fn on_slot_error_emits_error_then_status() {
     let mut m = mgr();
     m.connect_slot(0, &dev(1, "kb"));
     let acts = on_slot_error(&mut m, 0, ErrorTag::NotifyFailed);
-    assert_eq!(acts.len(), 2);
+    assert_eq!(acts.len(), 3);
     assert_eq!(
         acts[0],
         Action::Emit(UiEvent::Error(ErrorTag::NotifyFailed))
@@ -248,3 +496,122 @@ fn on_slot_error_with_surviving_link_reports_summary() {
     assert!(matches!(acts[1], Action::Emit(UiEvent::Connected(_))));
     assert_eq!(m.active_count(), 1);
 }
+
+// ── Battery levels ─────────────────────────────────────────────────────
+
+#[test]
+fn on_slot_battery_publishes_and_emits_levels() {
+    let mut m = mgr();
+    m.connect_slot(1, &dev(2, "Mouse"));
+    let actions = on_slot_battery(&mut m, 1, Some(80));
+    assert_eq!(actions.len(), 2);
+    assert_eq!(
+        actions[0],
+        Action::PublishBattery {
+            slot: 1,
+            level: Some(80)
+        }
+    );
+    assert_eq!(actions[1], Action::Emit(UiEvent::Battery([None, Some(80)])));
+    assert_eq!(m.battery(1), Some(80));
+}
+
+#[test]
+fn on_slot_battery_unchanged_level_is_noop() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "Keyboard"));
+    on_slot_battery(&mut m, 0, Some(50));
+    assert!(on_slot_battery(&mut m, 0, Some(50)).is_empty());
+}
+
+#[test]
+fn on_slot_battery_warns_once_when_low() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "Keyboard"));
+    on_slot_battery(&mut m, 0, Some(40));
+    let actions = on_slot_battery(&mut m, 0, Some(10));
+    assert!(actions.contains(&Action::Emit(UiEvent::LowBattery(dev(1, "Keyboard").name))));
+    let actions = on_slot_battery(&mut m, 0, Some(9));
+    assert!(!actions
+        .iter()
+        .any(|a| matches!(a, Action::Emit(UiEvent::LowBattery(_)))));
+}
+
+#[test]
+fn on_slot_battery_ignores_unoccupied_slot() {
+    let mut m = mgr();
+    assert!(on_slot_battery(&mut m, 0, Some(70)).is_empty());
+    assert_eq!(m.battery(0), None);
+}
+
+#[test]
+fn battery_cleared_on_link_loss() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "Keyboard"));
+    on_slot_battery(&mut m, 0, Some(70));
+    let actions = on_slot_battery(&mut m, 0, None);
+    // The USB host stops seeing the old level too, so the slot's next device
+    // doesn't inherit it.
+    assert_eq!(
+        actions.as_slice(),
+        &[
+            Action::PublishBattery {
+                slot: 0,
+                level: None
+            },
+            Action::Emit(UiEvent::Battery([None, None]))
+        ]
+    );
+    m.disconnect_slot(0);
+    assert_eq!(m.battery_levels(), [None, None]);
+}
+
+// ── Link quality ───────────────────────────────────────────────────────
+
+#[test]
+fn on_slot_rssi_emits_bars_when_they_move() {
+    let mut m = mgr();
+    m.connect_slot(1, &dev(2, "Mouse"));
+    let acts = on_slot_rssi(&mut m, 1, -55);
+    assert_eq!(
+        acts.as_slice(),
+        &[Action::Emit(UiEvent::Signal([None, Some(4)]))]
+    );
+    assert!(on_slot_rssi(&mut m, 1, -56).is_empty());
+}
+
+#[test]
+fn on_slot_rssi_warns_when_link_degrades() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "Mouse"));
+    let mut warned = 0;
+    for _ in 0..crate::ble::link_stats::RSSI_WINDOW * 2 {
+        warned += on_slot_rssi(&mut m, 0, -92)
+            .iter()
+            .filter(|a| **a == Action::Emit(UiEvent::WeakLink(dev(1, "Mouse").name)))
+            .count();
+    }
+    assert_eq!(warned, 1);
+}
+
+#[test]
+fn on_slot_rssi_ignores_links_not_up() {
+    let mut m = mgr();
+    m.reserve_slot(0, &dev(1, "kb"));
+    assert!(on_slot_rssi(&mut m, 0, -50).is_empty());
+    assert!(on_slot_rssi(&mut m, 5, -50).is_empty());
+}
+
+#[test]
+fn link_losses_survive_reconnect() {
+    let mut m = mgr();
+    m.connect_slot(0, &dev(1, "kb"));
+    on_slot_rssi(&mut m, 0, -50);
+    m.record_link_loss(0);
+    on_slot_disconnected(&mut m, 0);
+    m.connect_slot(0, &dev(1, "kb"));
+    let stats = m.link_stats(0).unwrap();
+    assert_eq!(stats.link_losses(), 1);
+    assert_eq!(stats.average(), None, "new link, new RSSI window");
+    assert_eq!(m.signal_levels(), [None, None]);
+}
@@ -11,25 +11,58 @@
 //! 4. Enables CCCD notifications on each input report characteristic.
 //! 5. Forwards received HID reports to the USB task via a channel.
 //!
+//! Alongside HID it subscribes to the peer's Battery Service (0x180F) Battery
+//! Level, if it has one, and surfaces level changes from the same run loop. It
+//! also reads the Device Information PnP ID (0x2A50), which selects the
+//! device's entry in the [`hid::quirks`] table.
+//!
+//! Composite peripherals (a keyboard with a touchpad, a receiver built into a
+//! device) may expose several HID services, each with its own Report Map.
+//! Every report is tagged with the instance it belongs to, each instance keeps
+//! its own parsed map, and a notification is classified against the map of
+//! the instance it arrived on. Which instances are found is up to
+//! [`discover_hid_services`]: nrf-softdevice's `gatt_client::discover` binds
+//! only the *first* service with a UUID (it keeps the first entry of the
+//! Primary Service Discovery response and can't start past a handle), so that
+//! is the one place that needs to learn about further instances. Walking the
+//! services with raw `sd_ble_gattc_primary_services_discover` isn't an option
+//! either: the SoftDevice's responses go to nrf-softdevice's own
+//! per-connection waiter, which only its GATT client calls can wait on.
+//!
+//! For bonded peers, steps 1–3 (and the Report Map read) are skipped when a
+//! [`GattCache`] from a previous connection is available. The cache is
+//! invalidated by a Service Changed indication or a handle mismatch (a CCCD
+//! write that fails on a cached handle), after which discovery runs afresh.
+//!
 //! The `#[gatt_client]` macro can only bind a single characteristic per UUID,
 //! so this uses a hand-rolled [`gatt_client::Client`] implementation instead.
 
+use crate::ble::long_read::{LongRead, LongReadStep, MAX_REPORT_MAP_LEN};
 use crate::ble::BleErrorTag;
+use crate::config;
 use crate::hid;
 use crate::hid::coalesce::ReportCoalescer;
 use crate::hid::keyboard::KeyboardLeds;
+use crate::hid::protocol_fallback::FallbackMonitor;
+use crate::hid::quirks::{self, PnpId, Quirks, PNP_ID_SIZE};
 use crate::hid::report_protocol::{HidDescriptor, ReportKind, ReportReference, ReportType};
 use crate::hid::HidReport;
+use crate::power::PeripheralPowerReceiver;
+use crate::power_logic::PeripheralPower;
+use crate::storage::gatt_cache::{
+    input_report_kind, BootHandles, CachedReport, GattCache, MAX_CACHED_REPORTS, MAX_HID_INSTANCES,
+};
 use crate::usb::hid_device::LedReceiver;
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
+use core::future::pending;
 use defmt::{info, warn};
-use embassy_futures::select::{select, select3};
+use embassy_futures::select::{select3, select4, Either3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
 use embassy_sync::signal::Signal;
 use heapless::Vec;
 use nrf_softdevice::ble::gatt_client::{
-    self, Characteristic, Client, Descriptor, DiscoverError, HvxType,
+    self, Characteristic, Client, Descriptor, DiscoverError, HvxType, ReadError,
 };
 use nrf_softdevice::ble::{Connection, Uuid};
 
@@ -38,53 +71,128 @@ const UUID_HID_SERVICE: u16 = 0x1812;
 const UUID_REPORT: u16 = 0x2A4D;
 const UUID_REPORT_MAP: u16 = 0x2A4B;
 const UUID_PROTOCOL_MODE: u16 = 0x2A4E;
+const UUID_HID_CONTROL_POINT: u16 = 0x2A4C;
+const UUID_BOOT_KEYBOARD_INPUT: u16 = 0x2A22;
+const UUID_BOOT_KEYBOARD_OUTPUT: u16 = 0x2A32;
+const UUID_BOOT_MOUSE_INPUT: u16 = 0x2A33;
 const UUID_REPORT_REFERENCE: u16 = 0x2908;
 const UUID_CCCD: u16 = 0x2902;
 
+// Generic Attribute service, for Service Changed indications.
+const UUID_GATT_SERVICE: u16 = 0x1801;
+const UUID_SERVICE_CHANGED: u16 = 0x2A05;
+
+// Battery Service, for the peer's Battery Level.
+const UUID_BATTERY_SERVICE: u16 = 0x180F;
+const UUID_BATTERY_LEVEL: u16 = 0x2A19;
+
+// Device Information Service, for the PnP ID that keys the quirks table.
+const UUID_DEVICE_INFORMATION: u16 = 0x180A;
+const UUID_PNP_ID: u16 = 0x2A50;
+
 /// Maximum number of HID Report (0x2A4D) characteristics tracked per device.
 /// A composite HID peripheral rarely exposes more than a handful of reports.
-const MAX_REPORTS: usize = 8;
+/// Matches the GATT cache capacity so every tracked report can be cached.
+const MAX_REPORTS: usize = MAX_CACHED_REPORTS;
+
+/// Largest notification payload: `ATT_MTU - 3` for the MTU the SoftDevice is
+/// configured with, which caps what any link negotiates. The SoftDevice
+/// doesn't deliver notifications longer than the link's MTU, so every one
+/// should fit whole; one that doesn't is dropped and logged.
+const MAX_REPORT_LEN: usize = config::BLE_ATT_MTU as usize - 3;
+
+/// Scratch size for one Report Map Read / Read Blob response (`ATT_MTU - 1`).
+const MAX_REPORT_CHUNK: usize = config::BLE_ATT_MTU as usize - 1;
 
-/// Largest notification payload we copy out of the GATT event (boot reports are
-/// ≤8 B; report-protocol notifications are capped by `att_mtu`).
-const MAX_REPORT_LEN: usize = 32;
+/// The parsed Report Map of each HID service instance, indexed by instance.
+pub type HidDescriptors = Vec<Option<HidDescriptor>, MAX_HID_INSTANCES>;
 
 /// A discovered HID Report characteristic and the descriptor handles needed to
 /// subscribe to and classify it.
 #[derive(Clone, Copy)]
 struct ReportCharacteristic {
+    /// HID service instance the report belongs to.
+    instance: u8,
     value_handle: u16,
     cccd_handle: Option<u16>,
     report_ref_handle: Option<u16>,
+    /// Resolved Report Reference (read after discovery, or from the cache).
+    report_ref: Option<ReportReference>,
 }
 
-/// An active subscription: a notifying value handle and the report kind resolved
-/// for it (`None` → defer to the heuristic classifier).
+/// An active subscription: a notifying value handle, its HID service instance
+/// and the report kind resolved for it (`None` → defer to the heuristic
+/// classifier, guided by that instance's Report Map).
 #[derive(Clone, Copy)]
 struct Subscription {
+    instance: u8,
     value_handle: u16,
     kind: Option<ReportKind>,
 }
 
 /// Notification event surfaced by the GATT run loop.
 pub struct ReportNotification {
+    instance: u8,
     kind: Option<ReportKind>,
     data: Vec<u8, MAX_REPORT_LEN>,
 }
 
+/// Everything the GATT run loop can surface for a HID peer.
+pub enum HidEvent {
+    Report(ReportNotification),
+    /// The peer indicated that its GATT database changed; cached handles are
+    /// stale.
+    ServiceChanged,
+    /// Battery Level notification (percent).
+    Battery(u8),
+}
+
+/// Why [`run_notification_loop`] returned.
+pub enum LoopEnd {
+    /// The link dropped.
+    Disconnected,
+    /// A Service Changed indication arrived; the caller should rediscover.
+    ServiceChanged,
+    /// Report Protocol notifications keep failing to classify; the caller
+    /// should try [`HidServiceClient::enter_boot_protocol`].
+    BootFallback,
+}
+
 /// Hand-rolled HID-over-GATT client.
 ///
 /// Captures every HID Report characteristic plus the Report Map and Protocol
 /// Mode handles. Handles are gathered during discovery; the notification
 /// subscriptions are resolved afterwards in [`HidServiceClient::subscribe_all`].
 pub struct HidServiceClient {
-    report_map_handle: Option<u16>,
+    /// Report Map handle of each HID service instance (`None` when rebuilt
+    /// from the cache, which holds the parsed maps instead).
+    report_map_handles: Vec<Option<u16>, MAX_HID_INSTANCES>,
     protocol_mode_handle: Option<u16>,
     reports: Vec<ReportCharacteristic, MAX_REPORTS>,
     subscriptions: Vec<Subscription, MAX_REPORTS>,
     /// Handle of the keyboard's LED **output** report characteristic, if any —
     /// where host LED (Caps/Num/Scroll) state is written back to the BLE keyboard.
     keyboard_led_handle: Option<u16>,
+    /// Service Changed value handle (Generic Attribute service), if subscribed.
+    service_changed_handle: Option<u16>,
+    /// HID Control Point: where Suspend / Exit Suspend are written.
+    control_point_handle: Option<u16>,
+    /// Battery Level value handle and CCCD (Battery Service), if the peer has
+    /// one. Notifications on it arrive through this client's run loop.
+    battery_level_handle: Option<u16>,
+    battery_level_cccd: Option<u16>,
+    /// Device Information PnP ID, and the quirks it selected.
+    pnp_id: Option<PnpId>,
+    quirks: Option<&'static Quirks>,
+    /// Boot Protocol characteristics, for the Boot Protocol fallback.
+    boot: BootHandles,
+    /// The peer is in Boot Protocol and we're subscribed to its boot reports.
+    boot_mode: bool,
+    /// Boot Protocol was already tried on this link (don't retry on failure).
+    boot_tried: bool,
+    /// Notifications dropped for being longer than [`MAX_REPORT_LEN`]; the
+    /// first is logged, so a link that delivers them shows in the field.
+    oversize_drops: Cell<u32>,
 }
 
 fn descriptor_handle(descriptors: &[Descriptor], uuid_16: u16) -> Option<u16> {
@@ -96,19 +204,31 @@ fn descriptor_handle(descriptors: &[Descriptor], uuid_16: u16) -> Option<u16> {
 }
 
 impl Client for HidServiceClient {
-    type Event = ReportNotification;
+    type Event = HidEvent;
 
     fn uuid() -> Uuid {
         Uuid::new_16(UUID_HID_SERVICE)
     }
 
     fn new_undiscovered(_conn: Connection) -> Self {
+        let mut report_map_handles = Vec::new();
+        let _ = report_map_handles.push(None);
         Self {
-            report_map_handle: None,
+            report_map_handles,
             protocol_mode_handle: None,
             reports: Vec::new(),
             subscriptions: Vec::new(),
             keyboard_led_handle: None,
+            service_changed_handle: None,
+            control_point_handle: None,
+            battery_level_handle: None,
+            battery_level_cccd: None,
+            pnp_id: None,
+            quirks: None,
+            boot: BootHandles::default(),
+            boot_mode: false,
+            boot_tried: false,
+            oversize_drops: Cell::new(0),
         }
     }
 
@@ -122,22 +242,35 @@ impl Client for HidServiceClient {
         };
 
         if uuid == Uuid::new_16(UUID_REPORT_MAP) {
-            self.report_map_handle = Some(characteristic.handle_value);
+            self.report_map_handles[0] = Some(characteristic.handle_value);
         } else if uuid == Uuid::new_16(UUID_PROTOCOL_MODE) {
             self.protocol_mode_handle = Some(characteristic.handle_value);
+        } else if uuid == Uuid::new_16(UUID_HID_CONTROL_POINT) {
+            self.control_point_handle = Some(characteristic.handle_value);
         } else if uuid == Uuid::new_16(UUID_REPORT) {
             // A Report characteristic with a CCCD is a notifiable *input* report;
             // its Report Reference descriptor tells us the report ID + direction.
             let _ = self.reports.push(ReportCharacteristic {
+                instance: 0,
                 value_handle: characteristic.handle_value,
                 cccd_handle: descriptor_handle(descriptors, UUID_CCCD),
                 report_ref_handle: descriptor_handle(descriptors, UUID_REPORT_REFERENCE),
+                report_ref: None,
             });
+        } else if uuid == Uuid::new_16(UUID_BOOT_KEYBOARD_INPUT) {
+            self.boot.keyboard_input = Some(characteristic.handle_value);
+            self.boot.keyboard_input_cccd = descriptor_handle(descriptors, UUID_CCCD);
+        } else if uuid == Uuid::new_16(UUID_BOOT_KEYBOARD_OUTPUT) {
+            self.boot.keyboard_output = Some(characteristic.handle_value);
+        } else if uuid == Uuid::new_16(UUID_BOOT_MOUSE_INPUT) {
+            self.boot.mouse_input = Some(characteristic.handle_value);
+            self.boot.mouse_input_cccd = descriptor_handle(descriptors, UUID_CCCD);
         }
     }
 
     fn discovery_complete(&mut self) -> Result<(), DiscoverError> {
-        if self.reports.is_empty() {
+        // Boot input characteristics alone are enough to be useful.
+        if self.reports.is_empty() && !self.boot.has_input() {
             return Err(DiscoverError::ServiceIncomplete);
         }
         Ok(())
@@ -150,41 +283,313 @@ impl Client for HidServiceClient {
         handle: u16,
         data: &[u8],
     ) -> Option<Self::Event> {
+        if type_ == HvxType::Indication && Some(handle) == self.service_changed_handle {
+            return Some(HidEvent::ServiceChanged);
+        }
         if type_ != HvxType::Notification {
             return None;
         }
+        if Some(handle) == self.battery_level_handle {
+            return data.first().map(|&level| HidEvent::Battery(level));
+        }
         // Only surface notifications from handles we actually subscribed to.
         let sub = self
             .subscriptions
             .iter()
             .find(|s| s.value_handle == handle)?;
 
-        let mut buf: Vec<u8, MAX_REPORT_LEN> = Vec::new();
-        let n = data.len().min(buf.capacity());
-        let _ = buf.extend_from_slice(&data[..n]);
-        Some(ReportNotification {
+        let Ok(data) = Vec::from_slice(data) else {
+            let drops = self.oversize_drops.get().saturating_add(1);
+            self.oversize_drops.set(drops);
+            if drops == 1 {
+                warn!(
+                    "Dropped a {}-byte notification (max {})",
+                    data.len(),
+                    MAX_REPORT_LEN
+                );
+            }
+            return None;
+        };
+        Some(HidEvent::Report(ReportNotification {
+            instance: sub.instance,
             kind: sub.kind,
-            data: buf,
-        })
+            data,
+        }))
     }
 }
 
-impl HidServiceClient {
-    /// Subscribe to every discovered input report characteristic, resolving each
-    /// one's kind from its Report Reference descriptor and the Report Map.
-    async fn subscribe_all(
+/// Minimal client for the Generic Attribute service (0x1801), used only to find
+/// the Service Changed characteristic and its CCCD.
+struct GattServiceClient {
+    service_changed: Option<(u16, Option<u16>)>,
+}
+
+impl Client for GattServiceClient {
+    type Event = ();
+
+    fn uuid() -> Uuid {
+        Uuid::new_16(UUID_GATT_SERVICE)
+    }
+
+    fn new_undiscovered(_conn: Connection) -> Self {
+        Self {
+            service_changed: None,
+        }
+    }
+
+    fn discovered_characteristic(
         &mut self,
-        conn: &Connection,
-        descriptor: Option<&HidDescriptor>,
-    ) -> Result<(), BleErrorTag> {
-        // Snapshot the (Copy) report list so we can fill `subscriptions` while
-        // iterating without aliasing `&mut self`.
-        let reports = self.reports.clone();
-
-        for report in reports.iter() {
-            // Read this characteristic's Report Reference (0x2908) once: it gives
-            // the report ID + direction (Input/Output/Feature).
-            let report_ref = match report.report_ref_handle {
+        characteristic: &Characteristic,
+        descriptors: &[Descriptor],
+    ) {
+        if characteristic.uuid == Some(Uuid::new_16(UUID_SERVICE_CHANGED)) {
+            self.service_changed = Some((
+                characteristic.handle_value,
+                descriptor_handle(descriptors, UUID_CCCD),
+            ));
+        }
+    }
+
+    fn discovery_complete(&mut self) -> Result<(), DiscoverError> {
+        match self.service_changed {
+            Some(_) => Ok(()),
+            None => Err(DiscoverError::ServiceIncomplete),
+        }
+    }
+
+    fn on_hvx(
+        &self,
+        _conn: &Connection,
+        _type_: HvxType,
+        _handle: u16,
+        _data: &[u8],
+    ) -> Option<Self::Event> {
+        None
+    }
+}
+
+/// Find the peer's Service Changed characteristic and enable indications on it,
+/// returning its value handle. `None` if the peer doesn't expose one (its GATT
+/// layout is then assumed static, and a handle mismatch is the only way a stale
+/// cache is detected).
+async fn subscribe_service_changed(conn: &Connection) -> Option<u16> {
+    let gatt: GattServiceClient = gatt_client::discover(conn).await.ok()?;
+    let (value_handle, cccd) = gatt.service_changed?;
+    if let Some(cccd) = cccd {
+        // Enable indications (write 0x0002 to the CCCD).
+        if gatt_client::write(conn, cccd, &[0x02, 0x00]).await.is_err() {
+            warn!("Could not enable Service Changed indications");
+            return None;
+        }
+    }
+    Some(value_handle)
+}
+
+/// Minimal client for the Battery Service (0x180F), used only to find the
+/// Battery Level characteristic and its CCCD.
+struct BatteryServiceClient {
+    battery_level: Option<(u16, Option<u16>)>,
+}
+
+impl Client for BatteryServiceClient {
+    type Event = ();
+
+    fn uuid() -> Uuid {
+        Uuid::new_16(UUID_BATTERY_SERVICE)
+    }
+
+    fn new_undiscovered(_conn: Connection) -> Self {
+        Self {
+            battery_level: None,
+        }
+    }
+
+    fn discovered_characteristic(
+        &mut self,
+        characteristic: &Characteristic,
+        descriptors: &[Descriptor],
+    ) {
+        if characteristic.uuid == Some(Uuid::new_16(UUID_BATTERY_LEVEL)) {
+            self.battery_level = Some((
+                characteristic.handle_value,
+                descriptor_handle(descriptors, UUID_CCCD),
+            ));
+        }
+    }
+
+    fn discovery_complete(&mut self) -> Result<(), DiscoverError> {
+        match self.battery_level {
+            Some(_) => Ok(()),
+            None => Err(DiscoverError::ServiceIncomplete),
+        }
+    }
+
+    fn on_hvx(
+        &self,
+        _conn: &Connection,
+        _type_: HvxType,
+        _handle: u16,
+        _data: &[u8],
+    ) -> Option<Self::Event> {
+        None
+    }
+}
+
+/// Minimal client for the Device Information Service (0x180A), used only to
+/// find the PnP ID characteristic.
+struct DeviceInformationClient {
+    pnp_id: Option<u16>,
+}
+
+impl Client for DeviceInformationClient {
+    type Event = ();
+
+    fn uuid() -> Uuid {
+        Uuid::new_16(UUID_DEVICE_INFORMATION)
+    }
+
+    fn new_undiscovered(_conn: Connection) -> Self {
+        Self { pnp_id: None }
+    }
+
+    fn discovered_characteristic(
+        &mut self,
+        characteristic: &Characteristic,
+        _descriptors: &[Descriptor],
+    ) {
+        if characteristic.uuid == Some(Uuid::new_16(UUID_PNP_ID)) {
+            self.pnp_id = Some(characteristic.handle_value);
+        }
+    }
+
+    fn discovery_complete(&mut self) -> Result<(), DiscoverError> {
+        match self.pnp_id {
+            Some(_) => Ok(()),
+            None => Err(DiscoverError::ServiceIncomplete),
+        }
+    }
+
+    fn on_hvx(
+        &self,
+        _conn: &Connection,
+        _type_: HvxType,
+        _handle: u16,
+        _data: &[u8],
+    ) -> Option<Self::Event> {
+        None
+    }
+}
+
+/// Read the peer's PnP ID (vendor / product / version). `None` if it has no
+/// Device Information Service or no PnP ID in it.
+async fn read_pnp_id(conn: &Connection) -> Option<PnpId> {
+    let dis: DeviceInformationClient = gatt_client::discover(conn).await.ok()?;
+    let mut buf = [0u8; PNP_ID_SIZE];
+    let n = gatt_client::read(conn, dis.pnp_id?, &mut buf).await.ok()?;
+    PnpId::parse(&buf[..n])
+}
+
+impl HidServiceClient {
+    /// Rebuild a client from a previous connection's discovery results, without
+    /// touching the radio.
+    fn from_cache(cache: &GattCache) -> Self {
+        let mut reports = Vec::new();
+        for r in &cache.reports {
+            let _ = reports.push(ReportCharacteristic {
+                instance: r.instance,
+                value_handle: r.value_handle,
+                cccd_handle: r.cccd_handle,
+                report_ref_handle: None,
+                report_ref: r.report_ref,
+            });
+        }
+        let mut report_map_handles = Vec::new();
+        for _ in &cache.descriptors {
+            let _ = report_map_handles.push(None);
+        }
+        Self {
+            report_map_handles,
+            protocol_mode_handle: cache.protocol_mode_handle,
+            reports,
+            subscriptions: Vec::new(),
+            keyboard_led_handle: cache.keyboard_led_handle,
+            service_changed_handle: cache.service_changed_handle,
+            control_point_handle: cache.control_point_handle,
+            battery_level_handle: cache.battery_level_handle,
+            battery_level_cccd: cache.battery_level_cccd,
+            pnp_id: cache.pnp_id,
+            quirks: cache.pnp_id.as_ref().and_then(quirks::lookup),
+            boot: cache.boot,
+            boot_mode: false,
+            boot_tried: false,
+            oversize_drops: Cell::new(0),
+        }
+    }
+
+    /// Snapshot the discovery results (plus the parsed Report Maps) for reuse
+    /// on the next connection.
+    pub fn to_cache(&self, descriptors: &HidDescriptors) -> GattCache {
+        let mut reports = Vec::new();
+        for r in &self.reports {
+            let _ = reports.push(CachedReport {
+                instance: r.instance,
+                value_handle: r.value_handle,
+                cccd_handle: r.cccd_handle,
+                report_ref: r.report_ref,
+            });
+        }
+        GattCache {
+            protocol_mode_handle: self.protocol_mode_handle,
+            keyboard_led_handle: self.keyboard_led_handle,
+            service_changed_handle: self.service_changed_handle,
+            control_point_handle: self.control_point_handle,
+            battery_level_handle: self.battery_level_handle,
+            battery_level_cccd: self.battery_level_cccd,
+            pnp_id: self.pnp_id,
+            reports,
+            descriptors: descriptors.clone(),
+            boot: self.boot,
+            prefer_boot: self.boot_mode,
+        }
+    }
+
+    /// Record the peer's PnP ID and look up its quirks.
+    fn set_pnp_id(&mut self, pnp_id: Option<PnpId>) {
+        self.pnp_id = pnp_id;
+        self.quirks = pnp_id.as_ref().and_then(quirks::lookup);
+        if let Some(id) = pnp_id {
+            info!(
+                "PnP ID: source={} vendor={:04x} product={:04x} version={:04x} quirks={}",
+                id.vendor_id_source,
+                id.vendor_id,
+                id.product_id,
+                id.product_version,
+                self.quirks.is_some()
+            );
+        }
+    }
+
+    /// The Report Maps with this device's report-kind overrides applied.
+    fn fix_descriptors(&self, mut descriptors: HidDescriptors) -> HidDescriptors {
+        if let Some(q) = self.quirks {
+            for descriptor in descriptors.iter_mut() {
+                *descriptor = q.fix_descriptor(*descriptor);
+            }
+        }
+        descriptors
+    }
+
+    /// Does this device's quirk entry say Report Protocol is hopeless?
+    fn forces_boot(&self) -> bool {
+        self.quirks.is_some_and(|q| q.force_boot)
+    }
+
+    /// Read every report's Report Reference (0x2908) once: it gives the report
+    /// ID + direction (Input/Output/Feature). Also picks out the keyboard LED
+    /// output report.
+    async fn read_report_references(&mut self, conn: &Connection) {
+        for report in self.reports.iter_mut() {
+            report.report_ref = match report.report_ref_handle {
                 Some(ref_handle) => {
                     let mut buf = [0u8; 2];
                     match gatt_client::read(conn, ref_handle, &mut buf).await {
@@ -197,28 +602,41 @@ impl HidServiceClient {
 
             // No CCCD → not a notifiable input. If it's an Output report, it's the
             // keyboard LED sink we write host Caps/Num/Scroll state to.
+            let is_output =
+                matches!(report.report_ref, Some(r) if r.report_type == ReportType::Output);
+            if report.cccd_handle.is_none() && is_output && self.keyboard_led_handle.is_none() {
+                self.keyboard_led_handle = Some(report.value_handle);
+                info!("Found keyboard LED output report");
+            }
+        }
+    }
+
+    /// Subscribe to every input report characteristic, resolving each one's
+    /// kind from its Report Reference and its instance's Report Map.
+    async fn subscribe_all(
+        &mut self,
+        conn: &Connection,
+        descriptors: &[Option<HidDescriptor>],
+    ) -> Result<(), BleErrorTag> {
+        self.subscriptions.clear();
+
+        for report in self.reports.iter() {
             let Some(cccd) = report.cccd_handle else {
-                let is_output =
-                    matches!(report_ref, Some(r) if r.report_type == ReportType::Output);
-                if is_output && self.keyboard_led_handle.is_none() {
-                    self.keyboard_led_handle = Some(report.value_handle);
-                    info!("Found keyboard LED output report");
-                }
                 continue;
             };
 
             // Input report: resolve its kind from the Report Reference mapped
-            // through the Report Map's report-ID table. Anything we can't resolve
-            // is subscribed with `kind = None` and classified by the heuristic
-            // fallback at notification time.
-            let kind = report_ref
-                .filter(ReportReference::is_input)
-                .and_then(|r| descriptor.and_then(|d| d.report_kind_for_id(r.report_id)));
+            // through its instance's Report Map report-ID table (IDs are only
+            // unique within one map). Anything we can't resolve is subscribed
+            // with `kind = None` and classified by the heuristic fallback at
+            // notification time.
+            let kind = input_report_kind(descriptors, report.instance, report.report_ref);
 
             // Enable notifications (write 0x0001 to the CCCD).
             match gatt_client::write(conn, cccd, &[0x01, 0x00]).await {
                 Ok(_) => {
                     let _ = self.subscriptions.push(Subscription {
+                        instance: report.instance,
                         value_handle: report.value_handle,
                         kind,
                     });
@@ -233,17 +651,144 @@ impl HidServiceClient {
         }
 
         info!(
-            "Subscribed to {} of {} HID report characteristics",
+            "Subscribed to {} of {} HID report characteristics ({} HID service(s))",
             self.subscriptions.len(),
-            self.reports.len()
+            self.reports.len(),
+            self.report_map_handles.len()
+        );
+        Ok(())
+    }
+
+    /// `true` when every notifiable report was subscribed. On a cached client a
+    /// shortfall means a cached CCCD handle no longer exists on the peer.
+    fn fully_subscribed(&self) -> bool {
+        let notifiable = self.reports.iter().filter(|r| r.cccd_handle.is_some());
+        self.subscriptions.len() == notifiable.count()
+    }
+
+    /// Can this link still switch to Boot Protocol? Needs the Protocol Mode
+    /// characteristic and at least one notifiable boot input report.
+    fn can_fall_back_to_boot(&self) -> bool {
+        !self.boot_mode
+            && !self.boot_tried
+            && self.protocol_mode_handle.is_some()
+            && self.boot.has_input()
+    }
+
+    /// Switch the peer to Boot Protocol (0) and subscribe to its Boot Keyboard
+    /// and Boot Mouse Input Reports instead of the Report characteristics.
+    /// Boot reports have a fixed layout, so no Report Map is needed. Tried at
+    /// most once per link.
+    pub async fn enter_boot_protocol(&mut self, conn: &Connection) -> Result<(), BleErrorTag> {
+        if !self.can_fall_back_to_boot() {
+            return Err(BleErrorTag::NotifyFailed);
+        }
+        self.boot_tried = true;
+
+        let Some(mode_handle) = self.protocol_mode_handle else {
+            return Err(BleErrorTag::NotifyFailed);
+        };
+        if gatt_client::write(conn, mode_handle, &[0u8]).await.is_err() {
+            warn!("Could not switch peer to Boot Protocol");
+            return Err(BleErrorTag::NotifyFailed);
+        }
+
+        self.subscriptions.clear();
+        let inputs = [
+            (
+                self.boot.keyboard_input,
+                self.boot.keyboard_input_cccd,
+                ReportKind::Keyboard,
+            ),
+            (
+                self.boot.mouse_input,
+                self.boot.mouse_input_cccd,
+                ReportKind::Mouse,
+            ),
+        ];
+        for (value, cccd, kind) in inputs {
+            let (Some(value_handle), Some(cccd)) = (value, cccd) else {
+                continue;
+            };
+            match gatt_client::write(conn, cccd, &[0x01, 0x00]).await {
+                Ok(_) => {
+                    let _ = self.subscriptions.push(Subscription {
+                        instance: 0,
+                        value_handle,
+                        kind: Some(kind),
+                    });
+                }
+                Err(_) => warn!("Could not enable notifications on a boot report"),
+            }
+        }
+
+        if self.subscriptions.is_empty() {
+            warn!("No boot reports could be subscribed");
+            return Err(BleErrorTag::NotifyFailed);
+        }
+        self.boot_mode = true;
+        info!(
+            "HID in Boot Protocol ({} boot report(s))",
+            self.subscriptions.len()
         );
         Ok(())
     }
 
+    /// Tell the peripheral to enter or leave suspend through its HID Control
+    /// Point (Write Without Response, per HOGP). The link's connection
+    /// parameters follow separately (see [`LinkSignals::power`]).
+    async fn set_peripheral_power(&self, conn: &Connection, power: PeripheralPower) {
+        if let Some(handle) = self.control_point_handle {
+            let value = [power.control_point_value()];
+            match gatt_client::write_without_response(conn, handle, &value).await {
+                Ok(()) => info!("HID Control Point: {:?}", power),
+                Err(_) => warn!("Failed to write HID Control Point"),
+            }
+        }
+    }
+
+    /// Find the peer's Battery Level characteristic. Peers without a Battery
+    /// Service (mains-powered ones) are normal and simply have no level.
+    async fn discover_battery(&mut self, conn: &Connection) {
+        let bas: Option<BatteryServiceClient> = gatt_client::discover(conn).await.ok();
+        let (handle, cccd) = bas.and_then(|b| b.battery_level).unzip();
+        self.battery_level_handle = handle;
+        self.battery_level_cccd = cccd.flatten();
+    }
+
+    /// Enable Battery Level notifications, where the peer supports them. A
+    /// peer that only allows reads still gets its level read once on connect.
+    async fn subscribe_battery(&self, conn: &Connection) {
+        if let Some(cccd) = self.battery_level_cccd {
+            if gatt_client::write(conn, cccd, &[0x01, 0x00]).await.is_err() {
+                warn!("Could not enable Battery Level notifications");
+            }
+        }
+    }
+
+    /// Read the peer's current Battery Level (percent), if it has one.
+    pub async fn read_battery_level(&self, conn: &Connection) -> Option<u8> {
+        let handle = self.battery_level_handle?;
+        let mut buf = [0u8; 1];
+        match gatt_client::read(conn, handle, &mut buf).await {
+            Ok(1) => Some(buf[0]),
+            _ => {
+                warn!("Could not read Battery Level");
+                None
+            }
+        }
+    }
+
     /// Write host LED (Caps/Num/Scroll) state to the BLE keyboard's output
-    /// report, if this device exposes one. No-op for non-keyboard peers.
+    /// report (the Boot Keyboard Output Report in Boot Protocol), if this
+    /// device exposes one. No-op for non-keyboard peers.
     async fn write_leds(&self, conn: &Connection, leds: KeyboardLeds) {
-        if let Some(handle) = self.keyboard_led_handle {
+        let led_handle = if self.boot_mode {
+            self.boot.keyboard_output
+        } else {
+            self.keyboard_led_handle
+        };
+        if let Some(handle) = led_handle {
             if gatt_client::write(conn, handle, &[leds.byte()])
                 .await
                 .is_err()
@@ -254,68 +799,205 @@ impl HidServiceClient {
     }
 }
 
+/// Why a HID service instance has no parsed Report Map.
+#[derive(defmt::Format)]
+enum ReportMapError {
+    /// The Read failed.
+    Read(ReadError),
+    /// The map held no report types we recognize.
+    Unrecognized,
+}
+
 /// Read and parse the Report Map (0x2A4B) so report IDs can be mapped to kinds.
-async fn read_report_map(conn: &Connection, client: &HidServiceClient) -> Option<HidDescriptor> {
-    let handle = client.report_map_handle?;
-    let mut buf = [0u8; 128];
-    match gatt_client::read(conn, handle, &mut buf).await {
-        Ok(n) => {
-            let desc = HidDescriptor::parse(&buf[..n]);
-            match desc {
-                Some(d) => info!(
-                    "Report map parsed: keyboard={} mouse={} consumer={}",
-                    d.has_keyboard, d.has_mouse, d.has_consumer
-                ),
-                None => warn!("Report map parsing returned no recognized report types"),
-            }
-            desc
+///
+/// Real keyboards and combo devices ship maps of a few hundred bytes, more
+/// than one ATT Read returns (`ATT_MTU - 1`); [`LongRead`] sequences the Read
+/// Blobs that fetch the rest. nrf-softdevice's GATT client only issues
+/// offset-0 Reads, and the SoftDevice's response to a raw `sd_ble_gattc_read`
+/// goes to the client's own per-connection waiter, so the continuation can't
+/// be issued from here yet: a first chunk that fills the response ends the
+/// read truncated. A truncated map has `complete` cleared so classification
+/// doesn't trust what the missing tail might have said.
+async fn read_report_map(conn: &Connection, handle: u16) -> Result<HidDescriptor, ReportMapError> {
+    let mut buf = [0u8; MAX_REPORT_MAP_LEN];
+    let mut chunk = [0u8; MAX_REPORT_CHUNK];
+    let mut read = LongRead::new(&mut buf);
+
+    let n = gatt_client::read(conn, handle, &mut chunk)
+        .await
+        .map_err(ReportMapError::Read)?;
+    let step = match read.accept(&chunk[..n]) {
+        // A short first chunk is the whole value.
+        LongReadStep::Continue { .. } if n < chunk.len() => read.end_of_value(),
+        // The rest needs a Read Blob (see above).
+        LongReadStep::Continue { .. } => read.failed(),
+        step => step,
+    };
+    let complete = match step {
+        LongReadStep::Complete(n) => {
+            info!("Report map read: {} bytes", n);
+            true
         }
-        Err(_) => {
-            warn!("Could not read HID report map");
-            None
+        LongReadStep::Truncated(n) => {
+            warn!("Report map truncated at {} bytes", n);
+            false
         }
+        LongReadStep::Continue { .. } => unreachable!(),
+    };
+
+    let mut desc = HidDescriptor::parse(read.data()).ok_or(ReportMapError::Unrecognized)?;
+    desc.complete &= complete;
+    info!(
+        "Report map parsed: keyboard={} mouse={} consumer={} complete={}",
+        desc.has_keyboard, desc.has_mouse, desc.has_consumer, desc.complete
+    );
+    Ok(desc)
+}
+
+/// Read and parse every HID service instance's Report Map, in instance order.
+async fn read_report_maps(conn: &Connection, client: &HidServiceClient) -> HidDescriptors {
+    let mut descriptors = Vec::new();
+    for handle in client.report_map_handles.iter() {
+        let descriptor = match handle {
+            Some(handle) => match read_report_map(conn, *handle).await {
+                Ok(descriptor) => Some(descriptor),
+                Err(err) => {
+                    warn!("No usable HID report map: {}", err);
+                    None
+                }
+            },
+            None => None,
+        };
+        let _ = descriptors.push(descriptor);
     }
+    descriptors
+}
+
+/// Discover the peer's HID service instances into one client, each report
+/// tagged with the instance it belongs to. The binding reaches the first
+/// instance only (see the module docs), which becomes instance 0.
+async fn discover_hid_services(conn: &Connection) -> Result<HidServiceClient, BleErrorTag> {
+    gatt_client::discover(conn)
+        .await
+        .map_err(|_| BleErrorTag::HidNotFound)
 }
 
-/// Discover the HID service and subscribe to all HID Report notifications.
+/// Force Report Protocol mode (1). Report Protocol is the GATT default, so this
+/// is mostly defensive; Boot Protocol is only entered as a fallback (see
+/// [`HidServiceClient::enter_boot_protocol`]).
+async fn set_report_protocol(conn: &Connection, client: &HidServiceClient) {
+    if let Some(handle) = client.protocol_mode_handle {
+        match gatt_client::write(conn, handle, &[1u8]).await {
+            Ok(_) => info!("Set HID protocol to Report mode"),
+            Err(_) => warn!("Could not set report protocol (using device default)"),
+        }
+    }
+}
+
+/// Set up HID on a secured link and subscribe to all HID Report notifications,
+/// reusing `cached` discovery results when they are available and still valid.
 ///
-/// Returns the client (which owns the subscription handles) and the parsed
-/// Report Map descriptor for notification-time classification.
+/// Returns the client (which owns the subscription handles), the parsed Report
+/// Map of each HID service instance for notification-time classification, and
+/// — when a full
+/// discovery ran — the fresh [`GattCache`] for the caller to persist (`None`
+/// when the cached one was reused unchanged).
 pub async fn discover_and_subscribe(
     conn: &Connection,
-) -> Result<(HidServiceClient, Option<HidDescriptor>), BleErrorTag> {
-    info!("Discovering HID service...");
+    cached: Option<GattCache>,
+) -> Result<(HidServiceClient, HidDescriptors, Option<GattCache>), BleErrorTag> {
+    if let Some(cache) = cached {
+        let mut client = HidServiceClient::from_cache(&cache);
+        let descriptors = client.fix_descriptors(cache.descriptors);
+        let boot = cache.prefer_boot || client.forces_boot();
+        if boot && client.enter_boot_protocol(conn).await.is_ok() {
+            info!("HID set up from GATT cache in Boot Protocol");
+            client.subscribe_battery(conn).await;
+            return Ok((client, descriptors, None));
+        }
+        set_report_protocol(conn, &client).await;
+        // A stale handle shows up as a CCCD write the peer rejects.
+        let subscribed = client.subscribe_all(conn, &descriptors).await;
+        if subscribed.is_ok() && client.fully_subscribed() {
+            info!("HID set up from GATT cache (discovery skipped)");
+            client.subscribe_battery(conn).await;
+            return Ok((client, descriptors, None));
+        }
+        warn!("GATT cache handle mismatch, rediscovering");
+    }
 
-    let mut client: HidServiceClient = gatt_client::discover(conn)
-        .await
-        .map_err(|_| BleErrorTag::HidNotFound)?;
+    info!("Discovering HID services...");
+
+    let mut client = discover_hid_services(conn).await?;
 
     info!(
-        "HID service discovered ({} report characteristics)",
+        "{} HID service(s) discovered ({} report characteristics)",
+        client.report_map_handles.len(),
         client.reports.len()
     );
 
-    // Force Report Protocol mode (1). Boot Protocol would route input to the
-    // Boot Keyboard/Mouse Input Report characteristics, which we don't track.
-    // Report Protocol is also the GATT default, so this is mostly defensive.
-    if let Some(handle) = client.protocol_mode_handle {
-        match gatt_client::write(conn, handle, &[1u8]).await {
-            Ok(_) => info!("Set HID protocol to Report mode"),
-            Err(_) => warn!("Could not set report protocol (using device default)"),
+    client.service_changed_handle = subscribe_service_changed(conn).await;
+    client.set_pnp_id(read_pnp_id(conn).await);
+    set_report_protocol(conn, &client).await;
+
+    let descriptors = client.fix_descriptors(read_report_maps(conn, &client).await);
+
+    client.read_report_references(conn).await;
+    let booted = client.forces_boot() && client.enter_boot_protocol(conn).await.is_ok();
+    if !booted {
+        if let Err(tag) = client.subscribe_all(conn, &descriptors).await {
+            // No usable Report characteristic: try the boot reports instead.
+            client.enter_boot_protocol(conn).await.map_err(|_| tag)?;
         }
     }
 
-    let descriptor = read_report_map(conn, &client).await;
+    client.discover_battery(conn).await;
+    client.subscribe_battery(conn).await;
+
+    let cache = client.to_cache(&descriptors);
+    Ok((client, descriptors, Some(cache)))
+}
+
+/// What the notification loop signals back to its slot task while it runs.
+/// The GATT callback can't await, so the slot task picks these up and does the
+/// channel sends and link updates.
+pub struct LinkSignals {
+    /// A Battery Level notification arrived.
+    pub battery: Signal<CriticalSectionRawMutex, u8>,
+    /// An input report arrived.
+    pub activity: Signal<CriticalSectionRawMutex, ()>,
+    /// The PC went to sleep or woke up (the peer has been told already).
+    pub power: Signal<CriticalSectionRawMutex, PeripheralPower>,
+    /// A profile hotkey was pressed (the profile index); the chord was not
+    /// forwarded.
+    pub hotkey: Signal<CriticalSectionRawMutex, usize>,
+}
 
-    client.subscribe_all(conn, descriptor.as_ref()).await?;
+impl Default for LinkSignals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    Ok((client, descriptor))
+impl LinkSignals {
+    pub const fn new() -> Self {
+        Self {
+            battery: Signal::new(),
+            activity: Signal::new(),
+            power: Signal::new(),
+            hotkey: Signal::new(),
+        }
+    }
 }
 
 /// Run the notification listener loop.
 ///
-/// Blocks until the connection drops.  Each received HID report is classified
-/// and forwarded to `report_tx` for the USB task to consume.
+/// Blocks until the connection drops or the peer indicates Service Changed (see
+/// [`LoopEnd`]). Each received HID report is classified (against the Report
+/// Map in `descriptors` of the HID service it came from) and forwarded to
+/// `report_tx` for the USB task to consume. Host LED changes (`led_rx`) and PC
+/// sleep/wake (`power_rx`) are forwarded to the peer while it runs; battery,
+/// input activity, power changes and profile hotkeys are raised on `signals`.
 ///
 /// The GATT callback (`gatt_client::run`) is *synchronous*, so it cannot await
 /// channel backpressure. Instead of the old `try_send`-and-drop — which could
@@ -327,10 +1009,12 @@ pub async fn discover_and_subscribe(
 pub async fn run_notification_loop(
     conn: &Connection,
     client: &HidServiceClient,
-    descriptor: Option<HidDescriptor>,
+    descriptors: &[Option<HidDescriptor>],
     report_tx: &Sender<'_, CriticalSectionRawMutex, HidReport, 16>,
     led_rx: Option<&mut LedReceiver>,
-) {
+    power_rx: Option<&mut PeripheralPowerReceiver>,
+    signals: &LinkSignals,
+) -> LoopEnd {
     info!("HID notification loop started");
 
     // Single-producer (sync GATT callback) / single-consumer (async drain)
@@ -338,19 +1022,54 @@ pub async fn run_notification_loop(
     // synchronously — never across an `.await` — so it cannot double-borrow.
     let coalescer: RefCell<ReportCoalescer> = RefCell::new(ReportCoalescer::new());
     let wake: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+    let service_changed: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+    let boot_fallback: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+    // Only watch for a broken Report Protocol while Boot is still an option.
+    let mut monitor = client.can_fall_back_to_boot().then(FallbackMonitor::new);
 
     // Producer: classify each notification and enqueue it (never blocks). A
     // report whose characteristic resolved to a known kind is classified
     // directly (its payload carries no report-ID prefix); otherwise we fall back
     // to the descriptor-guided heuristic.
-    let gatt_fut = gatt_client::run(conn, client, |event: ReportNotification| {
+    let gatt_fut = gatt_client::run(conn, client, |event: HidEvent| {
+        let event = match event {
+            HidEvent::Report(event) => event,
+            HidEvent::ServiceChanged => {
+                service_changed.signal(());
+                return;
+            }
+            HidEvent::Battery(level) => {
+                signals.battery.signal(level);
+                return;
+            }
+        };
         let parsed = match event.kind {
             Some(kind) => hid::classify_known(kind, &event.data),
-            None => hid::classify_notification_with_hint(&event.data, descriptor.as_ref()),
+            None => {
+                let descriptor = descriptors
+                    .get(event.instance as usize)
+                    .and_then(Option::as_ref);
+                hid::classify_notification_with_hint(&event.data, descriptor)
+            }
         };
-        if let Some(report) = parsed {
+        if let Some(m) = monitor.as_mut() {
+            if m.record(parsed.is_some()) {
+                boot_fallback.signal(());
+            }
+        }
+        if let Some(mut report) = parsed {
+            if let Some(q) = client.quirks {
+                q.apply(&mut report);
+            }
+            if let HidReport::Keyboard(keys) = &report {
+                if let Some(profile) = hid::hotkey::profile_hotkey(keys) {
+                    signals.hotkey.signal(profile);
+                    return;
+                }
+            }
             coalescer.borrow_mut().push(report);
             wake.signal(());
+            signals.activity.signal(());
         }
     });
 
@@ -369,20 +1088,53 @@ pub async fn run_notification_loop(
 
     // If this peer has a keyboard LED output report and we hold an LED receiver,
     // also forward host LED changes to it; otherwise just run producer+consumer.
-    match led_rx {
-        Some(rx) => {
-            let led_fut = async {
+    let led_fut = async {
+        match led_rx {
+            Some(rx) => loop {
+                let leds = rx.changed().await;
+                client.write_leds(conn, leds).await;
+            },
+            None => pending().await,
+        }
+    };
+
+    // Forward PC sleep/wake. A peripheral starts every connection awake, so
+    // one that (re)connects while the PC sleeps is suspended straight away.
+    let power_fut = async {
+        match power_rx {
+            Some(rx) => {
+                if rx.try_get() == Some(PeripheralPower::Suspended) {
+                    client
+                        .set_peripheral_power(conn, PeripheralPower::Suspended)
+                        .await;
+                    signals.power.signal(PeripheralPower::Suspended);
+                }
                 loop {
-                    let leds = rx.changed().await;
-                    client.write_leds(conn, leds).await;
+                    let power = rx.changed().await;
+                    client.set_peripheral_power(conn, power).await;
+                    signals.power.signal(power);
                 }
-            };
-            let _ = select3(gatt_fut, drain_fut, led_fut).await;
+            }
+            None => pending().await,
+        }
+    };
+
+    let io_fut = async {
+        let _ = select4(gatt_fut, drain_fut, led_fut, power_fut).await;
+    };
+
+    match select3(io_fut, service_changed.wait(), boot_fallback.wait()).await {
+        Either3::First(()) => {
+            info!("HID notification loop ended (connection closed)");
+            LoopEnd::Disconnected
         }
-        None => {
-            let _ = select(gatt_fut, drain_fut).await;
+        Either3::Second(()) => {
+            warn!("Peer GATT database changed");
+            LoopEnd::ServiceChanged
+        }
+        Either3::Third(()) => {
+            warn!("Report Protocol notifications unclassifiable, falling back to Boot");
+            LoopEnd::BootFallback
         }
     }
-
-    info!("HID notification loop ended (connection closed)");
 }
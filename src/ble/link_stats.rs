@@ -0,0 +1,200 @@
+//! Pure per-link quality statistics.
+//!
+//! Each slot keeps a rolling window of RSSI samples (taken once a second by
+//! the slot worker) and a count of links it lost without asking to. From those
+//! come the OLED's signal-strength bars and a one-shot "weak link" warning
+//! when the window's average sinks below [`WEAK_RSSI`], re-armed only once the
+//! link has clearly recovered.
+//!
+//! The SoftDevice wrapper reports that a link closed but not the HCI reason,
+//! so supervision timeouts are counted together with peer-initiated closes as
+//! link losses; S140 has no per-connection count of missed connection events
+//! to add.
+
+/// RSSI samples averaged per link.
+pub const RSSI_WINDOW: usize = 8;
+
+/// Average RSSI (dBm) below which a link counts as degraded. Around here HID
+/// links start losing packets to retransmission and a mouse visibly stutters.
+pub const WEAK_RSSI: i8 = -85;
+
+/// How far (dB) above [`WEAK_RSSI`] a degraded link must recover before it can
+/// warn again, so a link hovering at the threshold doesn't keep warning.
+pub const RECOVER_DB: i8 = 5;
+
+/// Signal-strength bars (0–4) for an average RSSI.
+pub fn signal_bars(rssi: i8) -> u8 {
+    match rssi {
+        -60.. => 4,
+        -70..=-61 => 3,
+        -80..=-71 => 2,
+        -90..=-81 => 1,
+        _ => 0,
+    }
+}
+
+/// What a new RSSI sample changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RssiUpdate {
+    /// The signal-strength bars moved.
+    pub bars_changed: bool,
+    /// The link just became degraded (warn once).
+    pub became_weak: bool,
+}
+
+/// Rolling statistics for one slot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkStats {
+    samples: [i8; RSSI_WINDOW],
+    len: usize,
+    next: usize,
+    weak: bool,
+    link_losses: u16,
+}
+
+impl Default for LinkStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkStats {
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; RSSI_WINDOW],
+            len: 0,
+            next: 0,
+            weak: false,
+            link_losses: 0,
+        }
+    }
+
+    /// Forget the RSSI window (a new link started); losses are kept.
+    pub fn restart(&mut self) {
+        *self = Self {
+            link_losses: self.link_losses,
+            ..Self::new()
+        };
+    }
+
+    /// Add an RSSI sample (dBm).
+    pub fn record_rssi(&mut self, rssi: i8) -> RssiUpdate {
+        let bars_before = self.bars();
+        self.samples[self.next] = rssi;
+        self.next = (self.next + 1) % RSSI_WINDOW;
+        self.len = (self.len + 1).min(RSSI_WINDOW);
+
+        let mut update = RssiUpdate {
+            bars_changed: self.bars() != bars_before,
+            became_weak: false,
+        };
+        // Judge only a full window, so one bad sample after connecting
+        // doesn't warn.
+        if let Some(average) = self.average().filter(|_| self.len == RSSI_WINDOW) {
+            if !self.weak && average < WEAK_RSSI {
+                self.weak = true;
+                update.became_weak = true;
+            } else if self.weak && average >= WEAK_RSSI + RECOVER_DB {
+                self.weak = false;
+            }
+        }
+        update
+    }
+
+    /// Mean of the window, or `None` before the first sample.
+    pub fn average(&self) -> Option<i8> {
+        let window = &self.samples[..self.len];
+        let sum: i32 = window.iter().map(|&s| s as i32).sum();
+        (self.len > 0).then(|| (sum / self.len as i32) as i8)
+    }
+
+    /// Weakest sample in the window.
+    pub fn minimum(&self) -> Option<i8> {
+        self.samples[..self.len].iter().copied().min()
+    }
+
+    /// Signal-strength bars for the window's average.
+    pub fn bars(&self) -> Option<u8> {
+        self.average().map(signal_bars)
+    }
+
+    /// Is the link currently degraded?
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The link closed without us asking (peer close or supervision timeout).
+    pub fn record_link_loss(&mut self) {
+        self.link_losses = self.link_losses.saturating_add(1);
+    }
+
+    pub fn link_losses(&self) -> u16 {
+        self.link_losses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(stats: &mut LinkStats, rssi: i8) -> RssiUpdate {
+        let mut last = RssiUpdate::default();
+        for _ in 0..RSSI_WINDOW {
+            let update = stats.record_rssi(rssi);
+            last.bars_changed |= update.bars_changed;
+            last.became_weak |= update.became_weak;
+        }
+        last
+    }
+
+    #[test]
+    fn bars_follow_rssi() {
+        assert_eq!(signal_bars(-40), 4);
+        assert_eq!(signal_bars(-60), 4);
+        assert_eq!(signal_bars(-65), 3);
+        assert_eq!(signal_bars(-75), 2);
+        assert_eq!(signal_bars(-88), 1);
+        assert_eq!(signal_bars(-95), 0);
+    }
+
+    #[test]
+    fn window_rolls_over() {
+        let mut stats = LinkStats::new();
+        assert_eq!(stats.average(), None);
+        assert!(stats.record_rssi(-50).bars_changed);
+        assert_eq!(stats.bars(), Some(4));
+        fill(&mut stats, -70);
+        // The -50 sample has rolled out.
+        assert_eq!(stats.average(), Some(-70));
+        assert_eq!(stats.minimum(), Some(-70));
+        assert_eq!(stats.bars(), Some(3));
+    }
+
+    #[test]
+    fn weak_link_warns_once_until_recovered() {
+        let mut stats = LinkStats::new();
+        // A bad first sample alone doesn't warn.
+        assert!(!stats.record_rssi(-95).became_weak);
+        assert!(fill(&mut stats, -90).became_weak);
+        assert!(stats.is_weak());
+        assert!(!fill(&mut stats, -90).became_weak);
+        // Back just above the threshold isn't recovered yet...
+        fill(&mut stats, WEAK_RSSI + 1);
+        assert!(stats.is_weak());
+        // ...clearly above it is, and the next dip warns again.
+        fill(&mut stats, -60);
+        assert!(!stats.is_weak());
+        assert!(fill(&mut stats, -92).became_weak);
+    }
+
+    #[test]
+    fn restart_keeps_link_losses() {
+        let mut stats = LinkStats::new();
+        fill(&mut stats, -90);
+        stats.record_link_loss();
+        stats.restart();
+        assert_eq!(stats.average(), None);
+        assert!(!stats.is_weak());
+        assert_eq!(stats.link_losses(), 1);
+    }
+}
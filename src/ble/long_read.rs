@@ -0,0 +1,186 @@
+//! Pure sequencing for ATT long reads (Read + Read Blob).
+//!
+//! A single ATT Read returns at most `ATT_MTU - 1` bytes. Longer attribute
+//! values — HID Report Maps routinely run to a few hundred bytes — have to be
+//! fetched with follow-up **Read Blob** requests at increasing offsets until
+//! the peer returns a short (or empty) chunk, or rejects the next offset with
+//! an ATT error (`Attribute Not Long` / `Invalid Offset`).
+//!
+//! This module only decides *what to do next* with each chunk: where the next
+//! offset is, when the value is complete, and when our buffer was too small.
+//! The GATT round trips live in [`crate::ble::hid_client`]. Being free of
+//! SoftDevice types it is unit-tested on the host, like
+//! [`crate::ble::reconnect`].
+
+/// Largest Report Map the HID-over-GATT profile allows (HOGP 1.0, §2.6.1).
+pub const MAX_REPORT_MAP_LEN: usize = 512;
+
+/// What the caller should do after feeding a chunk to [`LongRead`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LongReadStep {
+    /// Issue a Read Blob at this offset.
+    Continue { offset: u16 },
+    /// The whole value was read; it is the first `len` bytes of the buffer.
+    Complete(usize),
+    /// The value did not fit (or the read failed part-way); only the first
+    /// `len` bytes are valid and the value is known to be incomplete.
+    Truncated(usize),
+}
+
+/// Accumulates the chunks of one long read into a caller-provided buffer.
+pub struct LongRead<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    /// Size of the first chunk, i.e. the largest chunk the link delivers. A
+    /// later chunk shorter than this is the tail of the value.
+    chunk_max: Option<usize>,
+}
+
+impl<'a> LongRead<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            chunk_max: None,
+        }
+    }
+
+    /// Offset of the next chunk (0 before the first read).
+    pub fn offset(&self) -> usize {
+        self.len
+    }
+
+    /// Bytes accumulated so far.
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Feed one Read / Read Blob response.
+    pub fn accept(&mut self, chunk: &[u8]) -> LongReadStep {
+        if chunk.is_empty() {
+            return LongReadStep::Complete(self.len);
+        }
+
+        let room = self.buf.len() - self.len;
+        if chunk.len() > room {
+            self.buf[self.len..].copy_from_slice(&chunk[..room]);
+            self.len += room;
+            return LongReadStep::Truncated(self.len);
+        }
+        self.buf[self.len..self.len + chunk.len()].copy_from_slice(chunk);
+        self.len += chunk.len();
+
+        match self.chunk_max {
+            // The first response doesn't tell us whether the value is longer
+            // (we don't know the negotiated MTU here), so always ask for more;
+            // a short value ends with an `Attribute Not Long` error instead.
+            None => self.chunk_max = Some(chunk.len()),
+            Some(max) if chunk.len() < max => return LongReadStep::Complete(self.len),
+            Some(_) => {}
+        }
+
+        match u16::try_from(self.len) {
+            Ok(offset) => LongReadStep::Continue { offset },
+            Err(_) => LongReadStep::Truncated(self.len),
+        }
+    }
+
+    /// The peer rejected the next offset with an ATT error. After at least
+    /// one chunk that means we are past the end of the value.
+    pub fn end_of_value(&self) -> LongReadStep {
+        if self.chunk_max.is_some() {
+            LongReadStep::Complete(self.len)
+        } else {
+            LongReadStep::Truncated(0)
+        }
+    }
+
+    /// The read failed for a non-ATT reason (e.g. disconnect); keep what we
+    /// have but flag it as incomplete.
+    pub fn failed(&self) -> LongReadStep {
+        LongReadStep::Truncated(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_value_completes_on_attribute_not_long() {
+        let mut buf = [0u8; 64];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(rd.accept(&[1, 2, 3]), LongReadStep::Continue { offset: 3 });
+        assert_eq!(rd.end_of_value(), LongReadStep::Complete(3));
+        assert_eq!(rd.data(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn short_tail_chunk_completes() {
+        let mut buf = [0u8; 64];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(
+            rd.accept(&[0xAA; 22]),
+            LongReadStep::Continue { offset: 22 }
+        );
+        assert_eq!(
+            rd.accept(&[0xBB; 22]),
+            LongReadStep::Continue { offset: 44 }
+        );
+        assert_eq!(rd.accept(&[0xCC; 5]), LongReadStep::Complete(49));
+        assert_eq!(rd.data()[21], 0xAA);
+        assert_eq!(rd.data()[22], 0xBB);
+        assert_eq!(rd.data()[48], 0xCC);
+    }
+
+    #[test]
+    fn empty_chunk_at_exact_multiple_completes() {
+        let mut buf = [0u8; 64];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(rd.accept(&[1; 10]), LongReadStep::Continue { offset: 10 });
+        assert_eq!(rd.accept(&[2; 10]), LongReadStep::Continue { offset: 20 });
+        assert_eq!(rd.accept(&[]), LongReadStep::Complete(20));
+    }
+
+    #[test]
+    fn exactly_full_buffer_probes_once_more() {
+        let mut buf = [0u8; 20];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(rd.accept(&[1; 10]), LongReadStep::Continue { offset: 10 });
+        assert_eq!(rd.accept(&[2; 10]), LongReadStep::Continue { offset: 20 });
+        assert_eq!(rd.end_of_value(), LongReadStep::Complete(20));
+    }
+
+    #[test]
+    fn overflowing_buffer_is_truncated() {
+        let mut buf = [0u8; 15];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(rd.accept(&[1; 10]), LongReadStep::Continue { offset: 10 });
+        assert_eq!(rd.accept(&[2; 10]), LongReadStep::Truncated(15));
+        assert_eq!(rd.data(), &[1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn more_data_after_full_buffer_is_truncated() {
+        let mut buf = [0u8; 10];
+        let mut rd = LongRead::new(&mut buf);
+        assert_eq!(rd.accept(&[1; 10]), LongReadStep::Continue { offset: 10 });
+        assert_eq!(rd.accept(&[2; 10]), LongReadStep::Truncated(10));
+    }
+
+    #[test]
+    fn failure_mid_read_is_truncated() {
+        let mut buf = [0u8; 64];
+        let mut rd = LongRead::new(&mut buf);
+        rd.accept(&[1; 22]);
+        assert_eq!(rd.failed(), LongReadStep::Truncated(22));
+    }
+
+    #[test]
+    fn att_error_on_first_read_yields_nothing() {
+        let mut buf = [0u8; 64];
+        let rd = LongRead::new(&mut buf);
+        assert_eq!(rd.end_of_value(), LongReadStep::Truncated(0));
+    }
+}
@@ -12,13 +12,18 @@
 //! Communication with other tasks is done via Embassy channels defined
 //! in the crate root.
 
-// The pure coordination core and the advertisement parser are SoftDevice-free,
+// The pure coordination core, policies and the advertisement parser are SoftDevice-free,
 // so they compile for every target (host tests, the embedded firmware, and the
 // Renode `sim` build). The live BLE tasks below need the Nordic SoftDevice and
 // are only compiled into the real firmware (`embedded` feature).
 pub mod adv_parser;
+pub mod conn_params;
 pub mod coordinator;
+pub mod link_stats;
+pub mod long_read;
 pub mod reconnect;
+pub mod scan_list;
+pub mod security;
 
 #[cfg(feature = "embedded")]
 pub mod hid_client;
@@ -44,12 +49,30 @@ mod softdevice_types {
     /// Commands that the UI task can send to the BLE task.
     #[derive(Clone, Format)]
     pub enum BleCommand {
-        /// Start scanning for peripherals.
+        /// Start scanning for peripherals (restarts a scan in progress).
         StartScan,
+        /// Stop a scan in progress.
+        StopScan,
         /// Connect to the peripheral at the given index in the discovered list.
+        /// Stops a scan in progress. With every slot busy, the new device
+        /// replaces the link of the same role (or the least recently used).
         Connect(usize),
-        /// Disconnect the currently connected peripheral.
+        /// Disconnect every connected peripheral.
         Disconnect,
+        /// Disconnect the peripheral in the given slot only.
+        DisconnectSlot(usize),
+        /// Switch to the given device profile: disconnect the current
+        /// profile's peripherals and reconnect the new one's.
+        SwitchProfile(usize),
+        /// Write the settings (already changed through `SETTINGS_STORE`) to
+        /// flash.
+        SaveSettings,
+        /// Forget the paired device with the given address, with its bond.
+        ForgetDevice(Address),
+        /// The user allowed the USB host's export or import: run it.
+        AllowBackup(crate::hid::backup::Job),
+        /// The user turned the USB host's export or import down.
+        DeclineBackup(crate::hid::backup::Job),
     }
 
     /// Events the BLE task publishes for the UI / main loop.
@@ -57,16 +80,42 @@ mod softdevice_types {
     pub enum BleEvent {
         /// Scan started.
         ScanStarted,
-        /// A new peripheral was found during scanning.
+        /// A new peripheral was found during scanning; it is appended to the
+        /// list (indices are stable for the rest of the scan).
         DeviceFound(DiscoveredDevice),
-        /// Scan completed (no more results forthcoming).
-        ScanComplete,
+        /// A listed peripheral's name, icon or RSSI changed.
+        DeviceUpdated {
+            index: usize,
+            device: DiscoveredDevice,
+        },
+        /// Scan completed (no more results forthcoming). `cancelled` when it
+        /// was stopped early by a command rather than its window closing.
+        ScanComplete { cancelled: bool },
         /// Successfully connected & HID service ready.
         Connected(String<32>),
         /// Connection lost or intentionally closed.
         Disconnected,
         /// An error occurred (human-readable tag).
         Error(super::BleErrorTag),
+        /// Battery level of every slot (indexed by slot, `None` = unknown).
+        Battery([Option<u8>; coordinator::MAX_CONNECTIONS]),
+        /// The named device's battery just dropped into the low range.
+        LowBattery(String<32>),
+        /// Role and name of every occupied slot (indexed by slot).
+        Slots([Option<coordinator::SlotSummary>; coordinator::MAX_CONNECTIONS]),
+        /// Signal-strength bars (0–4) of every slot (indexed by slot).
+        Signal([Option<u8>; coordinator::MAX_CONNECTIONS]),
+        /// The named device's link just degraded.
+        WeakLink(String<32>),
+        /// Saved data was damaged in flash: an older copy was loaded instead,
+        /// or (`lost`) none was readable and defaults are in use.
+        StorageDamaged { lost: bool },
+        /// The given device profile is now active (its peripherals are being
+        /// reconnected).
+        ProfileSwitched(usize),
+        /// The USB host asked to export or import the paired devices; the
+        /// user is to allow it ([`BleCommand::AllowBackup`]) or not.
+        BackupRequested(crate::hid::backup::Job),
     }
 }
 
@@ -1,28 +1,44 @@
 //! Multi-device BLE connection manager.
 //!
-//! Supports up to two concurrent BLE HID peripheral links (typical:
-//! keyboard + mouse) with secure pairing and bonding.
+//! Supports up to [`MAX_CONNECTIONS`] concurrent BLE HID peripheral links
+//! (typical: keyboard + mouse) with secure pairing and bonding.
 
 use core::cell::RefCell;
+use core::future::Future;
+use core::pin::{pin, Pin};
 
-use crate::ble::coordinator::{self, Action, ConnManager, UiEvent, MAX_CONNECTIONS};
-use crate::ble::scanner::ScanResult;
+use crate::ble::conn_params::{self, ConnParams, LinkPolicy};
+use crate::ble::coordinator::{self, Action, ConnManager, DeviceRole, UiEvent, MAX_CONNECTIONS};
+use crate::ble::hid_client::{LinkSignals, LoopEnd};
+use crate::ble::reconnect::ReconnectTarget;
+use crate::ble::scan_list::{scan_timing, ScanTiming};
+use crate::ble::scanner::DeviceList;
 use crate::ble::{
     hid_client, reconnect, scanner, BleCommand, BleErrorTag, BleEvent, DiscoveredDevice,
 };
 use crate::config;
 use crate::config::MAX_PAIRED_DEVICES;
+use crate::hid::backup::{Job, Outcome};
 use crate::hid::HidReport;
-use crate::storage::{BondInfo, PairedDevice, DEVICE_STORE};
-use defmt::{info, warn};
-use embassy_futures::select::{select, Either};
+use crate::power_logic::PeripheralPower;
+use crate::storage::backup::{ImportError, MAX_BACKUP_SIZE};
+use crate::storage::bond_crypto::NONCE_SIZE;
+use crate::storage::gatt_cache::GattCache;
+use crate::storage::integrity::Health;
+use crate::storage::settings::Settings;
+use crate::storage::{self, BondInfo, PairedDevice, DEVICE_STORE, SETTINGS_STORE};
+use crate::usb::hid_device::{BACKUP_JOB, BACKUP_SESSION, HOST_CHANGED};
+use defmt::{error, info, warn};
+use embassy_futures::join::join5;
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::channel::{Receiver, Sender};
-use embassy_time::{Duration, Timer};
+use embassy_sync::channel::{Channel, Receiver, Sender};
+use embassy_time::{Delay, Duration, Timer};
 use heapless::Vec;
 use nrf_softdevice::ble::security::{IoCapabilities, SecurityHandler};
 use nrf_softdevice::ble::{
-    central, Address, Connection, EncryptError, EncryptionInfo, IdentityKey, MasterId, SecurityMode,
+    central, gatt_client, Address, Connection, EncryptError, EncryptionInfo, IdentityKey, MasterId,
+    SecurityMode,
 };
 use nrf_softdevice::raw;
 use nrf_softdevice::Softdevice;
@@ -31,7 +47,15 @@ use static_cell::StaticCell;
 /// The connection-slot state machine, specialised to the SoftDevice address
 /// type. The logic lives in (and is host-tested via)
 /// [`crate::ble::coordinator`]; here it is just instantiated.
-type MultiConnectionManager = ConnManager<Address>;
+type MultiConnectionManager = ConnManager<Address, MAX_CONNECTIONS>;
+
+/// Commands queued per slot worker.
+pub const SLOT_CMD_DEPTH: usize = 2;
+
+/// One command channel per connection slot, indexed by slot. Each slot's
+/// worker task (`connection_slot_task`) owns the receiving end of its entry.
+pub type SlotCommandChannels =
+    [Channel<CriticalSectionRawMutex, SlotCommand, SLOT_CMD_DEPTH>; MAX_CONNECTIONS];
 
 #[derive(Clone)]
 pub enum SlotCommand {
@@ -44,18 +68,50 @@ pub enum SlotEvent {
     Connected {
         slot: usize,
         device: DiscoveredDevice,
+        role: DeviceRole,
+    },
+    /// The peer sent input (at most once per `BLE_ACTIVITY_REPORT_SECS`).
+    Activity {
+        slot: usize,
+    },
+    /// A link RSSI sample (dBm), every `BLE_RSSI_SAMPLE_SECS`.
+    Rssi {
+        slot: usize,
+        rssi: i8,
     },
+    /// The link is gone; `lost` when it closed without us asking (peer close
+    /// or supervision timeout) rather than being replaced.
     Disconnected {
         slot: usize,
+        lost: bool,
     },
     Error {
         slot: usize,
         tag: BleErrorTag,
     },
+    /// The peer's Battery Level changed (`None` once the link is gone).
+    Battery {
+        slot: usize,
+        level: Option<u8>,
+    },
+    /// A slot (re)ran GATT discovery for the device at `address`; persist the
+    /// new cache, or drop the stale one when `cache` is `None`.
+    GattCacheChanged {
+        address: Address,
+        cache: Option<GattCache>,
+    },
+    /// The peer (a keyboard) sent a profile hotkey.
+    SwitchProfile {
+        profile: usize,
+    },
 }
 
+/// The bonds the SoftDevice asks for keys from: the device store's, plus
+/// room for one new bond until its device is persisted. Reloaded from the
+/// store whenever it changes, so a device the store evicts (the least
+/// recently used) loses its keys here too.
 struct Bonder {
-    peers: RefCell<Vec<BondInfo, MAX_PAIRED_DEVICES>>,
+    peers: RefCell<Vec<BondInfo, { MAX_PAIRED_DEVICES + 1 }>>,
 }
 
 impl Bonder {
@@ -89,7 +145,7 @@ impl SecurityHandler for Bonder {
     }
 
     fn can_bond(&self, _conn: &Connection) -> bool {
-        true
+        storage::settings().security_policy.bonds()
     }
 
     fn on_bonded(
@@ -107,7 +163,9 @@ impl SecurityHandler for Bonder {
         }
 
         if peers.is_full() {
-            peers.remove(0);
+            // The store holds at most `MAX_PAIRED_DEVICES` bonds, so the last
+            // entry is a bond whose device was never persisted.
+            peers.pop();
         }
 
         let _ = peers.push(BondInfo {
@@ -169,128 +227,540 @@ fn bonder() -> &'static Bonder {
     }
 }
 
+/// Scan-list "bonded" marker: does `address` belong to a stored bond?
+fn is_bonded(address: Address) -> bool {
+    bonder().bond_for_address(address).is_some()
+}
+
 pub async fn ble_task(
     sd: &'static Softdevice,
     cmd_rx: &Receiver<'static, CriticalSectionRawMutex, BleCommand, 4>,
     event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
-    slot0_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
-    slot1_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
+    slot_cmds: &'static SlotCommandChannels,
     slot_event_rx: &Receiver<'static, CriticalSectionRawMutex, SlotEvent, 8>,
 ) -> ! {
-    let mut flash = nrf_softdevice::Flash::take(sd);
-    {
+    let mut flash = storage::Flash::new(nrf_softdevice::Flash::take(sd), Delay);
+    let settings_health = {
+        let mut settings = SETTINGS_STORE.lock().await;
+        let health = settings.load_from_flash(&mut flash).await;
+        // Rewrite a damaged copy now rather than on the next change.
+        settings.save_to_flash(&mut flash).await;
+        health
+    };
+    let devices_health = {
+        let bond_key = storage::load_bond_key(&mut flash, sd).await;
         let mut store = DEVICE_STORE.lock().await;
-        store.load_from_flash(&mut flash).await;
+        store.set_bond_key(bond_key);
+        store.set_profile(storage::settings().active_profile);
+        let health = store.load_from_flash(&mut flash).await;
+        store.save_to_flash(&mut flash).await;
         bonder().load_bonds(&store.bonds());
+        health
+    };
+    if settings_health == Health::Lost || devices_health == Health::Lost {
+        event_tx.send(BleEvent::StorageDamaged { lost: true }).await;
+    } else if settings_health == Health::Recovered || devices_health == Health::Recovered {
+        event_tx
+            .send(BleEvent::StorageDamaged { lost: false })
+            .await;
     }
 
     let mut manager = MultiConnectionManager::new();
-    let mut last_scan: Option<ScanResult> = None;
+    // Devices of the current/last scan; `BleCommand::Connect` indexes into it.
+    let scan_list: RefCell<DeviceList> = RefCell::new(DeviceList::new());
 
     // Auto-reconnect the most-recently-used devices (up to the number of
     // connection slots) so a keyboard + mouse pair both come back after a
-    // reboot without manual re-selection.
-    let peers: Vec<(DiscoveredDevice, Option<BondInfo>), MAX_CONNECTIONS> = {
-        let store = DEVICE_STORE.lock().await;
-        let mut v = Vec::new();
-        for paired in store.iter_recent().take(MAX_CONNECTIONS) {
-            let device = DiscoveredDevice {
-                address: paired.address,
-                name: paired.name.clone(),
-                rssi: paired.last_rssi,
-            };
-            let _ = v.push((device, paired.bond));
-        }
-        v
-    };
+    // reboot without manual re-selection. They are connected when the scan
+    // looking for them ends.
+    let reconnecting = RefCell::new(recent_peers().await);
 
-    if !peers.is_empty() {
-        // Devices that use a rotating Resolvable Private Address advertise under
-        // a random address that differs from the one stored at pairing time, so
-        // a whitelist connect to the stored address would never match. Scan
-        // first and resolve each stored peer's IRK against the live
-        // advertisements so we reconnect to its *current* address.
-        let scan = scanner::scan(sd, event_tx).await.ok();
-        let scanned: &[DiscoveredDevice] =
-            scan.as_ref().map(|s| s.devices.as_slice()).unwrap_or(&[]);
-
-        let targets = reconnect::resolve_reconnect_targets(peers.len(), scanned.len(), |p, s| {
-            let (stored, bond) = &peers[p];
-            let advertised = scanned[s].address;
-            // Resolve a rotating RPA by IRK, or match a stable address directly.
-            bond.map(|b| b.peer_id.is_match(advertised))
-                .unwrap_or(false)
-                || stored.address == advertised
-        });
-
-        for (slot, target) in targets.iter().enumerate() {
-            let (stored, _) = &peers[target.peer];
-            let device = match target.scanned {
-                // Connect to the live (resolved) address, keeping the stored name.
-                Some(i) => DiscoveredDevice {
-                    address: scanned[i].address,
-                    name: stored.name.clone(),
-                    rssi: scanned[i].rssi,
-                },
-                // Not seen this scan — fall back to the stored address.
-                None => stored.clone(),
-            };
-            manager.reserve_slot(slot, &device);
-            send_slot_cmd(slot, SlotCommand::Connect(device), slot0_tx, slot1_tx).await;
-        }
-
-        last_scan = scan;
+    // A scan runs alongside command and slot-event handling, so links keep
+    // being serviced (and devices keep streaming to the UI) for the whole
+    // window. Dropping the future stops the SoftDevice scan.
+    let mut scanning = pin!(None);
+    if reconnecting.borrow().is_some() {
+        scanning.set(Some(start_scan(
+            sd,
+            &scan_list,
+            event_tx,
+            scan_timing(0),
+            &reconnecting,
+        )));
     }
 
     // The coordinator below is a thin interpreter: it asks the pure
     // `coordinator` reducers (host-tested) what to do for each command/event,
     // then performs the resulting I/O via `execute_action`.
     loop {
-        match select(cmd_rx.receive(), slot_event_rx.receive()).await {
-            Either::First(cmd) => match cmd {
+        let next = select4(
+            cmd_rx.receive(),
+            slot_event_rx.receive(),
+            scan_or_pending(scanning.as_mut()),
+            select(BACKUP_JOB.wait(), HOST_CHANGED.wait()),
+        )
+        .await;
+        match next {
+            Either4::First(cmd) => match cmd {
                 BleCommand::StartScan => {
-                    for action in coordinator::plan_start_scan(&manager) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
-                    }
-                    match scanner::scan(sd, event_tx).await {
-                        Ok(result) => last_scan = Some(result),
-                        Err(_) => last_scan = None,
-                    }
+                    // Existing links stay up; picking a device when every slot
+                    // is busy replaces just one of them (`plan_connect`).
+                    interrupt_scan(
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        &scan_list,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await;
+                    scanning.set(Some(start_scan(
+                        sd,
+                        &scan_list,
+                        event_tx,
+                        scan_timing(manager.active_count()),
+                        &reconnecting,
+                    )));
+                }
+                BleCommand::StopScan => {
+                    interrupt_scan(
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        &scan_list,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await;
                 }
                 BleCommand::Connect(index) => {
-                    let devices: &[DiscoveredDevice] = match &last_scan {
-                        Some(scan) => scan.devices.as_slice(),
-                        None => &[],
-                    };
-                    for action in coordinator::plan_connect(&mut manager, devices, index) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
+                    // The SoftDevice can't initiate a connection while scanning.
+                    interrupt_scan(
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        &scan_list,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await;
+                    let actions = coordinator::plan_connect(
+                        &mut manager,
+                        scan_list.borrow().devices(),
+                        index,
+                    );
+                    for action in actions {
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
                     }
                 }
                 BleCommand::Disconnect => {
                     for action in coordinator::plan_disconnect(&manager) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
+                    }
+                }
+                BleCommand::DisconnectSlot(slot) => {
+                    for action in coordinator::plan_disconnect_slot(&manager, slot) {
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
                     }
                 }
+                BleCommand::SwitchProfile(profile) => {
+                    if switch_profile(
+                        profile,
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await
+                    {
+                        scanning.set(Some(start_scan(
+                            sd,
+                            &scan_list,
+                            event_tx,
+                            scan_timing(0),
+                            &reconnecting,
+                        )));
+                    }
+                }
+                BleCommand::SaveSettings => {
+                    SETTINGS_STORE.lock().await.save_to_flash(&mut flash).await;
+                }
+                BleCommand::ForgetDevice(address) => {
+                    // A link to the device stays up until it drops; it just
+                    // won't come back.
+                    let mut store = DEVICE_STORE.lock().await;
+                    if store.forget(address) {
+                        store.save_to_flash(&mut flash).await;
+                        bonder().load_bonds(&store.bonds());
+                        info!("Forgot paired device {}", address);
+                    }
+                }
+                BleCommand::AllowBackup(job) => {
+                    // The host may have withdrawn it while the user looked.
+                    if BACKUP_SESSION.lock(|session| session.borrow_mut().confirm(job)) {
+                        run_backup_job(sd, job, &mut flash).await;
+                    }
+                }
+                BleCommand::DeclineBackup(job) => {
+                    BACKUP_SESSION.lock(|session| session.borrow_mut().decline(job));
+                    info!("Backup: {} declined", job);
+                }
             },
-            Either::Second(event) => match event {
-                SlotEvent::Connected { slot, device } => {
-                    for action in coordinator::on_slot_connected(&mut manager, slot, &device) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
+            Either4::Second(event) => match event {
+                SlotEvent::Connected { slot, device, role } => {
+                    for action in coordinator::on_slot_connected(&mut manager, slot, &device, role)
+                    {
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
                     }
                 }
-                SlotEvent::Disconnected { slot } => {
+                SlotEvent::Activity { slot } => manager.note_activity(slot),
+                SlotEvent::Rssi { slot, rssi } => {
+                    for action in coordinator::on_slot_rssi(&mut manager, slot, rssi) {
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
+                    }
+                }
+                SlotEvent::Disconnected { slot, lost } => {
+                    if lost {
+                        manager.record_link_loss(slot);
+                        if let Some(stats) = manager.link_stats(slot) {
+                            warn!(
+                                "slot {} link lost ({} so far, last min RSSI {:?})",
+                                slot,
+                                stats.link_losses(),
+                                stats.minimum()
+                            );
+                        }
+                    }
                     for action in coordinator::on_slot_disconnected(&mut manager, slot) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
                     }
                 }
                 SlotEvent::Error { slot, tag } => {
                     for action in coordinator::on_slot_error(&mut manager, slot, tag) {
-                        execute_action(action, event_tx, slot0_tx, slot1_tx, &mut flash).await;
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
+                    }
+                }
+                SlotEvent::Battery { slot, level } => {
+                    for action in coordinator::on_slot_battery(&mut manager, slot, level) {
+                        execute_action(action, event_tx, slot_cmds, &mut flash).await;
+                    }
+                }
+                SlotEvent::GattCacheChanged { address, cache } => {
+                    let mut store = DEVICE_STORE.lock().await;
+                    store.set_gatt_cache_for_address(address, cache);
+                    store.save_to_flash(&mut flash).await;
+                }
+                SlotEvent::SwitchProfile { profile } => {
+                    if switch_profile(
+                        profile,
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await
+                    {
+                        scanning.set(Some(start_scan(
+                            sd,
+                            &scan_list,
+                            event_tx,
+                            scan_timing(0),
+                            &reconnecting,
+                        )));
                     }
                 }
             },
+            // Scan window closed (or failed); the list stays for `Connect`.
+            Either4::Third(_) => {
+                scanning.set(None);
+                let pending = reconnecting.borrow_mut().take();
+                if let Some(pending) = pending {
+                    finish_reconnect(
+                        pending,
+                        &mut manager,
+                        &scan_list,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await;
+                }
+            }
+            Either4::Fourth(Either::First(job)) => {
+                event_tx.send(BleEvent::BackupRequested(job)).await;
+            }
+            Either4::Fourth(Either::Second(host)) => {
+                // Another PC took over the USB bus: bring back what was in
+                // use on it, switching profile (and devices) if that differs.
+                let profile = {
+                    let mut settings = SETTINGS_STORE.lock().await;
+                    settings.enter_host(host.fingerprint);
+                    settings.save_to_flash(&mut flash).await;
+                    storage::settings().active_profile
+                };
+                if DEVICE_STORE.lock().await.profile() != profile
+                    && switch_profile(
+                        profile as usize,
+                        scanning.as_mut(),
+                        &reconnecting,
+                        &mut manager,
+                        event_tx,
+                        slot_cmds,
+                        &mut flash,
+                    )
+                    .await
+                {
+                    scanning.set(Some(start_scan(
+                        sd,
+                        &scan_list,
+                        event_tx,
+                        scan_timing(0),
+                        &reconnecting,
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// The active profile's most recently used devices, one per slot, waiting on
+/// a scan to find them.
+///
+/// Devices that use a rotating Resolvable Private Address advertise under a
+/// random address that differs from the one stored at pairing time, so a
+/// whitelist connect to the stored address would never match. Scan first and
+/// resolve each stored peer's IRK against the live advertisements so we
+/// reconnect to its *current* address.
+struct PendingReconnect {
+    peers: Vec<(DiscoveredDevice, Option<BondInfo>), MAX_CONNECTIONS>,
+}
+
+impl PendingReconnect {
+    /// Where each peer is in `scanned`, if it is there.
+    fn targets(&self, scanned: &[DiscoveredDevice]) -> Vec<ReconnectTarget, MAX_CONNECTIONS> {
+        reconnect::resolve_reconnect_targets(self.peers.len(), scanned.len(), |p, s| {
+            let (stored, bond) = &self.peers[p];
+            let advertised = scanned[s].address;
+            // Resolve a rotating RPA by IRK, or match a stable address directly.
+            bond.map(|b| b.peer_id.is_match(advertised))
+                .unwrap_or(false)
+                || stored.address == advertised
+        })
+    }
+
+    /// Has the scan found every peer? Then it needn't run out its window.
+    fn all_seen(&self, scanned: &[DiscoveredDevice]) -> bool {
+        self.targets(scanned).iter().all(|t| t.scanned.is_some())
+    }
+}
+
+/// The active profile's devices to reconnect, or `None` when it has none.
+async fn recent_peers() -> Option<PendingReconnect> {
+    let store = DEVICE_STORE.lock().await;
+    let mut peers = Vec::new();
+    for paired in store.iter_recent().take(MAX_CONNECTIONS) {
+        let device = DiscoveredDevice {
+            address: paired.address,
+            name: paired.name.clone(),
+            rssi: paired.last_rssi,
+            icon: paired.icon,
+            bonded: true,
+        };
+        let _ = peers.push((device, paired.bond));
+    }
+    (!peers.is_empty()).then_some(PendingReconnect { peers })
+}
+
+/// Start a scan filling `list`. While a reconnect waits on it, it ends as
+/// soon as every peer has been seen.
+fn start_scan<'a>(
+    sd: &'a Softdevice,
+    list: &'a RefCell<DeviceList>,
+    event_tx: &'a Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
+    timing: ScanTiming,
+    reconnecting: &'a RefCell<Option<PendingReconnect>>,
+) -> impl Future<Output = Result<(), BleErrorTag>> + 'a {
+    scanner::scan(sd, list, event_tx, is_bonded, timing, move |list| {
+        reconnecting
+            .borrow()
+            .as_ref()
+            .is_some_and(|pending| pending.all_seen(list.devices()))
+    })
+}
+
+/// Connect a reconnect's peers once its scan is over: each at the address the
+/// scan found it under, else at the stored one.
+async fn finish_reconnect(
+    pending: PendingReconnect,
+    manager: &mut MultiConnectionManager,
+    scan_list: &RefCell<DeviceList>,
+    event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
+    slot_cmds: &'static SlotCommandChannels,
+    flash: &mut storage::Flash,
+) {
+    let scanned: Vec<DiscoveredDevice, { config::BLE_MAX_DISCOVERED }> =
+        scan_list.borrow().devices().iter().cloned().collect();
+    let targets = pending.targets(&scanned);
+    let stored: Vec<DiscoveredDevice, MAX_CONNECTIONS> = pending
+        .peers
+        .iter()
+        .map(|(device, _)| device.clone())
+        .collect();
+    for action in coordinator::plan_reconnect(manager, &stored, &scanned, &targets) {
+        execute_action(action, event_tx, slot_cmds, flash).await;
+    }
+}
+
+/// Cut the scan in progress short (see [`stop_scan`]). A reconnect waiting on
+/// it goes ahead with the peers seen so far rather than being lost.
+async fn interrupt_scan<F: Future>(
+    scan: Pin<&mut Option<F>>,
+    reconnecting: &RefCell<Option<PendingReconnect>>,
+    manager: &mut MultiConnectionManager,
+    scan_list: &RefCell<DeviceList>,
+    event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
+    slot_cmds: &'static SlotCommandChannels,
+    flash: &mut storage::Flash,
+) {
+    stop_scan(scan, event_tx).await;
+    let pending = reconnecting.borrow_mut().take();
+    if let Some(pending) = pending {
+        finish_reconnect(pending, manager, scan_list, event_tx, slot_cmds, flash).await;
+    }
+}
+
+/// Make `profile` the active device profile: stop the scan in progress, drop
+/// the current profile's links, and load the new one's devices and bonds
+/// (remembering the choice across reboots). Its devices are left in
+/// `reconnecting`; returns whether there are any, for the caller to start the
+/// scan that looks for them.
+async fn switch_profile<F: Future>(
+    profile: usize,
+    scan: Pin<&mut Option<F>>,
+    reconnecting: &RefCell<Option<PendingReconnect>>,
+    manager: &mut MultiConnectionManager,
+    event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
+    slot_cmds: &'static SlotCommandChannels,
+    flash: &mut storage::Flash,
+) -> bool {
+    if profile >= config::MAX_PROFILES {
+        warn!("No device profile {}", profile);
+        return false;
+    }
+    if DEVICE_STORE.lock().await.profile() as usize == profile {
+        event_tx.send(BleEvent::ProfileSwitched(profile)).await;
+        return false;
+    }
+
+    // The old profile's reconnect, if still looking, is moot.
+    stop_scan(scan, event_tx).await;
+    reconnecting.borrow_mut().take();
+    for action in coordinator::plan_disconnect(manager) {
+        execute_action(action, event_tx, slot_cmds, flash).await;
+    }
+    let health = {
+        let mut store = DEVICE_STORE.lock().await;
+        let health = store.switch_profile(flash, profile as u8).await;
+        store.save_to_flash(flash).await;
+        bonder().load_bonds(&store.bonds());
+        health
+    };
+    {
+        let mut settings = SETTINGS_STORE.lock().await;
+        settings.update(Settings {
+            active_profile: profile as u8,
+            ..storage::settings()
+        });
+        settings.save_to_flash(flash).await;
+    }
+    info!("Switched to profile {}", config::PROFILE_NAMES[profile]);
+    event_tx.send(BleEvent::ProfileSwitched(profile)).await;
+    if health != Health::Intact {
+        event_tx
+            .send(BleEvent::StorageDamaged {
+                lost: health == Health::Lost,
+            })
+            .await;
+    }
+
+    let pending = recent_peers().await;
+    let found = pending.is_some();
+    *reconnecting.borrow_mut() = pending;
+    found
+}
+
+/// Run a job of the USB backup interface against the device store: seal an
+/// export into the session, or merge an import, persist it and hand the
+/// imported bonds to the security handler. The container is sealed and
+/// opened in a copy, so the USB control handler isn't held off meanwhile.
+async fn run_backup_job(sd: &Softdevice, job: Job, flash: &mut storage::Flash) {
+    let mut buf = [0u8; MAX_BACKUP_SIZE];
+    let (key, len) = BACKUP_SESSION.lock(|session| session.borrow().job(&mut buf));
+    let key = storage::transfer_key(key);
+    let mut store = DEVICE_STORE.lock().await;
+    match job {
+        Job::Export => {
+            let mut nonce = [0u8; NONCE_SIZE];
+            let len = if nrf_softdevice::random_bytes(sd, &mut nonce).is_ok() {
+                store.export(&mut buf, &key, &nonce)
+            } else {
+                error!("Backup export: no randomness for the nonce");
+                0
+            };
+            let count = store.iter_recent().count();
+            BACKUP_SESSION.lock(|session| session.borrow_mut().exported(&buf[..len], count));
+            info!("Backup export ready");
+        }
+        Job::Import => {
+            let result = store.import(&mut buf[..len], &key);
+            BACKUP_SESSION.lock(|session| {
+                session.borrow_mut().imported(result.map_err(|e| match e {
+                    ImportError::Malformed => Outcome::Malformed,
+                    ImportError::UnsupportedVersion(_) => Outcome::UnsupportedVersion,
+                    ImportError::Unauthenticated => Outcome::Unauthenticated,
+                }))
+            });
+            match result {
+                Ok(count) => {
+                    store.save_to_flash(flash).await;
+                    bonder().load_bonds(&store.bonds());
+                    info!("Backup import merged {} devices", count);
+                }
+                Err(e) => warn!("Backup import refused: {:?}", e),
+            }
         }
     }
+    // The bonds were in the clear in here.
+    buf.fill(0);
+}
+
+/// Await the scan in progress, or never resolve when there is none.
+async fn scan_or_pending<F: Future>(scan: Pin<&mut Option<F>>) -> F::Output {
+    match scan.as_pin_mut() {
+        Some(fut) => fut.await,
+        None => core::future::pending().await,
+    }
+}
+
+/// Cancel the scan in progress, if any, telling the UI it ended so it stops
+/// waiting on it.
+async fn stop_scan<F: Future>(
+    mut scan: Pin<&mut Option<F>>,
+    event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
+) {
+    if scan.is_some() {
+        scan.set(None);
+        event_tx
+            .send(BleEvent::ScanComplete { cancelled: true })
+            .await;
+    }
 }
 
 /// Perform the I/O for one coordinator [`Action`]: drive slot workers, persist
@@ -299,18 +769,17 @@ pub async fn ble_task(
 async fn execute_action(
     action: Action<Address>,
     event_tx: &Sender<'static, CriticalSectionRawMutex, BleEvent, 8>,
-    slot0_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
-    slot1_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
-    flash: &mut nrf_softdevice::Flash,
+    slot_cmds: &'static SlotCommandChannels,
+    flash: &mut storage::Flash,
 ) {
     match action {
         Action::DisconnectSlot(slot) => {
-            send_slot_cmd(slot, SlotCommand::Disconnect, slot0_tx, slot1_tx).await;
+            send_slot_cmd(slot, SlotCommand::Disconnect, slot_cmds).await;
         }
         Action::ConnectSlot { slot, device } => {
-            send_slot_cmd(slot, SlotCommand::Connect(device), slot0_tx, slot1_tx).await;
+            send_slot_cmd(slot, SlotCommand::Connect(device), slot_cmds).await;
         }
-        Action::PersistDevice(device) => {
+        Action::PersistDevice { device, role } => {
             let mut store = DEVICE_STORE.lock().await;
             store.add(PairedDevice::new(
                 device.address,
@@ -320,16 +789,27 @@ async fn execute_action(
             if let Some(bond) = bonder().bond_for_address(device.address) {
                 store.set_bond_for_address(device.address, bond);
             }
+            store.record_connect(device.address, role, device.icon);
             store.save_to_flash(flash).await;
+            // Adding may have evicted a device, bond included.
+            bonder().load_bonds(&store.bonds());
         }
         Action::Emit(ui) => {
             let event = match ui {
                 UiEvent::Connected(summary) => BleEvent::Connected(summary),
                 UiEvent::Disconnected => BleEvent::Disconnected,
                 UiEvent::Error(tag) => BleEvent::Error(tag),
+                UiEvent::Battery(levels) => BleEvent::Battery(levels),
+                UiEvent::LowBattery(name) => BleEvent::LowBattery(name),
+                UiEvent::Slots(slots) => BleEvent::Slots(slots),
+                UiEvent::Signal(bars) => BleEvent::Signal(bars),
+                UiEvent::WeakLink(name) => BleEvent::WeakLink(name),
             };
             event_tx.send(event).await;
         }
+        Action::PublishBattery { slot, level } => {
+            crate::usb::hid_device::publish_battery(slot, level);
+        }
     }
 }
 
@@ -347,7 +827,7 @@ enum SlotOutcome {
 pub async fn connection_slot_task(
     slot: usize,
     sd: &'static Softdevice,
-    cmd_rx: &Receiver<'static, CriticalSectionRawMutex, SlotCommand, 2>,
+    cmd_rx: &Receiver<'static, CriticalSectionRawMutex, SlotCommand, SLOT_CMD_DEPTH>,
     slot_event_tx: &Sender<'static, CriticalSectionRawMutex, SlotEvent, 8>,
     report_tx: &Sender<'static, CriticalSectionRawMutex, HidReport, 16>,
 ) -> ! {
@@ -355,6 +835,8 @@ pub async fn connection_slot_task(
     // One host-LED receiver per slot (taken once; reused across reconnects). The
     // slot that holds the keyboard writes LED state through; others ignore it.
     let mut led_rx = crate::usb::hid_device::keyboard_led_receiver();
+    // Likewise one PC sleep/wake receiver per slot, for the HID Control Point.
+    let mut power_rx = crate::power::peripheral_power_receiver();
 
     loop {
         let cmd = match pending_cmd.take() {
@@ -364,7 +846,7 @@ pub async fn connection_slot_task(
 
         match cmd {
             SlotCommand::Connect(device) => {
-                match connect_and_run_secure(
+                let outcome = connect_and_run_secure(
                     sd,
                     &device,
                     report_tx,
@@ -372,17 +854,27 @@ pub async fn connection_slot_task(
                     slot,
                     cmd_rx,
                     led_rx.as_mut(),
+                    power_rx.as_mut(),
                 )
-                .await
-                {
+                .await;
+                // The link is gone either way; clear its battery level first so
+                // the coordinator still sees the slot occupied.
+                slot_event_tx
+                    .send(SlotEvent::Battery { slot, level: None })
+                    .await;
+                match outcome {
                     SlotOutcome::Closed => {
-                        slot_event_tx.send(SlotEvent::Disconnected { slot }).await;
+                        slot_event_tx
+                            .send(SlotEvent::Disconnected { slot, lost: true })
+                            .await;
                     }
                     SlotOutcome::Failed(tag) => {
                         slot_event_tx.send(SlotEvent::Error { slot, tag }).await;
                     }
                     SlotOutcome::Superseded(next_cmd) => {
-                        slot_event_tx.send(SlotEvent::Disconnected { slot }).await;
+                        slot_event_tx
+                            .send(SlotEvent::Disconnected { slot, lost: false })
+                            .await;
                         // Re-process the superseding command (Disconnect is a no-op
                         // here since the link is already torn down).
                         if let SlotCommand::Connect(_) = next_cmd {
@@ -396,16 +888,50 @@ pub async fn connection_slot_task(
     }
 }
 
-async fn send_slot_cmd(
-    slot: usize,
-    cmd: SlotCommand,
-    slot0_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
-    slot1_tx: &Sender<'static, CriticalSectionRawMutex, SlotCommand, 2>,
-) {
-    match slot {
-        0 => slot0_tx.send(cmd).await,
-        1 => slot1_tx.send(cmd).await,
-        _ => {}
+async fn send_slot_cmd(slot: usize, cmd: SlotCommand, slot_cmds: &'static SlotCommandChannels) {
+    if let Some(channel) = slot_cmds.get(slot) {
+        channel.send(cmd).await;
+    }
+}
+
+/// [`ConnParams`] in the SoftDevice's struct (same units).
+fn to_raw(params: ConnParams) -> raw::ble_gap_conn_params_t {
+    raw::ble_gap_conn_params_t {
+        min_conn_interval: params.min_interval,
+        max_conn_interval: params.max_interval,
+        slave_latency: params.slave_latency,
+        conn_sup_timeout: params.supervision_timeout,
+    }
+}
+
+fn from_raw(params: raw::ble_gap_conn_params_t) -> ConnParams {
+    ConnParams {
+        min_interval: params.min_conn_interval,
+        max_interval: params.max_conn_interval,
+        slave_latency: params.slave_latency,
+        supervision_timeout: params.conn_sup_timeout,
+    }
+}
+
+/// Ask for the ATT MTU and LL data length we're configured for, so reports up
+/// to `BLE_ATT_MTU - 3` bytes arrive whole and in a single LL packet. Both are
+/// best-effort: a peer that refuses keeps working at the smaller defaults.
+async fn negotiate_link_size(conn: &mut Connection, slot: usize) {
+    if gatt_client::att_mtu_exchange(conn, config::BLE_ATT_MTU)
+        .await
+        .is_err()
+    {
+        warn!("slot {} ATT MTU exchange failed", slot);
+    }
+
+    let params = raw::ble_gap_data_length_params_t {
+        max_tx_octets: config::BLE_DATA_LENGTH,
+        max_rx_octets: config::BLE_DATA_LENGTH,
+        max_tx_time_us: raw::BLE_GAP_DATA_LENGTH_AUTO as u16,
+        max_rx_time_us: raw::BLE_GAP_DATA_LENGTH_AUTO as u16,
+    };
+    if conn.data_length_update(Some(&params)).is_err() {
+        warn!("slot {} data length update failed", slot);
     }
 }
 
@@ -427,23 +953,23 @@ async fn connect_and_run_secure(
     report_tx: &Sender<'_, CriticalSectionRawMutex, HidReport, 16>,
     slot_event_tx: &Sender<'_, CriticalSectionRawMutex, SlotEvent, 8>,
     slot: usize,
-    cmd_rx: &Receiver<'_, CriticalSectionRawMutex, SlotCommand, 2>,
-    led_rx: Option<&mut crate::usb::hid_device::LedReceiver>,
+    cmd_rx: &Receiver<'_, CriticalSectionRawMutex, SlotCommand, SLOT_CMD_DEPTH>,
+    mut led_rx: Option<&mut crate::usb::hid_device::LedReceiver>,
+    mut power_rx: Option<&mut crate::power::PeripheralPowerReceiver>,
 ) -> SlotOutcome {
     info!("slot {} connecting to {}", slot, device.name.as_str());
 
+    // Until the Report Map is read, the advertised appearance is the best
+    // guess at what the link needs.
+    let hinted_role = DeviceRole::from_icon(device.icon);
+    let initial_params = conn_params::target(hinted_role, PeripheralPower::Awake, None);
     let whitelist = [&device.address];
     let conn_cfg = central::ConnectConfig {
         scan_config: central::ScanConfig {
             whitelist: Some(&whitelist),
             ..Default::default()
         },
-        conn_params: raw::ble_gap_conn_params_t {
-            min_conn_interval: config::BLE_CONN_INTERVAL_MIN,
-            max_conn_interval: config::BLE_CONN_INTERVAL_MAX,
-            slave_latency: config::BLE_SLAVE_LATENCY,
-            conn_sup_timeout: config::BLE_SUP_TIMEOUT,
-        },
+        conn_params: to_raw(initial_params),
         ..Default::default()
     };
 
@@ -451,7 +977,7 @@ async fn connect_and_run_secure(
     // commands: until `connect_with_security` returns there is no live
     // `Connection` to disconnect, so cancelling the future here is leak-free,
     // and once a link exists we must own it so we can explicitly disconnect it.
-    let conn = match central::connect_with_security(sd, &conn_cfg, bonder()).await {
+    let mut conn = match central::connect_with_security(sd, &conn_cfg, bonder()).await {
         Ok(conn) => conn,
         Err(_) => return SlotOutcome::Failed(BleErrorTag::ConnectFailed),
     };
@@ -459,7 +985,8 @@ async fn connect_and_run_secure(
     let secure_ok = match conn.encrypt() {
         Ok(()) => wait_for_secure_link(&conn).await,
         Err(EncryptError::PeerKeysNotFound) => {
-            if conn.request_pairing().is_ok() {
+            // Locked to the bonded devices: a new one may not pair.
+            if storage::settings().security_policy.pairs_new() && conn.request_pairing().is_ok() {
                 wait_for_secure_link(&conn).await
             } else {
                 false
@@ -474,31 +1001,190 @@ async fn connect_and_run_secure(
         return SlotOutcome::Failed(BleErrorTag::ConnectFailed);
     }
 
-    let (client, descriptor) = match hid_client::discover_and_subscribe(&conn).await {
-        Ok(v) => v,
-        Err(tag) => {
-            let _ = conn.disconnect();
-            return SlotOutcome::Failed(tag);
-        }
-    };
+    negotiate_link_size(&mut conn, slot).await;
 
+    // Bonded peers keep their attribute handles, so reuse the previous
+    // connection's discovery results when we have them.
+    let cached = DEVICE_STORE
+        .lock()
+        .await
+        .gatt_cache_for_address(conn.peer_address());
+
+    let (mut client, mut descriptors, fresh_cache) =
+        match hid_client::discover_and_subscribe(&conn, cached).await {
+            Ok(v) => v,
+            Err(tag) => {
+                let _ = conn.disconnect();
+                return SlotOutcome::Failed(tag);
+            }
+        };
+
+    let role = DeviceRole::from_descriptors(&descriptors);
     slot_event_tx
         .send(SlotEvent::Connected {
             slot,
             device: device.clone(),
+            role,
         })
         .await;
 
+    // Sent after `Connected` so the coordinator has persisted the device (and
+    // its bond) before the cache is attached to it.
+    if fresh_cache.is_some() {
+        slot_event_tx
+            .send(SlotEvent::GattCacheChanged {
+                address: device.address,
+                cache: fresh_cache,
+            })
+            .await;
+    }
+
+    if let Some(level) = client.read_battery_level(&conn).await {
+        slot_event_tx
+            .send(SlotEvent::Battery {
+                slot,
+                level: Some(level),
+            })
+            .await;
+    }
+
+    // Battery Level notifications, input activity, PC sleep/wake and profile
+    // hotkeys are signalled from the notification loop and handled here, where
+    // awaiting is allowed.
+    let signals = LinkSignals::new();
+    let forward_battery = async {
+        loop {
+            let level = signals.battery.wait().await;
+            slot_event_tx
+                .send(SlotEvent::Battery {
+                    slot,
+                    level: Some(level),
+                })
+                .await;
+        }
+    };
+    // Throttled: the coordinator only needs a rough "last used" ordering.
+    let forward_activity = async {
+        loop {
+            signals.activity.wait().await;
+            slot_event_tx.send(SlotEvent::Activity { slot }).await;
+            Timer::after(Duration::from_secs(config::BLE_ACTIVITY_REPORT_SECS)).await;
+        }
+    };
+    // Keep the connection parameters on the policy for the link's role: once
+    // the Report Map has told us the role, on PC sleep/wake, and after the
+    // peer changes them itself (the SoftDevice grants a peer's request as is).
+    let adapt_link = async {
+        let mut link = LinkPolicy::new(hinted_role, initial_params);
+        let mut update = link.set_role(role);
+        loop {
+            if let Some(params) = update {
+                // A refused request isn't the link's target; the next poll
+                // asks again.
+                match conn.set_conn_params(to_raw(params)) {
+                    Ok(()) => link.confirm(params),
+                    Err(_) => warn!("slot {} connection parameter update failed", slot),
+                }
+            }
+            let poll = Timer::after(Duration::from_secs(config::BLE_CONN_PARAMS_POLL_SECS));
+            update = match select(signals.power.wait(), poll).await {
+                Either::First(power) if storage::settings().relax_conn_on_suspend => {
+                    link.set_power(power)
+                }
+                Either::First(_) => None,
+                Either::Second(()) => link
+                    .observe(from_raw(conn.conn_params()))
+                    .or_else(|| link.pending()),
+            };
+        }
+    };
+    // The SoftDevice averages RSSI over the connection events since the last
+    // read; sample it at a steady pace for the link statistics.
+    let sample_rssi = async {
+        conn.start_rssi();
+        loop {
+            Timer::after(Duration::from_secs(config::BLE_RSSI_SAMPLE_SECS)).await;
+            if let Some(rssi) = conn.rssi() {
+                slot_event_tx.send(SlotEvent::Rssi { slot, rssi }).await;
+            }
+        }
+    };
+    let forward_hotkey = async {
+        loop {
+            let profile = signals.hotkey.wait().await;
+            slot_event_tx
+                .send(SlotEvent::SwitchProfile { profile })
+                .await;
+        }
+    };
+    let mut forward = core::pin::pin!(join5(
+        forward_battery,
+        forward_activity,
+        adapt_link,
+        sample_rssi,
+        forward_hotkey
+    ));
+
     // Run phase. A live `Connection` now exists, so race the notification loop
     // against incoming commands. If a command supersedes us, explicitly tear
     // the link down (dropping the future alone does NOT disconnect the radio
     // link in the SoftDevice, which would leak a central connection slot).
-    let run_fut = hid_client::run_notification_loop(&conn, &client, descriptor, report_tx, led_rx);
-    match select(cmd_rx.receive(), run_fut).await {
-        Either::First(next_cmd) => {
-            let _ = conn.disconnect();
-            SlotOutcome::Superseded(next_cmd)
+    loop {
+        let run_fut = hid_client::run_notification_loop(
+            &conn,
+            &client,
+            &descriptors,
+            report_tx,
+            led_rx.as_deref_mut(),
+            power_rx.as_deref_mut(),
+            &signals,
+        );
+        match select3(cmd_rx.receive(), run_fut, forward.as_mut()).await {
+            Either3::First(next_cmd) => {
+                let _ = conn.disconnect();
+                return SlotOutcome::Superseded(next_cmd);
+            }
+            Either3::Second(LoopEnd::Disconnected) => return SlotOutcome::Closed,
+            Either3::Second(LoopEnd::BootFallback) => {
+                // Report Protocol isn't producing usable reports; the boot
+                // reports have a fixed layout. Remember the choice so the next
+                // connection goes straight to Boot Protocol.
+                if client.enter_boot_protocol(&conn).await.is_ok() {
+                    slot_event_tx
+                        .send(SlotEvent::GattCacheChanged {
+                            address: device.address,
+                            cache: Some(client.to_cache(&descriptors)),
+                        })
+                        .await;
+                }
+            }
+            Either3::Second(LoopEnd::ServiceChanged) => {
+                // The peer's GATT layout changed under us: the cached handles are
+                // stale, so rediscover on the live link and replace the cache.
+                match hid_client::discover_and_subscribe(&conn, None).await {
+                    Ok((c, d, cache)) => {
+                        client = c;
+                        descriptors = d;
+                        slot_event_tx
+                            .send(SlotEvent::GattCacheChanged {
+                                address: device.address,
+                                cache,
+                            })
+                            .await;
+                    }
+                    Err(tag) => {
+                        slot_event_tx
+                            .send(SlotEvent::GattCacheChanged {
+                                address: device.address,
+                                cache: None,
+                            })
+                            .await;
+                        let _ = conn.disconnect();
+                        return SlotOutcome::Failed(tag);
+                    }
+                }
+            }
+            Either3::Third(_) => unreachable!("event forwarding never ends"),
         }
-        Either::Second(()) => SlotOutcome::Closed,
     }
 }
@@ -13,7 +13,6 @@
 //! stored peer maps to which scan result, deduping and capping to the available
 //! slots — so it can be unit-tested on the host.
 
-use crate::ble::coordinator::MAX_CONNECTIONS;
 use heapless::Vec;
 
 /// One resolved auto-reconnect target.
@@ -35,17 +34,18 @@ pub struct ReconnectTarget {
 /// `scanned` is `peer` — by resolving a rotating RPA against the peer's IRK, or
 /// by a plain address match for a stable address.
 ///
-/// Returns up to [`MAX_CONNECTIONS`] targets (one per connection slot), never
-/// assigning the same scan result to two different peers.
-pub fn resolve_reconnect_targets<F>(
+/// Returns up to `N` targets (one per connection slot; the firmware uses
+/// [`MAX_CONNECTIONS`](crate::ble::coordinator::MAX_CONNECTIONS)), never assigning the same scan result to two
+/// different peers.
+pub fn resolve_reconnect_targets<const N: usize, F>(
     peer_count: usize,
     scanned_count: usize,
     matches: F,
-) -> Vec<ReconnectTarget, MAX_CONNECTIONS>
+) -> Vec<ReconnectTarget, N>
 where
     F: Fn(usize, usize) -> bool,
 {
-    let mut targets: Vec<ReconnectTarget, MAX_CONNECTIONS> = Vec::new();
+    let mut targets: Vec<ReconnectTarget, N> = Vec::new();
     // Bitset of scan results already claimed by an earlier peer. Scans never
     // exceed `BLE_MAX_DISCOVERED` (8) entries, so a u32 is ample.
     let mut claimed: u32 = 0;
@@ -74,6 +74,9 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ble::coordinator::MAX_CONNECTIONS;
+
+    type Targets = Vec<ReconnectTarget, MAX_CONNECTIONS>;
 
     /// Build a matcher from an explicit `(peer, scanned)` truth table.
     fn matcher(pairs: &[(usize, usize)]) -> impl Fn(usize, usize) -> bool + '_ {
@@ -82,7 +85,7 @@ mod tests {
 
     #[test]
     fn no_peers_yields_no_targets() {
-        let targets = resolve_reconnect_targets(0, 3, |_, _| true);
+        let targets: Targets = resolve_reconnect_targets(0, 3, |_, _| true);
         assert!(targets.is_empty());
     }
 
@@ -90,7 +93,7 @@ mod tests {
     fn unmatched_peers_fall_back_to_stored_address() {
         // Nothing in the scan matches: each peer still gets a target with no
         // live address, so the caller connects to the stored one.
-        let targets = resolve_reconnect_targets(2, 4, |_, _| false);
+        let targets: Targets = resolve_reconnect_targets(2, 4, |_, _| false);
         assert_eq!(targets.len(), 2);
         assert_eq!(
             targets[0],
@@ -111,7 +114,7 @@ mod tests {
     #[test]
     fn resolved_rpa_uses_live_scan_address() {
         // Peer 0 resolves to scan result 2 (its rotated RPA).
-        let targets = resolve_reconnect_targets(1, 3, matcher(&[(0, 2)]));
+        let targets: Targets = resolve_reconnect_targets(1, 3, matcher(&[(0, 2)]));
         assert_eq!(targets.len(), 1);
         assert_eq!(
             targets[0],
@@ -126,7 +129,8 @@ mod tests {
     fn a_scan_result_is_not_claimed_by_two_peers() {
         // Both peers would match scan results 0 and 1; each must take a
         // distinct one (peer 0 → 0, peer 1 → 1).
-        let targets = resolve_reconnect_targets(2, 2, matcher(&[(0, 0), (0, 1), (1, 0), (1, 1)]));
+        let targets: Targets =
+            resolve_reconnect_targets(2, 2, matcher(&[(0, 0), (0, 1), (1, 0), (1, 1)]));
         assert_eq!(
             targets[0],
             ReconnectTarget {
@@ -146,14 +150,17 @@ mod tests {
     #[test]
     fn targets_are_capped_to_connection_slots() {
         // More stored peers than slots: only MAX_CONNECTIONS come back.
-        let targets = resolve_reconnect_targets(MAX_CONNECTIONS + 2, 0, |_, _| false);
+        let targets: Targets = resolve_reconnect_targets(MAX_CONNECTIONS + 2, 0, |_, _| false);
         assert_eq!(targets.len(), MAX_CONNECTIONS);
+
+        let two: Vec<ReconnectTarget, 2> = resolve_reconnect_targets(4, 0, |_, _| false);
+        assert_eq!(two.len(), 2);
     }
 
     #[test]
     fn mixes_resolved_and_fallback_targets() {
         // Peer 0 isn't in the scan (fallback); peer 1 resolves to scan 0.
-        let targets = resolve_reconnect_targets(2, 2, matcher(&[(1, 0)]));
+        let targets: Targets = resolve_reconnect_targets(2, 2, matcher(&[(1, 0)]));
         assert_eq!(
             targets[0],
             ReconnectTarget {
@@ -0,0 +1,300 @@
+//! Pure bookkeeping for a streaming scan.
+//!
+//! A scan reports devices to the UI as they are heard rather than after the
+//! window closes, so the list has to be stable while it grows: a device keeps
+//! the index it was first announced at (the UI and `BleCommand::Connect` both
+//! refer to devices by that index), and later packets only *update* it — a
+//! scan response that brings the name, or an RSSI that moved enough to be
+//! worth redrawing. [`ScanList`] owns that list and the per-address
+//! advertisement merging; the SoftDevice scan itself lives in
+//! [`crate::ble::scanner`].
+
+use crate::ble::adv_parser::{AddressKind, AdvReport};
+use crate::ble::coordinator::DeviceInfo;
+use heapless::Vec;
+
+/// Non-HID advertisers remembered while waiting for their other packet: a
+/// peripheral may put the HID UUID in its scan response and its name in the
+/// advertisement, or the other way round.
+pub const PENDING_REPORTS: usize = 4;
+
+/// RSSI change (dB) that is worth a `DeviceUpdated`; smaller jitter is
+/// dropped so a busy scan doesn't flood the UI channel.
+pub const RSSI_UPDATE_DB: i16 = 4;
+
+/// What a packet changed in the list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScanUpdate {
+    /// A new HID device was appended at this index.
+    Found(usize),
+    /// The device at this index changed name, icon or RSSI.
+    Updated(usize),
+}
+
+/// Devices heard during one scan, in discovery order.
+pub struct ScanList<A, const N: usize> {
+    devices: Vec<DeviceInfo<A>, N>,
+    /// Merged advertisement data for `devices` (same index).
+    reports: Vec<AdvReport, N>,
+    pending: Vec<(A, AdvReport), PENDING_REPORTS>,
+    /// How many of `devices` the UI has been told about (always a prefix).
+    announced: usize,
+}
+
+impl<A: Copy + PartialEq, const N: usize> Default for ScanList<A, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Copy + PartialEq, const N: usize> ScanList<A, N> {
+    pub const fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            reports: Vec::new(),
+            pending: Vec::new(),
+            announced: 0,
+        }
+    }
+
+    /// Forget everything (a new scan is starting).
+    pub fn clear(&mut self) {
+        self.devices.clear();
+        self.reports.clear();
+        self.pending.clear();
+        self.announced = 0;
+    }
+
+    pub fn devices(&self) -> &[DeviceInfo<A>] {
+        &self.devices
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.devices.is_full()
+    }
+
+    /// Merge one advertising or scan response packet from `address`.
+    /// `is_bonded` is only consulted when a device is first listed.
+    pub fn observe(
+        &mut self,
+        address: A,
+        address_kind: AddressKind,
+        rssi: i8,
+        data: &[u8],
+        is_bonded: impl FnOnce(A) -> bool,
+    ) -> Option<ScanUpdate> {
+        if let Some(i) = self.devices.iter().position(|d| d.address == address) {
+            let report = &mut self.reports[i];
+            report.merge(data);
+            let device = &mut self.devices[i];
+            let name = report.display_name();
+            let icon = report.icon();
+            let rssi_moved = (rssi as i16 - device.rssi as i16).abs() >= RSSI_UPDATE_DB;
+            if name == device.name && icon == device.icon && !rssi_moved {
+                return None;
+            }
+            device.name = name;
+            device.icon = icon;
+            // Only a reported RSSI is stored, so slow drift still adds up to
+            // an update eventually.
+            if rssi_moved {
+                device.rssi = rssi;
+            }
+            return Some(ScanUpdate::Updated(i));
+        }
+
+        let mut report = match self.pending.iter().position(|(a, _)| *a == address) {
+            Some(i) => self.pending.swap_remove(i).1,
+            None => AdvReport::new(address_kind),
+        };
+        report.merge(data);
+
+        if !report.has_hid_service() {
+            if self.pending.is_full() {
+                self.pending.remove(0);
+            }
+            let _ = self.pending.push((address, report));
+            return None;
+        }
+        if self.devices.is_full() {
+            return None;
+        }
+        let device = DeviceInfo {
+            address,
+            name: report.display_name(),
+            rssi,
+            icon: report.icon(),
+            bonded: is_bonded(address),
+        };
+        let _ = self.devices.push(device);
+        let _ = self.reports.push(report);
+        Some(ScanUpdate::Found(self.devices.len() - 1))
+    }
+
+    /// The merged advertisement data for the device at `index`.
+    pub fn report(&self, index: usize) -> Option<&AdvReport> {
+        self.reports.get(index)
+    }
+
+    /// Devices found but not yet sent to the UI, in order.
+    pub fn unannounced(&self) -> &[DeviceInfo<A>] {
+        &self.devices[self.announced..]
+    }
+
+    /// Record that the next `count` unannounced devices were sent.
+    pub fn mark_announced(&mut self, count: usize) {
+        self.announced = (self.announced + count).min(self.devices.len());
+    }
+
+    /// Has the UI been told about the device at `index`? Updates for devices
+    /// it hasn't seen yet are pointless (it will get the current state with
+    /// the `DeviceFound`).
+    pub fn is_announced(&self, index: usize) -> bool {
+        index < self.announced
+    }
+}
+
+/// Scan interval and window, in 0.625 ms units (the SoftDevice's).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanTiming {
+    pub interval: u16,
+    pub window: u16,
+}
+
+/// No HID links: listen continuously (60 ms window every 60 ms).
+pub const SCAN_TIMING_IDLE: ScanTiming = ScanTiming {
+    interval: 96,
+    window: 96,
+};
+
+/// HID links active: 30 ms every 300 ms, leaving the radio to the
+/// connection events so input latency doesn't suffer while the user scans.
+pub const SCAN_TIMING_CONNECTED: ScanTiming = ScanTiming {
+    interval: 480,
+    window: 48,
+};
+
+/// Scan duty cycle for the given number of active HID links.
+pub fn scan_timing(active_links: usize) -> ScanTiming {
+    if active_links == 0 {
+        SCAN_TIMING_IDLE
+    } else {
+        SCAN_TIMING_CONNECTED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::adv_parser::DeviceIcon;
+
+    type List = ScanList<u8, 3>;
+
+    const HID_ADV: [u8; 4] = [0x03, 0x03, 0x12, 0x18];
+    const KBD_RSP: [u8; 9] = [0x04, 0x09, b'K', b'b', b'd', 0x03, 0x19, 0xC1, 0x03];
+
+    fn observe(list: &mut List, address: u8, rssi: i8, data: &[u8]) -> Option<ScanUpdate> {
+        list.observe(address, AddressKind::Public, rssi, data, |a| a == 0xB0)
+    }
+
+    #[test]
+    fn hid_devices_are_appended_in_discovery_order() {
+        let mut list = List::new();
+        assert_eq!(
+            observe(&mut list, 0xA0, -40, &HID_ADV),
+            Some(ScanUpdate::Found(0))
+        );
+        assert_eq!(
+            observe(&mut list, 0xB0, -60, &HID_ADV),
+            Some(ScanUpdate::Found(1))
+        );
+        // A louder packet from the first device doesn't reorder the list.
+        assert_eq!(
+            observe(&mut list, 0xA0, -30, &HID_ADV),
+            Some(ScanUpdate::Updated(0))
+        );
+        let addrs: std::vec::Vec<u8> = list.devices().iter().map(|d| d.address).collect();
+        assert_eq!(addrs, [0xA0, 0xB0]);
+        assert!(!list.devices()[0].bonded);
+        assert!(list.devices()[1].bonded);
+    }
+
+    #[test]
+    fn scan_response_updates_name_and_icon() {
+        let mut list = List::new();
+        observe(&mut list, 0xA0, -40, &HID_ADV);
+        assert_eq!(list.devices()[0].name.as_str(), "Unknown");
+        assert_eq!(
+            observe(&mut list, 0xA0, -40, &KBD_RSP),
+            Some(ScanUpdate::Updated(0))
+        );
+        assert_eq!(list.devices()[0].name.as_str(), "Kbd");
+        assert_eq!(list.devices()[0].icon, DeviceIcon::Keyboard);
+        // Same packet again: nothing changed.
+        assert_eq!(observe(&mut list, 0xA0, -40, &KBD_RSP), None);
+    }
+
+    #[test]
+    fn rssi_jitter_is_not_reported() {
+        let mut list = List::new();
+        observe(&mut list, 0xA0, -40, &HID_ADV);
+        assert_eq!(observe(&mut list, 0xA0, -42, &HID_ADV), None);
+        assert_eq!(observe(&mut list, 0xA0, -43, &HID_ADV), None);
+        assert_eq!(list.devices()[0].rssi, -40);
+        // Drift adds up against the last reported value.
+        assert_eq!(
+            observe(&mut list, 0xA0, -44, &HID_ADV),
+            Some(ScanUpdate::Updated(0))
+        );
+        assert_eq!(list.devices()[0].rssi, -44);
+    }
+
+    #[test]
+    fn hid_uuid_in_scan_response_keeps_advertised_name() {
+        let mut list = List::new();
+        assert_eq!(observe(&mut list, 0xA0, -40, &KBD_RSP), None);
+        assert_eq!(
+            observe(&mut list, 0xA0, -40, &HID_ADV),
+            Some(ScanUpdate::Found(0))
+        );
+        assert_eq!(list.devices()[0].name.as_str(), "Kbd");
+    }
+
+    #[test]
+    fn full_list_ignores_new_devices() {
+        let mut list = List::new();
+        for a in 0..3 {
+            observe(&mut list, a, -50, &HID_ADV);
+        }
+        assert!(list.is_full());
+        assert_eq!(observe(&mut list, 9, -50, &HID_ADV), None);
+    }
+
+    #[test]
+    fn announcing_tracks_a_prefix() {
+        let mut list = List::new();
+        observe(&mut list, 0xA0, -40, &HID_ADV);
+        observe(&mut list, 0xB0, -40, &HID_ADV);
+        assert_eq!(list.unannounced().len(), 2);
+        list.mark_announced(1);
+        assert!(list.is_announced(0));
+        assert!(!list.is_announced(1));
+        assert_eq!(list.unannounced()[0].address, 0xB0);
+        list.mark_announced(5);
+        assert!(list.unannounced().is_empty());
+        list.clear();
+        assert!(list.devices().is_empty());
+        assert!(!list.is_announced(0));
+    }
+
+    #[test]
+    fn duty_cycle_drops_with_active_links() {
+        assert_eq!(scan_timing(0), SCAN_TIMING_IDLE);
+        assert_eq!(scan_timing(1), SCAN_TIMING_CONNECTED);
+        // At most a fifth of the airtime once a link is up.
+        let connected = scan_timing(2);
+        assert!(connected.window * 5 <= connected.interval);
+    }
+}
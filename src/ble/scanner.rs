@@ -1,100 +1,137 @@
 //! BLE GAP scanner - discovers nearby peripherals.
 //!
-//! Uses the SoftDevice Central-role scanning API.  Discovered devices
-//! are filtered by the presence of the HID Service UUID (0x1812) in
-//! their advertisement data, then pushed into the UI event channel.
+//! Uses the SoftDevice Central-role scanning API.  Advertising and scan
+//! response packets are merged per address into an [`AdvReport`]; devices
+//! are filtered by the presence of the HID Service UUID (0x1812) in either,
+//! then streamed to the UI event channel as they are found.
+//!
+//! [`AdvReport`]: crate::ble::adv_parser::AdvReport
+
+use core::cell::RefCell;
 
-use crate::ble::adv_parser::{contains_hid_service_uuid, extract_device_name};
-use crate::ble::{BleErrorTag, BleEvent, DiscoveredDevice};
-use crate::config::{BLE_MAX_DISCOVERED, BLE_SCAN_DURATION_SECS};
+use crate::ble::adv_parser::AddressKind;
+use crate::ble::scan_list::{ScanList, ScanTiming, ScanUpdate};
+use crate::ble::{BleErrorTag, BleEvent};
+use crate::config::BLE_MAX_DISCOVERED;
+use crate::storage;
 use defmt::info;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
 use embassy_time::{with_timeout, Duration};
-use heapless::Vec;
-use nrf_softdevice::ble::central;
+use nrf_softdevice::ble::{central, Address};
 use nrf_softdevice::Softdevice;
 
-/// Result of a single scan pass.
-pub struct ScanResult {
-    pub devices: Vec<DiscoveredDevice, BLE_MAX_DISCOVERED>,
+/// The devices of the current (or last) scan, by stable index.
+pub type DeviceList = ScanList<Address, BLE_MAX_DISCOVERED>;
+
+/// Send the devices the UI hasn't heard about yet, in order, stopping at the
+/// first one the channel has no room for (the next packet retries).
+fn announce_pending(
+    list: &mut DeviceList,
+    event_tx: &Sender<'_, CriticalSectionRawMutex, BleEvent, 8>,
+) {
+    let mut sent = 0;
+    for device in list.unannounced() {
+        if event_tx
+            .try_send(BleEvent::DeviceFound(device.clone()))
+            .is_err()
+        {
+            break;
+        }
+        sent += 1;
+    }
+    list.mark_announced(sent);
 }
 
-/// Run a BLE scan for `BLE_SCAN_DURATION_SECS` seconds.
+/// Run a BLE scan for up to the `scan_duration_secs` setting, filling `list`.
+///
+/// Devices are streamed to `event_tx` while the scan runs: a
+/// `BleEvent::DeviceFound` when a HID peripheral is first heard (its index in
+/// `list` never changes), then `BleEvent::DeviceUpdated` when its name, icon
+/// or RSSI changes. The SoftDevice scan callback cannot `.await`, so these are
+/// `try_send`s; a `DeviceFound` the channel had no room for is retried on the
+/// next packet and flushed before `BleEvent::ScanComplete`.
 ///
-/// Discovered HID peripherals are buffered during the scan (the SoftDevice scan
-/// callback cannot `.await`), then emitted to `event_tx` as a burst of
-/// `BleEvent::DeviceFound` followed by `BleEvent::ScanComplete` once the scan
-/// window closes.
+/// `is_bonded` marks devices whose address matches an existing bond (for a
+/// Resolvable Private Address, by resolving it against the bond's IRK).
+/// `timing` sets the duty cycle (see [`crate::ble::scan_list::scan_timing`]).
+/// `done` ends the scan before its window once it holds for the list so far;
+/// it is asked each time a new device is found.
 ///
-/// Returns the accumulated list so the connection manager can index into it.
+/// Dropping the future cancels the scan; `list` keeps what was found so far
+/// and the caller reports `ScanComplete { cancelled: true }` itself.
 pub async fn scan(
     sd: &Softdevice,
+    list: &RefCell<DeviceList>,
     event_tx: &Sender<'_, CriticalSectionRawMutex, BleEvent, 8>,
-) -> Result<ScanResult, BleErrorTag> {
-    info!("BLE scan starting ({} s window)", BLE_SCAN_DURATION_SECS);
+    is_bonded: impl Fn(Address) -> bool,
+    timing: ScanTiming,
+    done: impl Fn(&DeviceList) -> bool,
+) -> Result<(), BleErrorTag> {
+    let duration_secs = storage::settings().scan_duration_secs;
+    info!(
+        "BLE scan starting ({} s window, {}/{} duty)",
+        duration_secs, timing.window, timing.interval
+    );
+    list.borrow_mut().clear();
     event_tx.send(BleEvent::ScanStarted).await;
 
-    let mut found: Vec<DiscoveredDevice, BLE_MAX_DISCOVERED> = Vec::new();
-
     let config = central::ScanConfig {
         // Active scan to retrieve scan-response data (device names).
         active: true,
+        interval: timing.interval.into(),
+        window: timing.window.into(),
         ..Default::default()
     };
 
-    // We set up a deadline so the scan doesn't run forever.
-    let deadline = embassy_time::Instant::now() + Duration::from_secs(BLE_SCAN_DURATION_SECS);
-
-    // The SoftDevice scan callback receives each advertisement.
-    // We use a closure that captures our state.
+    // The SoftDevice scan callback receives each advertisement and scan
+    // response; both are merged into one entry per address.
     let scan_fut = central::scan(sd, &config, |params| {
         let data =
             unsafe { core::slice::from_raw_parts(params.data.p_data, params.data.len as usize) };
 
-        // Check if we've exceeded our time budget.
-        if embassy_time::Instant::now() > deadline {
-            return Some(()); // Signal scan to stop
-        }
-
-        // Parse advertisement data looking for the HID Service UUID.
-        let has_hid_service = contains_hid_service_uuid(data);
-
-        if has_hid_service {
-            // Extract device name from advertisement or scan response.
-            let name = extract_device_name(data);
-
-            let device = DiscoveredDevice {
-                address: nrf_softdevice::ble::Address::from_raw(params.peer_addr),
-                name,
-                rssi: params.rssi,
-            };
-
-            // Avoid duplicates (same address).
-            let already_seen = found.iter().any(|d| d.address == device.address);
-            if !already_seen && !found.is_full() {
-                info!("Found: {} (RSSI {})", device.name.as_str(), device.rssi);
-                // We can't await inside this closure, so the event_tx send
-                // happens after scan completes.  Instead, buffer here.
-                let _ = found.push(device);
+        let mut list = list.borrow_mut();
+        let update = list.observe(
+            Address::from_raw(params.peer_addr),
+            AddressKind::from_raw(params.peer_addr.addr_type()),
+            params.rssi,
+            data,
+            &is_bonded,
+        );
+        let found = matches!(update, Some(ScanUpdate::Found(_)));
+        match update {
+            Some(ScanUpdate::Found(index)) => {
+                let device = &list.devices()[index];
+                info!(
+                    "Found: {} (RSSI {}, TX {}, {}, bonded {})",
+                    device.name.as_str(),
+                    device.rssi,
+                    list.report(index).and_then(|r| r.tx_power),
+                    device.icon,
+                    device.bonded
+                );
+            }
+            Some(ScanUpdate::Updated(index)) if list.is_announced(index) => {
+                // Best effort: a dropped update is superseded by the next one.
+                let device = list.devices()[index].clone();
+                let _ = event_tx.try_send(BleEvent::DeviceUpdated { index, device });
             }
+            _ => {}
         }
+        announce_pending(&mut list, event_tx);
 
         // Return None to keep scanning, Some(()) to stop.
-        if found.is_full() {
-            Some(()) // Buffer full - stop early
+        if list.is_full() || (found && done(&list)) {
+            Some(()) // List full, or the caller has what it wanted
         } else {
             None
         }
     });
 
-    // Hard backstop: the deadline above is only evaluated when an advertisement
-    // arrives, so in a quiet RF environment the scan callback might never fire
-    // and `central::scan` would otherwise run forever. Cap the whole scan with a
-    // wall-clock timeout (slightly beyond the window) so the UI can never hang.
-    let timeout = Duration::from_secs(BLE_SCAN_DURATION_SECS) + Duration::from_secs(2);
-    match with_timeout(timeout, scan_fut).await {
-        // Scan stopped itself (deadline reached or buffer full).
+    // The scan window: the SoftDevice scan itself has no end, and in a quiet
+    // RF environment the callback might never fire, so bound it by wall clock.
+    match with_timeout(Duration::from_secs(duration_secs as u64), scan_fut).await {
+        // Scan stopped itself (list full, or `done`).
         Ok(Ok(())) => {}
         // SoftDevice reported a scan error.
         Ok(Err(_e)) => {
@@ -104,124 +141,25 @@ pub async fn scan(
                 .await;
             return Err(BleErrorTag::ScanFailed);
         }
-        // Backstop fired — proceed with whatever was discovered so far.
-        Err(_timeout) => {
-            info!("BLE scan hit hard timeout backstop");
-        }
-    }
-
-    // Now send all found devices to the UI.
-    for device in found.iter() {
-        event_tx.send(BleEvent::DeviceFound(device.clone())).await;
-    }
-    event_tx.send(BleEvent::ScanComplete).await;
-
-    info!("BLE scan complete - {} devices found", found.len());
-
-    Ok(ScanResult { devices: found })
-}
-
-// ═══════════════════════════════════════════════════════════════════════════
-// Unit Tests (run on host, not embedded)
-// ═══════════════════════════════════════════════════════════════════════════
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn detect_hid_uuid_in_advertisement() {
-        // AD structure: len=3, type=0x03 (Complete 16-bit UUIDs), UUID=0x1812
-        let ad_data = [
-            0x03, 0x03, 0x12, 0x18, // HID Service UUID in little-endian
-        ];
-        assert!(contains_hid_service_uuid(&ad_data));
-    }
-
-    #[test]
-    fn no_hid_uuid_in_advertisement() {
-        // AD structure with Battery Service UUID (0x180F) instead
-        let ad_data = [
-            0x03, 0x03, 0x0F, 0x18, // Battery Service UUID
-        ];
-        assert!(!contains_hid_service_uuid(&ad_data));
-    }
-
-    #[test]
-    fn hid_uuid_among_multiple_uuids() {
-        // Multiple 16-bit UUIDs: 0x180F (Battery), 0x1812 (HID), 0x1801 (GATT)
-        let ad_data = [
-            0x07, 0x03, // len=7, type=0x03 (Complete 16-bit UUIDs)
-            0x0F, 0x18, // Battery
-            0x12, 0x18, // HID - this should be found
-            0x01, 0x18, // GATT
-        ];
-        assert!(contains_hid_service_uuid(&ad_data));
-    }
-
-    #[test]
-    fn incomplete_uuid_list() {
-        // AD type 0x02 = Incomplete 16-bit UUIDs (should still be checked)
-        let ad_data = [
-            0x03, 0x02, 0x12, 0x18, // HID Service UUID
-        ];
-        assert!(contains_hid_service_uuid(&ad_data));
-    }
-
-    #[test]
-    fn empty_advertisement_data() {
-        let ad_data: [u8; 0] = [];
-        assert!(!contains_hid_service_uuid(&ad_data));
-    }
-
-    #[test]
-    fn malformed_ad_length_zero() {
-        let ad_data = [0x00]; // len=0 should break parsing
-        assert!(!contains_hid_service_uuid(&ad_data));
+        // Window closed.
+        Err(_timeout) => {}
     }
 
-    #[test]
-    fn extract_complete_local_name() {
-        // AD structure: len=8, type=0x09 (Complete Local Name), "Keyboard"
-        let ad_data = [
-            0x09, 0x09, // len=9, type=0x09
-            b'K', b'e', b'y', b'b', b'o', b'a', b'r', b'd',
-        ];
-        let name = extract_device_name(&ad_data);
-        assert_eq!(name.as_str(), "Keyboard");
+    // Flush any DeviceFound the callback couldn't fit into the channel.
+    loop {
+        let next = list.borrow().unannounced().first().cloned();
+        let Some(device) = next else { break };
+        event_tx.send(BleEvent::DeviceFound(device)).await;
+        list.borrow_mut().mark_announced(1);
     }
+    event_tx
+        .send(BleEvent::ScanComplete { cancelled: false })
+        .await;
 
-    #[test]
-    fn extract_shortened_local_name() {
-        // AD structure: len=4, type=0x08 (Shortened Local Name), "BT K"
-        let ad_data = [
-            0x05, 0x08, // len=5, type=0x08
-            b'B', b'T', b' ', b'K',
-        ];
-        let name = extract_device_name(&ad_data);
-        assert_eq!(name.as_str(), "BT K");
-    }
-
-    #[test]
-    fn no_name_in_advertisement() {
-        // Only flags, no name
-        let ad_data = [
-            0x02, 0x01, 0x06, // Flags: LE General Discoverable
-        ];
-        let name = extract_device_name(&ad_data);
-        assert_eq!(name.as_str(), "Unknown");
-    }
+    info!(
+        "BLE scan complete - {} devices found",
+        list.borrow().devices().len()
+    );
 
-    #[test]
-    fn name_truncated_to_32_chars() {
-        // Very long name that exceeds 32 characters
-        let mut ad_data = [0u8; 40];
-        ad_data[0] = 35; // len
-        ad_data[1] = 0x09; // Complete Local Name
-        for i in 2..37 {
-            ad_data[i] = b'X';
-        }
-        let name = extract_device_name(&ad_data);
-        assert_eq!(name.len(), 32); // Truncated to heapless::String<32> capacity
-    }
+    Ok(())
 }
@@ -0,0 +1,65 @@
+//! Security policy: which peripherals may pair with the bridge, and whether
+//! their keys are kept.
+//!
+//! Links are always encrypted; the policy only decides what happens when a
+//! peripheral without stored keys connects. Locking the bridge to its bonded
+//! devices keeps a stranger's keyboard from being paired from the device list
+//! once the desk is set up.
+
+/// What the bridge does with peripherals that have no stored bond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SecurityPolicy {
+    /// Pair and bond new peripherals, so they reconnect without pairing again.
+    #[default]
+    Bond,
+    /// Pair new peripherals for the connection only, keeping no keys.
+    PairOnly,
+    /// Refuse to pair: only already bonded peripherals can connect.
+    BondedOnly,
+}
+
+impl SecurityPolicy {
+    /// Every policy, in the order of their stored bytes.
+    pub const ALL: [SecurityPolicy; 3] = [
+        SecurityPolicy::Bond,
+        SecurityPolicy::PairOnly,
+        SecurityPolicy::BondedOnly,
+    ];
+
+    /// The policy stored as `byte`, if it is one.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// The byte this policy is stored as.
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Whether a peripheral without a bond may pair.
+    pub fn pairs_new(self) -> bool {
+        self != SecurityPolicy::BondedOnly
+    }
+
+    /// Whether pairing stores the peripheral's keys.
+    pub fn bonds(self) -> bool {
+        self == SecurityPolicy::Bond
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_decide_pairing_and_bonding() {
+        assert!(SecurityPolicy::Bond.pairs_new() && SecurityPolicy::Bond.bonds());
+        assert!(SecurityPolicy::PairOnly.pairs_new() && !SecurityPolicy::PairOnly.bonds());
+        assert!(!SecurityPolicy::BondedOnly.pairs_new());
+        for policy in SecurityPolicy::ALL {
+            assert_eq!(SecurityPolicy::from_byte(policy.to_byte()), Some(policy));
+        }
+        assert_eq!(SecurityPolicy::from_byte(3), None);
+    }
+}
@@ -1,31 +1,44 @@
 //! Application-wide constants and compile-time configuration.
 //!
 //! All hardware pin assignments, timing parameters, and protocol
-//! constants live here so they can be tuned in one place.
+//! constants live here so they can be tuned in one place. The ones users can
+//! change at runtime are only the defaults of their
+//! [`Settings`](crate::storage::settings::Settings) field; runtime code reads
+//! the settings.
 
 // BLE
 
-/// Duration of a BLE scan window (seconds).
+/// Duration of a BLE scan window (seconds). Default of a setting.
 pub const BLE_SCAN_DURATION_SECS: u64 = 8;
 
 /// Maximum number of BLE peripherals we can discover in one scan.
 pub const BLE_MAX_DISCOVERED: usize = 8;
 
-/// BLE connection interval range (in 1.25 ms units).
-/// 6 = 7.5 ms (lowest latency for HID).
-pub const BLE_CONN_INTERVAL_MIN: u16 = 6;
-pub const BLE_CONN_INTERVAL_MAX: u16 = 12;
+/// Relax BLE connection parameters while the PC sleeps (peripherals suspended
+/// via their HID Control Point), so battery-powered peripherals can idle. The
+/// per-role and suspended parameters themselves are the policy in
+/// `ble::conn_params`. Default of a setting.
+pub const BLE_RELAX_CONN_ON_SUSPEND: bool = true;
 
-/// BLE slave latency (number of connection events the peripheral can skip).
-pub const BLE_SLAVE_LATENCY: u16 = 0;
+/// Link RSSI sampling period per slot (seconds), for the link statistics.
+pub const BLE_RSSI_SAMPLE_SECS: u64 = 1;
 
-/// SoftDevice connection event length (in 1.25 ms units).
-/// Must not exceed the connection interval; with two simultaneous links a
-/// short event length lets the scheduler interleave both. 6 = 7.5 ms.
-pub const BLE_CONN_EVENT_LENGTH: u16 = 6;
+/// How often a slot checks whether its peer changed the connection parameters
+/// (seconds). Polled: nrf-softdevice consumes the GAP parameter-update event
+/// itself and only exposes the resulting `Connection::conn_params()`.
+pub const BLE_CONN_PARAMS_POLL_SECS: u64 = 2;
 
-/// BLE supervision timeout (in 10 ms units). 400 = 4 s.
-pub const BLE_SUP_TIMEOUT: u16 = 400;
+/// ATT MTU configured in the SoftDevice and requested on every link.
+/// 247 fills one 251-byte LL packet exactly, leaving 244 bytes of notification
+/// payload — enough for NKRO bitmaps and vendor reports.
+pub const BLE_ATT_MTU: u16 = 247;
+
+/// LL payload length requested via Data Length Extension (the BLE 4.2 maximum).
+pub const BLE_DATA_LENGTH: u16 = 251;
+
+/// Minimum spacing of a slot's "peer sent input" reports to the coordinator
+/// (seconds). They only rank slots for replacement, so coarse is fine.
+pub const BLE_ACTIVITY_REPORT_SECS: u64 = 5;
 
 // USB
 
@@ -54,13 +67,32 @@ pub const USB_HID_POLL_MS: u8 = 1;
 //   I²C SCL        → P0.27
 //   Status LED     → P0.06
 
-/// Button debounce time (ms).
+/// Button debounce time (ms). Default of a setting.
 pub const BUTTON_DEBOUNCE_MS: u64 = 50;
 
-/// Enable automatic OLED screen power-off after inactivity.
+/// Hold time that makes a press long (ms); held Up/Down start repeating then.
+pub const BUTTON_LONG_PRESS_MS: u32 = 600;
+
+/// Window for the second press of a double press of Select (ms). A short
+/// Select is reported this much later.
+pub const BUTTON_DOUBLE_PRESS_MS: u32 = 250;
+
+/// Repeat period of a held Up/Down (ms).
+pub const BUTTON_REPEAT_MS: u32 = 150;
+
+/// Most time between two presses that still makes them a chord (ms).
+pub const BUTTON_CHORD_MS: u32 = 80;
+
+/// Run on one button (SELECT, P0.24) for boards like the nRF52840 Dongle:
+/// a short press moves to the next item, a long press selects it.
+pub const SINGLE_BUTTON_MODE: bool = false;
+
+/// Enable automatic OLED screen power-off after inactivity. Default of a
+/// setting.
 pub const SCREEN_AUTO_OFF_ENABLED: bool = true;
 
-/// Inactivity timeout before OLED is turned off (seconds).
+/// Inactivity timeout before OLED is turned off (seconds). Default of a
+/// setting.
 pub const SCREEN_AUTO_OFF_TIMEOUT_SECS: u64 = 120;
 
 // Paired-device storage
@@ -68,8 +100,25 @@ pub const SCREEN_AUTO_OFF_TIMEOUT_SECS: u64 = 120;
 /// Maximum number of paired devices tracked in storage.
 pub const MAX_PAIRED_DEVICES: usize = 4;
 
+/// Reconnects to already-known devices batched before their recency/connect
+/// counts are written to flash (other changes are written at once).
+pub const STORAGE_USAGE_SAVE_BATCH: u8 = 4;
+
 /// Flash page index where pairing storage starts (4 KB per page on nRF52840).
 pub const STORAGE_FLASH_PAGE_START: u32 = 240;
 
 /// Number of flash pages reserved for pairing storage.
 pub const STORAGE_FLASH_PAGE_COUNT: u32 = 4;
+
+/// Names of the device profiles, each with its own paired-device list
+/// (switched from the UI or with Ctrl+Alt+Shift+F1.., one per profile). Each
+/// profile's list takes its own room in the storage region.
+pub const PROFILE_NAMES: [&str; MAX_PROFILES] = ["Desk", "Meeting room", "Travel"];
+
+/// Number of device profiles.
+pub const MAX_PROFILES: usize = 3;
+
+/// PCs upstream (behind a monitor's USB switch, say) whose settings are
+/// remembered: the profile, modifier layout and screen auto-off in use on
+/// each are put back when it enumerates the bridge again.
+pub const MAX_KNOWN_HOSTS: usize = 4;
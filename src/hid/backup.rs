@@ -0,0 +1,457 @@
+//! Vendor-defined HID interface for exporting and importing the paired
+//! devices (bonds included) between bridges.
+//!
+//! Everything runs over feature reports on the control pipe, so a host tool
+//! needs no driver, only `hidraw` / `HidD_SetFeature`-style access:
+//!
+//! - **Command** (report 1). SET `[op][transfer key ×16][length u16 LE]`
+//!   starts an export ([`OP_EXPORT`]), an import of `length` bytes
+//!   ([`OP_IMPORT`]) or aborts ([`OP_ABORT`]). GET returns the status
+//!   `[phase][outcome][device count][length u16 LE]`, padded.
+//! - **Data** (report 2). Once the status reads [`Phase::Sending`], each GET
+//!   returns the next [`CHUNK_SIZE`] bytes of the export. During
+//!   [`Phase::Receiving`], each SET appends the next chunk of the import.
+//!
+//! Nothing leaves or enters the store until someone at the bridge agrees: an
+//! export, or an import once fully received, waits in [`Phase::Confirming`]
+//! until the user allows it on the device ([`BackupSession::confirm`]) or
+//! turns it down ([`BackupSession::decline`]).
+//!
+//! [`BackupSession`] is the protocol state; the USB control handler feeds it
+//! and the task owning the device store runs the [`Job`]s it hands out, on a
+//! copy of the container. The container itself is built and checked by the
+//! store (`storage::backup`).
+
+/// Command / status feature report ID.
+pub const COMMAND_REPORT_ID: u8 = 1;
+/// Data chunk feature report ID.
+pub const DATA_REPORT_ID: u8 = 2;
+
+/// Command report payload: op + transfer key + length.
+pub const COMMAND_SIZE: usize = 19;
+/// Data report payload.
+pub const CHUNK_SIZE: usize = 63;
+
+pub const OP_EXPORT: u8 = 1;
+pub const OP_IMPORT: u8 = 2;
+pub const OP_ABORT: u8 = 3;
+
+/// USB HID Report Descriptor: the two vendor feature reports.
+#[rustfmt::skip]
+pub const BACKUP_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01,       // Usage (0x01)
+    0xA1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x85, COMMAND_REPORT_ID,
+    0x09, 0x02,       //   Usage (0x02)
+    0x95, COMMAND_SIZE as u8,
+    0xB1, 0x02,       //   Feature (Data, Variable, Absolute)
+    0x85, DATA_REPORT_ID,
+    0x09, 0x03,       //   Usage (0x03)
+    0x95, CHUNK_SIZE as u8,
+    0xB1, 0x02,       //   Feature (Data, Variable, Absolute)
+    0xC0,             // End Collection
+];
+
+/// Where a transfer stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    Idle = 0,
+    /// The store is building the export.
+    Exporting = 1,
+    /// The export is ready to read.
+    Sending = 2,
+    /// Import chunks are being written.
+    Receiving = 3,
+    /// The store is merging the import.
+    Importing = 4,
+    /// Finished; the outcome tells how.
+    Done = 5,
+    /// Waiting for the user to allow the export or import on the bridge.
+    Confirming = 6,
+}
+
+/// How the last transfer ended (or why a command was refused).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outcome {
+    Ok = 0,
+    /// A job was still running.
+    Busy = 1,
+    /// The import is larger than a container can be.
+    TooLarge = 2,
+    /// Not a container, or cut short.
+    Malformed = 3,
+    /// Made by firmware with a newer container version.
+    UnsupportedVersion = 4,
+    /// Tampered with, or sealed under another transfer key.
+    Unauthenticated = 5,
+    /// The store couldn't build the export.
+    Failed = 6,
+    /// An unknown command.
+    Unsupported = 7,
+    /// The user turned the request down on the bridge.
+    Declined = 8,
+}
+
+/// Work for the task owning the device store, once the user allowed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Job {
+    /// Seal the store under [`BackupSession::job`]'s key, then hand the
+    /// container to [`BackupSession::exported`].
+    Export,
+    /// Merge the container [`BackupSession::job`] copied out, then call
+    /// [`BackupSession::imported`].
+    Import,
+}
+
+/// One transfer at a time, over a container buffer of `N` bytes.
+pub struct BackupSession<const N: usize> {
+    phase: Phase,
+    outcome: Outcome,
+    /// The job waiting in [`Phase::Confirming`].
+    pending: Option<Job>,
+    count: u8,
+    key: [u8; 16],
+    data: [u8; N],
+    len: usize,
+    cursor: usize,
+}
+
+impl<const N: usize> BackupSession<N> {
+    pub const fn new() -> Self {
+        Self {
+            phase: Phase::Idle,
+            outcome: Outcome::Ok,
+            pending: None,
+            count: 0,
+            key: [0; 16],
+            data: [0; N],
+            len: 0,
+            cursor: 0,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Handle a SET_REPORT (`data` starts with the report ID). Returns the
+    /// job to ask the user about when the request is complete.
+    pub fn set_report(&mut self, data: &[u8]) -> Option<Job> {
+        match data.split_first() {
+            Some((&COMMAND_REPORT_ID, command)) => self.command(command),
+            Some((&DATA_REPORT_ID, chunk)) => self.receive(chunk),
+            _ => None,
+        }
+    }
+
+    fn command(&mut self, command: &[u8]) -> Option<Job> {
+        if command.len() < COMMAND_SIZE {
+            return None;
+        }
+        let asking = self.phase == Phase::Confirming && command[0] != OP_ABORT;
+        if asking || matches!(self.phase, Phase::Exporting | Phase::Importing) {
+            // The store task runs the job until it reports back, and a
+            // request the user is looking at can only be withdrawn.
+            self.outcome = Outcome::Busy;
+            return None;
+        }
+        self.clear();
+        self.key.copy_from_slice(&command[1..17]);
+        match command[0] {
+            OP_EXPORT => Some(self.ask(Job::Export)),
+            OP_IMPORT => {
+                let len = u16::from_le_bytes([command[17], command[18]]) as usize;
+                if len == 0 || len > N {
+                    self.finish(Outcome::TooLarge);
+                } else {
+                    self.len = len;
+                    self.phase = Phase::Receiving;
+                }
+                None
+            }
+            OP_ABORT => {
+                self.key = [0; 16];
+                None
+            }
+            _ => {
+                self.finish(Outcome::Unsupported);
+                None
+            }
+        }
+    }
+
+    fn receive(&mut self, chunk: &[u8]) -> Option<Job> {
+        if self.phase != Phase::Receiving {
+            return None;
+        }
+        let take = chunk.len().min(CHUNK_SIZE).min(self.len - self.cursor);
+        self.data[self.cursor..self.cursor + take].copy_from_slice(&chunk[..take]);
+        self.cursor += take;
+        if self.cursor < self.len {
+            return None;
+        }
+        Some(self.ask(Job::Import))
+    }
+
+    fn ask(&mut self, job: Job) -> Job {
+        self.phase = Phase::Confirming;
+        self.pending = Some(job);
+        job
+    }
+
+    /// The user allowed `job`. Returns whether it is still the request
+    /// waiting (the host may have withdrawn it); the store task then runs it.
+    pub fn confirm(&mut self, job: Job) -> bool {
+        if self.phase != Phase::Confirming || self.pending != Some(job) {
+            return false;
+        }
+        self.pending = None;
+        self.phase = match job {
+            Job::Export => Phase::Exporting,
+            Job::Import => Phase::Importing,
+        };
+        true
+    }
+
+    /// The user turned `job` down.
+    pub fn decline(&mut self, job: Job) {
+        if self.phase == Phase::Confirming && self.pending == Some(job) {
+            self.finish(Outcome::Declined);
+        }
+    }
+
+    /// Handle a GET_REPORT for `id`, writing the report (ID first) to `buf`.
+    pub fn get_report(&mut self, id: u8, buf: &mut [u8]) -> Option<usize> {
+        match id {
+            COMMAND_REPORT_ID => {
+                let report = buf.get_mut(..1 + COMMAND_SIZE)?;
+                report.fill(0);
+                report[0] = COMMAND_REPORT_ID;
+                report[1] = self.phase as u8;
+                report[2] = self.outcome as u8;
+                report[3] = self.count;
+                report[4..6].copy_from_slice(&(self.len as u16).to_le_bytes());
+                Some(report.len())
+            }
+            DATA_REPORT_ID => {
+                if self.phase != Phase::Sending {
+                    return None;
+                }
+                let report = buf.get_mut(..1 + CHUNK_SIZE)?;
+                report.fill(0);
+                report[0] = DATA_REPORT_ID;
+                let take = CHUNK_SIZE.min(self.len - self.cursor);
+                report[1..1 + take].copy_from_slice(&self.data[self.cursor..self.cursor + take]);
+                self.cursor += take;
+                if self.cursor >= self.len {
+                    self.finish(Outcome::Ok);
+                }
+                Some(report.len())
+            }
+            _ => None,
+        }
+    }
+
+    /// The running job's transfer key, with (for an import) the container
+    /// copied into `buf`, so the store task seals and opens it without
+    /// holding the session. Returns the key and the container's length.
+    pub fn job(&self, buf: &mut [u8; N]) -> ([u8; 16], usize) {
+        let len = match self.phase {
+            Phase::Importing => self.len,
+            _ => 0,
+        };
+        buf[..len].copy_from_slice(&self.data[..len]);
+        (self.key, len)
+    }
+
+    /// The store built the export `container` (empty = it couldn't), holding
+    /// `count` devices.
+    pub fn exported(&mut self, container: &[u8], count: usize) {
+        if self.phase != Phase::Exporting {
+            return;
+        }
+        self.key = [0; 16];
+        let len = container.len();
+        if len == 0 || len > N {
+            self.finish(Outcome::Failed);
+            return;
+        }
+        self.data[..len].copy_from_slice(container);
+        self.len = len;
+        self.cursor = 0;
+        self.count = count.min(u8::MAX as usize) as u8;
+        self.phase = Phase::Sending;
+    }
+
+    /// The store merged the import: how many devices, or why not.
+    pub fn imported(&mut self, result: Result<usize, Outcome>) {
+        if self.phase != Phase::Importing {
+            return;
+        }
+        match result {
+            Ok(count) => {
+                self.count = count.min(u8::MAX as usize) as u8;
+                self.finish(Outcome::Ok);
+            }
+            Err(outcome) => self.finish(outcome),
+        }
+    }
+
+    /// End the transfer, wiping the key and container.
+    fn finish(&mut self, outcome: Outcome) {
+        let count = self.count;
+        let len = self.len;
+        self.clear();
+        self.phase = Phase::Done;
+        self.outcome = outcome;
+        self.count = count;
+        self.len = len;
+    }
+
+    fn clear(&mut self) {
+        self.phase = Phase::Idle;
+        self.outcome = Outcome::Ok;
+        self.pending = None;
+        self.count = 0;
+        self.key = [0; 16];
+        self.data.fill(0);
+        self.len = 0;
+        self.cursor = 0;
+    }
+}
+
+impl<const N: usize> Default for BackupSession<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Session = BackupSession<100>;
+
+    fn command(op: u8, key: u8, len: u16) -> [u8; 1 + COMMAND_SIZE] {
+        let mut report = [0u8; 1 + COMMAND_SIZE];
+        report[0] = COMMAND_REPORT_ID;
+        report[1] = op;
+        report[2..18].fill(key);
+        report[18..].copy_from_slice(&len.to_le_bytes());
+        report
+    }
+
+    fn status(session: &mut Session) -> (u8, u8, u8, u16) {
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            session.get_report(COMMAND_REPORT_ID, &mut buf),
+            Some(1 + COMMAND_SIZE)
+        );
+        (buf[1], buf[2], buf[3], u16::from_le_bytes([buf[4], buf[5]]))
+    }
+
+    #[test]
+    fn export_is_read_back_in_chunks() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.set_report(&command(OP_EXPORT, 0xAA, 0)),
+            Some(Job::Export)
+        );
+        assert_eq!(status(&mut session).0, Phase::Confirming as u8);
+        assert!(session.confirm(Job::Export));
+        let mut buf = [0u8; 100];
+        assert_eq!(session.job(&mut buf), ([0xAA; 16], 0));
+        let container: [u8; 70] = core::array::from_fn(|i| i as u8);
+        session.exported(&container, 2);
+        assert_eq!(status(&mut session), (Phase::Sending as u8, 0, 2, 70));
+
+        let mut out = heapless::Vec::<u8, 128>::new();
+        let mut report = [0u8; 64];
+        while session.phase() == Phase::Sending {
+            assert_eq!(session.get_report(DATA_REPORT_ID, &mut report), Some(64));
+            out.extend_from_slice(&report[1..]).unwrap();
+        }
+        assert_eq!(out[..70], core::array::from_fn::<u8, 70, _>(|i| i as u8));
+        assert_eq!(status(&mut session), (Phase::Done as u8, 0, 2, 70));
+        assert_eq!(session.get_report(DATA_REPORT_ID, &mut report), None);
+    }
+
+    #[test]
+    fn import_is_written_in_chunks_then_handed_to_the_store() {
+        let mut session = Session::new();
+        assert_eq!(session.set_report(&command(OP_IMPORT, 0x55, 70)), None);
+        let mut chunk = [DATA_REPORT_ID; 1 + CHUNK_SIZE];
+        chunk[1..].fill(0x11);
+        assert_eq!(session.set_report(&chunk), None);
+        chunk[1..].fill(0x22);
+        assert_eq!(session.set_report(&chunk), Some(Job::Import));
+        assert!(session.confirm(Job::Import));
+
+        let mut buf = [0u8; 100];
+        assert_eq!(session.job(&mut buf), ([0x55; 16], 70));
+        assert_eq!(buf[62..64], [0x11, 0x22]);
+
+        // Busy until the store reports back.
+        assert_eq!(session.set_report(&command(OP_EXPORT, 0, 0)), None);
+        assert_eq!(status(&mut session).1, Outcome::Busy as u8);
+        session.imported(Ok(3));
+        assert_eq!(status(&mut session), (Phase::Done as u8, 0, 3, 70));
+        assert_eq!(session.job(&mut buf).0, [0; 16]);
+    }
+
+    #[test]
+    fn nothing_runs_until_the_user_allows_it() {
+        let mut session = Session::new();
+        session.set_report(&command(OP_EXPORT, 0xAA, 0));
+        // Only the request on screen can be allowed, and the host can only
+        // withdraw it meanwhile.
+        assert!(!session.confirm(Job::Import));
+        assert_eq!(session.set_report(&command(OP_IMPORT, 1, 10)), None);
+        assert_eq!(
+            status(&mut session),
+            (Phase::Confirming as u8, Outcome::Busy as u8, 0, 0)
+        );
+        session.exported(&[1; 10], 1);
+        assert_eq!(status(&mut session).0, Phase::Confirming as u8);
+
+        session.decline(Job::Export);
+        assert_eq!(
+            status(&mut session),
+            (Phase::Done as u8, Outcome::Declined as u8, 0, 0)
+        );
+        assert!(!session.confirm(Job::Export));
+        assert_eq!(session.job(&mut [0; 100]).0, [0; 16]);
+
+        // A withdrawn request can't be allowed afterwards.
+        session.set_report(&command(OP_IMPORT, 1, 10));
+        assert_eq!(session.set_report(&[DATA_REPORT_ID; 11]), Some(Job::Import));
+        session.set_report(&command(OP_ABORT, 1, 0));
+        assert!(!session.confirm(Job::Import));
+        assert_eq!(status(&mut session).0, Phase::Idle as u8);
+    }
+
+    #[test]
+    fn refused_imports_and_aborts_report_their_outcome() {
+        let mut session = Session::new();
+        session.set_report(&command(OP_IMPORT, 1, 101));
+        assert_eq!(status(&mut session).1, Outcome::TooLarge as u8);
+
+        session.set_report(&command(OP_IMPORT, 1, 10));
+        session.set_report(&[DATA_REPORT_ID; 11]);
+        session.confirm(Job::Import);
+        session.imported(Err(Outcome::Unauthenticated));
+        assert_eq!(status(&mut session).1, Outcome::Unauthenticated as u8);
+
+        session.set_report(&command(OP_IMPORT, 1, 10));
+        session.set_report(&command(OP_ABORT, 1, 0));
+        assert_eq!(status(&mut session).0, Phase::Idle as u8);
+        assert_eq!(session.job(&mut [0; 100]).0, [0; 16]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Host-visible battery levels of the connected BLE peripherals.
+//!
+//! Each BLE slot's Battery Service level is re-exposed to the USB host as a HID
+//! **Battery Strength** usage (Generic Device Controls 0x06 / 0x20), which
+//! operating systems pick up natively (e.g. Linux creates a power-supply entry
+//! per report). Each slot gets its own application collection and report ID so
+//! the levels stay distinct.
+
+use crate::ble::coordinator::MAX_CONNECTIONS;
+
+/// Levels at or below this percentage trigger the low-battery warning.
+pub const LOW_BATTERY_PERCENT: u8 = 15;
+
+/// Size of one battery input report: report ID + level.
+pub const BATTERY_REPORT_SIZE: usize = 2;
+
+/// One slot's application collection; byte [`COLLECTION_REPORT_ID_OFFSET`]
+/// is patched with the slot's report ID.
+#[rustfmt::skip]
+const SLOT_COLLECTION: [u8; 21] = [
+    0x05, 0x06,       // Usage Page (Generic Device Controls)
+    0x09, 0x20,       // Usage (Battery Strength)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, 0x00,       //   Report ID (slot + 1)
+    0x09, 0x20,       //   Usage (Battery Strength)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x64,       //   Logical Maximum (100)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0xC0,             // End Collection
+];
+
+const COLLECTION_REPORT_ID_OFFSET: usize = 7;
+
+const DESCRIPTOR_LEN: usize = SLOT_COLLECTION.len() * MAX_CONNECTIONS;
+
+const fn build_descriptor() -> [u8; DESCRIPTOR_LEN] {
+    let mut out = [0u8; DESCRIPTOR_LEN];
+    let mut slot = 0;
+    while slot < MAX_CONNECTIONS {
+        let base = slot * SLOT_COLLECTION.len();
+        let mut i = 0;
+        while i < SLOT_COLLECTION.len() {
+            out[base + i] = SLOT_COLLECTION[i];
+            i += 1;
+        }
+        out[base + COLLECTION_REPORT_ID_OFFSET] = slot as u8 + 1;
+        slot += 1;
+    }
+    out
+}
+
+/// USB HID Report Descriptor: one Battery Strength input per BLE slot,
+/// report ID = slot + 1, logical range 0–100 %.
+pub const BATTERY_REPORT_DESCRIPTOR: &[u8] = &build_descriptor();
+
+/// One slot's battery level, as sent to the USB host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryReport {
+    pub slot: usize,
+    /// Percentage, 0–100.
+    pub level: u8,
+}
+
+impl BatteryReport {
+    /// Build a report, clamping the level to the BAS range (0–100 %). `None`
+    /// for a slot the descriptor doesn't declare.
+    pub fn new(slot: usize, level: u8) -> Option<Self> {
+        (slot < MAX_CONNECTIONS).then_some(Self {
+            slot,
+            level: level.min(100),
+        })
+    }
+
+    /// Serialize to USB HID report bytes (report ID first).
+    pub fn serialize(&self, buf: &mut [u8]) -> usize {
+        if buf.len() < BATTERY_REPORT_SIZE {
+            return 0;
+        }
+        buf[0] = self.slot as u8 + 1;
+        buf[1] = self.level;
+        BATTERY_REPORT_SIZE
+    }
+}
+
+/// Did the level just drop into the low range? Only the crossing warns, so a
+/// device hovering at a low level doesn't nag on every notification.
+pub fn became_low(previous: Option<u8>, level: u8) -> bool {
+    level <= LOW_BATTERY_PERCENT && previous.is_none_or(|p| p > LOW_BATTERY_PERCENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_carries_slot_id_and_level() {
+        let mut buf = [0u8; BATTERY_REPORT_SIZE];
+        let n = BatteryReport::new(1, 42).unwrap().serialize(&mut buf);
+        assert_eq!(n, BATTERY_REPORT_SIZE);
+        assert_eq!(buf, [2, 42]);
+    }
+
+    #[test]
+    fn level_is_clamped_and_slot_checked() {
+        assert_eq!(BatteryReport::new(0, 250).unwrap().level, 100);
+        assert!(BatteryReport::new(MAX_CONNECTIONS, 50).is_none());
+    }
+
+    #[test]
+    fn descriptor_declares_one_report_per_slot() {
+        let report_ids = BATTERY_REPORT_DESCRIPTOR
+            .windows(2)
+            .filter(|w| w[0] == 0x85)
+            .count();
+        assert_eq!(report_ids, MAX_CONNECTIONS);
+        for slot in 0..MAX_CONNECTIONS {
+            let at = slot * SLOT_COLLECTION.len() + COLLECTION_REPORT_ID_OFFSET;
+            assert_eq!(BATTERY_REPORT_DESCRIPTOR[at - 1], 0x85);
+            assert_eq!(BATTERY_REPORT_DESCRIPTOR[at], slot as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn low_warning_only_on_crossing() {
+        assert!(became_low(None, 10));
+        assert!(became_low(Some(50), LOW_BATTERY_PERCENT));
+        assert!(!became_low(Some(12), 10), "already low");
+        assert!(!became_low(Some(50), 40));
+        assert!(!became_low(None, 80));
+    }
+}
@@ -0,0 +1,66 @@
+//! Bridge hotkeys: chords on a BLE keyboard that the bridge acts on itself
+//! instead of passing them to the PC.
+//!
+//! Ctrl+Alt+Shift+F1, F2, … switch to the first, second, … device profile
+//! (`config::PROFILE_NAMES`). Either side's modifiers count; GUI must be up,
+//! and the F key must be the only key down, so the chord is hard to hit by
+//! accident. The report carrying the chord is swallowed; the modifiers'
+//! presses and releases still reach the PC, which sees no key in between.
+
+use crate::config::MAX_PROFILES;
+use crate::hid::keyboard::KeyboardReport;
+
+/// Ctrl, Shift and Alt, folded onto the left-hand modifier bits.
+const CHORD_MODIFIERS: u8 = 0x07;
+
+/// Usage ID of F1 (F2.. follow on).
+const KEY_F1: u8 = 0x3A;
+
+/// The profile `report`'s chord switches to, if it is one.
+pub fn profile_hotkey(report: &KeyboardReport) -> Option<usize> {
+    if (report.modifier | (report.modifier >> 4)) & 0x0F != CHORD_MODIFIERS {
+        return None;
+    }
+    let mut keys = report.keycodes.iter().filter(|&&k| k != 0);
+    let key = *keys.next()?;
+    if keys.next().is_some() {
+        return None;
+    }
+    let profile = key.checked_sub(KEY_F1)? as usize;
+    (profile < MAX_PROFILES).then_some(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(modifier: u8, keycodes: &[u8]) -> KeyboardReport {
+        let mut report = KeyboardReport {
+            modifier,
+            ..KeyboardReport::empty()
+        };
+        report.keycodes[..keycodes.len()].copy_from_slice(keycodes);
+        report
+    }
+
+    #[test]
+    fn ctrl_alt_shift_f_keys_pick_profiles() {
+        assert_eq!(profile_hotkey(&report(0x07, &[KEY_F1])), Some(0));
+        // Right-hand modifiers, and a mix, count too.
+        assert_eq!(profile_hotkey(&report(0x70, &[0, KEY_F1 + 1])), Some(1));
+        assert_eq!(profile_hotkey(&report(0x25, &[KEY_F1])), Some(0));
+        assert_eq!(
+            profile_hotkey(&report(0x07, &[KEY_F1 + MAX_PROFILES as u8])),
+            None
+        );
+    }
+
+    #[test]
+    fn other_chords_pass_through() {
+        assert_eq!(profile_hotkey(&report(0x05, &[KEY_F1])), None);
+        assert_eq!(profile_hotkey(&report(0x0F, &[KEY_F1])), None);
+        assert_eq!(profile_hotkey(&report(0x07, &[KEY_F1, 0x04])), None);
+        assert_eq!(profile_hotkey(&report(0x07, &[0x04])), None);
+        assert_eq!(profile_hotkey(&report(0x07, &[])), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Modifier layouts: which modifier the PC sees for each one pressed on the
+//! BLE keyboard.
+//!
+//! A PC keyboard on a Mac has Alt where Cmd belongs (next to the space bar),
+//! and people switching between a Mac and a PC often want Ctrl to act as Cmd
+//! so their shortcuts stay under the same finger. The layout is applied to
+//! keyboard reports on their way to the USB host, after the bridge's own
+//! hotkeys were matched, so it never changes what the hotkeys are.
+//!
+//! [`ModifierLayout::Auto`] follows the host's operating system as guessed
+//! from its enumeration ([`crate::usb::host_id`]): on a Mac, Alt and GUI trade
+//! places and Print Screen, Scroll Lock and Pause become F13–F15, where Apple
+//! keyboards have them; elsewhere nothing changes. Any other layout is a
+//! manual override and applies on every host, without the key changes.
+
+use crate::hid::keyboard::KeyboardReport;
+use crate::usb::host_id::HostOs;
+
+/// Left Ctrl, Alt and GUI in the modifier byte (the right-hand ones are the
+/// same bits shifted up by four).
+const CTRL: u8 = 0x01;
+const ALT: u8 = 0x04;
+const GUI: u8 = 0x08;
+
+/// Keys moved on a Mac: Print Screen, Scroll Lock and Pause to F13–F15.
+const MAC_KEYS: [(u8, u8); 3] = [(0x46, 0x68), (0x47, 0x69), (0x48, 0x6A)];
+
+/// How modifiers are rearranged for the PC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModifierLayout {
+    /// Modifiers pass through unchanged.
+    Standard,
+    /// Alt and GUI (Option and Cmd) trade places, matching a Mac's key order.
+    SwapAltGui,
+    /// Ctrl and GUI trade places, so PC shortcuts work as Cmd shortcuts.
+    SwapCtrlGui,
+    /// Whatever suits the host's operating system (see the module docs).
+    #[default]
+    Auto,
+}
+
+impl ModifierLayout {
+    /// Every layout, in the order of their stored bytes.
+    pub const ALL: [ModifierLayout; 4] = [
+        ModifierLayout::Standard,
+        ModifierLayout::SwapAltGui,
+        ModifierLayout::SwapCtrlGui,
+        ModifierLayout::Auto,
+    ];
+
+    /// The layout stored as `byte`, if it is one.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// The byte this layout is stored as.
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// What this layout does on a host running `os`.
+    pub fn layer(self, os: HostOs) -> Layer {
+        match (self, os) {
+            (ModifierLayout::Auto, HostOs::MacOs) => Layer {
+                modifiers: ModifierLayout::SwapAltGui,
+                mac_keys: true,
+            },
+            (ModifierLayout::Auto, _) => Layer::default(),
+            (modifiers, _) => Layer {
+                modifiers,
+                mac_keys: false,
+            },
+        }
+    }
+}
+
+/// The changes made to keyboard reports for the current host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Layer {
+    /// Never [`ModifierLayout::Auto`]: that is resolved by then.
+    pub modifiers: ModifierLayout,
+    /// Move Print Screen, Scroll Lock and Pause to F13–F15.
+    pub mac_keys: bool,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            modifiers: ModifierLayout::Standard,
+            mac_keys: false,
+        }
+    }
+}
+
+impl Layer {
+    /// Rearrange `report` for the PC.
+    pub fn apply(&self, report: &mut KeyboardReport) {
+        report.modifier = match self.modifiers {
+            ModifierLayout::Standard | ModifierLayout::Auto => report.modifier,
+            ModifierLayout::SwapAltGui => swap(report.modifier, ALT, GUI),
+            ModifierLayout::SwapCtrlGui => swap(report.modifier, CTRL, GUI),
+        };
+        if self.mac_keys {
+            for key in &mut report.keycodes {
+                if let Some(&(_, to)) = MAC_KEYS.iter().find(|(from, _)| from == key) {
+                    *key = to;
+                }
+            }
+        }
+    }
+}
+
+/// Swap modifier bits `a` and `b`, on both sides of the keyboard.
+fn swap(modifier: u8, a: u8, b: u8) -> u8 {
+    let (a, b) = (a | (a << 4), b | (b << 4));
+    let moved_ab = (modifier & a) << (b.trailing_zeros() - a.trailing_zeros());
+    let moved_ba = (modifier & b) >> (b.trailing_zeros() - a.trailing_zeros());
+    (modifier & !(a | b)) | moved_ab | moved_ba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(layer: Layer, modifier: u8, key: u8) -> (u8, u8) {
+        let mut report = KeyboardReport {
+            modifier,
+            ..KeyboardReport::empty()
+        };
+        report.keycodes[1] = key;
+        layer.apply(&mut report);
+        (report.modifier, report.keycodes[1])
+    }
+
+    fn fixed(modifiers: ModifierLayout) -> Layer {
+        modifiers.layer(HostOs::Unknown)
+    }
+
+    #[test]
+    fn layouts_swap_their_pair_on_both_sides() {
+        // Left Alt+Shift, right Ctrl.
+        let modifier = ALT | 0x02 | (CTRL << 4);
+        assert_eq!(
+            applied(fixed(ModifierLayout::Standard), modifier, 0x06),
+            (modifier, 0x06)
+        );
+        assert_eq!(
+            applied(fixed(ModifierLayout::SwapAltGui), modifier, 0x06),
+            (GUI | 0x02 | (CTRL << 4), 0x06)
+        );
+        assert_eq!(
+            applied(fixed(ModifierLayout::SwapCtrlGui), modifier, 0x06),
+            (ALT | 0x02 | (GUI << 4), 0x06)
+        );
+        // Both of a pair held stay held.
+        assert_eq!(
+            applied(fixed(ModifierLayout::SwapAltGui), ALT | GUI, 0),
+            (ALT | GUI, 0)
+        );
+    }
+
+    #[test]
+    fn auto_follows_the_host_and_an_override_does_not() {
+        let mac = ModifierLayout::Auto.layer(HostOs::MacOs);
+        assert_eq!(applied(mac, ALT, 0x46), (GUI, 0x68));
+        assert_eq!(applied(mac, 0, 0x48), (0, 0x6A));
+        for os in [HostOs::Unknown, HostOs::Windows, HostOs::Linux] {
+            assert_eq!(ModifierLayout::Auto.layer(os), Layer::default());
+        }
+        // A manual layout is the same on a Mac, without the key changes.
+        let manual = ModifierLayout::SwapCtrlGui.layer(HostOs::MacOs);
+        assert_eq!(applied(manual, CTRL, 0x46), (GUI, 0x46));
+        assert_eq!(
+            ModifierLayout::Standard.layer(HostOs::MacOs),
+            Layer::default()
+        );
+    }
+
+    #[test]
+    fn layout_bytes_round_trip() {
+        for layout in ModifierLayout::ALL {
+            assert_eq!(ModifierLayout::from_byte(layout.to_byte()), Some(layout));
+        }
+        assert_eq!(ModifierLayout::from_byte(4), None);
+    }
+}
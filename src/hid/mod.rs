@@ -5,10 +5,16 @@
 //! is no separate host reimplementation. `defmt::Format` is derived only when
 //! the `defmt` feature is on (firmware builds).
 
+pub mod backup;
+pub mod battery;
 pub mod coalesce;
 pub mod consumer;
+pub mod hotkey;
 pub mod keyboard;
+pub mod layout;
 pub mod mouse;
+pub mod protocol_fallback;
+pub mod quirks;
 pub mod report_protocol;
 
 use report_protocol::{HidDescriptor, ReportKind};
@@ -81,9 +87,13 @@ pub fn classify_notification_with_hint(
             return classify_report(report_id, &data[1..]);
         }
 
-        // Descriptor present but no report IDs — boot-protocol device.
-        if let Some(report) = classify_report(0, data) {
-            return Some(report);
+        // Descriptor present but no report IDs — boot-protocol device. Only
+        // trust that when the whole map was seen: a truncated map may simply
+        // have lost its Report ID items, so use the heuristic instead.
+        if desc.complete {
+            if let Some(report) = classify_report(0, data) {
+                return Some(report);
+            }
         }
     }
 
@@ -0,0 +1,83 @@
+//! When to give up on Report Protocol and fall back to Boot Protocol.
+//!
+//! Some cheap presenters and keyboards ship a Report Map that is broken or
+//! unreadable, so their Report Protocol notifications never classify. They
+//! still work through the fixed-format Boot Keyboard/Mouse Input
+//! characteristics. This is the hardware-free decision half; switching the
+//! peer's Protocol Mode and re-subscribing lives in `ble::hid_client`.
+
+/// Consecutive unclassifiable notifications, with no good one in between,
+/// before we switch to Boot Protocol.
+pub const BOOT_FALLBACK_AFTER: u8 = 8;
+
+/// Watches notification classification on one link.
+///
+/// A link that has produced even one classifiable report is treated as
+/// working: vendor reports we don't understand are expected on real devices
+/// and must not flip a healthy keyboard into Boot Protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FallbackMonitor {
+    failures: u8,
+    healthy: bool,
+}
+
+impl FallbackMonitor {
+    pub const fn new() -> Self {
+        Self {
+            failures: 0,
+            healthy: false,
+        }
+    }
+
+    /// Record one notification's classification result. Returns `true`
+    /// exactly once, when the link should switch to Boot Protocol.
+    pub fn record(&mut self, classified: bool) -> bool {
+        if self.healthy {
+            return false;
+        }
+        if classified {
+            self.healthy = true;
+            self.failures = 0;
+            return false;
+        }
+        self.failures = self.failures.saturating_add(1);
+        self.failures == BOOT_FALLBACK_AFTER
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_after_consecutive_failures() {
+        let mut m = FallbackMonitor::new();
+        for _ in 1..BOOT_FALLBACK_AFTER {
+            assert!(!m.record(false));
+        }
+        assert!(m.record(false));
+    }
+
+    #[test]
+    fn triggers_only_once() {
+        let mut m = FallbackMonitor::new();
+        for _ in 0..BOOT_FALLBACK_AFTER {
+            m.record(false);
+        }
+        for _ in 0..2 * BOOT_FALLBACK_AFTER as usize {
+            assert!(!m.record(false));
+        }
+    }
+
+    #[test]
+    fn one_good_report_marks_link_healthy() {
+        let mut m = FallbackMonitor::new();
+        for _ in 1..BOOT_FALLBACK_AFTER {
+            m.record(false);
+        }
+        assert!(!m.record(true));
+        for _ in 0..2 * BOOT_FALLBACK_AFTER as usize {
+            assert!(!m.record(false));
+        }
+    }
+}
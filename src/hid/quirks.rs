@@ -0,0 +1,309 @@
+//! Per-device workarounds, keyed by the Device Information Service PnP ID.
+//!
+//! Some peripherals get HID wrong in ways the generic path can't detect: a
+//! Report Map that labels a report ID as the wrong kind, a keyboard that puts
+//! garbage in the reserved byte, a mouse whose wheel runs backwards, or keys
+//! that send the wrong usage. [`QUIRKS`] lists those devices by vendor/product
+//! and says what to override. Matching and applying a quirk is pure; reading
+//! the PnP ID (0x2A50) and deciding when to apply lives in `ble::hid_client`.
+
+use super::report_protocol::{HidDescriptor, ReportKind};
+use super::HidReport;
+
+/// PnP ID Vendor ID Source: assigned by the Bluetooth SIG.
+pub const VENDOR_SOURCE_BLUETOOTH: u8 = 0x01;
+/// PnP ID Vendor ID Source: assigned by the USB Implementers Forum.
+pub const VENDOR_SOURCE_USB: u8 = 0x02;
+
+/// Size of the PnP ID characteristic value.
+pub const PNP_ID_SIZE: usize = 7;
+
+/// Device Information Service PnP ID (0x2A50).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PnpId {
+    pub vendor_id_source: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub product_version: u16,
+}
+
+impl PnpId {
+    /// Parse the characteristic value: source (u8), then vendor, product and
+    /// version (u16 LE each). `None` if it's short or the source is reserved.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < PNP_ID_SIZE {
+            return None;
+        }
+        let vendor_id_source = data[0];
+        if !matches!(
+            vendor_id_source,
+            VENDOR_SOURCE_BLUETOOTH | VENDOR_SOURCE_USB
+        ) {
+            return None;
+        }
+        Some(Self {
+            vendor_id_source,
+            vendor_id: u16::from_le_bytes([data[1], data[2]]),
+            product_id: u16::from_le_bytes([data[3], data[4]]),
+            product_version: u16::from_le_bytes([data[5], data[6]]),
+        })
+    }
+
+    /// Serialize to the characteristic wire format.
+    pub fn serialize(&self) -> [u8; PNP_ID_SIZE] {
+        let v = self.vendor_id.to_le_bytes();
+        let p = self.product_id.to_le_bytes();
+        let r = self.product_version.to_le_bytes();
+        [self.vendor_id_source, v[0], v[1], p[0], p[1], r[0], r[1]]
+    }
+}
+
+/// What to override for one device. Every field defaults to "no change".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Report kind by report ID, replacing whatever the Report Map claims.
+    pub report_kinds: &'static [(u8, ReportKind)],
+    /// Report Protocol never works on this device; go straight to Boot.
+    pub force_boot: bool,
+    /// The keyboard fills the reserved byte; zero it before it reaches USB.
+    pub clear_reserved: bool,
+    /// The wheel reports the opposite direction.
+    pub invert_wheel: bool,
+    /// Keyboard usages the device sends wrongly: `(sent, meant)`.
+    pub keymap: &'static [(u8, u8)],
+}
+
+impl Quirks {
+    pub const NONE: Self = Self {
+        report_kinds: &[],
+        force_boot: false,
+        clear_reserved: false,
+        invert_wheel: false,
+        keymap: &[],
+    };
+
+    /// Apply the report-kind overrides to a parsed Report Map. A device whose
+    /// map couldn't be read or parsed still gets a descriptor carrying just
+    /// the overrides (marked incomplete).
+    pub fn fix_descriptor(&self, descriptor: Option<HidDescriptor>) -> Option<HidDescriptor> {
+        if self.report_kinds.is_empty() {
+            return descriptor;
+        }
+        let mut desc = descriptor.unwrap_or(HidDescriptor {
+            has_keyboard: false,
+            has_mouse: false,
+            has_consumer: false,
+            keyboard_report_id: None,
+            mouse_report_id: None,
+            consumer_report_id: None,
+            complete: false,
+        });
+        for &(id, kind) in self.report_kinds {
+            // The ID belongs to `kind` now, not to whatever the map said.
+            for slot in [
+                &mut desc.keyboard_report_id,
+                &mut desc.mouse_report_id,
+                &mut desc.consumer_report_id,
+            ] {
+                if *slot == Some(id) {
+                    *slot = None;
+                }
+            }
+            match kind {
+                ReportKind::Keyboard => {
+                    desc.has_keyboard = true;
+                    desc.keyboard_report_id = Some(id);
+                }
+                ReportKind::Mouse => {
+                    desc.has_mouse = true;
+                    desc.mouse_report_id = Some(id);
+                }
+                ReportKind::Consumer => {
+                    desc.has_consumer = true;
+                    desc.consumer_report_id = Some(id);
+                }
+            }
+        }
+        Some(desc)
+    }
+
+    /// Fix up one classified report before it is forwarded to USB.
+    pub fn apply(&self, report: &mut HidReport) {
+        match report {
+            HidReport::Keyboard(k) => {
+                if self.clear_reserved {
+                    k.reserved = 0;
+                }
+                for code in k.keycodes.iter_mut() {
+                    if let Some(&(_, meant)) = self.keymap.iter().find(|(sent, _)| sent == code) {
+                        *code = meant;
+                    }
+                }
+            }
+            HidReport::Mouse(m) => {
+                if self.invert_wheel {
+                    m.wheel = m.wheel.saturating_neg();
+                }
+            }
+            HidReport::Consumer(_) => {}
+        }
+    }
+}
+
+/// One quirks-table row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuirkEntry {
+    pub vendor_id_source: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub quirks: Quirks,
+}
+
+/// Known misbehaving devices. Add a row per device (matched on vendor ID
+/// source, vendor ID and product ID; any product version).
+pub const QUIRKS: &[QuirkEntry] = &[];
+
+/// Find the quirks for a device in `table`.
+pub fn lookup_in(table: &'static [QuirkEntry], pnp: &PnpId) -> Option<&'static Quirks> {
+    table
+        .iter()
+        .find(|e| {
+            e.vendor_id_source == pnp.vendor_id_source
+                && e.vendor_id == pnp.vendor_id
+                && e.product_id == pnp.product_id
+        })
+        .map(|e| &e.quirks)
+}
+
+/// Find the quirks for a device in [`QUIRKS`].
+pub fn lookup(pnp: &PnpId) -> Option<&'static Quirks> {
+    lookup_in(QUIRKS, pnp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::keyboard::KeyboardReport;
+    use crate::hid::mouse::MouseReport;
+
+    const TABLE: &[QuirkEntry] = &[
+        QuirkEntry {
+            vendor_id_source: VENDOR_SOURCE_USB,
+            vendor_id: 0x1234,
+            product_id: 0x0001,
+            quirks: Quirks {
+                report_kinds: &[(2, ReportKind::Keyboard)],
+                force_boot: false,
+                clear_reserved: true,
+                invert_wheel: false,
+                // Device sends Keyboard Application (0x65) for its Power key.
+                keymap: &[(0x65, 0x66)],
+            },
+        },
+        QuirkEntry {
+            vendor_id_source: VENDOR_SOURCE_BLUETOOTH,
+            vendor_id: 0x1234,
+            product_id: 0x0001,
+            quirks: Quirks {
+                invert_wheel: true,
+                ..Quirks::NONE
+            },
+        },
+    ];
+
+    fn pnp(source: u8, vendor_id: u16, product_id: u16) -> PnpId {
+        PnpId {
+            vendor_id_source: source,
+            vendor_id,
+            product_id,
+            product_version: 0x0100,
+        }
+    }
+
+    #[test]
+    fn pnp_id_round_trips() {
+        let id = pnp(VENDOR_SOURCE_USB, 0x046D, 0xB023);
+        assert_eq!(PnpId::parse(&id.serialize()), Some(id));
+        assert_eq!(
+            PnpId::parse(&[0x02, 0x6D, 0x04, 0x23, 0xB0, 0x00, 0x01]),
+            Some(id)
+        );
+    }
+
+    #[test]
+    fn pnp_id_rejects_short_or_reserved_source() {
+        assert_eq!(PnpId::parse(&[0x02, 0x6D, 0x04, 0x23, 0xB0, 0x00]), None);
+        assert_eq!(
+            PnpId::parse(&[0x00, 0x6D, 0x04, 0x23, 0xB0, 0x00, 0x01]),
+            None
+        );
+    }
+
+    #[test]
+    fn lookup_matches_source_vendor_and_product() {
+        let usb = lookup_in(TABLE, &pnp(VENDOR_SOURCE_USB, 0x1234, 0x0001)).unwrap();
+        assert!(usb.clear_reserved);
+        let sig = lookup_in(TABLE, &pnp(VENDOR_SOURCE_BLUETOOTH, 0x1234, 0x0001)).unwrap();
+        assert!(sig.invert_wheel);
+        assert!(lookup_in(TABLE, &pnp(VENDOR_SOURCE_USB, 0x1234, 0x0002)).is_none());
+    }
+
+    #[test]
+    fn report_kind_override_moves_the_id() {
+        let quirks = &TABLE[0].quirks;
+        let parsed = HidDescriptor {
+            has_keyboard: true,
+            has_mouse: true,
+            has_consumer: false,
+            keyboard_report_id: Some(1),
+            mouse_report_id: Some(2),
+            consumer_report_id: None,
+            complete: true,
+        };
+        let fixed = quirks.fix_descriptor(Some(parsed)).unwrap();
+        assert_eq!(fixed.report_kind_for_id(2), Some(ReportKind::Keyboard));
+        assert_eq!(fixed.mouse_report_id, None);
+        assert!(fixed.complete);
+    }
+
+    #[test]
+    fn override_without_report_map_builds_incomplete_descriptor() {
+        let fixed = TABLE[0].quirks.fix_descriptor(None).unwrap();
+        assert_eq!(fixed.report_kind_for_id(2), Some(ReportKind::Keyboard));
+        assert!(!fixed.complete);
+        assert_eq!(Quirks::NONE.fix_descriptor(None), None);
+    }
+
+    #[test]
+    fn keyboard_fixups_clear_reserved_and_remap() {
+        let mut report = HidReport::Keyboard(KeyboardReport {
+            modifier: 0,
+            reserved: 0x5A,
+            keycodes: [0x04, 0x65, 0, 0, 0, 0],
+        });
+        TABLE[0].quirks.apply(&mut report);
+        let HidReport::Keyboard(k) = report else {
+            panic!("still a keyboard report");
+        };
+        assert_eq!(k.reserved, 0);
+        assert_eq!(k.keycodes, [0x04, 0x66, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn mouse_wheel_inverted() {
+        let mut report = HidReport::Mouse(MouseReport {
+            buttons: 0,
+            x: 3,
+            y: -2,
+            wheel: i8::MIN,
+            pan: 0,
+        });
+        TABLE[1].quirks.apply(&mut report);
+        let HidReport::Mouse(m) = report else {
+            panic!("still a mouse report");
+        };
+        assert_eq!(m.wheel, i8::MAX);
+        assert_eq!((m.x, m.y), (3, -2));
+    }
+}
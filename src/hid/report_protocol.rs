@@ -53,6 +53,17 @@ impl From<u8> for ReportType {
     }
 }
 
+impl From<ReportType> for u8 {
+    fn from(value: ReportType) -> Self {
+        match value {
+            ReportType::Input => 1,
+            ReportType::Output => 2,
+            ReportType::Feature => 3,
+            ReportType::Other(other) => other,
+        }
+    }
+}
+
 /// Parsed HID **Report Reference** descriptor (UUID `0x2908`).
 ///
 /// Each HID Report characteristic (`0x2A4D`) carries one of these, identifying
@@ -143,7 +154,7 @@ impl From<u16> for DesktopUsage {
 }
 
 /// Parsed HID descriptor.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct HidDescriptor {
     /// Does this device have a keyboard report?
     pub has_keyboard: bool,
@@ -157,6 +168,10 @@ pub struct HidDescriptor {
     pub mouse_report_id: Option<u8>,
     /// Report ID for consumer input, when present.
     pub consumer_report_id: Option<u8>,
+    /// Was the whole Report Map seen? `false` when the map was truncated (cut
+    /// short by the read, a dangling item, or unbalanced collections), in which
+    /// case the *absence* of a report kind or report ID proves nothing.
+    pub complete: bool,
 }
 
 impl HidDescriptor {
@@ -182,6 +197,9 @@ impl HidDescriptor {
 
 impl HidDescriptor {
     /// Parse a HID Report Descriptor.
+    ///
+    /// A truncated map still yields whatever it did describe, with
+    /// [`complete`](Self::complete) cleared.
     pub fn parse(data: &[u8]) -> Option<Self> {
         let mut desc = HidDescriptor {
             has_keyboard: false,
@@ -190,6 +208,7 @@ impl HidDescriptor {
             keyboard_report_id: None,
             mouse_report_id: None,
             consumer_report_id: None,
+            complete: false,
         };
 
         // Parser state.
@@ -199,6 +218,8 @@ impl HidDescriptor {
         let mut report_size: u16 = 0;
         let mut report_count: u16 = 0;
         let mut _bit_offset: u16 = 0;
+        let mut depth: u8 = 0;
+        let mut balanced = true;
 
         let mut i = 0;
         while i < data.len() {
@@ -265,13 +286,12 @@ impl HidDescriptor {
                             _bit_offset += total_bits;
                         }
                         // Collection
-                        0x0A => {
-                            // Start of collection - track depth if needed.
-                        }
+                        0x0A => depth = depth.saturating_add(1),
                         // End Collection
-                        0x0C => {
-                            // End of collection.
-                        }
+                        0x0C => match depth.checked_sub(1) {
+                            Some(d) => depth = d,
+                            None => balanced = false,
+                        },
                         _ => {}
                     }
                     // Per HID spec, Local items (Usage, Usage Min/Max, etc.) only
@@ -306,6 +326,8 @@ impl HidDescriptor {
             i += 1 + size;
         }
 
+        desc.complete = i == data.len() && depth == 0 && balanced;
+
         if desc.has_keyboard || desc.has_mouse || desc.has_consumer {
             Some(desc)
         } else {
@@ -65,6 +65,34 @@ fn parse_stops_on_truncated_item() {
     assert!(HidDescriptor::parse(&[0x06, 0x01]).is_none());
 }
 
+#[test]
+fn parse_reports_complete_map() {
+    // Usage Page (Consumer), Collection (Application), Input, End Collection.
+    let desc = HidDescriptor::parse(&[0x05, 0x0C, 0xA1, 0x01, 0x81, 0x02, 0xC0]).unwrap();
+    assert!(desc.complete);
+}
+
+#[test]
+fn parse_flags_unclosed_collection_as_incomplete() {
+    // Map cut off before End Collection: kinds seen so far still reported.
+    let desc = HidDescriptor::parse(&[0x05, 0x0C, 0xA1, 0x01, 0x81, 0x02]).unwrap();
+    assert!(desc.has_consumer);
+    assert!(!desc.complete);
+}
+
+#[test]
+fn parse_flags_dangling_item_as_incomplete() {
+    // Trailing 2-byte Usage Page item with only one data byte.
+    let desc = HidDescriptor::parse(&[0x05, 0x0C, 0x81, 0x02, 0x06, 0x01]).unwrap();
+    assert!(!desc.complete);
+}
+
+#[test]
+fn parse_flags_unbalanced_end_collection_as_incomplete() {
+    let desc = HidDescriptor::parse(&[0x05, 0x0C, 0x81, 0x02, 0xC0]).unwrap();
+    assert!(!desc.complete);
+}
+
 // ── Descriptor-guided classification ────────────────────────────────────────
 
 fn kbd_desc(report_id: Option<u8>) -> HidDescriptor {
@@ -75,6 +103,7 @@ fn kbd_desc(report_id: Option<u8>) -> HidDescriptor {
         keyboard_report_id: report_id,
         mouse_report_id: None,
         consumer_report_id: None,
+        complete: true,
     }
 }
 
@@ -110,6 +139,22 @@ fn hint_boot_protocol_when_no_report_ids() {
     ));
 }
 
+#[test]
+fn hint_incomplete_map_without_report_ids_uses_heuristic() {
+    // A truncated map may have lost its Report ID items: an ID-prefixed
+    // keyboard report must not be parsed as a boot report.
+    let mut desc = kbd_desc(None);
+    desc.complete = false;
+    let data = [1, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+    match classify_notification_with_hint(&data, Some(&desc)) {
+        Some(HidReport::Keyboard(kb)) => {
+            assert_eq!(kb.modifier, 0x02);
+            assert_eq!(kb.keycodes[0], 0x04);
+        }
+        other => panic!("expected keyboard, got {other:?}"),
+    }
+}
+
 #[test]
 fn hint_heuristic_when_no_descriptor() {
     let data = [0x01, 0x10, 0x20, 0x00]; // 4-byte mouse
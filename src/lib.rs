@@ -7,7 +7,11 @@
 //!
 //! The SoftDevice-coupled BLE modules (`multi_conn`, `hid_client`, `scanner`) and
 //! `storage`/`usb` are *not* included here; only their pure cores are
-//! (`ble::adv_parser`, `ble::coordinator`).
+//! (`ble::adv_parser`, `ble::coordinator`, the scan, link and
+//! connection-parameter bookkeeping, the `storage/` record formats and the
+//! generic device store). That split is the rule for new code too: a module
+//! that decides something takes plain values in and hands plain values back,
+//! and its SoftDevice, flash or USB calls live in the shell that uses it.
 
 #![cfg_attr(not(test), no_std)]
 
@@ -15,45 +19,163 @@
 // firmware (`defmt::Format` is feature-gated inside it).
 pub mod hid;
 
+// Compile-time constants; the pure modules take their defaults from here.
+pub mod config;
+
 #[path = "ble/adv_parser.rs"]
 mod ble_adv_parser_impl;
 
+#[path = "ble/conn_params.rs"]
+mod ble_conn_params_impl;
+
 #[path = "ble/coordinator.rs"]
 mod ble_coordinator_impl;
 
+#[path = "ble/link_stats.rs"]
+mod ble_link_stats_impl;
+
 #[path = "ble/reconnect.rs"]
 mod ble_reconnect_impl;
 
+#[path = "ble/long_read.rs"]
+mod ble_long_read_impl;
+
+#[path = "ble/scan_list.rs"]
+mod ble_scan_list_impl;
+
+#[path = "ble/security.rs"]
+mod ble_security_impl;
+
 // Pure flash-record framing (host-tested independently of the embedded
 // `storage` shell, which is SoftDevice-coupled and not compiled here).
 #[cfg(test)]
 #[path = "storage/framing.rs"]
 mod storage_framing_impl;
 
+// Pure GATT discovery-cache wire format (same reasoning as `framing`).
+#[cfg(test)]
+#[path = "storage/gatt_cache.rs"]
+mod storage_gatt_cache_impl;
+
+// Pure CRC envelope and A/B copy selection (same reasoning as `framing`).
+#[cfg(test)]
+#[path = "storage/integrity.rs"]
+mod storage_integrity_impl;
+
+// Pure bond encryption: software AES-128, AES-CCM and key derivation (same
+// reasoning as `framing`).
+#[cfg(test)]
+#[path = "storage/bond_crypto.rs"]
+mod storage_bond_crypto_impl;
+
+// Pure export container for moving devices between bridges (same reasoning
+// as `framing`).
+#[cfg(test)]
+#[path = "storage/backup.rs"]
+mod storage_backup_impl;
+
+// Pure settings schema and wire format (same reasoning as `framing`).
+#[cfg(test)]
+#[path = "storage/settings.rs"]
+mod storage_settings_impl;
+
+// Flash region access (map storage, write retries, A/B copies) and the
+// generic paired-device store, run against a RAM flash.
+#[cfg(test)]
+#[path = "storage/region.rs"]
+mod storage_region_impl;
+
+#[cfg(test)]
+#[path = "storage/device_store.rs"]
+mod storage_device_store_impl;
+
+#[cfg(test)]
+#[path = "storage/ram_flash.rs"]
+mod storage_ram_flash_impl;
+
+// The storage modules reach each other as `crate::storage::*`, as in the
+// firmware.
+#[cfg(test)]
+mod storage {
+    pub(crate) use crate::storage_backup_impl as backup;
+    pub(crate) use crate::storage_bond_crypto_impl as bond_crypto;
+    pub(crate) use crate::storage_framing_impl as framing;
+    pub(crate) use crate::storage_gatt_cache_impl as gatt_cache;
+    pub(crate) use crate::storage_integrity_impl as integrity;
+    pub(crate) use crate::storage_ram_flash_impl as ram_flash;
+    pub(crate) use crate::storage_region_impl as region;
+    pub(crate) use crate::storage_settings_impl as settings;
+}
+
+#[path = "usb/host_id.rs"]
+mod usb_host_id_impl;
+
 #[path = "power_logic.rs"]
 mod power_logic_impl;
+#[path = "ui/gesture.rs"]
+mod ui_gesture_impl;
 #[path = "ui/input_logic.rs"]
 mod ui_input_logic_impl;
 #[path = "ui/ui_logic.rs"]
 mod ui_ui_logic_impl;
 
+// The menu's view of the stored settings (needs `storage::settings`, so
+// test-only like it).
+#[cfg(test)]
+#[path = "ui/settings_menu.rs"]
+mod ui_settings_menu_impl;
+
 pub mod ble {
     pub mod adv_parser {
-        pub use crate::ble_adv_parser_impl::{contains_hid_service_uuid, extract_device_name};
+        pub use crate::ble_adv_parser_impl::{
+            AddressKind, AdvReport, DeviceIcon, ManufacturerData, ServiceData,
+        };
+    }
+    /// Pure per-role connection-parameter policy.
+    pub mod conn_params {
+        pub use crate::ble_conn_params_impl::*;
     }
     /// Pure BLE coordination core (connection-slot state machine + reducers).
     pub mod coordinator {
         pub use crate::ble_coordinator_impl::*;
     }
+    /// Pure per-slot link quality statistics (RSSI window, link losses).
+    pub mod link_stats {
+        pub use crate::ble_link_stats_impl::*;
+    }
     /// Pure boot-time auto-reconnect planner (RPA resolution sequencing).
     pub mod reconnect {
         pub use crate::ble_reconnect_impl::*;
     }
+    /// Pure ATT long-read (Read Blob) sequencing.
+    pub mod long_read {
+        pub use crate::ble_long_read_impl::*;
+    }
+    /// Pure streaming-scan bookkeeping (stable device list, duty cycle).
+    pub mod scan_list {
+        pub use crate::ble_scan_list_impl::*;
+    }
+    /// Pure pairing/bonding policy.
+    pub mod security {
+        pub use crate::ble_security_impl::*;
+    }
+}
+
+pub mod usb {
+    /// Pure host identification from USB enumeration behaviour.
+    pub mod host_id {
+        pub use crate::usb_host_id_impl::*;
+    }
 }
 
 pub mod ui {
     pub use crate::ui_ui_logic_impl::{ButtonEvent, Screen};
 
+    /// Pure button gesture recognition (press timing).
+    pub mod gesture {
+        pub use crate::ui_gesture_impl::*;
+    }
+
     pub mod input_logic {
         pub use crate::ui_input_logic_impl::next_scan_dots;
     }
@@ -65,7 +187,10 @@ pub mod ui {
 }
 
 pub mod power_logic {
-    pub use crate::power_logic_impl::{next_power_state, screen_should_be_on, PowerState};
+    pub use crate::power_logic_impl::{
+        next_power_state, peripheral_power, peripheral_power_change, screen_should_be_on,
+        PeripheralPower, PowerState,
+    };
 }
 
 #[cfg(test)]
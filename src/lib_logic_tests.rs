@@ -5,6 +5,13 @@ use super::hid::keyboard::KeyboardReport;
 use super::hid::mouse::MouseReport;
 use super::hid::HidReport;
 
+fn parse_adv(ad_data: &[u8]) -> crate::ble::adv_parser::AdvReport {
+    use crate::ble::adv_parser::{AddressKind, AdvReport};
+    let mut report = AdvReport::new(AddressKind::Public);
+    report.merge(ad_data);
+    report
+}
+
 #[test]
 fn hid_report_serialize_keyboard() {
     let report = HidReport::Keyboard(KeyboardReport {
@@ -73,22 +80,16 @@ fn hid_report_equality() {
 #[test]
 fn ble_adv_parser_detects_hid_uuid() {
     let ad_data = [0x03, 0x03, 0x12, 0x18];
-    assert!(crate::ble::adv_parser::contains_hid_service_uuid(&ad_data));
+    assert!(parse_adv(&ad_data).has_hid_service());
 }
 
 #[test]
 fn ble_adv_parser_extracts_name_or_unknown() {
     let named = [0x05, 0x09, b'M', b'o', b'u', b's'];
-    assert_eq!(
-        crate::ble::adv_parser::extract_device_name(&named).as_str(),
-        "Mous"
-    );
+    assert_eq!(parse_adv(&named).display_name().as_str(), "Mous");
 
     let unnamed = [0x02, 0x01, 0x06];
-    assert_eq!(
-        crate::ble::adv_parser::extract_device_name(&unnamed).as_str(),
-        "Unknown"
-    );
+    assert_eq!(parse_adv(&unnamed).display_name().as_str(), "Unknown");
 }
 
 #[test]
@@ -103,20 +104,16 @@ fn ui_input_logic_scan_dots_cycle() {
 #[test]
 fn ble_adv_parser_rejects_non_hid_uuid() {
     let ad_data = [0x03, 0x03, 0x0F, 0x18];
-    assert!(!crate::ble::adv_parser::contains_hid_service_uuid(&ad_data));
+    assert!(!parse_adv(&ad_data).has_hid_service());
 }
 
 #[test]
 fn ble_adv_parser_handles_malformed_lengths() {
     let ad_zero_len = [0x00];
-    assert!(!crate::ble::adv_parser::contains_hid_service_uuid(
-        &ad_zero_len
-    ));
+    assert!(!parse_adv(&ad_zero_len).has_hid_service());
 
     let ad_too_short = [0x05, 0x03, 0x12];
-    assert!(!crate::ble::adv_parser::contains_hid_service_uuid(
-        &ad_too_short
-    ));
+    assert!(!parse_adv(&ad_too_short).has_hid_service());
 }
 
 #[test]
@@ -127,10 +124,94 @@ fn ble_adv_parser_name_is_truncated_to_capacity() {
     for i in 2..37 {
         ad_data[i] = b'X';
     }
-    let name = crate::ble::adv_parser::extract_device_name(&ad_data);
+    let name = parse_adv(&ad_data).display_name();
     assert_eq!(name.len(), 32);
 }
 
+#[test]
+fn ble_adv_report_parses_rich_fields() {
+    use crate::ble::adv_parser::{AddressKind, AdvReport, DeviceIcon};
+
+    let ad_data = [
+        0x02, 0x01, 0x05, // Flags
+        0x03, 0x19, 0xC1, 0x03, // Appearance: Keyboard
+        0x02, 0x0A, 0xF8, // TX power -8 dBm
+        0x05, 0x03, 0x12, 0x18, 0x0F, 0x18, // HID, Battery
+        0x06, 0xFF, 0x4C, 0x00, 0x01, 0x02, 0x03, // Manufacturer 0x004C
+        0x05, 0x16, 0x0F, 0x18, 0x55, 0xAA, // Service data for 0x180F
+    ];
+    let mut report = AdvReport::new(AddressKind::from_raw(2));
+    report.merge(&ad_data);
+
+    assert_eq!(report.address_kind, AddressKind::Resolvable);
+    assert_eq!(report.flags, Some(0x05));
+    assert_eq!(report.tx_power, Some(-8));
+    assert_eq!(report.icon(), DeviceIcon::Keyboard);
+    assert!(report.has_hid_service());
+    assert_eq!(report.uuids16.as_slice(), &[0x1812, 0x180F]);
+    let mfr = report.manufacturer.as_ref().unwrap();
+    assert_eq!(
+        (mfr.company_id, mfr.data.as_slice()),
+        (0x004C, &[1, 2, 3][..])
+    );
+    assert_eq!(report.service_data[0].uuid, 0x180F);
+    assert_eq!(report.service_data[0].data.as_slice(), &[0x55, 0xAA]);
+    assert_eq!(report.display_name().as_str(), "Unknown");
+}
+
+#[test]
+fn ble_adv_report_merges_scan_response() {
+    use crate::ble::adv_parser::{AddressKind, AdvReport, DeviceIcon};
+
+    let mut report = AdvReport::new(AddressKind::Public);
+    // Advertisement: HID UUID and a shortened name.
+    report.merge(&[0x03, 0x03, 0x12, 0x18, 0x04, 0x08, b'M', b'X', b' ']);
+    assert_eq!(report.display_name().as_str(), "MX ");
+    // Scan response: complete name, appearance, a 128-bit UUID.
+    let mut scan_rsp = [0u8; 30];
+    scan_rsp[..9].copy_from_slice(&[0x08, 0x09, b'M', b'X', b' ', b'K', b'e', b'y', b's']);
+    scan_rsp[9..13].copy_from_slice(&[0x03, 0x19, 0xC2, 0x03]);
+    scan_rsp[13] = 0x11;
+    scan_rsp[14] = 0x07;
+    scan_rsp[15..30].copy_from_slice(&[0xAB; 15]);
+    // One byte short of a full 128-bit UUID: the structure is malformed and
+    // parsing stops there without touching what came before.
+    report.merge(&scan_rsp);
+    assert_eq!(report.display_name().as_str(), "MX Keys");
+    assert!(report.name_complete);
+    assert_eq!(report.icon(), DeviceIcon::Mouse);
+    assert!(report.uuids128.is_empty());
+
+    // A later shortened name doesn't replace the complete one; UUIDs don't repeat.
+    report.merge(&[0x03, 0x03, 0x12, 0x18, 0x02, 0x08, b'M']);
+    assert_eq!(report.display_name().as_str(), "MX Keys");
+    assert_eq!(report.uuids16.as_slice(), &[0x1812]);
+}
+
+#[test]
+fn ble_adv_report_collects_128_bit_uuids() {
+    use crate::ble::adv_parser::{AddressKind, AdvReport};
+
+    let mut ad_data = [0u8; 18];
+    ad_data[0] = 0x11;
+    ad_data[1] = 0x06;
+    ad_data[2..].copy_from_slice(&[0x5A; 16]);
+    let mut report = AdvReport::new(AddressKind::RandomStatic);
+    report.merge(&ad_data);
+    assert_eq!(report.uuids128.as_slice(), &[[0x5A; 16]]);
+    assert!(!report.has_hid_service());
+}
+
+#[test]
+fn ble_device_icon_from_appearance() {
+    use crate::ble::adv_parser::DeviceIcon;
+
+    assert_eq!(DeviceIcon::from_appearance(0x03C1).glyph(), 'K');
+    assert_eq!(DeviceIcon::from_appearance(0x03C2).glyph(), 'M');
+    assert_eq!(DeviceIcon::from_appearance(0x03C4), DeviceIcon::Gamepad);
+    assert_eq!(DeviceIcon::from_appearance(0x0000), DeviceIcon::Other);
+}
+
 #[test]
 fn screen_power_policy_auto_off_enabled_after_timeout() {
     assert!(crate::power_logic::screen_should_be_on(
@@ -27,10 +27,13 @@
 //! |---------------------|------------------------------------------------------|
 //! | `softdevice_task`   | Runs the SoftDevice event loop; forwards USB power events |
 //! | `ble_task`          | BLE coordinator: scan, slot orchestration, flash persist |
-//! | `ble_slot{0,1}_task`| Per-slot connect/secure + HID notification loop      |
+//! | `ble_slot_task` (×N)| Per-slot connect/secure + HID notification loop      |
 //! | `usb_device_task`   | USB enumeration and endpoint servicing               |
 //! | `hid_writer_task`   | Forwards BLE reports → USB HID endpoints              |
-//! | `button_*_task`     | Per-button debounced GPIO watcher (×3)               |
+//! | `battery_writer_task`| Forwards BLE peer battery levels → USB battery report |
+//! | `host_watch_task`   | Tells which PC enumerated the bridge (monitor switch) |
+//! | `button_*_task`     | Per-button debounced GPIO watcher (×3, or SELECT only) |
+//! | `gesture_task`      | Times button edges into short/long/double/chord presses |
 //!
 //! The UI state machine runs in `main` itself (reacting to button and BLE events
 //! and driving the OLED), not a separate task.
@@ -61,14 +64,19 @@ use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use nrf_softdevice::SocEvent;
 
-use crate::ble::multi_conn::{self, SlotCommand, SlotEvent};
+use crate::ble::conn_params;
+use crate::ble::coordinator::{self, MAX_CONNECTIONS};
+use crate::ble::multi_conn::{self, SlotCommandChannels, SlotEvent};
 use crate::ble::{BleCommand, BleEvent};
 use crate::hid::HidReport;
 use crate::power::PowerManager;
+use crate::ui::gesture::Edge;
+use crate::ui::ui_logic::Button;
 use crate::ui::{ButtonEvent, Screen};
 use crate::usb::hid_device;
 use embassy_time::{Duration, Timer};
 use heapless::Vec;
+use nrf_softdevice::ble::Address;
 
 /// BLE HID reports → USB HID writer.
 static HID_REPORT_CHANNEL: Channel<CriticalSectionRawMutex, HidReport, 16> = Channel::new();
@@ -79,16 +87,16 @@ static BLE_CMD_CHANNEL: Channel<CriticalSectionRawMutex, BleCommand, 4> = Channe
 /// BLE → UI events (device found, connected, error).
 static BLE_EVENT_CHANNEL: Channel<CriticalSectionRawMutex, BleEvent, 8> = Channel::new();
 
-/// Coordinator -> BLE slot 0 command channel.
-static BLE_SLOT0_CMD_CHANNEL: Channel<CriticalSectionRawMutex, SlotCommand, 2> = Channel::new();
-
-/// Coordinator -> BLE slot 1 command channel.
-static BLE_SLOT1_CMD_CHANNEL: Channel<CriticalSectionRawMutex, SlotCommand, 2> = Channel::new();
+/// Coordinator -> BLE slot command channels (index = slot).
+static BLE_SLOT_CMD_CHANNELS: SlotCommandChannels = [const { Channel::new() }; MAX_CONNECTIONS];
 
 /// BLE slot workers -> coordinator event channel.
 static BLE_SLOT_EVENT_CHANNEL: Channel<CriticalSectionRawMutex, SlotEvent, 8> = Channel::new();
 
-/// Button press events → UI.
+/// Debounced button edges → gesture recognizer.
+static BUTTON_EDGE_CHANNEL: Channel<CriticalSectionRawMutex, Edge, 8> = Channel::new();
+
+/// Button gestures → UI.
 static BUTTON_CHANNEL: Channel<CriticalSectionRawMutex, ButtonEvent, 4> = Channel::new();
 
 bind_interrupts!(struct TwimIrqs {
@@ -104,15 +112,20 @@ fn softdevice_config() -> nrf_softdevice::Config {
             accuracy: nrf_softdevice::raw::NRF_CLOCK_LF_ACCURACY_500_PPM as u8,
         }),
         conn_gap: Some(nrf_softdevice::raw::ble_gap_conn_cfg_t {
-            conn_count: 2,
-            event_length: config::BLE_CONN_EVENT_LENGTH,
+            conn_count: MAX_CONNECTIONS as u8,
+            event_length: coordinator::conn_event_length(
+                conn_params::FASTEST_INTERVAL,
+                MAX_CONNECTIONS,
+            ),
+        }),
+        conn_gatt: Some(nrf_softdevice::raw::ble_gatt_conn_cfg_t {
+            att_mtu: config::BLE_ATT_MTU,
         }),
-        conn_gatt: Some(nrf_softdevice::raw::ble_gatt_conn_cfg_t { att_mtu: 64 }),
         gap_role_count: Some(nrf_softdevice::raw::ble_gap_cfg_role_count_t {
-            adv_set_count: 0,      // we don't advertise
-            periph_role_count: 0,  // we don't act as peripheral
-            central_role_count: 2, // up to two central connections
-            central_sec_count: 2,
+            adv_set_count: 0,                          // we don't advertise
+            periph_role_count: 0,                      // we don't act as peripheral
+            central_role_count: MAX_CONNECTIONS as u8, // one central link per slot
+            central_sec_count: MAX_CONNECTIONS as u8,
             _bitfield_1: nrf_softdevice::raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
         }),
         ..Default::default()
@@ -142,31 +155,20 @@ async fn ble_task(sd: &'static nrf_softdevice::Softdevice) -> ! {
         sd,
         &BLE_CMD_CHANNEL.receiver(),
         &BLE_EVENT_CHANNEL.sender(),
-        &BLE_SLOT0_CMD_CHANNEL.sender(),
-        &BLE_SLOT1_CMD_CHANNEL.sender(),
+        &BLE_SLOT_CMD_CHANNELS,
         &BLE_SLOT_EVENT_CHANNEL.receiver(),
     )
     .await
 }
 
-#[embassy_executor::task]
-async fn ble_slot0_task(sd: &'static nrf_softdevice::Softdevice) -> ! {
-    multi_conn::connection_slot_task(
-        0,
-        sd,
-        &BLE_SLOT0_CMD_CHANNEL.receiver(),
-        &BLE_SLOT_EVENT_CHANNEL.sender(),
-        &HID_REPORT_CHANNEL.sender(),
-    )
-    .await
-}
-
-#[embassy_executor::task]
-async fn ble_slot1_task(sd: &'static nrf_softdevice::Softdevice) -> ! {
+/// One worker per connection slot, spawned from a pool of
+/// [`MAX_CONNECTIONS`].
+#[embassy_executor::task(pool_size = MAX_CONNECTIONS)]
+async fn ble_slot_task(slot: usize, sd: &'static nrf_softdevice::Softdevice) -> ! {
     multi_conn::connection_slot_task(
-        1,
+        slot,
         sd,
-        &BLE_SLOT1_CMD_CHANNEL.receiver(),
+        &BLE_SLOT_CMD_CHANNELS[slot].receiver(),
         &BLE_SLOT_EVENT_CHANNEL.sender(),
         &HID_REPORT_CHANNEL.sender(),
     )
@@ -187,19 +189,109 @@ async fn hid_writer_task(
     hid_device::hid_writer_task(keyboard, mouse, consumer, &HID_REPORT_CHANNEL.receiver()).await
 }
 
+#[embassy_executor::task]
+async fn host_watch_task() -> ! {
+    hid_device::host_watch_task().await
+}
+
+#[embassy_executor::task]
+async fn battery_writer_task(
+    battery: embassy_usb::class::hid::HidWriter<'static, hid_device::UsbDriver, 8>,
+) -> ! {
+    hid_device::battery_writer_task(battery).await
+}
+
+/// Button debounce from the live settings.
+fn debounce_ms() -> u64 {
+    storage::settings().button_debounce_ms as u64
+}
+
 #[embassy_executor::task]
 async fn button_up_task(pin: Peri<'static, AnyPin>) -> ! {
-    ui::buttons::button_task(pin, ButtonEvent::Up, &BUTTON_CHANNEL.sender()).await
+    ui::buttons::button_task(pin, Button::Up, &BUTTON_EDGE_CHANNEL.sender(), debounce_ms).await
 }
 
 #[embassy_executor::task]
 async fn button_down_task(pin: Peri<'static, AnyPin>) -> ! {
-    ui::buttons::button_task(pin, ButtonEvent::Down, &BUTTON_CHANNEL.sender()).await
+    ui::buttons::button_task(
+        pin,
+        Button::Down,
+        &BUTTON_EDGE_CHANNEL.sender(),
+        debounce_ms,
+    )
+    .await
 }
 
 #[embassy_executor::task]
 async fn button_select_task(pin: Peri<'static, AnyPin>) -> ! {
-    ui::buttons::button_task(pin, ButtonEvent::Select, &BUTTON_CHANNEL.sender()).await
+    ui::buttons::button_task(
+        pin,
+        Button::Select,
+        &BUTTON_EDGE_CHANNEL.sender(),
+        debounce_ms,
+    )
+    .await
+}
+
+#[embassy_executor::task]
+async fn gesture_task() -> ! {
+    ui::buttons::gesture_task(
+        &BUTTON_EDGE_CHANNEL.receiver(),
+        &BUTTON_CHANNEL.sender(),
+        config::SINGLE_BUTTON_MODE,
+    )
+    .await
+}
+
+/// Labels of the active profile's paired devices, most recently used first,
+/// and the address each refers to.
+async fn paired_devices() -> (
+    Vec<heapless::String<32>, { config::MAX_PAIRED_DEVICES }>,
+    Vec<Address, { config::MAX_PAIRED_DEVICES }>,
+) {
+    let store = storage::DEVICE_STORE.lock().await;
+    let labels = store
+        .iter_recent()
+        .map(|d| ui::ui_logic::device_label(d.icon.glyph(), d.name.as_str(), d.bond.is_some()))
+        .collect();
+    (labels, store.iter_recent().map(|d| d.address).collect())
+}
+
+/// Render one of the settings screens (a menu, an editor, the paired devices,
+/// the forget confirmation or about).
+async fn draw_settings<I2C>(
+    display: &mut ui::display::Display<I2C>,
+    screen: Screen,
+    selected: usize,
+    paired: &[heapless::String<32>],
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let settings = storage::settings();
+    match screen {
+        Screen::Menu(menu) => {
+            ui::display::draw_menu(display, menu, selected, |s| {
+                ui::settings_menu::value(&settings, s)
+            })
+            .await
+        }
+        Screen::Edit(setting) => ui::display::draw_editor(display, setting, selected as u16).await,
+        Screen::PairedDevices => ui::display::draw_paired_devices(display, paired, selected).await,
+        Screen::Forget => {
+            let name = paired.get(selected).map_or("", |label| label.as_str());
+            ui::display::draw_forget(display, name).await
+        }
+        Screen::About => {
+            ui::display::draw_about(
+                display,
+                env!("CARGO_PKG_VERSION"),
+                settings.active_profile as usize,
+                paired.len(),
+            )
+            .await
+        }
+        _ => {}
+    }
 }
 
 #[embassy_executor::main]
@@ -231,10 +323,13 @@ async fn main(spawner: Spawner) {
         usb.mouse_writer,
         usb.consumer_writer,
     )));
+    spawner.spawn(unwrap!(battery_writer_task(usb.battery_writer)));
+    spawner.spawn(unwrap!(host_watch_task()));
     info!("USB HID device started");
 
-    spawner.spawn(unwrap!(ble_slot0_task(sd)));
-    spawner.spawn(unwrap!(ble_slot1_task(sd)));
+    for slot in 0..MAX_CONNECTIONS {
+        spawner.spawn(unwrap!(ble_slot_task(slot, sd)));
+    }
     spawner.spawn(unwrap!(ble_task(sd)));
     info!("BLE task started");
 
@@ -252,10 +347,15 @@ async fn main(spawner: Spawner) {
     ui::display::draw_home(&mut display, false, "").await;
     info!("OLED display initialised");
 
-    spawner.spawn(unwrap!(button_up_task(p.P0_11.into())));
-    spawner.spawn(unwrap!(button_down_task(p.P0_12.into())));
+    spawner.spawn(unwrap!(gesture_task()));
     spawner.spawn(unwrap!(button_select_task(p.P0_24.into())));
-    info!("Button handlers started (3 buttons)");
+    if config::SINGLE_BUTTON_MODE {
+        info!("Button handlers started (1 button)");
+    } else {
+        spawner.spawn(unwrap!(button_up_task(p.P0_11.into())));
+        spawner.spawn(unwrap!(button_down_task(p.P0_12.into())));
+        info!("Button handlers started (3 buttons)");
+    }
 
     info!("Entering UI main loop");
     let mut screen = Screen::Home;
@@ -263,6 +363,18 @@ async fn main(spawner: Spawner) {
     let mut device_count: usize = 0;
     let mut devices: Vec<heapless::String<32>, 8> = Vec::new();
     let mut connected_name: heapless::String<32> = heapless::String::new();
+    let mut battery: Option<heapless::String<21>> = None;
+    // Signal bars per slot, for the Connected screen.
+    let mut signal: [Option<u8>; MAX_CONNECTIONS] = [None; MAX_CONNECTIONS];
+    // Occupied slots for the Slots screen: labels, and the slot each refers to.
+    let mut slot_labels: Vec<heapless::String<32>, MAX_CONNECTIONS> = Vec::new();
+    let mut slot_ids: Vec<usize, MAX_CONNECTIONS> = Vec::new();
+    // Paired devices for the settings screens, read when they are opened:
+    // labels, and the address each refers to.
+    let mut paired: Vec<heapless::String<32>, { config::MAX_PAIRED_DEVICES }> = Vec::new();
+    let mut paired_ids: Vec<Address, { config::MAX_PAIRED_DEVICES }> = Vec::new();
+    // Seconds left on a warning toast (0 = not showing).
+    let mut toast_secs: u8 = 0;
     let mut power = PowerManager::new();
     let mut display_powered_off = false;
     let mut scan_dots: u8 = 0;
@@ -297,26 +409,78 @@ async fn main(spawner: Spawner) {
                             .await
                         }
                         Screen::Scanning => ui::display::draw_scanning(&mut display, 0).await,
+                        Screen::Connecting => ui::display::draw_connecting(&mut display, 0).await,
                         Screen::DeviceList => {
                             ui::display::draw_device_list(&mut display, &devices, selected).await
                         }
                         Screen::Connected => {
-                            ui::display::draw_connected(&mut display, connected_name.as_str()).await
+                            ui::display::draw_connected(
+                                &mut display,
+                                connected_name.as_str(),
+                                battery.as_deref(),
+                                &signal,
+                            )
+                            .await
+                        }
+                        Screen::Slots => {
+                            ui::display::draw_slot_list(&mut display, &slot_labels, selected).await
+                        }
+                        Screen::Profiles => {
+                            let active = storage::settings().active_profile as usize;
+                            ui::display::draw_profile_list(&mut display, active, selected).await
                         }
                         Screen::Error => ui::display::draw_error(&mut display, "Ready").await,
+                        Screen::Menu(_)
+                        | Screen::Edit(_)
+                        | Screen::PairedDevices
+                        | Screen::Forget
+                        | Screen::About => {
+                            draw_settings(&mut display, screen, selected, &paired).await
+                        }
+                        Screen::ConfirmBackup(job) => {
+                            ui::display::draw_confirm_backup(&mut display, job).await
+                        }
                     }
                     continue;
                 }
 
                 // Decide the transition with the pure UI reducer, then apply
                 // its outcome (state + redraw + BLE command).
-                let outcome = ui::ui_logic::on_button(screen, btn, selected, device_count);
+                let count = match screen {
+                    Screen::Slots => slot_labels.len(),
+                    Screen::Profiles => config::MAX_PROFILES,
+                    Screen::PairedDevices => paired.len(),
+                    _ => device_count,
+                };
+                let outcome = ui::ui_logic::on_button(screen, btn, selected, count);
+                let opened_from_menu = matches!(screen, Screen::Menu(_));
                 screen = outcome.screen;
                 selected = outcome.selected;
                 if outcome.reset_devices {
                     device_count = 0;
                     devices.clear();
                 }
+                if opened_from_menu && matches!(screen, Screen::PairedDevices | Screen::About) {
+                    (paired, paired_ids) = paired_devices().await;
+                }
+                if let Some(setting) = outcome.load_value {
+                    selected = ui::settings_menu::value(&storage::settings(), setting) as usize;
+                }
+                // Settings and the paired list change here, before the redraw
+                // shows them; flash is written by the BLE task.
+                let mut forgotten = None;
+                match outcome.command {
+                    Some(ui::ui_logic::UiCommand::SetSetting(setting, value)) => {
+                        let mut settings = storage::settings();
+                        ui::settings_menu::set_value(&mut settings, setting, value);
+                        storage::SETTINGS_STORE.lock().await.update(settings);
+                    }
+                    Some(ui::ui_logic::UiCommand::ForgetDevice(i)) if i < paired.len() => {
+                        paired.remove(i);
+                        forgotten = Some(paired_ids.remove(i));
+                    }
+                    _ => {}
+                }
                 match outcome.redraw {
                     ui::ui_logic::Redraw::Scanning => {
                         scan_dots = 0;
@@ -325,16 +489,59 @@ async fn main(spawner: Spawner) {
                     ui::ui_logic::Redraw::DeviceList => {
                         ui::display::draw_device_list(&mut display, &devices, selected).await;
                     }
+                    ui::ui_logic::Redraw::Connecting => {
+                        scan_dots = 0;
+                        ui::display::draw_connecting(&mut display, scan_dots).await;
+                    }
                     ui::ui_logic::Redraw::Home => {
                         ui::display::draw_home(&mut display, false, "").await;
                     }
+                    ui::ui_logic::Redraw::Slots => {
+                        ui::display::draw_slot_list(&mut display, &slot_labels, selected).await;
+                    }
+                    ui::ui_logic::Redraw::Profiles => {
+                        let active = storage::settings().active_profile as usize;
+                        ui::display::draw_profile_list(&mut display, active, selected).await;
+                    }
+                    ui::ui_logic::Redraw::Connected => {
+                        ui::display::draw_connected(
+                            &mut display,
+                            connected_name.as_str(),
+                            battery.as_deref(),
+                            &signal,
+                        )
+                        .await;
+                    }
+                    ui::ui_logic::Redraw::Menu
+                    | ui::ui_logic::Redraw::Edit
+                    | ui::ui_logic::Redraw::PairedDevices
+                    | ui::ui_logic::Redraw::Forget
+                    | ui::ui_logic::Redraw::About => {
+                        draw_settings(&mut display, screen, selected, &paired).await;
+                    }
                     ui::ui_logic::Redraw::None => {}
                 }
                 if let Some(cmd) = outcome.command {
                     let ble_cmd = match cmd {
                         ui::ui_logic::UiCommand::StartScan => BleCommand::StartScan,
+                        ui::ui_logic::UiCommand::StopScan => BleCommand::StopScan,
                         ui::ui_logic::UiCommand::Connect(index) => BleCommand::Connect(index),
                         ui::ui_logic::UiCommand::Disconnect => BleCommand::Disconnect,
+                        ui::ui_logic::UiCommand::DisconnectSlot(i) => match slot_ids.get(i) {
+                            Some(&slot) => BleCommand::DisconnectSlot(slot),
+                            // The list changed under the user; nothing to do.
+                            None => continue,
+                        },
+                        ui::ui_logic::UiCommand::SwitchProfile(i) => BleCommand::SwitchProfile(i),
+                        ui::ui_logic::UiCommand::SetSetting(..) => BleCommand::SaveSettings,
+                        ui::ui_logic::UiCommand::ForgetDevice(_) => match forgotten {
+                            Some(address) => BleCommand::ForgetDevice(address),
+                            None => continue,
+                        },
+                        ui::ui_logic::UiCommand::AllowBackup(job) => BleCommand::AllowBackup(job),
+                        ui::ui_logic::UiCommand::DeclineBackup(job) => {
+                            BleCommand::DeclineBackup(job)
+                        }
                     };
                     BLE_CMD_CHANNEL.send(ble_cmd).await;
                 }
@@ -346,17 +553,23 @@ async fn main(spawner: Spawner) {
                         ui::display::set_power(&mut display, true).await;
                         display_powered_off = false;
                     }
-                    screen = Screen::Scanning;
-                    selected = 0;
                     device_count = 0;
                     devices.clear();
-                    scan_dots = 0;
-                    ui::display::draw_scanning(&mut display, scan_dots).await;
+                    screen = ui::ui_logic::on_link_event(screen, Screen::Scanning);
+                    if screen == Screen::Scanning {
+                        selected = 0;
+                        scan_dots = 0;
+                        ui::display::draw_scanning(&mut display, scan_dots).await;
+                    }
                 }
 
                 BleEvent::DeviceFound(dev) => {
                     if !devices.is_full() {
-                        let _ = devices.push(dev.name.clone());
+                        let _ = devices.push(ui::ui_logic::device_label(
+                            dev.icon.glyph(),
+                            dev.name.as_str(),
+                            dev.bonded,
+                        ));
                     }
                     device_count = devices.len();
                     info!(
@@ -365,40 +578,182 @@ async fn main(spawner: Spawner) {
                         dev.name.as_str(),
                         dev.rssi
                     );
-                }
-
-                BleEvent::ScanComplete => {
-                    screen = ui::ui_logic::on_scan_complete(device_count);
+                    screen = ui::ui_logic::on_device_found(screen);
                     if screen == Screen::DeviceList {
-                        selected = selected.min(device_count.saturating_sub(1));
                         ui::display::draw_device_list(&mut display, &devices, selected).await;
-                    } else {
-                        ui::display::draw_error(&mut display, "No devices found").await;
+                    }
+                }
+
+                BleEvent::DeviceUpdated { index, device } => {
+                    if let Some(label) = devices.get_mut(index) {
+                        *label = ui::ui_logic::device_label(
+                            device.icon.glyph(),
+                            device.name.as_str(),
+                            device.bonded,
+                        );
+                        if screen == Screen::DeviceList {
+                            ui::display::draw_device_list(&mut display, &devices, selected).await;
+                        }
+                    }
+                }
+
+                BleEvent::ScanComplete { cancelled } => {
+                    let verdict = ui::ui_logic::on_scan_complete(screen, device_count, cancelled);
+                    if verdict != screen {
+                        screen = verdict;
+                        if screen == Screen::DeviceList {
+                            selected = selected.min(device_count.saturating_sub(1));
+                            ui::display::draw_device_list(&mut display, &devices, selected).await;
+                        } else {
+                            ui::display::draw_error(&mut display, "No devices found").await;
+                        }
                     }
                 }
 
                 BleEvent::Connected(name) => {
-                    screen = Screen::Connected;
+                    screen = ui::ui_logic::on_link_event(screen, Screen::Connected);
                     devices.clear();
                     connected_name = name.clone();
                     power.set_ble_connected(true);
-                    ui::display::draw_connected(&mut display, name.as_str()).await;
+                    if screen == Screen::Connected {
+                        ui::display::draw_connected(
+                            &mut display,
+                            name.as_str(),
+                            battery.as_deref(),
+                            &signal,
+                        )
+                        .await;
+                    }
                     info!("UI: connected to {}", name.as_str());
                 }
 
+                BleEvent::Battery(levels) => {
+                    battery = ui::ui_logic::battery_line(&levels);
+                    if screen == Screen::Connected && toast_secs == 0 {
+                        ui::display::draw_connected(
+                            &mut display,
+                            connected_name.as_str(),
+                            battery.as_deref(),
+                            &signal,
+                        )
+                        .await;
+                    }
+                }
+
+                BleEvent::Slots(slots) => {
+                    slot_labels.clear();
+                    slot_ids.clear();
+                    for (slot, summary) in slots.iter().enumerate() {
+                        if summary.is_none() {
+                            signal[slot] = None;
+                        }
+                        if let Some(summary) = summary {
+                            let _ = slot_labels.push(ui::ui_logic::device_label(
+                                summary.role.glyph(),
+                                summary.name.as_str(),
+                                false,
+                            ));
+                            let _ = slot_ids.push(slot);
+                        }
+                    }
+                    if screen == Screen::Slots {
+                        selected = selected.min(slot_labels.len());
+                        ui::display::draw_slot_list(&mut display, &slot_labels, selected).await;
+                    }
+                }
+
+                BleEvent::Signal(bars) => {
+                    signal = bars;
+                    if screen == Screen::Connected && toast_secs == 0 {
+                        ui::display::draw_connected(
+                            &mut display,
+                            connected_name.as_str(),
+                            battery.as_deref(),
+                            &signal,
+                        )
+                        .await;
+                    }
+                }
+
+                BleEvent::WeakLink(name) => {
+                    info!("UI: weak link to {}", name.as_str());
+                    if screen == Screen::Connected {
+                        power.activity();
+                        if display_powered_off {
+                            ui::display::set_power(&mut display, true).await;
+                            display_powered_off = false;
+                        }
+                        toast_secs = ui::ui_logic::TOAST_SECS;
+                        ui::display::draw_weak_link(&mut display, name.as_str()).await;
+                    }
+                }
+
+                BleEvent::LowBattery(name) => {
+                    info!("UI: low battery on {}", name.as_str());
+                    if screen == Screen::Connected {
+                        // Worth waking the panel for.
+                        power.activity();
+                        if display_powered_off {
+                            ui::display::set_power(&mut display, true).await;
+                            display_powered_off = false;
+                        }
+                        toast_secs = ui::ui_logic::TOAST_SECS;
+                        ui::display::draw_low_battery(&mut display, name.as_str()).await;
+                    }
+                }
+
+                BleEvent::StorageDamaged { lost } => {
+                    info!("UI: storage damaged (lost: {})", lost);
+                    power.activity();
+                    if display_powered_off {
+                        ui::display::set_power(&mut display, true).await;
+                        display_powered_off = false;
+                    }
+                    toast_secs = ui::ui_logic::TOAST_SECS;
+                    ui::display::draw_storage_damaged(&mut display, lost).await;
+                }
+
+                BleEvent::BackupRequested(job) => {
+                    info!("UI: host asks for backup {}", job);
+                    power.activity();
+                    if display_powered_off {
+                        ui::display::set_power(&mut display, true).await;
+                        display_powered_off = false;
+                    }
+                    screen = Screen::ConfirmBackup(job);
+                    toast_secs = 0;
+                    ui::display::draw_confirm_backup(&mut display, job).await;
+                }
+
+                BleEvent::ProfileSwitched(profile) => {
+                    info!("UI: profile {} active", profile);
+                    power.activity();
+                    if display_powered_off {
+                        ui::display::set_power(&mut display, true).await;
+                        display_powered_off = false;
+                    }
+                    toast_secs = ui::ui_logic::TOAST_SECS;
+                    ui::display::draw_profile_switched(&mut display, profile).await;
+                }
+
                 BleEvent::Disconnected => {
-                    screen = Screen::Home;
+                    screen = ui::ui_logic::on_link_event(screen, Screen::Home);
                     devices.clear();
-                    selected = 0;
                     device_count = 0;
                     connected_name.clear();
                     power.set_ble_connected(false);
-                    ui::display::draw_home(&mut display, false, "").await;
+                    if screen == Screen::Home {
+                        selected = 0;
+                        ui::display::draw_home(&mut display, false, "").await;
+                    }
                     info!("UI: disconnected");
                 }
 
                 BleEvent::Error(tag) => {
-                    screen = Screen::Error;
+                    screen = ui::ui_logic::on_link_event(screen, Screen::Error);
+                    if screen != Screen::Error {
+                        continue;
+                    }
                     let msg = match tag {
                         ble::BleErrorTag::ScanFailed => "Scan failed",
                         ble::BleErrorTag::ConnectFailed => "Connect failed",
@@ -426,10 +781,49 @@ async fn main(spawner: Spawner) {
                     display_powered_off = false;
                 }
 
-                // Animate the scanning spinner once per tick while scanning.
-                if screen == Screen::Scanning && !display_powered_off {
-                    scan_dots = ui::input_logic::next_scan_dots(scan_dots);
-                    ui::display::draw_scanning(&mut display, scan_dots).await;
+                // Put the screen back once a warning has been up long enough.
+                if toast_secs > 0 {
+                    toast_secs -= 1;
+                    if toast_secs == 0 && !display_powered_off {
+                        match screen {
+                            Screen::Connected => {
+                                ui::display::draw_connected(
+                                    &mut display,
+                                    connected_name.as_str(),
+                                    battery.as_deref(),
+                                    &signal,
+                                )
+                                .await
+                            }
+                            Screen::Home => {
+                                ui::display::draw_home(
+                                    &mut display,
+                                    !connected_name.is_empty(),
+                                    connected_name.as_str(),
+                                )
+                                .await
+                            }
+                            Screen::ConfirmBackup(job) => {
+                                ui::display::draw_confirm_backup(&mut display, job).await
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                // Animate the spinner once per tick while scanning or connecting.
+                if !display_powered_off {
+                    match screen {
+                        Screen::Scanning => {
+                            scan_dots = ui::input_logic::next_scan_dots(scan_dots);
+                            ui::display::draw_scanning(&mut display, scan_dots).await;
+                        }
+                        Screen::Connecting => {
+                            scan_dots = ui::input_logic::next_scan_dots(scan_dots);
+                            ui::display::draw_connecting(&mut display, scan_dots).await;
+                        }
+                        _ => {}
+                    }
                 }
             }
 
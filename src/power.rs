@@ -3,7 +3,10 @@
 //! This board is **bus-powered** through the monitor's USB hub, not battery
 //! powered, so the aggressive low-power modes are intentionally *not* used:
 //! - We keep the fast 7.5 ms BLE connection interval for low HID latency rather
-//!   than relaxing it to save a few mA that wall power makes irrelevant.
+//!   than relaxing it to save a few mA that wall power makes irrelevant. The
+//!   exception is while the PC sleeps: the *peripherals* are battery powered,
+//!   so they are told to suspend (HID Control Point) and their links relaxed
+//!   until the bus resumes — see [`peripheral_power_receiver`].
 //! - We never enter System-OFF: it would drop USB enumeration and the BLE links,
 //!   which must stay up for the monitor hub. The Embassy executor already idles
 //!   the CPU (WFE / System-ON-Idle) automatically between events.
@@ -13,10 +16,13 @@
 //! just button presses and connect/disconnect events (see [`note_hid_activity`]).
 //! The state-transition policy is the pure, host-tested [`crate::power_logic`].
 
-use crate::config;
-use crate::power_logic::{self, next_power_state};
+use crate::ble::coordinator::MAX_CONNECTIONS;
+use crate::power_logic::{self, next_power_state, PeripheralPower};
+use crate::storage;
 use core::sync::atomic::{AtomicBool, Ordering};
 use defmt::info;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::{Receiver as WatchReceiver, Watch};
 use embassy_time::Instant;
 
 pub use crate::power_logic::PowerState;
@@ -37,6 +43,20 @@ pub fn note_hid_activity() {
     HID_ACTIVITY.store(true, Ordering::Relaxed);
 }
 
+/// Whether BLE peripherals should be suspended, published on every change.
+/// One receiver per BLE slot; `Watch` keeps only the newest value.
+static PERIPHERAL_POWER: Watch<CriticalSectionRawMutex, PeripheralPower, MAX_CONNECTIONS> =
+    Watch::new();
+
+/// Receiver handle a BLE slot task uses to follow [`PeripheralPower`] changes.
+pub type PeripheralPowerReceiver =
+    WatchReceiver<'static, CriticalSectionRawMutex, PeripheralPower, MAX_CONNECTIONS>;
+
+/// Take one of the per-slot peripheral power receivers.
+pub fn peripheral_power_receiver() -> Option<PeripheralPowerReceiver> {
+    PERIPHERAL_POWER.receiver()
+}
+
 /// Power manager tracks activity and manages sleep modes.
 pub struct PowerManager {
     state: PowerState,
@@ -79,6 +99,10 @@ impl PowerManager {
             return;
         }
 
+        if let Some(p) = power_logic::peripheral_power_change(self.usb_suspended, suspended) {
+            info!("Power: peripherals {:?}", p);
+            PERIPHERAL_POWER.sender().send(p);
+        }
         self.usb_suspended = suspended;
         info!("Power: usb_suspended={}", suspended);
 
@@ -94,11 +118,12 @@ impl PowerManager {
     pub fn display_on(&self) -> bool {
         let base_display_on = matches!(self.state, PowerState::Active | PowerState::Idle);
         let idle_secs = self.last_activity.elapsed().as_secs();
+        let settings = storage::settings();
         power_logic::screen_should_be_on(
             base_display_on,
-            config::SCREEN_AUTO_OFF_ENABLED,
+            settings.screen_auto_off,
             idle_secs,
-            config::SCREEN_AUTO_OFF_TIMEOUT_SECS,
+            settings.screen_auto_off_secs as u64,
         )
     }
 
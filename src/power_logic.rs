@@ -39,6 +39,47 @@ pub fn next_power_state(
     }
 }
 
+/// What connected BLE HID peripherals should be doing, told to them through
+/// their HID Control Point so they can save battery while the PC sleeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PeripheralPower {
+    Awake,
+    Suspended,
+}
+
+impl PeripheralPower {
+    /// HID Control Point (0x2A4C) value that puts a peripheral in this state:
+    /// `Suspend` (0x00) or `Exit Suspend` (0x01).
+    pub fn control_point_value(self) -> u8 {
+        match self {
+            PeripheralPower::Suspended => 0x00,
+            PeripheralPower::Awake => 0x01,
+        }
+    }
+}
+
+/// Peripherals are suspended exactly while the USB bus is: they follow the PC
+/// going to sleep and waking up, not our [`PowerState`]. Plain idleness must
+/// keep keyboards instantly responsive, and activity while the PC sleeps (a
+/// key press that doesn't wake it, a link coming up) briefly makes us
+/// `Active` without the bus resuming; following the state would wake the
+/// peripherals only for the next tick to suspend them again.
+pub fn peripheral_power(usb_suspended: bool) -> PeripheralPower {
+    if usb_suspended {
+        PeripheralPower::Suspended
+    } else {
+        PeripheralPower::Awake
+    }
+}
+
+/// Control Point command to send when the USB bus goes from `was_suspended`
+/// to `suspended`, or `None` when peripherals should be left as they are.
+pub fn peripheral_power_change(was_suspended: bool, suspended: bool) -> Option<PeripheralPower> {
+    let (before, after) = (peripheral_power(was_suspended), peripheral_power(suspended));
+    (before != after).then_some(after)
+}
+
 /// Decide whether screen should be on based on base power state and inactivity policy.
 pub fn screen_should_be_on(
     base_display_on: bool,
@@ -86,6 +127,40 @@ mod tests {
         assert_eq!(next_power_state(0, true, true, IDLE), PowerState::LowPower);
     }
 
+    #[test]
+    fn usb_suspend_suspends_peripherals_and_resume_wakes_them() {
+        assert_eq!(
+            peripheral_power_change(false, true),
+            Some(PeripheralPower::Suspended)
+        );
+        assert_eq!(
+            peripheral_power_change(true, false),
+            Some(PeripheralPower::Awake)
+        );
+        assert_eq!(PeripheralPower::Suspended.control_point_value(), 0x00);
+        assert_eq!(PeripheralPower::Awake.control_point_value(), 0x01);
+    }
+
+    #[test]
+    fn idle_transitions_leave_peripherals_alone() {
+        // Idle and back happens with the bus awake throughout.
+        assert_eq!(
+            next_power_state(IDLE + 1, false, true, IDLE),
+            PowerState::Idle
+        );
+        assert_eq!(peripheral_power_change(false, false), None);
+    }
+
+    #[test]
+    fn activity_while_suspended_leaves_peripherals_suspended() {
+        // Input or a new link while the PC sleeps makes us Active until the
+        // next tick puts us back in LowPower; the bus stays suspended, so the
+        // peripherals hear nothing either way.
+        assert_eq!(next_power_state(0, true, true, IDLE), PowerState::LowPower);
+        assert_eq!(peripheral_power(true), PeripheralPower::Suspended);
+        assert_eq!(peripheral_power_change(true, true), None);
+    }
+
     #[test]
     fn long_idle_without_ble_is_low_power() {
         // No link to keep alive and idle for >2× the timeout.
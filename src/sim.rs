@@ -24,6 +24,10 @@
 
 mod ble;
 mod config;
+// Hardware-free; the coordinator uses its battery policy.
+mod hid;
+// Hardware-free; the BLE connection-parameter policy follows its power states.
+mod power_logic;
 mod ui;
 
 use core::fmt::Write as _;
@@ -43,8 +47,10 @@ use embassy_sync::channel::Channel;
 use embassy_time::{Duration, Timer};
 use heapless::String;
 
-use crate::ble::coordinator::{self, Action, ConnManager, DeviceInfo, UiEvent};
-use crate::ui::ui_logic::{self, Redraw, UiCommand};
+use crate::ble::adv_parser::DeviceIcon;
+use crate::ble::coordinator::{self, Action, ConnManager, DeviceInfo, DeviceRole, UiEvent};
+use crate::ui::gesture::Edge;
+use crate::ui::ui_logic::{self, Button, Redraw, UiCommand};
 use crate::ui::{ButtonEvent, Screen};
 
 bind_interrupts!(struct Irqs {
@@ -56,11 +62,25 @@ bind_interrupts!(struct Irqs {
 /// the same logic runs here and in the real firmware.
 type SimAddr = u32;
 
+static BUTTON_EDGE_CHANNEL: Channel<CriticalSectionRawMutex, Edge, 8> = Channel::new();
 static BUTTON_CHANNEL: Channel<CriticalSectionRawMutex, ButtonEvent, 4> = Channel::new();
 
 #[embassy_executor::task(pool_size = 3)]
-async fn button_task(pin: Peri<'static, AnyPin>, event: ButtonEvent) -> ! {
-    ui::buttons::button_task(pin, event, &BUTTON_CHANNEL.sender()).await
+async fn button_task(pin: Peri<'static, AnyPin>, button: Button) -> ! {
+    ui::buttons::button_task(pin, button, &BUTTON_EDGE_CHANNEL.sender(), || {
+        config::BUTTON_DEBOUNCE_MS
+    })
+    .await
+}
+
+#[embassy_executor::task]
+async fn gesture_task() -> ! {
+    ui::buttons::gesture_task(
+        &BUTTON_EDGE_CHANNEL.receiver(),
+        &BUTTON_CHANNEL.sender(),
+        config::SINGLE_BUTTON_MODE,
+    )
+    .await
 }
 
 /// Synthetic button stimulus.
@@ -121,7 +141,7 @@ fn log_action(uart: &mut Uarte<'static, UARTE0>, action: &Action<SimAddr>) {
                 device.address
             )
         }
-        Action::PersistDevice(device) => {
+        Action::PersistDevice { device, .. } => {
             slog!(uart, "  action: PersistDevice addr={:#x}", device.address)
         }
         Action::Emit(UiEvent::Connected(name)) => {
@@ -129,6 +149,30 @@ fn log_action(uart: &mut Uarte<'static, UARTE0>, action: &Action<SimAddr>) {
         }
         Action::Emit(UiEvent::Disconnected) => slog!(uart, "  action: UI Disconnected"),
         Action::Emit(UiEvent::Error(_)) => slog!(uart, "  action: UI Error"),
+        Action::Emit(UiEvent::Battery(levels)) => {
+            slog!(uart, "  action: UI Battery {:?}", levels)
+        }
+        Action::Emit(UiEvent::LowBattery(name)) => {
+            slog!(uart, "  action: UI LowBattery '{}'", name.as_str())
+        }
+        Action::Emit(UiEvent::Signal(bars)) => {
+            slog!(uart, "  action: UI Signal {:?}", bars)
+        }
+        Action::Emit(UiEvent::WeakLink(name)) => {
+            slog!(uart, "  action: UI WeakLink '{}'", name.as_str())
+        }
+        Action::Emit(UiEvent::Slots(slots)) => {
+            let occupied = slots.iter().filter(|s| s.is_some()).count();
+            slog!(uart, "  action: UI Slots ({} occupied)", occupied)
+        }
+        Action::PublishBattery { slot, level } => {
+            slog!(
+                uart,
+                "  action: PublishBattery slot={} level={:?}",
+                slot,
+                level
+            )
+        }
     }
 }
 
@@ -147,7 +191,8 @@ fn scenario_step(
             for a in coordinator::plan_connect(manager, devices, 0) {
                 log_action(uart, &a);
                 if let Action::ConnectSlot { slot, device } = a {
-                    for b in coordinator::on_slot_connected(manager, slot, &device) {
+                    let role = DeviceRole::from_icon(device.icon);
+                    for b in coordinator::on_slot_connected(manager, slot, &device, role) {
                         log_action(uart, &b);
                     }
                 }
@@ -158,7 +203,8 @@ fn scenario_step(
             for a in coordinator::plan_connect(manager, devices, 1) {
                 log_action(uart, &a);
                 if let Action::ConnectSlot { slot, device } = a {
-                    for b in coordinator::on_slot_connected(manager, slot, &device) {
+                    let role = DeviceRole::from_icon(device.icon);
+                    for b in coordinator::on_slot_connected(manager, slot, &device, role) {
                         log_action(uart, &b);
                     }
                 }
@@ -199,9 +245,10 @@ async fn main(spawner: Spawner) {
         "bt2usb-sim starting (SoftDevice-free Renode build)"
     );
 
-    spawner.spawn(unwrap!(button_task(p.P0_11.into(), ButtonEvent::Up)));
-    spawner.spawn(unwrap!(button_task(p.P0_12.into(), ButtonEvent::Down)));
-    spawner.spawn(unwrap!(button_task(p.P0_24.into(), ButtonEvent::Select)));
+    spawner.spawn(unwrap!(button_task(p.P0_11.into(), Button::Up)));
+    spawner.spawn(unwrap!(button_task(p.P0_12.into(), Button::Down)));
+    spawner.spawn(unwrap!(button_task(p.P0_24.into(), Button::Select)));
+    spawner.spawn(unwrap!(gesture_task()));
     spawner.spawn(unwrap!(ui_stimulus()));
     slog!(
         &mut uart,
@@ -213,11 +260,15 @@ async fn main(spawner: Spawner) {
             address: 0xA1,
             name: name32("Keyboard"),
             rssi: -42,
+            icon: DeviceIcon::Keyboard,
+            bonded: false,
         },
         DeviceInfo {
             address: 0xB2,
             name: name32("Mouse"),
             rssi: -55,
+            icon: DeviceIcon::Mouse,
+            bonded: false,
         },
     ];
     let mut manager: ConnManager<SimAddr> = ConnManager::new();
@@ -238,9 +289,20 @@ async fn main(spawner: Spawner) {
         .await
         {
             Either::First(btn) => {
-                let outcome = ui_logic::on_button(screen, btn, selected, device_count);
+                let count = match screen {
+                    Screen::Profiles => config::MAX_PROFILES,
+                    // No flash here: nothing is paired.
+                    Screen::PairedDevices => 0,
+                    _ => device_count,
+                };
+                let outcome = ui_logic::on_button(screen, btn, selected, count);
                 screen = outcome.screen;
                 selected = outcome.selected;
+                if let Some(setting) = outcome.load_value {
+                    // No stored settings either: editors start from the
+                    // lowest value.
+                    selected = setting.editor().clamp(0) as usize;
+                }
                 slog!(
                     &mut uart,
                     "button {:?} -> screen {:?} (selected {})",
@@ -251,14 +313,42 @@ async fn main(spawner: Spawner) {
                 match outcome.redraw {
                     Redraw::Scanning => slog!(&mut uart, "  redraw: Scanning"),
                     Redraw::DeviceList => slog!(&mut uart, "  redraw: DeviceList"),
+                    Redraw::Connecting => slog!(&mut uart, "  redraw: Connecting"),
                     Redraw::Home => slog!(&mut uart, "  redraw: Home"),
+                    Redraw::Slots => slog!(&mut uart, "  redraw: Slots"),
+                    Redraw::Connected => slog!(&mut uart, "  redraw: Connected"),
+                    Redraw::Profiles => slog!(&mut uart, "  redraw: Profiles"),
+                    Redraw::Menu => slog!(&mut uart, "  redraw: Menu"),
+                    Redraw::Edit => slog!(&mut uart, "  redraw: Edit"),
+                    Redraw::PairedDevices => slog!(&mut uart, "  redraw: PairedDevices"),
+                    Redraw::Forget => slog!(&mut uart, "  redraw: Forget"),
+                    Redraw::About => slog!(&mut uart, "  redraw: About"),
                     Redraw::None => {}
                 }
                 if let Some(cmd) = outcome.command {
                     match cmd {
                         UiCommand::StartScan => slog!(&mut uart, "  cmd: StartScan"),
+                        UiCommand::StopScan => slog!(&mut uart, "  cmd: StopScan"),
                         UiCommand::Connect(i) => slog!(&mut uart, "  cmd: Connect({})", i),
                         UiCommand::Disconnect => slog!(&mut uart, "  cmd: Disconnect"),
+                        UiCommand::DisconnectSlot(i) => {
+                            slog!(&mut uart, "  cmd: DisconnectSlot({})", i)
+                        }
+                        UiCommand::SwitchProfile(i) => {
+                            slog!(&mut uart, "  cmd: SwitchProfile({})", i)
+                        }
+                        UiCommand::SetSetting(setting, value) => {
+                            slog!(&mut uart, "  cmd: SetSetting({:?}, {})", setting, value)
+                        }
+                        UiCommand::ForgetDevice(i) => {
+                            slog!(&mut uart, "  cmd: ForgetDevice({})", i)
+                        }
+                        UiCommand::AllowBackup(job) => {
+                            slog!(&mut uart, "  cmd: AllowBackup({:?})", job)
+                        }
+                        UiCommand::DeclineBackup(job) => {
+                            slog!(&mut uart, "  cmd: DeclineBackup({:?})", job)
+                        }
                     }
                 }
             }
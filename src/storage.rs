@@ -1,53 +1,59 @@
-//! Persistent storage for paired devices and BLE bonding keys.
+//! Persistent storage for paired devices, BLE bonding keys and settings.
 //!
 //! Uses the nRF52840's internal flash via `sequential-storage` crate
 //! to store BLE addresses, display names, RSSI hints, and bonding keys
-//! for previously paired devices so they can be auto-reconnected on power-up.
+//! for previously paired devices so they can be auto-reconnected on power-up,
+//! plus the user-adjustable [`settings`].
 //!
 //! Storage layout:
-//!   - Each record is a serialized `PairedDevice` with optional `BondInfo`.
+//!   - `KEY_PAIRED_DEVICES`: each record is a serialized `PairedDevice` with
+//!     optional `BondInfo` and, for bonded devices, an optional GATT discovery
+//!     cache. This is the first device profile's list; the others keep theirs
+//!     (and their usage) under keys of their own (`region::profile_keys`).
+//!   - `KEY_SETTINGS`: the settings blob (schema in [`settings`]).
+//!   - `KEY_INSTALL_SECRET`: a random secret made on first boot, from which
+//!     (with the chip's device ID) the key sealing the bonds is derived, and
+//!     the floor of the counter their nonces come from (see [`bond_crypto`]).
+//!   - Each blob is sealed with a CRC and kept as two alternating copies, A
+//!     under the key itself and B under `key | KEY_COPY_B` (see [`integrity`]),
+//!     so a torn write or bit flip falls back to the previous save.
 //!   - Records are appended sequentially; the flash pages are managed
 //!     by `sequential-storage` which handles wear levelling and GC.
+//!
+//! The store logic itself is hardware-free and host-tested: [`region`] (map
+//! access, write retries, A/B copies) and [`device_store`] (generic over the
+//! address and bond types). This shell instantiates them with the SoftDevice
+//! flash and types, and owns the global stores.
 
+pub mod backup;
+pub mod bond_crypto;
 mod codec;
+pub mod device_store;
 mod framing;
-
-use codec::{
-    deserialize_address, deserialize_bond, serialize_address, serialize_bond, ADDRESS_RECORD_SIZE,
-    BOND_RECORD_SIZE,
-};
-
-use crate::config::{MAX_PAIRED_DEVICES, STORAGE_FLASH_PAGE_COUNT, STORAGE_FLASH_PAGE_START};
+pub mod gatt_cache;
+pub mod integrity;
+#[cfg(test)]
+mod ram_flash;
+pub mod region;
+pub mod settings;
+
+use bond_crypto::{aes128_encrypt, BondKey, NonceCounter};
+use integrity::{Health, Next};
+use region::Region;
+use settings::{Loaded, Settings, MAX_SETTINGS_SIZE, SCHEMA_VERSION};
+
+use core::cell::Cell;
 use defmt::{debug, error, info, warn};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Timer};
 use heapless::Vec;
 use nrf_softdevice::ble::{Address, EncryptionInfo, IdentityKey, MasterId};
-use sequential_storage::cache::NoCache;
-
-/// Flash page size for nRF52840 (4 KB).
-const FLASH_PAGE_SIZE: u32 = 4096;
-
-/// Start address of our storage region.
-const STORAGE_START: u32 = STORAGE_FLASH_PAGE_START * FLASH_PAGE_SIZE;
-
-/// End address (exclusive) of our storage region.
-const STORAGE_END: u32 = (STORAGE_FLASH_PAGE_START + STORAGE_FLASH_PAGE_COUNT) * FLASH_PAGE_SIZE;
+use nrf_softdevice::{raw, Softdevice};
 
-/// Key for the paired devices list in the map storage.
-const KEY_PAIRED_DEVICES: u8 = 0x01;
-
-// Versioned multi-record framing (magic/version/length prefixes) lives in
-// `framing`; per-record wire sizes (ADDRESS_RECORD_SIZE, BOND_RECORD_SIZE) in `codec`.
-
-/// Retry budget for a flash write that races BLE radio timeslots.
-const FLASH_WRITE_ATTEMPTS: u8 = 3;
-const FLASH_RETRY_BACKOFF_MS: u64 = 20;
-
-/// Maximum serialized size for paired device records.
-/// 4 devices × (address/name metadata + BLE bond keys) plus versioning overhead.
-const MAX_RECORD_SIZE: usize = 512;
+/// The storage region on the SoftDevice flash, with write retries paced by
+/// the Embassy timer.
+pub type Flash = Region<nrf_softdevice::Flash, embassy_time::Delay>;
 
 /// BLE bonding keys stored alongside the paired-device record.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -58,359 +64,247 @@ pub struct BondInfo {
 }
 
 /// A paired device record stored in flash.
-#[derive(Clone, Debug)]
-pub struct PairedDevice {
-    /// BLE address (6 bytes + 1 address type byte).
-    pub address: Address,
-    /// Device name (for UI display, truncated to 32 bytes).
-    pub name: heapless::String<32>,
-    /// Last RSSI seen (for sorting by signal strength).
-    pub last_rssi: i8,
-    /// BLE bonding keys for reconnecting without pairing again.
-    pub bond: Option<BondInfo>,
-}
-
-impl PairedDevice {
-    /// Create a new paired device record.
-    pub fn new(address: Address, name: &str, rssi: i8) -> Self {
-        let mut n: heapless::String<32> = heapless::String::new();
-        // Truncate name to fit heapless::String<32> capacity.
-        for c in name.chars().take(32) {
-            let _ = n.push(c);
-        }
-        Self {
-            address,
-            name: n,
-            last_rssi: rssi,
-            bond: None,
-        }
-    }
-
-    fn serialize_base(&self, buf: &mut [u8]) -> usize {
-        let name_bytes = self.name.as_bytes();
-
-        // Format: [7 addr+type][1 rssi][1 name_len][name_bytes...]
-        let total = ADDRESS_RECORD_SIZE + 1 + 1 + name_bytes.len();
-        if buf.len() < total {
-            return 0;
-        }
-
-        serialize_address(self.address, &mut buf[..ADDRESS_RECORD_SIZE]);
-        buf[7] = self.last_rssi as u8;
-        buf[8] = name_bytes.len() as u8;
-        buf[9..9 + name_bytes.len()].copy_from_slice(name_bytes);
-        total
-    }
-
-    /// Serialize to bytes for flash storage.
-    fn serialize(&self, buf: &mut [u8]) -> usize {
-        let base_len = self.serialize_base(buf);
-        if base_len == 0 || buf.len() < base_len + 1 {
-            return 0;
-        }
-
-        match self.bond {
-            Some(bond) => {
-                if buf.len() < base_len + 1 + BOND_RECORD_SIZE {
-                    return 0;
-                }
-                buf[base_len] = 1;
-                serialize_bond(
-                    &bond,
-                    &mut buf[base_len + 1..base_len + 1 + BOND_RECORD_SIZE],
-                );
-                base_len + 1 + BOND_RECORD_SIZE
-            }
-            None => {
-                buf[base_len] = 0;
-                base_len + 1
-            }
-        }
-    }
+pub type PairedDevice = device_store::PairedDevice<Address, BondInfo>;
 
-    fn deserialize_base(data: &[u8]) -> Option<(Self, usize)> {
-        if data.len() < 9 {
-            return None;
-        }
+/// In-memory cache of paired devices, synced with flash.
+pub type DeviceStore = device_store::DeviceStore<Address, BondInfo>;
 
-        let address = deserialize_address(&data[..ADDRESS_RECORD_SIZE]);
-        let rssi = data[7] as i8;
-        let name_len = data[8] as usize;
+/// Global device store (protected by mutex for async access).
+pub static DEVICE_STORE: Mutex<CriticalSectionRawMutex, DeviceStore> =
+    Mutex::new(DeviceStore::new());
 
-        if data.len() < 9 + name_len {
-            return None;
-        }
+/// Key for the settings blob in the map storage (next to
+/// `region::KEY_PAIRED_DEVICES`).
+const KEY_SETTINGS: u8 = 0x02;
 
-        let name_slice = &data[9..9 + name_len];
-        let mut name: heapless::String<32> = heapless::String::new();
-        if let Ok(s) = core::str::from_utf8(name_slice) {
-            for c in s.chars().take(32) {
-                let _ = name.push(c);
-            }
-        }
+/// Settings blob size including its integrity envelope.
+const SEALED_SETTINGS_SIZE: usize = MAX_SETTINGS_SIZE + integrity::HEADER_SIZE;
 
-        Some((
-            Self {
-                address,
-                name,
-                last_rssi: rssi,
-                bond: None,
-            },
-            9 + name_len,
-        ))
-    }
+/// Live settings. A blocking mutex around a `Cell`, so any task (or sync
+/// code) can read them without awaiting; [`SettingsStore`] updates them.
+static LIVE_SETTINGS: BlockingMutex<CriticalSectionRawMutex, Cell<Settings>> =
+    BlockingMutex::new(Cell::new(Settings::DEFAULT));
 
-    /// Deserialize a versioned record from bytes.
-    fn deserialize(data: &[u8]) -> Option<Self> {
-        let (mut device, mut offset) = Self::deserialize_base(data)?;
-        if offset < data.len() {
-            let has_bond = data[offset] != 0;
-            offset += 1;
-            if has_bond {
-                device.bond = deserialize_bond(data.get(offset..offset + BOND_RECORD_SIZE)?);
-            }
-        }
-        Some(device)
-    }
+/// The settings currently in effect (the defaults until loaded from flash).
+pub fn settings() -> Settings {
+    LIVE_SETTINGS.lock(Cell::get)
 }
 
-/// In-memory cache of paired devices, synced with flash.
-pub struct DeviceStore {
-    /// Cached list of paired devices.
-    devices: Vec<PairedDevice, MAX_PAIRED_DEVICES>,
-    /// Dirty flag - true if cache differs from flash.
+/// The persisted settings, synced with flash.
+pub struct SettingsStore {
+    /// What was loaded, including any newer-schema fields to write back.
+    loaded: Loaded,
+    /// Dirty flag - true if the settings differ from flash.
     dirty: bool,
+    /// Which flash copy the next save overwrites.
+    next: Next,
+    /// The USB host upstream, once identified; changes are bound to it.
+    host: Option<u32>,
 }
 
-impl DeviceStore {
-    /// Create an empty store.
+impl SettingsStore {
+    /// Defaults, until loaded.
     pub const fn new() -> Self {
         Self {
-            devices: Vec::new(),
+            loaded: Loaded {
+                settings: Settings::DEFAULT,
+                carried: Vec::new(),
+                version: SCHEMA_VERSION,
+            },
             dirty: false,
+            next: Next::FIRST,
+            host: None,
         }
     }
 
-    /// Async load from flash using sequential-storage.
-    pub async fn load_from_flash(
-        &mut self,
-        flash: &mut impl embedded_storage_async::nor_flash::NorFlash,
-    ) {
-        let mut buf = [0u8; MAX_RECORD_SIZE];
-
-        // sequential-storage 7 exposes a stateful `MapStorage` (the standalone
-        // `map::fetch_item` free function was removed). It borrows the flash for
-        // the duration of the access and is dropped before we return.
-        let config = sequential_storage::map::MapConfig::new(STORAGE_START..STORAGE_END);
-        let mut map = sequential_storage::map::MapStorage::<u8, _, _>::new(flash, config, NoCache);
-
-        match map.fetch_item::<&[u8]>(&mut buf, &KEY_PAIRED_DEVICES).await {
-            Ok(Some(data)) => {
-                self.devices.clear();
-                self.deserialize_all(data);
-                info!("Loaded {} devices from flash", self.devices.len());
-            }
-            Ok(None) => {
-                info!("No paired devices in flash");
-                self.devices.clear();
-            }
-            Err(e) => {
-                error!("Flash read error: {:?}", defmt::Debug2Format(&e));
-                self.devices.clear();
-            }
-        }
-        self.dirty = false;
+    /// Load (and migrate) the settings from flash and put them into effect.
+    /// Anything missing or unreadable comes back as its default; a damaged
+    /// copy is rewritten on the next save.
+    pub async fn load_from_flash(&mut self, flash: &mut Flash) -> Health {
+        let mut buf_a = [0u8; SEALED_SETTINGS_SIZE];
+        let mut buf_b = [0u8; SEALED_SETTINGS_SIZE];
+
+        let chosen = flash
+            .fetch_sealed(KEY_SETTINGS, &mut buf_a, &mut buf_b)
+            .await;
+        self.loaded = match chosen.payload {
+            Some(data) => settings::decode(data),
+            None => Loaded::default(),
+        };
+        LIVE_SETTINGS.lock(|live| live.set(self.loaded.settings));
+        self.next = chosen.next;
+        self.dirty = chosen.health != Health::Intact;
+        info!(
+            "Settings (schema {}): {:?}",
+            self.loaded.version, self.loaded.settings
+        );
+        chosen.health
     }
 
-    /// Persist all paired devices to flash.
-    pub async fn save_to_flash(
-        &mut self,
-        flash: &mut impl embedded_storage_async::nor_flash::NorFlash,
-    ) {
-        if !self.dirty {
-            debug!("DeviceStore: no changes to save");
-            return;
+    /// Change the settings. They take effect at once and reach flash on the
+    /// next [`Self::save_to_flash`].
+    pub fn update(&mut self, mut settings: Settings) {
+        if let Some(host) = self.host {
+            settings.bind_host(host);
         }
-
-        let mut buf = [0u8; MAX_RECORD_SIZE];
-        let mut data_buf = [0u8; MAX_RECORD_SIZE];
-
-        let len = self.serialize_all(&mut data_buf);
-        let item: &[u8] = &data_buf[..len];
-
-        let config = sequential_storage::map::MapConfig::new(STORAGE_START..STORAGE_END);
-        let mut map = sequential_storage::map::MapStorage::<u8, _, _>::new(flash, config, NoCache);
-
-        // SoftDevice flash operations need radio-idle timeslots and can fail with
-        // a transient busy/timeout error while BLE links are active (this save
-        // runs right at connect time). Retry a few times with a short backoff.
-        for attempt in 1..=FLASH_WRITE_ATTEMPTS {
-            match map
-                .store_item::<&[u8]>(&mut buf, &KEY_PAIRED_DEVICES, &item)
-                .await
-            {
-                Ok(_) => {
-                    info!("Saved {} devices to flash", self.devices.len());
-                    self.dirty = false;
-                    return;
-                }
-                Err(e) => {
-                    if attempt < FLASH_WRITE_ATTEMPTS {
-                        warn!("Flash write busy (attempt {}), retrying", attempt);
-                        Timer::after(Duration::from_millis(FLASH_RETRY_BACKOFF_MS)).await;
-                    } else {
-                        error!(
-                            "Flash write failed after {} attempts: {:?}",
-                            FLASH_WRITE_ATTEMPTS,
-                            defmt::Debug2Format(&e)
-                        );
-                    }
-                }
-            }
+        if self.loaded.settings != settings {
+            self.loaded.settings = settings;
+            LIVE_SETTINGS.lock(|live| live.set(settings));
+            self.dirty = true;
         }
     }
 
-    /// Serialize all devices to a byte buffer using the versioned framing.
-    fn serialize_all(&self, buf: &mut [u8]) -> usize {
-        let Some(mut writer) = framing::Writer::new(buf) else {
-            return 0;
-        };
-        for device in &self.devices {
-            // Stop at the first record that doesn't fit (writer rolls it back).
-            if !writer.push(|slot| device.serialize(slot)) {
-                break;
-            }
+    /// The USB host upstream is now `fingerprint` (see
+    /// [`crate::usb::host_id`]): put the settings bound to it back into effect
+    /// if it was seen before, or bind the current ones to it. Later changes
+    /// are bound to it too.
+    pub fn enter_host(&mut self, fingerprint: u32) {
+        let mut settings = self.loaded.settings;
+        if let Some(binding) = settings.host(fingerprint) {
+            settings.apply_host(&binding);
         }
-        writer.finish()
+        self.host = Some(fingerprint);
+        self.update(settings);
     }
 
-    /// Deserialize all devices from a byte buffer.
-    fn deserialize_all(&mut self, data: &[u8]) {
-        if data.is_empty() {
+    /// Persist the settings to flash.
+    pub async fn save_to_flash(&mut self, flash: &mut Flash) {
+        if !self.dirty {
+            debug!("SettingsStore: no changes to save");
             return;
         }
 
-        if framing::is_versioned(data) {
-            self.deserialize_versioned(data);
-        } else {
-            self.deserialize_legacy(data);
+        let mut data_buf = [0u8; MAX_SETTINGS_SIZE];
+        let len = settings::encode(&self.loaded, &mut data_buf);
+        if len == 0 {
+            error!("Settings do not fit their flash record");
+            return;
         }
-    }
 
-    fn deserialize_versioned(&mut self, data: &[u8]) {
-        for record in framing::records(data) {
-            if let Some(device) = PairedDevice::deserialize(record) {
-                if !self.devices.is_full() {
-                    let _ = self.devices.push(device);
-                }
-            }
+        if flash
+            .store_sealed(KEY_SETTINGS, &mut self.next, &data_buf[..len])
+            .await
+        {
+            info!("Saved settings to flash");
+            self.dirty = false;
         }
     }
+}
 
-    fn deserialize_legacy(&mut self, data: &[u8]) {
-        let count = data[0] as usize;
-        let mut offset = 1;
+/// Global settings store (protected by mutex for async access).
+pub static SETTINGS_STORE: Mutex<CriticalSectionRawMutex, SettingsStore> =
+    Mutex::new(SettingsStore::new());
 
-        for _ in 0..count {
-            if offset >= data.len() {
-                break;
-            }
+/// Key for the per-install secret the bond key is derived from, stored with
+/// the floor of the bond nonce counter (`[secret][floor u32 LE]`).
+const KEY_INSTALL_SECRET: u8 = 0x04;
 
-            // Read name length to determine record size.
-            if offset + 9 > data.len() {
-                break;
-            }
-            let name_len = data[offset + 8] as usize;
-            let record_len = 9 + name_len;
+/// Size of the install secret (one AES-128 key).
+const INSTALL_SECRET_SIZE: usize = 16;
 
-            if offset + record_len > data.len() {
-                break;
-            }
+/// Size of the install secret blob: the secret, then the nonce floor.
+const INSTALL_BLOB_SIZE: usize = INSTALL_SECRET_SIZE + 4;
 
-            if let Some((device, _)) =
-                PairedDevice::deserialize_base(&data[offset..offset + record_len])
-            {
-                if !self.devices.is_full() {
-                    let _ = self.devices.push(device);
-                }
-            }
+/// FICR `DEVICEID[0..2]`: the chip's factory-programmed 64-bit random ID.
+const FICR_DEVICEID: *const u32 = 0x1000_0060 as *const u32;
 
-            offset += record_len;
-        }
-    }
-
-    /// Add a newly paired device.
-    pub fn add(&mut self, device: PairedDevice) {
-        // If already stored (same address), update the record. Only persist
-        // (mark dirty) when something we care about for reconnect actually
-        // changed — RSSI churns on every reconnect and is just a UI hint, so
-        // updating it alone must not cause a flash write (avoidable wear).
-        if let Some(existing) = self
-            .devices
-            .iter_mut()
-            .find(|d| d.address == device.address)
-        {
-            let name_changed = existing.name != device.name;
-            let bond_changed = device.bond.is_some() && existing.bond != device.bond;
-
-            existing.last_rssi = device.last_rssi;
-            if name_changed {
-                existing.name = device.name.clone();
-            }
-            if bond_changed {
-                existing.bond = device.bond;
-            }
-            if name_changed || bond_changed {
-                self.dirty = true;
-                info!("Updated existing paired device");
-            }
-            return;
-        }
-
-        // If at capacity, evict the oldest entry.
-        if self.devices.is_full() {
-            warn!("Paired device store full - evicting oldest entry");
-            self.devices.remove(0);
-        }
+fn device_id() -> u64 {
+    // SAFETY: FICR is read-only memory, always mapped and readable.
+    let (low, high) = unsafe {
+        (
+            core::ptr::read_volatile(FICR_DEVICEID),
+            core::ptr::read_volatile(FICR_DEVICEID.add(1)),
+        )
+    };
+    ((high as u64) << 32) | low as u64
+}
 
-        let _ = self.devices.push(device);
-        self.dirty = true;
-        info!("Added paired device - now storing {}", self.devices.len());
+/// AES-128 on the ECB peripheral through the SoftDevice, which owns it while
+/// enabled. Falls back to software AES if the SoftDevice refuses.
+fn ecb_encrypt(key: &[u8; 16], block: &mut [u8; 16]) {
+    let mut data = raw::nrf_ecb_hal_data_t {
+        key: *key,
+        cleartext: *block,
+        ciphertext: [0; 16],
+    };
+    // SAFETY: `data` is a valid, exclusively borrowed ECB block for the call.
+    let ret = unsafe { raw::sd_ecb_block_encrypt(&mut data) };
+    if ret == raw::NRF_SUCCESS {
+        *block = data.ciphertext;
+    } else {
+        aes128_encrypt(key, block);
     }
+}
 
-    /// Iterate paired devices most-recently-added first, for auto-reconnect of
-    /// multiple links (e.g. keyboard + mouse) on boot.
-    pub fn iter_recent(&self) -> impl Iterator<Item = &PairedDevice> {
-        self.devices.iter().rev()
+/// The key a backup export/import is sealed under: the one the host supplied
+/// with the request.
+pub fn transfer_key(key: [u8; 16]) -> BondKey {
+    BondKey {
+        key,
+        encrypt: ecb_encrypt,
     }
+}
 
-    /// Return all stored BLE bonds.
-    pub fn bonds(&self) -> Vec<BondInfo, MAX_PAIRED_DEVICES> {
-        let mut bonds = Vec::new();
-        for device in &self.devices {
-            if let Some(bond) = device.bond {
-                let _ = bonds.push(bond);
+/// Derive the key sealing the bonds, making and storing the install secret
+/// on first boot (or if it was lost), and reserve this boot's block of bond
+/// nonce counters. `None` if no secret can be had (no randomness, or it
+/// can't be stored); the device list is then read-only. If the block can't
+/// be reserved the bonds can still be opened, but not sealed again.
+pub async fn load_bond_key(flash: &mut Flash, sd: &Softdevice) -> Option<(BondKey, NonceCounter)> {
+    let mut buf_a = [0u8; INSTALL_BLOB_SIZE + integrity::HEADER_SIZE];
+    let mut buf_b = [0u8; INSTALL_BLOB_SIZE + integrity::HEADER_SIZE];
+
+    let chosen = flash
+        .fetch_sealed(KEY_INSTALL_SECRET, &mut buf_a, &mut buf_b)
+        .await;
+    let mut secret = [0u8; INSTALL_SECRET_SIZE];
+    let (floor, created) = match chosen.payload {
+        Some(stored) if stored.len() == INSTALL_BLOB_SIZE => {
+            let (stored_secret, floor) = stored.split_at(INSTALL_SECRET_SIZE);
+            secret.copy_from_slice(stored_secret);
+            (
+                u32::from_le_bytes([floor[0], floor[1], floor[2], floor[3]]),
+                false,
+            )
+        }
+        _ => {
+            // A blob of the wrong size is as good as none.
+            if chosen.health != Health::Intact || chosen.payload.is_some() {
+                warn!("Install secret lost - stored bonds can't be opened");
+            }
+            if nrf_softdevice::random_bytes(sd, &mut secret).is_err() {
+                error!("No randomness for the install secret");
+                return None;
             }
+            (0, true)
+        }
+    };
+
+    // Store the raised floor before handing out any counter below it, so a
+    // reset can only skip counters, never reuse them. This also restores a
+    // damaged copy.
+    let reserved = NonceCounter::reserve(floor);
+    let mut blob = [0u8; INSTALL_BLOB_SIZE];
+    blob[..INSTALL_SECRET_SIZE].copy_from_slice(&secret);
+    let end = reserved.map_or(floor, |(_, end)| end);
+    blob[INSTALL_SECRET_SIZE..].copy_from_slice(&end.to_le_bytes());
+    let mut next = chosen.next;
+    let stored = flash
+        .store_sealed(KEY_INSTALL_SECRET, &mut next, &blob)
+        .await;
+    if created {
+        if !stored {
+            error!("Install secret not stored");
+            return None;
         }
-        bonds
+        info!("Created install secret");
     }
-
-    /// Attach or update a bond for the matching device.
-    pub fn set_bond_for_address(&mut self, address: Address, bond: BondInfo) {
-        if let Some(device) = self
-            .devices
-            .iter_mut()
-            .find(|d| d.address == address || bond.peer_id.is_match(d.address))
-        {
-            if device.bond != Some(bond) {
-                device.bond = Some(bond);
-                self.dirty = true;
-                info!("Updated stored BLE bond");
-            }
+    let key = BondKey::derive(ecb_encrypt, &secret, device_id());
+    match reserved {
+        Some((nonces, _)) if stored => Some((key, nonces)),
+        _ => {
+            error!("No bond nonces reserved - paired devices are read-only");
+            Some((key, NonceCounter::EMPTY))
         }
     }
 }
-
-/// Global device store (protected by mutex for async access).
-pub static DEVICE_STORE: Mutex<CriticalSectionRawMutex, DeviceStore> =
-    Mutex::new(DeviceStore::new());
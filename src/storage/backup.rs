@@ -0,0 +1,142 @@
+//! Pure container format for moving the paired devices (bonds included) to
+//! another bridge.
+//!
+//! The export is sealed with AES-CCM under a transfer key the operator's host
+//! tool supplies with the request, so the bonds only ever leave the bridge
+//! encrypted, and an import that was tampered with (or made under another key)
+//! is rejected as a whole. The payload is a [`framing`](super::framing) blob
+//! of device records, the same record format the store keeps in flash but
+//! with the bonds in the clear inside the container.
+//!
+//! Layout:
+//! ```text
+//! [0..4]    magic "BTBK"
+//! [4]       container version
+//! [5..18]   nonce (random per export)
+//! [18..n-8] payload, encrypted
+//! [n-8..n]  tag over the header and payload
+//! ```
+
+use crate::storage::bond_crypto::{BondKey, NONCE_SIZE, TAG_SIZE};
+use crate::storage::region::MAX_ITEM_SIZE;
+
+const MAGIC: [u8; 4] = *b"BTBK";
+
+/// Container version this firmware writes and reads.
+pub const VERSION: u8 = 1;
+
+/// Header bytes in front of the payload.
+pub const HEADER_SIZE: usize = MAGIC.len() + 1 + NONCE_SIZE;
+
+/// Largest payload: the same budget as the device list in flash.
+pub const MAX_PAYLOAD_SIZE: usize = MAX_ITEM_SIZE;
+
+/// Largest container.
+pub const MAX_BACKUP_SIZE: usize = HEADER_SIZE + MAX_PAYLOAD_SIZE + TAG_SIZE;
+
+/// Why a container was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ImportError {
+    /// Not a container, or cut short.
+    Malformed,
+    /// Written by firmware with a newer container version.
+    UnsupportedVersion(u8),
+    /// Tampered with, or sealed under another transfer key.
+    Unauthenticated,
+}
+
+/// Seal the payload `fill` writes (into the slice it's given, returning its
+/// length) as a container in `buf`. Returns the container length, or 0 if
+/// `buf` can't hold a container.
+pub fn seal(
+    key: &BondKey,
+    nonce: &[u8; NONCE_SIZE],
+    buf: &mut [u8],
+    fill: impl FnOnce(&mut [u8]) -> usize,
+) -> usize {
+    if buf.len() < HEADER_SIZE + TAG_SIZE {
+        return 0;
+    }
+    let (header, rest) = buf.split_at_mut(HEADER_SIZE);
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()] = VERSION;
+    header[MAGIC.len() + 1..].copy_from_slice(nonce);
+
+    let room = (rest.len() - TAG_SIZE).min(MAX_PAYLOAD_SIZE);
+    let len = fill(&mut rest[..room]);
+    let tag = key.seal(nonce, header, &mut rest[..len]);
+    rest[len..len + TAG_SIZE].copy_from_slice(&tag);
+    HEADER_SIZE + len + TAG_SIZE
+}
+
+/// Check and decrypt the container in `data` in place, returning its payload.
+pub fn open<'a>(key: &BondKey, data: &'a mut [u8]) -> Result<&'a [u8], ImportError> {
+    if data.len() < HEADER_SIZE + TAG_SIZE || data[..MAGIC.len()] != MAGIC {
+        return Err(ImportError::Malformed);
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(ImportError::UnsupportedVersion(version));
+    }
+    let (header, rest) = data.split_at_mut(HEADER_SIZE);
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce.copy_from_slice(&header[MAGIC.len() + 1..]);
+    let (payload, tag) = rest.split_at_mut(rest.len() - TAG_SIZE);
+    if !key.open(&nonce, header, payload, tag) {
+        return Err(ImportError::Unauthenticated);
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::bond_crypto::aes128_encrypt;
+
+    fn key(byte: u8) -> BondKey {
+        BondKey {
+            key: [byte; 16],
+            encrypt: aes128_encrypt,
+        }
+    }
+
+    fn sealed(payload: &[u8]) -> heapless::Vec<u8, 64> {
+        let mut buf = [0u8; 64];
+        let len = seal(&key(1), &[9; NONCE_SIZE], &mut buf, |out| {
+            out[..payload.len()].copy_from_slice(payload);
+            payload.len()
+        });
+        heapless::Vec::from_slice(&buf[..len]).unwrap()
+    }
+
+    #[test]
+    fn container_round_trips_encrypted() {
+        let mut data = sealed(b"bond keys");
+        assert_eq!(data.len(), HEADER_SIZE + 9 + TAG_SIZE);
+        assert!(!data.windows(9).any(|w| w == b"bond keys"));
+        assert_eq!(open(&key(1), &mut data), Ok(&b"bond keys"[..]));
+    }
+
+    #[test]
+    fn tampering_wrong_key_and_foreign_data_are_refused() {
+        for i in 0..sealed(b"bond keys").len() {
+            let mut data = sealed(b"bond keys");
+            data[i] ^= 0x01;
+            assert!(open(&key(1), &mut data).is_err(), "byte {i}");
+        }
+        assert_eq!(
+            open(&key(2), &mut sealed(b"bond keys")),
+            Err(ImportError::Unauthenticated)
+        );
+
+        let mut newer = sealed(b"bond keys");
+        newer[MAGIC.len()] = VERSION + 1;
+        assert_eq!(
+            open(&key(1), &mut newer),
+            Err(ImportError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(open(&key(1), &mut [0u8; 8]), Err(ImportError::Malformed));
+        assert_eq!(seal(&key(1), &[0; NONCE_SIZE], &mut [0u8; 8], |_| 0), 0);
+    }
+}
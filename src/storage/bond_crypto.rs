@@ -0,0 +1,391 @@
+//! Pure, hardware-free encryption of bond keys at rest: AES-CCM over a
+//! pluggable AES-128 block function, plus the key derivation.
+//!
+//! The store seals the bond section of every paired-device record (LTK, IRK,
+//! identity address) with AES-CCM, authenticating the rest of the record
+//! (address and name) as associated data so a bond can't be moved onto another
+//! device's record. The key is derived from the chip's device ID and a
+//! per-install random secret.
+//!
+//! A CCM nonce must never repeat under one key. Each save seals its bonds
+//! under a counter from a [`NonceCounter`], whose position lives with the
+//! install secret rather than in the device list: the list can be lost (and
+//! its generation restart) while the key stays the same.
+//!
+//! CCM only ever needs the *forward* block cipher. On the target that's the
+//! SoftDevice's ECB peripheral; [`aes128_encrypt`] is the software path, used
+//! on the host (and as the fallback). Parameters follow RFC 3610 with
+//! `L = 2` (13-byte nonce) and an 8-byte tag.
+
+/// AES-128 encryption of one block in place, under `key`.
+pub type BlockEncrypt = fn(key: &[u8; 16], block: &mut [u8; 16]);
+
+/// CCM nonce length (`15 - L`, with `L = 2`).
+pub const NONCE_SIZE: usize = 13;
+
+/// CCM authentication tag length.
+pub const TAG_SIZE: usize = 8;
+
+/// The bond encryption key and the block cipher to use it with.
+#[derive(Clone, Copy)]
+pub struct BondKey {
+    pub key: [u8; 16],
+    pub encrypt: BlockEncrypt,
+}
+
+impl BondKey {
+    /// Derive the key from the per-install `secret` and the chip's 64-bit
+    /// `device_id`: one AES block of the ID and a fixed label, under the
+    /// secret. A copied flash image (secret included) is still useless on a
+    /// different chip.
+    pub fn derive(encrypt: BlockEncrypt, secret: &[u8; 16], device_id: u64) -> Self {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&device_id.to_le_bytes());
+        block[8..].copy_from_slice(b"bt2usbBK");
+        encrypt(secret, &mut block);
+        Self {
+            key: block,
+            encrypt,
+        }
+    }
+
+    /// Encrypt `data` in place and return its tag.
+    pub fn seal(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &mut [u8]) -> [u8; TAG_SIZE] {
+        let mac = self.cbc_mac(nonce, aad, data);
+        self.ctr(nonce, data);
+        let mut tag = [0u8; TAG_SIZE];
+        let s0 = self.keystream(nonce, 0);
+        for (t, (m, s)) in tag.iter_mut().zip(mac.iter().zip(&s0)) {
+            *t = m ^ s;
+        }
+        tag
+    }
+
+    /// Decrypt `data` in place, returning whether `tag` authenticates it
+    /// (and `aad`). On `false` the contents of `data` are meaningless.
+    pub fn open(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        if tag.len() != TAG_SIZE {
+            return false;
+        }
+        self.ctr(nonce, data);
+        let mac = self.cbc_mac(nonce, aad, data);
+        let s0 = self.keystream(nonce, 0);
+        // Constant-time compare.
+        let diff = tag
+            .iter()
+            .zip(mac.iter().zip(&s0))
+            .fold(0u8, |acc, (t, (m, s))| acc | (t ^ m ^ s));
+        diff == 0
+    }
+
+    /// Counter block `A_i` encrypted: the keystream block for counter `i`.
+    fn keystream(&self, nonce: &[u8; NONCE_SIZE], i: u16) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[0] = 1; // L - 1
+        block[1..14].copy_from_slice(nonce);
+        block[14..].copy_from_slice(&i.to_be_bytes());
+        (self.encrypt)(&self.key, &mut block);
+        block
+    }
+
+    /// XOR `data` with the keystream from counter 1.
+    fn ctr(&self, nonce: &[u8; NONCE_SIZE], data: &mut [u8]) {
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let stream = self.keystream(nonce, i as u16 + 1);
+            for (d, s) in chunk.iter_mut().zip(&stream) {
+                *d ^= s;
+            }
+        }
+    }
+
+    /// CBC-MAC over `B_0`, the length-prefixed `aad` and `data`.
+    fn cbc_mac(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &[u8]) -> [u8; 16] {
+        let mut b0 = [0u8; 16];
+        let adata = if aad.is_empty() { 0 } else { 0x40 };
+        b0[0] = adata | (((TAG_SIZE as u8 - 2) / 2) << 3) | 1;
+        b0[1..14].copy_from_slice(nonce);
+        b0[14..].copy_from_slice(&(data.len() as u16).to_be_bytes());
+
+        let mut mac = CbcMac {
+            bond_key: self,
+            x: b0,
+            fill: 16,
+        };
+        mac.flush();
+        if !aad.is_empty() {
+            mac.absorb(&(aad.len() as u16).to_be_bytes());
+            mac.absorb(aad);
+            mac.flush();
+        }
+        mac.absorb(data);
+        mac.flush();
+        mac.x
+    }
+}
+
+/// Counters handed out per boot. The block is reserved in flash before its
+/// first counter is used, so losing the last position only skips ahead.
+pub const NONCE_BLOCK: u32 = 1 << 16;
+
+/// Source of never-repeating nonce counters: the block `next..end` reserved
+/// for this boot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NonceCounter {
+    next: u32,
+    end: u32,
+}
+
+impl NonceCounter {
+    /// Counters `next..end`.
+    pub const fn new(next: u32, end: u32) -> Self {
+        Self { next, end }
+    }
+
+    /// A counter with nothing left to hand out: bonds can be opened but not
+    /// sealed.
+    pub const EMPTY: Self = Self::new(0, 0);
+
+    /// Reserve the block starting at the stored `floor`. Returns the counter
+    /// and the floor to store *before* using it, or `None` once the counter
+    /// space is used up.
+    pub fn reserve(floor: u32) -> Option<(Self, u32)> {
+        let end = floor.checked_add(NONCE_BLOCK)?;
+        Some((Self::new(floor, end), end))
+    }
+
+    /// The next unused counter, or `None` once the block is used up.
+    pub fn take(&mut self) -> Option<u32> {
+        (self.next < self.end).then(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+}
+
+/// Running CBC-MAC state: `fill` bytes of the current block are absorbed.
+struct CbcMac<'a> {
+    bond_key: &'a BondKey,
+    x: [u8; 16],
+    fill: usize,
+}
+
+impl CbcMac<'_> {
+    fn absorb(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.fill == 16 {
+                self.flush();
+            }
+            self.x[self.fill] ^= byte;
+            self.fill += 1;
+        }
+    }
+
+    /// Encrypt a started block (zero padding is implicit in the XOR).
+    fn flush(&mut self) {
+        if self.fill > 0 {
+            (self.bond_key.encrypt)(&self.bond_key.key, &mut self.x);
+            self.fill = 0;
+        }
+    }
+}
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+fn xtime(x: u8) -> u8 {
+    (x << 1) ^ ((x >> 7) * 0x1b)
+}
+
+fn sub_bytes(state: &mut [u8; 16]) {
+    for byte in state.iter_mut() {
+        *byte = SBOX[*byte as usize];
+    }
+}
+
+/// The state is column-major (`state[row + 4 * column]`); row `r` rotates
+/// left by `r`.
+fn shift_rows(state: &mut [u8; 16]) {
+    let old = *state;
+    for (i, byte) in state.iter_mut().enumerate() {
+        let (row, column) = (i % 4, i / 4);
+        *byte = old[row + 4 * ((column + row) % 4)];
+    }
+}
+
+fn mix_columns(state: &mut [u8; 16]) {
+    for column in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] = a0 ^ all ^ xtime(a0 ^ a1);
+        column[1] = a1 ^ all ^ xtime(a1 ^ a2);
+        column[2] = a2 ^ all ^ xtime(a2 ^ a3);
+        column[3] = a3 ^ all ^ xtime(a3 ^ a0);
+    }
+}
+
+fn add_round_key(state: &mut [u8; 16], round_key: &[u8]) {
+    for (byte, key) in state.iter_mut().zip(round_key) {
+        *byte ^= key;
+    }
+}
+
+/// Software AES-128 (FIPS-197) block encryption. Small rather than fast: it
+/// only runs for a handful of blocks per flash save or load.
+pub fn aes128_encrypt(key: &[u8; 16], block: &mut [u8; 16]) {
+    let mut round_keys = [0u8; 176];
+    round_keys[..16].copy_from_slice(key);
+    let mut rcon = 1u8;
+    for word in 4..44 {
+        let at = 4 * word;
+        let mut t = [
+            round_keys[at - 4],
+            round_keys[at - 3],
+            round_keys[at - 2],
+            round_keys[at - 1],
+        ];
+        if word % 4 == 0 {
+            t = [
+                SBOX[t[1] as usize] ^ rcon,
+                SBOX[t[2] as usize],
+                SBOX[t[3] as usize],
+                SBOX[t[0] as usize],
+            ];
+            rcon = xtime(rcon);
+        }
+        for (j, byte) in t.iter().enumerate() {
+            round_keys[at + j] = round_keys[at - 16 + j] ^ byte;
+        }
+    }
+
+    add_round_key(block, &round_keys[..16]);
+    for round in 1..10 {
+        sub_bytes(block);
+        shift_rows(block);
+        mix_columns(block);
+        add_round_key(block, &round_keys[16 * round..16 * round + 16]);
+    }
+    sub_bytes(block);
+    shift_rows(block);
+    add_round_key(block, &round_keys[160..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonce_blocks_never_overlap() {
+        let (mut first, floor) = NonceCounter::reserve(0).unwrap();
+        assert_eq!(floor, NONCE_BLOCK);
+        assert_eq!(first.take(), Some(0));
+        assert_eq!(first.take(), Some(1));
+        // The next boot starts past everything this one could hand out,
+        // however far it got.
+        let (mut second, _) = NonceCounter::reserve(floor).unwrap();
+        assert_eq!(second.take(), Some(NONCE_BLOCK));
+        assert_eq!(NonceCounter::reserve(u32::MAX - NONCE_BLOCK + 1), None);
+
+        let mut last = NonceCounter::new(5, 6);
+        assert_eq!(last.take(), Some(5));
+        assert_eq!(last.take(), None);
+        assert_eq!(NonceCounter::EMPTY.clone().take(), None);
+    }
+
+    #[test]
+    fn aes128_matches_fips_197() {
+        let key: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let mut block: [u8; 16] = core::array::from_fn(|i| (i as u8) * 0x11);
+        aes128_encrypt(&key, &mut block);
+        assert_eq!(
+            block,
+            [
+                0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+                0xc5, 0x5a
+            ]
+        );
+    }
+
+    /// RFC 3610, packet vector #1.
+    fn rfc3610() -> (BondKey, [u8; NONCE_SIZE], [u8; 8], [u8; 23]) {
+        let key = BondKey {
+            key: core::array::from_fn(|i| 0xC0 + i as u8),
+            encrypt: aes128_encrypt,
+        };
+        let nonce = [
+            0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5,
+        ];
+        let aad = core::array::from_fn(|i| i as u8);
+        let data = core::array::from_fn(|i| 0x08 + i as u8);
+        (key, nonce, aad, data)
+    }
+
+    #[test]
+    fn ccm_matches_rfc_3610() {
+        let (key, nonce, aad, mut data) = rfc3610();
+        let tag = key.seal(&nonce, &aad, &mut data);
+        assert_eq!(
+            data,
+            [
+                0x58, 0x8C, 0x97, 0x9A, 0x61, 0xC6, 0x63, 0xD2, 0xF0, 0x66, 0xD0, 0xC2, 0xC0, 0xF9,
+                0x89, 0x80, 0x6D, 0x5F, 0x6B, 0x61, 0xDA, 0xC3, 0x84
+            ]
+        );
+        assert_eq!(tag, [0x17, 0xE8, 0xD1, 0x2C, 0xFD, 0xF9, 0x26, 0xE0]);
+
+        assert!(key.open(&nonce, &aad, &mut data, &tag));
+        assert_eq!(data, rfc3610().3);
+    }
+
+    #[test]
+    fn ccm_rejects_tampering() {
+        let (key, nonce, aad, mut data) = rfc3610();
+        let tag = key.seal(&nonce, &aad, &mut data);
+
+        let mut flipped = data;
+        flipped[5] ^= 1;
+        assert!(!key.open(&nonce, &aad, &mut flipped, &tag));
+
+        let mut other_aad = aad;
+        other_aad[0] ^= 1;
+        assert!(!key.open(&nonce, &other_aad, &mut data.clone(), &tag));
+
+        let mut other_nonce = nonce;
+        other_nonce[0] ^= 1;
+        assert!(!key.open(&other_nonce, &aad, &mut data.clone(), &tag));
+
+        let other_key = BondKey {
+            key: [0; 16],
+            ..key
+        };
+        assert!(!other_key.open(&nonce, &aad, &mut data.clone(), &tag));
+        assert!(!key.open(&nonce, &aad, &mut data, &tag[..4]));
+    }
+
+    #[test]
+    fn derived_key_depends_on_secret_and_device() {
+        let secret = [7u8; 16];
+        let a = BondKey::derive(aes128_encrypt, &secret, 0x0123_4567_89AB_CDEF);
+        let b = BondKey::derive(aes128_encrypt, &secret, 0x0123_4567_89AB_CDEE);
+        let c = BondKey::derive(aes128_encrypt, &[8u8; 16], 0x0123_4567_89AB_CDEF);
+        let again = BondKey::derive(aes128_encrypt, &secret, 0x0123_4567_89AB_CDEF);
+        assert_eq!(a.key, again.key);
+        assert_ne!(a.key, b.key);
+        assert_ne!(a.key, c.key);
+    }
+}
@@ -1,16 +1,18 @@
 //! Byte-level wire format for paired-device and bond records in flash.
 //!
 //! Pure (de)serialization of BLE addresses and bonding keys, kept separate from
-//! the persistence logic in `storage.rs`.
+//! the persistence logic in `device_store`, which reaches them through its
+//! [`Record`]/[`Bond`] traits.
 
+use super::device_store::{Bond, Record};
 use super::BondInfo;
 use nrf_softdevice::ble::{Address, AddressType, EncryptionInfo, IdentityKey, MasterId};
 use nrf_softdevice::raw;
 
 /// Serialized size of a BLE address: 6 address bytes + 1 address-type byte.
-pub(super) const ADDRESS_RECORD_SIZE: usize = 7;
+const ADDRESS_RECORD_SIZE: usize = 7;
 /// Serialized size of a bond record (ediv + rand + ltk + flags + irk + address).
-pub(super) const BOND_RECORD_SIZE: usize = 50;
+const BOND_RECORD_SIZE: usize = 50;
 
 fn address_type_to_byte(address_type: AddressType) -> u8 {
     match address_type {
@@ -33,18 +35,18 @@ fn byte_to_address_type(value: u8) -> AddressType {
     }
 }
 
-pub(super) fn serialize_address(address: Address, buf: &mut [u8]) {
+fn serialize_address(address: Address, buf: &mut [u8]) {
     buf[0..6].copy_from_slice(&address.bytes());
     buf[6] = address_type_to_byte(address.address_type());
 }
 
-pub(super) fn deserialize_address(data: &[u8]) -> Address {
+fn deserialize_address(data: &[u8]) -> Address {
     let mut bytes = [0u8; 6];
     bytes.copy_from_slice(&data[0..6]);
     Address::new(byte_to_address_type(data[6]), bytes)
 }
 
-pub(super) fn serialize_bond(bond: &BondInfo, buf: &mut [u8]) {
+fn serialize_bond(bond: &BondInfo, buf: &mut [u8]) {
     buf[0..2].copy_from_slice(&bond.master_id.ediv.to_le_bytes());
     buf[2..10].copy_from_slice(&bond.master_id.rand);
     buf[10..26].copy_from_slice(&bond.key.ltk);
@@ -53,7 +55,7 @@ pub(super) fn serialize_bond(bond: &BondInfo, buf: &mut [u8]) {
     serialize_address(bond.peer_id.addr, &mut buf[43..50]);
 }
 
-pub(super) fn deserialize_bond(data: &[u8]) -> Option<BondInfo> {
+fn deserialize_bond(data: &[u8]) -> Option<BondInfo> {
     if data.len() < BOND_RECORD_SIZE {
         return None;
     }
@@ -81,3 +83,33 @@ pub(super) fn deserialize_bond(data: &[u8]) -> Option<BondInfo> {
         }),
     })
 }
+
+impl Record for Address {
+    const SIZE: usize = ADDRESS_RECORD_SIZE;
+
+    fn write(&self, buf: &mut [u8]) {
+        serialize_address(*self, buf);
+    }
+
+    fn read(data: &[u8]) -> Option<Self> {
+        Some(deserialize_address(data))
+    }
+}
+
+impl Record for BondInfo {
+    const SIZE: usize = BOND_RECORD_SIZE;
+
+    fn write(&self, buf: &mut [u8]) {
+        serialize_bond(self, buf);
+    }
+
+    fn read(data: &[u8]) -> Option<Self> {
+        deserialize_bond(data)
+    }
+}
+
+impl Bond<Address> for BondInfo {
+    fn matches(&self, address: Address) -> bool {
+        self.peer_id.is_match(address)
+    }
+}
@@ -0,0 +1,1399 @@
+//! Pure paired-device store: the in-memory list, its add/evict/update rules and
+//! its flash record format.
+//!
+//! Each device also carries usage metadata — when it was last connected (a
+//! sequence number), how often, and its role and icon — which orders
+//! reconnects and picks the device to evict (least recently used). It lives in
+//! a small blob of its own under `KEY_DEVICE_USAGE`, so a reconnect doesn't
+//! rewrite the device list, and plain recency updates are batched
+//! (`STORAGE_USAGE_SAVE_BATCH`) to spare the flash.
+//!
+//! The store holds one profile's devices at a time: each profile keeps its
+//! own device list and usage blobs ([`region::profile_keys`]), and
+//! [`DeviceStore::switch_profile`] saves one and loads another.
+//!
+//! Generic over the address type `A` and bond type `B` (like
+//! `coordinator::DeviceInfo<A>`), so host tests run it against plain stand-ins
+//! and a RAM flash. The firmware instantiates it with the SoftDevice `Address`
+//! and [`BondInfo`](super::BondInfo), whose wire forms live in `codec`.
+
+use crate::ble::adv_parser::DeviceIcon;
+use crate::ble::coordinator::DeviceRole;
+use crate::config::{MAX_PAIRED_DEVICES, STORAGE_USAGE_SAVE_BATCH};
+use crate::storage::backup::{self, ImportError};
+use crate::storage::bond_crypto::{BondKey, NonceCounter, NONCE_SIZE, TAG_SIZE};
+use crate::storage::framing;
+use crate::storage::gatt_cache::GattCache;
+use crate::storage::integrity::{self, Health, Next};
+use crate::storage::region::{self, Region, MAX_ITEM_SIZE};
+use embedded_hal_async::delay::DelayNs;
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Vec;
+
+/// Fixed-size wire form of a stored address or bond.
+pub trait Record: Sized {
+    /// Serialized size in bytes.
+    const SIZE: usize;
+    /// Write into `buf` (exactly `SIZE` bytes).
+    fn write(&self, buf: &mut [u8]);
+    /// Read back from `data` (exactly `SIZE` bytes); `None` if malformed.
+    fn read(data: &[u8]) -> Option<Self>;
+}
+
+/// A stored bond: its wire form plus address matching.
+pub trait Bond<A>: Record + Copy + PartialEq {
+    /// `address` is this bond's peer: its identity address, or a private
+    /// address that resolves to it.
+    fn matches(&self, address: A) -> bool;
+}
+
+/// Bond section flags, in front of the bond section of a device record.
+const BOND_NONE: u8 = 0;
+/// `[bond]` in plaintext, as written before bonds were encrypted.
+const BOND_PLAIN: u8 = 1;
+/// `[counter u32 LE][bond, AES-CCM encrypted][tag]`, authenticating the
+/// record in front of it (address, RSSI and name) as associated data.
+const BOND_SEALED: u8 = 2;
+
+/// Bytes of the nonce counter in a sealed bond section.
+const COUNTER_SIZE: usize = 4;
+
+/// Largest bond wire form the store can seal.
+const MAX_BOND_SIZE: usize = 64;
+
+/// How a record's bond section read back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BondRead {
+    /// No bond stored.
+    Absent,
+    /// A plaintext bond (to be re-saved encrypted).
+    Plain,
+    /// A sealed bond that authenticated.
+    Sealed,
+    /// A sealed bond that failed authentication (tampered, or sealed under
+    /// another key).
+    Rejected,
+    /// A sealed bond, but no key to open it with.
+    Locked,
+}
+
+/// CCM nonce of a sealed bond: the save's counter (from the store's
+/// [`NonceCounter`], never repeated under a key), then the record's address
+/// bytes (unique within a save), then a domain byte.
+fn bond_nonce(counter: u32, address: &[u8]) -> [u8; NONCE_SIZE] {
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce[..COUNTER_SIZE].copy_from_slice(&counter.to_le_bytes());
+    for (n, a) in nonce[COUNTER_SIZE..NONCE_SIZE - 1].iter_mut().zip(address) {
+        *n = *a;
+    }
+    nonce[NONCE_SIZE - 1] = b'B';
+    nonce
+}
+
+/// Bytes of a usage record after its address:
+/// `[last_used u32 LE][connects u16 LE][role][icon]`.
+const USAGE_SIZE: usize = 8;
+
+/// Usage blob size (framed records, before the integrity envelope).
+const USAGE_BLOB_SIZE: usize = 160;
+
+fn role_to_byte(role: DeviceRole) -> u8 {
+    match role {
+        DeviceRole::Other => 0,
+        DeviceRole::Keyboard => 1,
+        DeviceRole::Pointer => 2,
+        DeviceRole::Consumer => 3,
+    }
+}
+
+fn byte_to_role(value: u8) -> DeviceRole {
+    match value {
+        1 => DeviceRole::Keyboard,
+        2 => DeviceRole::Pointer,
+        3 => DeviceRole::Consumer,
+        _ => DeviceRole::Other,
+    }
+}
+
+fn icon_to_byte(icon: DeviceIcon) -> u8 {
+    match icon {
+        DeviceIcon::Other => 0,
+        DeviceIcon::Keyboard => 1,
+        DeviceIcon::Mouse => 2,
+        DeviceIcon::Gamepad => 3,
+    }
+}
+
+fn byte_to_icon(value: u8) -> DeviceIcon {
+    match value {
+        1 => DeviceIcon::Keyboard,
+        2 => DeviceIcon::Mouse,
+        3 => DeviceIcon::Gamepad,
+        _ => DeviceIcon::Other,
+    }
+}
+
+/// A paired device record stored in flash.
+#[derive(Clone, Debug)]
+pub struct PairedDevice<A, B> {
+    /// BLE address (6 bytes + 1 address type byte on the SoftDevice).
+    pub address: A,
+    /// Device name (for UI display, truncated to 32 bytes).
+    pub name: heapless::String<32>,
+    /// Last RSSI seen (for sorting by signal strength).
+    pub last_rssi: i8,
+    /// BLE bonding keys for reconnecting without pairing again.
+    pub bond: Option<B>,
+    /// Cached GATT discovery results (bonded devices only), so reconnect can
+    /// skip service discovery.
+    pub gatt_cache: Option<GattCache>,
+    /// The store's connection sequence number at the last successful
+    /// connection (0 = not connected since usage was recorded).
+    pub last_used: u32,
+    /// Successful connections so far.
+    pub connects: u16,
+    /// What the device was last used as (from its Report Map).
+    pub role: DeviceRole,
+    /// Device-list icon, from its advertised GAP Appearance.
+    pub icon: DeviceIcon,
+}
+
+impl<A: Record, B: Record> PairedDevice<A, B> {
+    /// Create a new paired device record.
+    pub fn new(address: A, name: &str, rssi: i8) -> Self {
+        let mut n: heapless::String<32> = heapless::String::new();
+        // Truncate name to fit heapless::String<32> capacity.
+        for c in name.chars().take(32) {
+            let _ = n.push(c);
+        }
+        Self {
+            address,
+            name: n,
+            last_rssi: rssi,
+            bond: None,
+            gatt_cache: None,
+            last_used: 0,
+            connects: 0,
+            role: DeviceRole::Other,
+            icon: DeviceIcon::Other,
+        }
+    }
+
+    fn serialize_base(&self, buf: &mut [u8]) -> usize {
+        let name_bytes = self.name.as_bytes();
+
+        // Format: [address][1 rssi][1 name_len][name_bytes...]
+        let name_at = A::SIZE + 2;
+        let total = name_at + name_bytes.len();
+        if buf.len() < total {
+            return 0;
+        }
+
+        self.address.write(&mut buf[..A::SIZE]);
+        buf[A::SIZE] = self.last_rssi as u8;
+        buf[A::SIZE + 1] = name_bytes.len() as u8;
+        buf[name_at..total].copy_from_slice(name_bytes);
+        total
+    }
+
+    /// Serialize to bytes, sealing the bond with `seal`'s key under its
+    /// counter (flash), or in plaintext without (inside a sealed export).
+    fn serialize(&self, buf: &mut [u8], seal: Option<(&BondKey, u32)>) -> usize {
+        let base_len = self.serialize_base(buf);
+        if base_len == 0 || buf.len() < base_len + 1 {
+            return 0;
+        }
+
+        let bond_end = match (&self.bond, seal) {
+            (Some(bond), Some((key, counter))) => {
+                let bond_at = base_len + 1 + COUNTER_SIZE;
+                let tag_at = bond_at + B::SIZE;
+                if buf.len() < tag_at + TAG_SIZE {
+                    return 0;
+                }
+                buf[base_len] = BOND_SEALED;
+                buf[base_len + 1..bond_at].copy_from_slice(&counter.to_le_bytes());
+                bond.write(&mut buf[bond_at..tag_at]);
+                let nonce = bond_nonce(counter, &buf[..A::SIZE]);
+                let (record, bond_bytes) = buf.split_at_mut(bond_at);
+                let tag = key.seal(&nonce, &record[..base_len], &mut bond_bytes[..B::SIZE]);
+                buf[tag_at..tag_at + TAG_SIZE].copy_from_slice(&tag);
+                tag_at + TAG_SIZE
+            }
+            (Some(bond), None) => {
+                if buf.len() < base_len + 1 + B::SIZE {
+                    return 0;
+                }
+                buf[base_len] = BOND_PLAIN;
+                bond.write(&mut buf[base_len + 1..base_len + 1 + B::SIZE]);
+                base_len + 1 + B::SIZE
+            }
+            (None, _) => {
+                buf[base_len] = BOND_NONE;
+                base_len + 1
+            }
+        };
+
+        // Optional trailing GATT cache section. Records written before it
+        // existed simply end after the bond, which reads back as "no cache".
+        if buf.len() < bond_end + 1 {
+            return 0;
+        }
+        match &self.gatt_cache {
+            Some(cache) => {
+                let written = cache.serialize(&mut buf[bond_end + 1..]);
+                if written == 0 {
+                    // Doesn't fit: drop the cache rather than the device.
+                    buf[bond_end] = 0;
+                    return bond_end + 1;
+                }
+                buf[bond_end] = 1;
+                bond_end + 1 + written
+            }
+            None => {
+                buf[bond_end] = 0;
+                bond_end + 1
+            }
+        }
+    }
+
+    fn deserialize_base(data: &[u8]) -> Option<(Self, usize)> {
+        let name_at = A::SIZE + 2;
+        if data.len() < name_at {
+            return None;
+        }
+
+        let address = A::read(&data[..A::SIZE])?;
+        let rssi = data[A::SIZE] as i8;
+        let name_len = data[A::SIZE + 1] as usize;
+
+        if data.len() < name_at + name_len {
+            return None;
+        }
+
+        let name_slice = &data[name_at..name_at + name_len];
+        let mut name: heapless::String<32> = heapless::String::new();
+        if let Ok(s) = core::str::from_utf8(name_slice) {
+            for c in s.chars().take(32) {
+                let _ = name.push(c);
+            }
+        }
+
+        Some((
+            Self {
+                address,
+                name,
+                last_rssi: rssi,
+                bond: None,
+                gatt_cache: None,
+                last_used: 0,
+                connects: 0,
+                role: DeviceRole::Other,
+                icon: DeviceIcon::Other,
+            },
+            name_at + name_len,
+        ))
+    }
+
+    /// Serialize the usage record: `[address]` then `USAGE_SIZE` bytes.
+    fn serialize_usage(&self, buf: &mut [u8]) -> usize {
+        let total = A::SIZE + USAGE_SIZE;
+        if buf.len() < total {
+            return 0;
+        }
+        self.address.write(&mut buf[..A::SIZE]);
+        let usage = &mut buf[A::SIZE..total];
+        usage[..4].copy_from_slice(&self.last_used.to_le_bytes());
+        usage[4..6].copy_from_slice(&self.connects.to_le_bytes());
+        usage[6] = role_to_byte(self.role);
+        usage[7] = icon_to_byte(self.icon);
+        total
+    }
+
+    /// Apply a usage record (`data` after its address).
+    fn apply_usage(&mut self, usage: &[u8]) {
+        self.last_used = u32::from_le_bytes([usage[0], usage[1], usage[2], usage[3]]);
+        self.connects = u16::from_le_bytes([usage[4], usage[5]]);
+        self.role = byte_to_role(usage[6]);
+        self.icon = byte_to_icon(usage[7]);
+    }
+
+    /// Deserialize a versioned record from bytes, opening a sealed bond with
+    /// `key`. A bond that can't be opened is dropped (the device must pair
+    /// again), not the device.
+    fn deserialize(data: &[u8], key: Option<&BondKey>) -> Option<(Self, BondRead)> {
+        let (mut device, mut offset) = Self::deserialize_base(data)?;
+        let base_len = offset;
+        let mut read = BondRead::Absent;
+        if offset < data.len() {
+            let flag = data[offset];
+            offset += 1;
+            match flag {
+                BOND_NONE => {}
+                BOND_PLAIN => {
+                    device.bond = B::read(data.get(offset..offset + B::SIZE)?);
+                    offset += B::SIZE;
+                    read = BondRead::Plain;
+                }
+                _ => {
+                    let sealed = data.get(offset..offset + COUNTER_SIZE + B::SIZE + TAG_SIZE)?;
+                    offset += sealed.len();
+                    let (counter, rest) = sealed.split_at(COUNTER_SIZE);
+                    let (bond_bytes, tag) = rest.split_at(B::SIZE);
+                    let counter =
+                        u32::from_le_bytes([counter[0], counter[1], counter[2], counter[3]]);
+                    let nonce = bond_nonce(counter, &data[..A::SIZE]);
+                    let mut plain = [0u8; MAX_BOND_SIZE];
+                    let plain = plain.get_mut(..B::SIZE)?;
+                    plain.copy_from_slice(bond_bytes);
+                    read = match key {
+                        Some(key) if key.open(&nonce, &data[..base_len], plain, tag) => {
+                            device.bond = B::read(plain);
+                            BondRead::Sealed
+                        }
+                        Some(_) => BondRead::Rejected,
+                        None => BondRead::Locked,
+                    };
+                }
+            }
+        }
+        if offset < data.len() {
+            let has_cache = data[offset] != 0;
+            offset += 1;
+            if has_cache {
+                // A stale/corrupt cache is just a miss; never lose the device.
+                device.gatt_cache = GattCache::deserialize(&data[offset..]);
+            }
+        }
+        if matches!(read, BondRead::Rejected | BondRead::Locked) {
+            // Handles cached under a bond we can't use are useless.
+            device.gatt_cache = None;
+        }
+        Some((device, read))
+    }
+}
+
+/// In-memory cache of paired devices, synced with flash.
+pub struct DeviceStore<A, B> {
+    /// Cached list of paired devices.
+    devices: Vec<PairedDevice<A, B>, MAX_PAIRED_DEVICES>,
+    /// Dirty flag - true if cache differs from flash.
+    dirty: bool,
+    /// Which flash copy the next save overwrites.
+    next: Next,
+    /// Sequence number of the latest connection (the highest `last_used`).
+    clock: u32,
+    /// Usage changed in a way the next save should persist.
+    usage_dirty: bool,
+    /// Connections recorded since the usage was last saved.
+    pending_connects: u8,
+    /// Which flash copy the next usage save overwrites.
+    usage_next: Next,
+    /// Key sealing the bonds in flash, and the counters for their nonces.
+    /// Without a key the device list is read-only: saving would drop the
+    /// bonds it can't open.
+    bond_key: Option<(BondKey, NonceCounter)>,
+    /// Profile whose devices are loaded.
+    profile: u8,
+}
+
+impl<A, B> DeviceStore<A, B> {
+    /// Create an empty store.
+    pub const fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            dirty: false,
+            next: Next::FIRST,
+            clock: 0,
+            usage_dirty: false,
+            pending_connects: 0,
+            usage_next: Next::FIRST,
+            bond_key: None,
+            profile: 0,
+        }
+    }
+
+    /// Set the key bonds are sealed with and the counters their nonces come
+    /// from (before loading). `None` if the key couldn't be derived.
+    pub fn set_bond_key(&mut self, key: Option<(BondKey, NonceCounter)>) {
+        self.bond_key = key;
+    }
+
+    /// Profile whose devices are loaded.
+    pub fn profile(&self) -> u8 {
+        self.profile
+    }
+
+    /// Set the profile the next load reads (before the first load; later,
+    /// use `switch_profile`).
+    pub fn set_profile(&mut self, profile: u8) {
+        self.profile = profile;
+    }
+}
+
+impl<A: Record + Copy + PartialEq, B: Bond<A>> DeviceStore<A, B> {
+    /// Load the profile's devices from flash. A damaged copy is rewritten on
+    /// the next save.
+    pub async fn load_from_flash(
+        &mut self,
+        flash: &mut Region<impl NorFlash, impl DelayNs>,
+    ) -> Health {
+        let (devices_key, usage_key) = region::profile_keys(self.profile);
+        let mut buf_a = [0u8; MAX_ITEM_SIZE];
+        let mut buf_b = [0u8; MAX_ITEM_SIZE];
+
+        self.devices.clear();
+        self.dirty = false;
+        let chosen = flash
+            .fetch_sealed(devices_key, &mut buf_a, &mut buf_b)
+            .await;
+        if let Some(data) = chosen.payload {
+            self.deserialize_all(data);
+        }
+        #[cfg(feature = "defmt")]
+        defmt::info!(
+            "Loaded {} devices of profile {} from flash",
+            self.devices.len(),
+            self.profile
+        );
+        self.next = chosen.next;
+        self.dirty |= chosen.health != Health::Intact;
+        let health = chosen.health;
+
+        let chosen = flash.fetch_sealed(usage_key, &mut buf_a, &mut buf_b).await;
+        if let Some(data) = chosen.payload {
+            self.deserialize_usage(data);
+        }
+        self.clock = self.devices.iter().map(|d| d.last_used).max().unwrap_or(0);
+        self.usage_next = chosen.next;
+        self.usage_dirty = chosen.health != Health::Intact;
+        self.pending_connects = 0;
+        health.max(chosen.health)
+    }
+
+    /// Persist all paired devices to flash.
+    pub async fn save_to_flash(&mut self, flash: &mut Region<impl NorFlash, impl DelayNs>) {
+        let (devices_key, usage_key) = region::profile_keys(self.profile);
+        let usage_due = self.usage_dirty || self.pending_connects >= STORAGE_USAGE_SAVE_BATCH;
+        if !self.dirty && !usage_due {
+            #[cfg(feature = "defmt")]
+            defmt::debug!("DeviceStore: no changes to save");
+            return;
+        }
+
+        let sealing = match self.bond_key.as_mut() {
+            Some((key, nonces)) if self.dirty => Some((*key, nonces.take())),
+            _ => None,
+        };
+        if self.dirty && sealing.is_none() {
+            #[cfg(feature = "defmt")]
+            defmt::error!("No bond key - paired devices not saved");
+        } else if let Some((_, None)) = sealing {
+            #[cfg(feature = "defmt")]
+            defmt::error!("No bond nonces left - paired devices not saved");
+        } else if let Some((key, Some(counter))) = sealing {
+            let mut data_buf = [0u8; MAX_ITEM_SIZE - integrity::HEADER_SIZE];
+            let len = self.serialize_all(&mut data_buf, &key, counter);
+
+            if flash
+                .store_sealed(devices_key, &mut self.next, &data_buf[..len])
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                defmt::info!("Saved {} devices to flash", self.devices.len());
+                self.dirty = false;
+            }
+        }
+
+        if usage_due {
+            let mut data_buf = [0u8; USAGE_BLOB_SIZE];
+            let len = self.serialize_usage_all(&mut data_buf);
+
+            if flash
+                .store_sealed(usage_key, &mut self.usage_next, &data_buf[..len])
+                .await
+            {
+                self.usage_dirty = false;
+                self.pending_connects = 0;
+            }
+        }
+    }
+
+    /// Save the loaded profile (batched recency included) and load `profile`.
+    pub async fn switch_profile(
+        &mut self,
+        flash: &mut Region<impl NorFlash, impl DelayNs>,
+        profile: u8,
+    ) -> Health {
+        self.usage_dirty |= self.pending_connects > 0;
+        self.save_to_flash(flash).await;
+        #[cfg(feature = "defmt")]
+        if self.dirty || self.usage_dirty {
+            defmt::warn!("Profile {} not fully saved before switching", self.profile);
+        }
+        self.profile = profile;
+        self.load_from_flash(flash).await
+    }
+
+    /// Serialize every device's usage record using the versioned framing.
+    fn serialize_usage_all(&self, buf: &mut [u8]) -> usize {
+        let Some(mut writer) = framing::Writer::new(buf) else {
+            return 0;
+        };
+        for device in &self.devices {
+            if !writer.push(|slot| device.serialize_usage(slot)) {
+                break;
+            }
+        }
+        writer.finish()
+    }
+
+    /// Apply the usage records to the loaded devices. Records of devices no
+    /// longer stored are dropped.
+    fn deserialize_usage(&mut self, data: &[u8]) {
+        for record in framing::records(data) {
+            if record.len() < A::SIZE + USAGE_SIZE {
+                continue;
+            }
+            let Some(address) = A::read(&record[..A::SIZE]) else {
+                continue;
+            };
+            if let Some(device) = self.devices.iter_mut().find(|d| d.address == address) {
+                device.apply_usage(&record[A::SIZE..]);
+            }
+        }
+    }
+
+    /// Serialize all devices to a byte buffer using the versioned framing,
+    /// sealing their bonds under this save's `counter`.
+    fn serialize_all(&self, buf: &mut [u8], key: &BondKey, counter: u32) -> usize {
+        let Some(mut writer) = framing::Writer::new(buf) else {
+            return 0;
+        };
+        for device in &self.devices {
+            // Stop at the first record that doesn't fit (writer rolls it back).
+            if !writer.push(|slot| device.serialize(slot, Some((key, counter)))) {
+                break;
+            }
+        }
+        writer.finish()
+    }
+
+    /// Deserialize all devices from a byte buffer.
+    fn deserialize_all(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        if framing::is_versioned(data) {
+            self.deserialize_versioned(data);
+        } else {
+            self.deserialize_legacy(data);
+        }
+    }
+
+    fn deserialize_versioned(&mut self, data: &[u8]) {
+        for record in framing::records(data) {
+            if let Some((device, read)) =
+                PairedDevice::deserialize(record, self.bond_key.as_ref().map(|(key, _)| key))
+            {
+                match read {
+                    // Re-save plaintext bonds encrypted, and drop rejected ones.
+                    BondRead::Plain | BondRead::Rejected => self.dirty = true,
+                    BondRead::Absent | BondRead::Sealed | BondRead::Locked => {}
+                }
+                #[cfg(feature = "defmt")]
+                match read {
+                    BondRead::Rejected => {
+                        defmt::warn!("Stored bond failed authentication - dropped")
+                    }
+                    BondRead::Locked => defmt::warn!("Stored bond locked - no bond key"),
+                    _ => {}
+                }
+                if !self.devices.is_full() {
+                    let _ = self.devices.push(device);
+                }
+            }
+        }
+    }
+
+    fn deserialize_legacy(&mut self, data: &[u8]) {
+        let count = data[0] as usize;
+        let mut offset = 1;
+        let name_len_at = A::SIZE + 1;
+
+        for _ in 0..count {
+            if offset >= data.len() {
+                break;
+            }
+
+            // Read name length to determine record size.
+            if offset + name_len_at + 1 > data.len() {
+                break;
+            }
+            let name_len = data[offset + name_len_at] as usize;
+            let record_len = name_len_at + 1 + name_len;
+
+            if offset + record_len > data.len() {
+                break;
+            }
+
+            if let Some((device, _)) =
+                PairedDevice::deserialize_base(&data[offset..offset + record_len])
+            {
+                if !self.devices.is_full() {
+                    let _ = self.devices.push(device);
+                }
+            }
+
+            offset += record_len;
+        }
+    }
+
+    /// Export every device, bonds included, as a [`backup`] container sealed
+    /// under `transfer`: least recently used first, so an import replays the
+    /// recency order. Returns the container length (0 if `buf` is too small).
+    pub fn export(&self, buf: &mut [u8], transfer: &BondKey, nonce: &[u8; NONCE_SIZE]) -> usize {
+        backup::seal(transfer, nonce, buf, |payload| {
+            let Some(mut writer) = framing::Writer::new(payload) else {
+                return 0;
+            };
+            let order: Vec<&PairedDevice<A, B>, MAX_PAIRED_DEVICES> = self.iter_recent().collect();
+            for device in order.iter().rev() {
+                if !writer.push(|slot| device.serialize(slot, None)) {
+                    break;
+                }
+            }
+            writer.finish()
+        })
+    }
+
+    /// Merge the devices of a [`backup`] container (decrypted in place) into
+    /// the store, returning how many it held. Known devices take the imported
+    /// name and bond; new ones are added as `add` does. Either way they become
+    /// the most recently used, in the exporter's order. Reaches flash on the
+    /// next save.
+    pub fn import(&mut self, data: &mut [u8], transfer: &BondKey) -> Result<usize, ImportError> {
+        let payload = backup::open(transfer, data)?;
+        if !framing::is_versioned(payload) {
+            return Err(ImportError::Malformed);
+        }
+        let mut count = 0;
+        for record in framing::records(payload) {
+            if let Some((device, _)) = PairedDevice::deserialize(record, None) {
+                self.merge(device);
+                count += 1;
+            }
+        }
+        #[cfg(feature = "defmt")]
+        defmt::info!("Imported {} paired devices", count);
+        Ok(count)
+    }
+
+    fn merge(&mut self, device: PairedDevice<A, B>) {
+        let address = device.address;
+        let (bond, cache) = (device.bond, device.gatt_cache.clone());
+        self.add(device);
+        let Some(stored) = self.devices.iter_mut().find(|d| d.address == address) else {
+            return;
+        };
+        // Keep the exporter's GATT cache when it belongs to the bond in use.
+        if stored.gatt_cache.is_none() && cache.is_some() && stored.bond == bond {
+            stored.gatt_cache = cache;
+            self.dirty = true;
+        }
+        self.clock = self.clock.wrapping_add(1);
+        stored.last_used = self.clock;
+        self.usage_dirty = true;
+    }
+
+    /// Add a newly paired device.
+    pub fn add(&mut self, device: PairedDevice<A, B>) {
+        // If already stored (same address), update the record. Only persist
+        // (mark dirty) when something we care about for reconnect actually
+        // changed — RSSI churns on every reconnect and is just a UI hint, so
+        // updating it alone must not cause a flash write (avoidable wear).
+        if let Some(existing) = self
+            .devices
+            .iter_mut()
+            .find(|d| d.address == device.address)
+        {
+            let name_changed = existing.name != device.name;
+            let bond_changed = device.bond.is_some() && existing.bond != device.bond;
+
+            existing.last_rssi = device.last_rssi;
+            if name_changed {
+                existing.name = device.name.clone();
+            }
+            if bond_changed {
+                // New keys mean a re-pair: don't trust the old handles.
+                existing.bond = device.bond;
+                existing.gatt_cache = None;
+            }
+            if name_changed || bond_changed {
+                self.dirty = true;
+                #[cfg(feature = "defmt")]
+                defmt::info!("Updated existing paired device");
+            }
+            return;
+        }
+
+        // If at capacity, evict the least recently used entry (the oldest
+        // added among those never connected).
+        if self.devices.is_full() {
+            if let Some(lru) = self.least_recent() {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("Paired device store full - evicting least recently used");
+                self.devices.remove(lru);
+            }
+        }
+
+        let _ = self.devices.push(device);
+        self.dirty = true;
+        self.usage_dirty = true;
+        #[cfg(feature = "defmt")]
+        defmt::info!("Added paired device - now storing {}", self.devices.len());
+    }
+
+    /// Forget the device at `address`, with its bond and cache. Returns
+    /// whether it was stored.
+    pub fn forget(&mut self, address: A) -> bool {
+        let Some(index) = self.devices.iter().position(|d| d.address == address) else {
+            return false;
+        };
+        self.devices.remove(index);
+        self.dirty = true;
+        self.usage_dirty = true;
+        true
+    }
+
+    /// Count a successful connection to `address` (the stored address, or one
+    /// its bond resolves): the device becomes the most recently used. Recency
+    /// alone reaches flash in batches of `STORAGE_USAGE_SAVE_BATCH`; a new
+    /// role or icon on the next save. An `Other` icon (appearance not
+    /// advertised, e.g. on a directed reconnect) keeps the known one.
+    pub fn record_connect(&mut self, address: A, role: DeviceRole, icon: DeviceIcon) {
+        let Some(device) = self
+            .devices
+            .iter_mut()
+            .find(|d| d.address == address || d.bond.is_some_and(|b| b.matches(address)))
+        else {
+            return;
+        };
+        self.clock = self.clock.wrapping_add(1);
+        device.last_used = self.clock;
+        device.connects = device.connects.saturating_add(1);
+        if device.role != role {
+            device.role = role;
+            self.usage_dirty = true;
+        }
+        if icon != DeviceIcon::Other && device.icon != icon {
+            device.icon = icon;
+            self.usage_dirty = true;
+        }
+        self.pending_connects = self.pending_connects.saturating_add(1);
+    }
+
+    /// Index of the least recently used device; among never-connected ones,
+    /// the oldest added.
+    fn least_recent(&self) -> Option<usize> {
+        (0..self.devices.len()).min_by_key(|&i| self.devices[i].last_used)
+    }
+
+    /// Iterate paired devices most recently connected first (never-connected
+    /// ones last, newest added first), for auto-reconnect of multiple links
+    /// (e.g. keyboard + mouse) on boot.
+    pub fn iter_recent(&self) -> impl Iterator<Item = &PairedDevice<A, B>> {
+        let mut order: Vec<usize, MAX_PAIRED_DEVICES> = (0..self.devices.len()).collect();
+        order.sort_unstable_by_key(|&i| core::cmp::Reverse((self.devices[i].last_used, i)));
+        order.into_iter().map(move |i| &self.devices[i])
+    }
+
+    /// Return all stored BLE bonds.
+    pub fn bonds(&self) -> Vec<B, MAX_PAIRED_DEVICES> {
+        let mut bonds = Vec::new();
+        for device in &self.devices {
+            if let Some(bond) = device.bond {
+                let _ = bonds.push(bond);
+            }
+        }
+        bonds
+    }
+
+    /// Attach or update a bond for the matching device.
+    pub fn set_bond_for_address(&mut self, address: A, bond: B) {
+        if let Some(device) = self
+            .devices
+            .iter_mut()
+            .find(|d| d.address == address || bond.matches(d.address))
+        {
+            if device.bond != Some(bond) {
+                device.bond = Some(bond);
+                device.gatt_cache = None;
+                self.dirty = true;
+                #[cfg(feature = "defmt")]
+                defmt::info!("Updated stored BLE bond");
+            }
+        }
+    }
+
+    /// Index of the bonded device at `address` (its stored address, or a live
+    /// RPA that resolves to its identity).
+    fn bonded_index(&self, address: A) -> Option<usize> {
+        self.devices.iter().position(|d| match d.bond {
+            Some(bond) => d.address == address || bond.matches(address),
+            None => false,
+        })
+    }
+
+    /// Cached GATT discovery results for the bonded device at `address`.
+    pub fn gatt_cache_for_address(&self, address: A) -> Option<GattCache> {
+        let index = self.bonded_index(address)?;
+        self.devices[index].gatt_cache.clone()
+    }
+
+    /// Replace (or, with `None`, invalidate) the GATT cache of the bonded device
+    /// at `address`. Unbonded devices are never cached: without a bond the peer
+    /// gives no Service Changed guarantee, so its handles can't be trusted.
+    pub fn set_gatt_cache_for_address(&mut self, address: A, cache: Option<GattCache>) {
+        let Some(index) = self.bonded_index(address) else {
+            return;
+        };
+        let device = &mut self.devices[index];
+        if device.gatt_cache != cache {
+            device.gatt_cache = cache;
+            self.dirty = true;
+            #[cfg(feature = "defmt")]
+            defmt::info!("Updated stored GATT cache");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::bond_crypto::aes128_encrypt;
+    use crate::storage::gatt_cache::BootHandles;
+    use crate::storage::ram_flash::{NoDelay, RamFlash};
+    use crate::storage::region::KEY_PAIRED_DEVICES;
+    use embassy_futures::block_on;
+
+    /// One-byte stand-in for a BLE address.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Addr(u8);
+
+    impl Record for Addr {
+        const SIZE: usize = 1;
+
+        fn write(&self, buf: &mut [u8]) {
+            buf[0] = self.0;
+        }
+
+        fn read(data: &[u8]) -> Option<Self> {
+            Some(Addr(data[0]))
+        }
+    }
+
+    /// Stand-in bond: a key byte plus the identity and private address it
+    /// resolves.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct TestBond {
+        key: u8,
+        identity: u8,
+        private: u8,
+    }
+
+    impl Record for TestBond {
+        const SIZE: usize = 3;
+
+        fn write(&self, buf: &mut [u8]) {
+            buf.copy_from_slice(&[self.key, self.identity, self.private]);
+        }
+
+        fn read(data: &[u8]) -> Option<Self> {
+            Some(TestBond {
+                key: data[0],
+                identity: data[1],
+                private: data[2],
+            })
+        }
+    }
+
+    impl Bond<Addr> for TestBond {
+        fn matches(&self, address: Addr) -> bool {
+            address.0 == self.identity || address.0 == self.private
+        }
+    }
+
+    type Store = DeviceStore<Addr, TestBond>;
+    type Device = PairedDevice<Addr, TestBond>;
+
+    fn bond(identity: u8) -> TestBond {
+        TestBond {
+            key: 0x40 | identity,
+            identity,
+            private: 0x80 | identity,
+        }
+    }
+
+    fn cache() -> GattCache {
+        GattCache {
+            protocol_mode_handle: Some(0x0010),
+            keyboard_led_handle: None,
+            service_changed_handle: Some(0x0008),
+            control_point_handle: None,
+            battery_level_handle: None,
+            battery_level_cccd: None,
+            pnp_id: None,
+            reports: Vec::new(),
+            descriptors: Vec::new(),
+            boot: BootHandles::default(),
+            prefer_boot: false,
+        }
+    }
+
+    fn region() -> Region<RamFlash, NoDelay> {
+        Region::new(RamFlash::new(), NoDelay)
+    }
+
+    fn key(byte: u8) -> BondKey {
+        BondKey::derive(aes128_encrypt, &[byte; 16], 0x0123_4567_89AB_CDEF)
+    }
+
+    /// An empty store with the bond key.
+    fn store() -> Store {
+        let mut store = Store::new();
+        store.set_bond_key(Some((key(7), NonceCounter::new(0, 100))));
+        store
+    }
+
+    fn reload(flash: &mut Region<RamFlash, NoDelay>) -> (Store, Health) {
+        let mut store = store();
+        let health = block_on(store.load_from_flash(flash));
+        (store, health)
+    }
+
+    fn names(store: &Store) -> heapless::Vec<&str, MAX_PAIRED_DEVICES> {
+        store.iter_recent().map(|d| d.name.as_str()).collect()
+    }
+
+    #[test]
+    fn devices_bonds_and_caches_survive_save_and_load() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.add(Device::new(Addr(2), "Mouse", -55));
+        store.set_bond_for_address(Addr(1), bond(1));
+        store.set_gatt_cache_for_address(Addr(1), Some(cache()));
+        block_on(store.save_to_flash(&mut flash));
+
+        let (loaded, health) = reload(&mut flash);
+        assert_eq!(health, Health::Intact);
+        assert_eq!(names(&loaded), ["Mouse", "Keyboard"]);
+        assert_eq!(loaded.bonds(), [bond(1)]);
+        // The cache is found through the bond's private address as well.
+        assert_eq!(loaded.gatt_cache_for_address(Addr(0x81)), Some(cache()));
+        assert_eq!(loaded.gatt_cache_for_address(Addr(2)), None);
+        assert_eq!(loaded.iter_recent().next().unwrap().last_rssi, -55);
+    }
+
+    #[test]
+    fn full_store_evicts_the_oldest_device() {
+        let mut flash = region();
+        let mut store = store();
+        for (i, name) in ["A", "B", "C", "D", "E"].iter().enumerate() {
+            store.add(Device::new(Addr(i as u8), name, -50));
+        }
+        assert_eq!(names(&store), ["E", "D", "C", "B"]);
+
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(names(&reload(&mut flash).0), ["E", "D", "C", "B"]);
+    }
+
+    #[test]
+    fn forgotten_devices_stay_forgotten() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.add(Device::new(Addr(2), "Mouse", -55));
+        store.set_bond_for_address(Addr(1), bond(1));
+        block_on(store.save_to_flash(&mut flash));
+
+        assert!(store.forget(Addr(1)));
+        assert!(!store.forget(Addr(1)));
+        assert!(store.bonds().is_empty());
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(names(&reload(&mut flash).0), ["Mouse"]);
+    }
+
+    #[test]
+    fn rssi_only_update_does_not_write_flash() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        block_on(store.save_to_flash(&mut flash));
+        let writes = flash_writes(&mut flash);
+
+        store.add(Device::new(Addr(1), "Keyboard", -70));
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(flash_writes(&mut flash), writes);
+
+        store.add(Device::new(Addr(1), "Keyboard K2", -70));
+        block_on(store.save_to_flash(&mut flash));
+        assert!(flash_writes(&mut flash) > writes);
+        assert_eq!(names(&reload(&mut flash).0), ["Keyboard K2"]);
+    }
+
+    #[test]
+    fn rebonding_drops_the_gatt_cache() {
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.set_bond_for_address(Addr(1), bond(1));
+        store.set_gatt_cache_for_address(Addr(1), Some(cache()));
+
+        let mut rebond = bond(1);
+        rebond.key = 0x99;
+        store.set_bond_for_address(Addr(0x81), rebond);
+        assert_eq!(store.bonds(), [rebond]);
+        assert_eq!(store.gatt_cache_for_address(Addr(1)), None);
+    }
+
+    #[test]
+    fn busy_flash_is_retried_and_a_failed_save_stays_pending() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+
+        // Within the retry budget: saved.
+        fail_writes(&mut flash, 2);
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(names(&reload(&mut flash).0), ["Keyboard"]);
+
+        // Out of budget: not saved, but still dirty, so the next save lands.
+        store.add(Device::new(Addr(2), "Mouse", -40));
+        fail_writes(&mut flash, 3);
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(names(&reload(&mut flash).0), ["Keyboard"]);
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(names(&reload(&mut flash).0), ["Mouse", "Keyboard"]);
+    }
+
+    #[test]
+    fn legacy_blob_loads_and_is_resaved_sealed() {
+        let mut flash = region();
+        // Pre-framing layout: [count] then [addr][rssi][name_len][name].
+        let legacy = [2, 1, 0xD8, 2, b'K', b'B', 2, 0xC9, 1, b'M'];
+        assert!(block_on(flash.store(KEY_PAIRED_DEVICES, &legacy)));
+
+        let (mut store, health) = reload(&mut flash);
+        assert_eq!(health, Health::Intact);
+        assert_eq!(names(&store), ["M", "KB"]);
+        assert_eq!(store.iter_recent().last().unwrap().last_rssi, -40);
+
+        store.add(Device::new(Addr(3), "Pad", -60));
+        block_on(store.save_to_flash(&mut flash));
+        let (store, health) = reload(&mut flash);
+        assert_eq!(health, Health::Intact);
+        assert_eq!(names(&store), ["Pad", "M", "KB"]);
+    }
+
+    #[test]
+    fn saves_alternate_between_copies() {
+        let mut flash = region();
+        let mut store = store();
+        for (i, name) in ["A", "B", "C"].iter().enumerate() {
+            store.add(Device::new(Addr(i as u8), name, -50));
+            block_on(store.save_to_flash(&mut flash));
+        }
+        let mut a = [0u8; MAX_ITEM_SIZE];
+        let mut b = [0u8; MAX_ITEM_SIZE];
+        let chosen = block_on(flash.fetch_sealed(KEY_PAIRED_DEVICES, &mut a, &mut b));
+        // Generations 1 (B), 2 (A), 3 (B): the newest is in B, A is next.
+        assert_eq!(chosen.next.slot, integrity::Slot::A);
+        assert_eq!(chosen.next.generation, 4);
+    }
+
+    #[test]
+    fn recency_follows_connections_not_pairing_order() {
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.add(Device::new(Addr(2), "Mouse", -40));
+        store.add(Device::new(Addr(3), "Remote", -40));
+        store.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Keyboard);
+        store.record_connect(Addr(3), DeviceRole::Consumer, DeviceIcon::Other);
+        store.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Other);
+        // Never-connected devices come last.
+        assert_eq!(names(&store), ["Keyboard", "Remote", "Mouse"]);
+
+        let keyboard = store.iter_recent().next().unwrap();
+        assert_eq!(keyboard.connects, 2);
+        assert_eq!(keyboard.role, DeviceRole::Keyboard);
+        // The directed reconnect didn't advertise an appearance.
+        assert_eq!(keyboard.icon, DeviceIcon::Keyboard);
+    }
+
+    #[test]
+    fn full_store_evicts_the_least_recently_used_device() {
+        let mut store = store();
+        for (i, name) in ["A", "B", "C", "D"].iter().enumerate() {
+            store.add(Device::new(Addr(i as u8), name, -50));
+            store.record_connect(Addr(i as u8), DeviceRole::Other, DeviceIcon::Other);
+        }
+        store.record_connect(Addr(0), DeviceRole::Other, DeviceIcon::Other);
+        store.add(Device::new(Addr(4), "E", -50));
+        assert_eq!(names(&store), ["A", "D", "C", "E"]);
+    }
+
+    #[test]
+    fn reconnects_reach_flash_in_batches() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.add(Device::new(Addr(2), "Mouse", -40));
+        store.set_bond_for_address(Addr(2), bond(2));
+        store.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Keyboard);
+        block_on(store.save_to_flash(&mut flash));
+        let writes = flash_writes(&mut flash);
+
+        // Through its private address; same role and icon: recency only.
+        store.record_connect(Addr(0x82), DeviceRole::Other, DeviceIcon::Other);
+        block_on(store.save_to_flash(&mut flash));
+        assert_eq!(flash_writes(&mut flash), writes);
+        assert_eq!(names(&reload(&mut flash).0), ["Keyboard", "Mouse"]);
+
+        for _ in 1..STORAGE_USAGE_SAVE_BATCH {
+            store.record_connect(Addr(2), DeviceRole::Other, DeviceIcon::Other);
+            block_on(store.save_to_flash(&mut flash));
+        }
+        let (loaded, health) = reload(&mut flash);
+        assert_eq!(health, Health::Intact);
+        assert_eq!(names(&loaded), ["Mouse", "Keyboard"]);
+        let mouse = loaded.iter_recent().next().unwrap();
+        assert_eq!(mouse.connects, STORAGE_USAGE_SAVE_BATCH as u16);
+        let keyboard = loaded.iter_recent().nth(1).unwrap();
+        assert_eq!(
+            (keyboard.role, keyboard.icon),
+            (DeviceRole::Keyboard, DeviceIcon::Keyboard)
+        );
+
+        // Loaded recency carries on from where it was.
+        let mut loaded = loaded;
+        loaded.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Other);
+        assert_eq!(names(&loaded), ["Keyboard", "Mouse"]);
+    }
+
+    /// The stored device-list payload.
+    fn stored_devices(flash: &mut Region<RamFlash, NoDelay>) -> heapless::Vec<u8, MAX_ITEM_SIZE> {
+        let mut a = [0u8; MAX_ITEM_SIZE];
+        let mut b = [0u8; MAX_ITEM_SIZE];
+        let chosen = block_on(flash.fetch_sealed(KEY_PAIRED_DEVICES, &mut a, &mut b));
+        heapless::Vec::from_slice(chosen.payload.unwrap()).unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn bonds_are_encrypted_at_rest() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.set_bond_for_address(Addr(1), bond(1));
+        block_on(store.save_to_flash(&mut flash));
+
+        let payload = stored_devices(&mut flash);
+        assert!(!contains(&payload, &[0x41, 1, 0x81]));
+        assert_eq!(reload(&mut flash).0.bonds(), [bond(1)]);
+    }
+
+    #[test]
+    fn plaintext_bonds_are_resaved_encrypted() {
+        let mut flash = region();
+        // [addr][rssi][name_len][name][bond flag 1][bond][no cache]
+        let record = [1, 0xD8, 2, b'K', b'B', BOND_PLAIN, 0x41, 1, 0x81, 0];
+        let mut blob = [0u8; 32];
+        let mut writer = framing::Writer::new(&mut blob).unwrap();
+        assert!(writer.push(|slot| {
+            slot[..record.len()].copy_from_slice(&record);
+            record.len()
+        }));
+        let len = writer.finish();
+        let mut next = Next::FIRST;
+        assert!(block_on(flash.store_sealed(
+            KEY_PAIRED_DEVICES,
+            &mut next,
+            &blob[..len]
+        )));
+
+        let (mut store, _) = reload(&mut flash);
+        assert_eq!(store.bonds(), [bond(1)]);
+        block_on(store.save_to_flash(&mut flash));
+        assert!(!contains(&stored_devices(&mut flash), &[0x41, 1, 0x81]));
+        assert_eq!(reload(&mut flash).0.bonds(), [bond(1)]);
+    }
+
+    #[test]
+    fn bonds_that_fail_authentication_are_dropped_not_the_device() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.set_bond_for_address(Addr(1), bond(1));
+        store.set_gatt_cache_for_address(Addr(1), Some(cache()));
+        block_on(store.save_to_flash(&mut flash));
+
+        // Sealed under another install's key.
+        let mut other = Store::new();
+        other.set_bond_key(Some((key(8), NonceCounter::new(0, 100))));
+        block_on(other.load_from_flash(&mut flash));
+        assert_eq!(names(&other), ["Keyboard"]);
+        assert!(other.bonds().is_empty());
+        assert_eq!(other.gatt_cache_for_address(Addr(1)), None);
+
+        // The record's name is authenticated too.
+        let mut buf = [0u8; 64];
+        let len = store.devices[0].serialize(&mut buf, Some((&key(7), 1)));
+        let good = PairedDevice::<Addr, TestBond>::deserialize(&buf[..len], Some(&key(7)));
+        assert_eq!(
+            good.map(|(d, read)| (d.bond, read)),
+            Some((Some(bond(1)), BondRead::Sealed))
+        );
+        buf[3 + 7] ^= 1;
+        let bad = PairedDevice::<Addr, TestBond>::deserialize(&buf[..len], Some(&key(7)));
+        assert_eq!(
+            bad.map(|(d, read)| (d.bond, read)),
+            Some((None, BondRead::Rejected))
+        );
+    }
+
+    #[test]
+    fn each_save_takes_a_fresh_nonce_counter() {
+        let mut flash = region();
+        let mut store = Store::new();
+        store.set_bond_key(Some((key(7), NonceCounter::new(40, 41))));
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.set_bond_for_address(Addr(1), bond(1));
+        block_on(store.save_to_flash(&mut flash));
+        let (reloaded, _) = reload(&mut flash);
+        assert_eq!(reloaded.bonds(), [bond(1)]);
+
+        // Counter 40 is spent; sealing again would reuse its nonce.
+        store.add(Device::new(Addr(2), "Mouse", -40));
+        block_on(store.save_to_flash(&mut flash));
+        let (reloaded, _) = reload(&mut flash);
+        assert_eq!(names(&reloaded), ["Keyboard"]);
+        assert_eq!(reloaded.bonds(), [bond(1)]);
+    }
+
+    #[test]
+    fn without_a_bond_key_the_device_list_is_read_only() {
+        let mut flash = region();
+        let mut store = store();
+        store.add(Device::new(Addr(1), "Keyboard", -40));
+        store.set_bond_for_address(Addr(1), bond(1));
+        block_on(store.save_to_flash(&mut flash));
+
+        let mut locked = Store::new();
+        block_on(locked.load_from_flash(&mut flash));
+        assert_eq!(names(&locked), ["Keyboard"]);
+        assert!(locked.bonds().is_empty());
+
+        // Saving now would drop the bond it can't open.
+        locked.add(Device::new(Addr(2), "Mouse", -40));
+        block_on(locked.save_to_flash(&mut flash));
+        let (reloaded, _) = reload(&mut flash);
+        assert_eq!(names(&reloaded), ["Keyboard"]);
+        assert_eq!(reloaded.bonds(), [bond(1)]);
+    }
+
+    #[test]
+    fn export_and_import_move_devices_and_bonds_to_another_bridge() {
+        let transfer = key(0x33);
+        let mut source = store();
+        source.add(Device::new(Addr(1), "Keyboard", -40));
+        source.add(Device::new(Addr(2), "Mouse", -40));
+        source.set_bond_for_address(Addr(1), bond(1));
+        source.set_gatt_cache_for_address(Addr(1), Some(cache()));
+        source.record_connect(Addr(2), DeviceRole::Pointer, DeviceIcon::Mouse);
+        source.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Keyboard);
+        let mut buf = [0u8; backup::MAX_BACKUP_SIZE];
+        let len = source.export(&mut buf, &transfer, &[5; NONCE_SIZE]);
+        assert!(!contains(&buf[..len], &[0x41, 1, 0x81]));
+
+        // The spare has its own bond key and a device of its own.
+        let mut flash = region();
+        let mut spare = Store::new();
+        spare.set_bond_key(Some((key(9), NonceCounter::new(0, 100))));
+        spare.add(Device::new(Addr(7), "Old", -60));
+        assert_eq!(spare.import(&mut buf[..len], &transfer), Ok(2));
+        assert_eq!(names(&spare), ["Keyboard", "Mouse", "Old"]);
+        assert_eq!(spare.bonds(), [bond(1)]);
+        assert_eq!(spare.gatt_cache_for_address(Addr(0x81)), Some(cache()));
+
+        block_on(spare.save_to_flash(&mut flash));
+        let mut reloaded = Store::new();
+        reloaded.set_bond_key(Some((key(9), NonceCounter::new(0, 100))));
+        block_on(reloaded.load_from_flash(&mut flash));
+        assert_eq!(names(&reloaded), ["Keyboard", "Mouse", "Old"]);
+        assert_eq!(reloaded.bonds(), [bond(1)]);
+    }
+
+    #[test]
+    fn import_under_the_wrong_transfer_key_changes_nothing() {
+        let mut source = store();
+        source.add(Device::new(Addr(1), "Keyboard", -40));
+        let mut buf = [0u8; backup::MAX_BACKUP_SIZE];
+        let len = source.export(&mut buf, &key(0x33), &[5; NONCE_SIZE]);
+
+        let mut spare = store();
+        spare.add(Device::new(Addr(7), "Old", -60));
+        assert_eq!(
+            spare.import(&mut buf[..len], &key(0x34)),
+            Err(ImportError::Unauthenticated)
+        );
+        assert_eq!(names(&spare), ["Old"]);
+    }
+
+    #[test]
+    fn profiles_keep_their_own_devices_and_recency() {
+        let mut flash = region();
+        let mut bridge = store();
+        bridge.add(Device::new(Addr(1), "Keyboard", -40));
+        bridge.add(Device::new(Addr(2), "Mouse", -50));
+        block_on(bridge.save_to_flash(&mut flash));
+        // A batched reconnect is saved before the profile is left.
+        bridge.record_connect(Addr(1), DeviceRole::Keyboard, DeviceIcon::Keyboard);
+
+        assert_eq!(
+            block_on(bridge.switch_profile(&mut flash, 1)),
+            Health::Intact
+        );
+        assert_eq!(bridge.profile(), 1);
+        assert_eq!(names(&bridge), [] as [&str; 0]);
+        bridge.add(Device::new(Addr(1), "Keyboard", -40));
+        bridge.set_bond_for_address(Addr(1), bond(1));
+        bridge.add(Device::new(Addr(3), "Remote", -60));
+        block_on(bridge.save_to_flash(&mut flash));
+
+        block_on(bridge.switch_profile(&mut flash, 0));
+        assert_eq!(names(&bridge), ["Keyboard", "Mouse"]);
+        assert_eq!(bridge.bonds(), []);
+
+        // The first profile is the store from before profiles existed.
+        let (loaded, _) = reload(&mut flash);
+        assert_eq!(names(&loaded), ["Keyboard", "Mouse"]);
+        let mut other = store();
+        other.set_profile(1);
+        block_on(other.load_from_flash(&mut flash));
+        assert_eq!(names(&other), ["Remote", "Keyboard"]);
+        assert_eq!(other.bonds(), [bond(1)]);
+    }
+
+    fn flash_writes(flash: &mut Region<RamFlash, NoDelay>) -> usize {
+        flash.flash_mut().writes
+    }
+
+    fn fail_writes(flash: &mut Region<RamFlash, NoDelay>, count: u8) {
+        flash.flash_mut().fail_writes = count;
+    }
+}
@@ -0,0 +1,583 @@
+//! Pure, hardware-free wire format for the per-device GATT discovery cache.
+//!
+//! Full HID-over-GATT discovery (primary service + characteristic discovery,
+//! one Report Reference read per report, a Report Map read) costs several
+//! connection events per round trip, which is most of the delay between a
+//! bonded keyboard waking up and its first keystroke reaching the host. For a
+//! **bonded** peer the attribute handles are stable until the peer says
+//! otherwise (a Service Changed indication), so we cache everything discovery
+//! produced and reuse it on reconnect.
+//!
+//! This module only owns the cached *data* and its byte layout; when to use,
+//! refresh or invalidate it is decided by [`crate::ble::hid_client`] and the
+//! store.
+//!
+//! Composite peripherals may expose more than one HID service, each with its
+//! own Report Map; every report is cached with the index of the instance it
+//! belongs to, and each instance keeps its own parsed map.
+//!
+//! Wire layout:
+//! ```text
+//! [0]      cache version (0x07)
+//! [1..3]   Protocol Mode value handle      (u16 LE, 0 = absent)
+//! [3..5]   keyboard LED output handle      (u16 LE, 0 = absent)
+//! [5..7]   Service Changed value handle    (u16 LE, 0 = absent)
+//! [7]      HID service instance count
+//! [8]      report count
+//! [9..19]  boot keyboard input, its CCCD, boot keyboard output, boot mouse
+//!          input, its CCCD (u16 LE each, 0 = absent)
+//! [19]     flags (bit0 peer needs Boot Protocol)
+//! [20..22] HID Control Point value handle (u16 LE, 0 = absent)
+//! [22..26] Battery Level value handle, its CCCD (u16 LE each, 0 = absent)
+//! [26..33] Device Information PnP ID, as on the wire (source 0 = absent)
+//! [33..]   per instance: [map present:u8][map flags:u8][keyboard / mouse /
+//!          consumer report IDs:u8 each, 0 = none]; map flags are bit0
+//!          keyboard, bit1 mouse, bit2 consumer, bit3 map read completely
+//! [..]     repeated: [instance:u8][value:u16][cccd:u16][ref present:u8]
+//!          [report id:u8][report type:u8]
+//! ```
+//!
+//! ATT handle 0 and HID report ID 0 are both reserved by their specs, so 0 is a
+//! safe encoding for "absent".
+
+use crate::hid::quirks::PnpId;
+use crate::hid::report_protocol::{HidDescriptor, ReportKind, ReportReference, ReportType};
+use heapless::Vec;
+
+// v2 added the "map complete" flag, v3 the Boot Protocol handles, v4 the HID
+// Control Point, v5 the Battery Level, v6 the PnP ID, v7 multiple HID service
+// instances; older blobs are a miss and get refreshed.
+const CACHE_VERSION: u8 = 0x07;
+const HEADER_SIZE: usize = 33;
+const INSTANCE_SIZE: usize = 5;
+const REPORT_SIZE: usize = 8;
+
+/// Maximum number of HID service instances tracked per device. Composite
+/// devices (a keyboard with a touchpad, a receiver built into a device) have
+/// two; more than that hasn't been seen in the wild.
+pub const MAX_HID_INSTANCES: usize = 2;
+
+/// Maximum number of HID Report characteristics cached per device (matches the
+/// number the HID client tracks).
+pub const MAX_CACHED_REPORTS: usize = 8;
+
+/// Largest serialized cache, for sizing flash record buffers.
+pub const MAX_CACHE_SIZE: usize =
+    HEADER_SIZE + MAX_HID_INSTANCES * INSTANCE_SIZE + MAX_CACHED_REPORTS * REPORT_SIZE;
+
+/// One cached HID Report characteristic, with its Report Reference already
+/// resolved so reconnect needs no descriptor reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachedReport {
+    /// Index of the HID service instance the report belongs to.
+    pub instance: u8,
+    pub value_handle: u16,
+    pub cccd_handle: Option<u16>,
+    pub report_ref: Option<ReportReference>,
+}
+
+/// The HID service's Boot Protocol characteristics, used when a peer's Report
+/// Protocol doesn't work (see [`crate::hid::protocol_fallback`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BootHandles {
+    /// Boot Keyboard Input Report (0x2A22) value handle and its CCCD.
+    pub keyboard_input: Option<u16>,
+    pub keyboard_input_cccd: Option<u16>,
+    /// Boot Keyboard Output Report (0x2A32): the LED sink in Boot Protocol.
+    pub keyboard_output: Option<u16>,
+    /// Boot Mouse Input Report (0x2A33) value handle and its CCCD.
+    pub mouse_input: Option<u16>,
+    pub mouse_input_cccd: Option<u16>,
+}
+
+impl BootHandles {
+    /// Does the peer expose anything we could read input from in Boot Protocol?
+    pub fn has_input(&self) -> bool {
+        self.keyboard_input_cccd.is_some() || self.mouse_input_cccd.is_some()
+    }
+}
+
+/// Kind of an input report: its Report Reference's ID looked up in the Report
+/// Map of the HID service instance it belongs to (report IDs are only unique
+/// within one map). `None` leaves it to the heuristic classifier at
+/// notification time.
+pub fn input_report_kind(
+    descriptors: &[Option<HidDescriptor>],
+    instance: u8,
+    report_ref: Option<ReportReference>,
+) -> Option<ReportKind> {
+    let descriptor = descriptors.get(instance as usize)?.as_ref()?;
+    report_ref
+        .filter(ReportReference::is_input)
+        .and_then(|r| descriptor.report_kind_for_id(r.report_id))
+}
+
+/// Everything HID discovery learned about a bonded peer's GATT database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GattCache {
+    pub protocol_mode_handle: Option<u16>,
+    pub keyboard_led_handle: Option<u16>,
+    /// Service Changed (0x2A05) value handle; an indication on it invalidates
+    /// the cache.
+    pub service_changed_handle: Option<u16>,
+    /// HID Control Point (0x2A4C) value handle, for suspend/exit-suspend.
+    pub control_point_handle: Option<u16>,
+    /// Battery Level (0x2A19) value handle and its CCCD (Battery Service).
+    pub battery_level_handle: Option<u16>,
+    pub battery_level_cccd: Option<u16>,
+    /// Device Information PnP ID, which selects the device's quirks.
+    pub pnp_id: Option<PnpId>,
+    pub reports: Vec<CachedReport, MAX_CACHED_REPORTS>,
+    /// The parsed Report Map of each HID service instance, so reconnect skips
+    /// the (long) map reads.
+    pub descriptors: Vec<Option<HidDescriptor>, MAX_HID_INSTANCES>,
+    pub boot: BootHandles,
+    /// Report Protocol didn't work on a previous connection; go straight to
+    /// Boot Protocol.
+    pub prefer_boot: bool,
+}
+
+fn put_handle(buf: &mut [u8], handle: Option<u16>) {
+    buf[..2].copy_from_slice(&handle.unwrap_or(0).to_le_bytes());
+}
+
+fn get_handle(data: &[u8]) -> Option<u16> {
+    match u16::from_le_bytes([data[0], data[1]]) {
+        0 => None,
+        h => Some(h),
+    }
+}
+
+fn non_zero(id: u8) -> Option<u8> {
+    (id != 0).then_some(id)
+}
+
+impl GattCache {
+    /// Serialize into `buf`, returning the byte count (0 if it doesn't fit).
+    pub fn serialize(&self, buf: &mut [u8]) -> usize {
+        let total = self.serialized_len();
+        if buf.len() < total {
+            return 0;
+        }
+
+        buf[0] = CACHE_VERSION;
+        put_handle(&mut buf[1..3], self.protocol_mode_handle);
+        put_handle(&mut buf[3..5], self.keyboard_led_handle);
+        put_handle(&mut buf[5..7], self.service_changed_handle);
+        buf[7] = self.descriptors.len() as u8;
+        buf[8] = self.reports.len() as u8;
+        put_handle(&mut buf[9..11], self.boot.keyboard_input);
+        put_handle(&mut buf[11..13], self.boot.keyboard_input_cccd);
+        put_handle(&mut buf[13..15], self.boot.keyboard_output);
+        put_handle(&mut buf[15..17], self.boot.mouse_input);
+        put_handle(&mut buf[17..19], self.boot.mouse_input_cccd);
+        buf[19] = self.prefer_boot as u8;
+        put_handle(&mut buf[20..22], self.control_point_handle);
+        put_handle(&mut buf[22..24], self.battery_level_handle);
+        put_handle(&mut buf[24..26], self.battery_level_cccd);
+        match &self.pnp_id {
+            Some(pnp) => buf[26..33].copy_from_slice(&pnp.serialize()),
+            None => buf[26..33].fill(0),
+        }
+
+        for (i, descriptor) in self.descriptors.iter().enumerate() {
+            let d = &mut buf[HEADER_SIZE + i * INSTANCE_SIZE..][..INSTANCE_SIZE];
+            match descriptor {
+                Some(desc) => {
+                    d[0] = 1;
+                    d[1] = (desc.has_keyboard as u8)
+                        | (desc.has_mouse as u8) << 1
+                        | (desc.has_consumer as u8) << 2
+                        | (desc.complete as u8) << 3;
+                    d[2] = desc.keyboard_report_id.unwrap_or(0);
+                    d[3] = desc.mouse_report_id.unwrap_or(0);
+                    d[4] = desc.consumer_report_id.unwrap_or(0);
+                }
+                None => d.fill(0),
+            }
+        }
+
+        let reports_start = HEADER_SIZE + self.descriptors.len() * INSTANCE_SIZE;
+        for (i, report) in self.reports.iter().enumerate() {
+            let r = &mut buf[reports_start + i * REPORT_SIZE..][..REPORT_SIZE];
+            r[0] = report.instance;
+            put_handle(&mut r[1..3], Some(report.value_handle));
+            put_handle(&mut r[3..5], report.cccd_handle);
+            match report.report_ref {
+                Some(rr) => {
+                    r[5] = 1;
+                    r[6] = rr.report_id;
+                    r[7] = rr.report_type.into();
+                }
+                None => r[5..8].fill(0),
+            }
+        }
+        total
+    }
+
+    /// Deserialize a cache blob. Returns `None` for an unknown version or a
+    /// truncated/corrupt blob, which callers treat as a cache miss.
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || data[0] != CACHE_VERSION {
+            return None;
+        }
+        let instances = data[7] as usize;
+        let count = data[8] as usize;
+        let reports_start = HEADER_SIZE + instances * INSTANCE_SIZE;
+        if instances > MAX_HID_INSTANCES
+            || count > MAX_CACHED_REPORTS
+            || data.len() < reports_start + count * REPORT_SIZE
+        {
+            return None;
+        }
+
+        let mut descriptors = Vec::new();
+        for i in 0..instances {
+            let d = &data[HEADER_SIZE + i * INSTANCE_SIZE..][..INSTANCE_SIZE];
+            let _ = descriptors.push((d[0] != 0).then(|| HidDescriptor {
+                has_keyboard: d[1] & 0x01 != 0,
+                has_mouse: d[1] & 0x02 != 0,
+                has_consumer: d[1] & 0x04 != 0,
+                keyboard_report_id: non_zero(d[2]),
+                mouse_report_id: non_zero(d[3]),
+                consumer_report_id: non_zero(d[4]),
+                complete: d[1] & 0x08 != 0,
+            }));
+        }
+
+        let mut reports = Vec::new();
+        for i in 0..count {
+            let r = &data[reports_start + i * REPORT_SIZE..][..REPORT_SIZE];
+            // A report of an instance we don't have: corrupt blob.
+            if r[0] as usize >= instances {
+                return None;
+            }
+            let report_ref = (r[5] != 0).then(|| ReportReference {
+                report_id: r[6],
+                report_type: ReportType::from(r[7]),
+            });
+            let _ = reports.push(CachedReport {
+                instance: r[0],
+                // A zero value handle can't be a real attribute: corrupt blob.
+                value_handle: get_handle(&r[1..3])?,
+                cccd_handle: get_handle(&r[3..5]),
+                report_ref,
+            });
+        }
+
+        Some(Self {
+            protocol_mode_handle: get_handle(&data[1..3]),
+            keyboard_led_handle: get_handle(&data[3..5]),
+            service_changed_handle: get_handle(&data[5..7]),
+            control_point_handle: get_handle(&data[20..22]),
+            battery_level_handle: get_handle(&data[22..24]),
+            battery_level_cccd: get_handle(&data[24..26]),
+            pnp_id: PnpId::parse(&data[26..33]),
+            reports,
+            descriptors,
+            boot: BootHandles {
+                keyboard_input: get_handle(&data[9..11]),
+                keyboard_input_cccd: get_handle(&data[11..13]),
+                keyboard_output: get_handle(&data[13..15]),
+                mouse_input: get_handle(&data[15..17]),
+                mouse_input_cccd: get_handle(&data[17..19]),
+            },
+            prefer_boot: data[19] & 0x01 != 0,
+        })
+    }
+
+    /// Serialized size of this cache.
+    pub fn serialized_len(&self) -> usize {
+        HEADER_SIZE + self.descriptors.len() * INSTANCE_SIZE + self.reports.len() * REPORT_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> GattCache {
+        let mut reports = Vec::new();
+        reports
+            .push(CachedReport {
+                instance: 0,
+                value_handle: 0x0012,
+                cccd_handle: Some(0x0013),
+                report_ref: Some(ReportReference {
+                    report_id: 1,
+                    report_type: ReportType::Input,
+                }),
+            })
+            .unwrap();
+        reports
+            .push(CachedReport {
+                instance: 0,
+                value_handle: 0x0016,
+                cccd_handle: None,
+                report_ref: Some(ReportReference {
+                    report_id: 1,
+                    report_type: ReportType::Output,
+                }),
+            })
+            .unwrap();
+        reports
+            .push(CachedReport {
+                instance: 0,
+                value_handle: 0x001A,
+                cccd_handle: Some(0x001B),
+                report_ref: None,
+            })
+            .unwrap();
+        GattCache {
+            protocol_mode_handle: Some(0x0010),
+            keyboard_led_handle: Some(0x0016),
+            service_changed_handle: Some(0x0003),
+            control_point_handle: Some(0x0018),
+            battery_level_handle: Some(0x0040),
+            battery_level_cccd: Some(0x0041),
+            pnp_id: Some(PnpId {
+                vendor_id_source: 0x02,
+                vendor_id: 0x046D,
+                product_id: 0xB023,
+                product_version: 0x0100,
+            }),
+            reports,
+            descriptors: Vec::from_slice(&[Some(HidDescriptor {
+                has_keyboard: true,
+                has_mouse: false,
+                has_consumer: true,
+                keyboard_report_id: Some(1),
+                mouse_report_id: None,
+                consumer_report_id: Some(3),
+                complete: true,
+            })])
+            .unwrap(),
+            boot: BootHandles {
+                keyboard_input: Some(0x0020),
+                keyboard_input_cccd: Some(0x0021),
+                keyboard_output: Some(0x0023),
+                mouse_input: None,
+                mouse_input_cccd: None,
+            },
+            prefer_boot: false,
+        }
+    }
+
+    fn round_trip(cache: &GattCache) -> GattCache {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = cache.serialize(&mut buf);
+        assert_eq!(n, cache.serialized_len());
+        GattCache::deserialize(&buf[..n]).expect("valid cache")
+    }
+
+    #[test]
+    fn full_cache_round_trips() {
+        let cache = sample();
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    #[test]
+    fn absent_handles_and_descriptor_round_trip() {
+        let cache = GattCache {
+            protocol_mode_handle: None,
+            keyboard_led_handle: None,
+            service_changed_handle: None,
+            control_point_handle: None,
+            battery_level_handle: None,
+            battery_level_cccd: None,
+            pnp_id: None,
+            reports: Vec::new(),
+            descriptors: Vec::from_slice(&[None]).unwrap(),
+            boot: BootHandles::default(),
+            prefer_boot: false,
+        };
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    #[test]
+    fn incomplete_descriptor_round_trips() {
+        let mut cache = sample();
+        if let Some(d) = cache.descriptors[0].as_mut() {
+            d.complete = false;
+        }
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    /// A keyboard-and-touchpad combo with one HID service per function.
+    fn composite() -> GattCache {
+        let mut cache = sample();
+        cache
+            .descriptors
+            .push(Some(HidDescriptor {
+                has_keyboard: false,
+                has_mouse: true,
+                has_consumer: false,
+                keyboard_report_id: None,
+                mouse_report_id: None,
+                consumer_report_id: None,
+                complete: true,
+            }))
+            .unwrap();
+        cache
+            .reports
+            .push(CachedReport {
+                instance: 1,
+                value_handle: 0x0052,
+                cccd_handle: Some(0x0053),
+                report_ref: Some(ReportReference {
+                    report_id: 0,
+                    report_type: ReportType::Input,
+                }),
+            })
+            .unwrap();
+        cache
+    }
+
+    #[test]
+    fn second_hid_instance_round_trips() {
+        let cache = composite();
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    #[test]
+    fn two_instances_classify_against_their_own_maps() {
+        use crate::hid::{classify_known, HidReport};
+
+        // Both services number their input report 1: a keyboard in the first
+        // map, the touchpad in the second.
+        let mut cache = composite();
+        if let Some(d) = cache.descriptors[1].as_mut() {
+            d.mouse_report_id = Some(1);
+        }
+        let touchpad = ReportReference {
+            report_id: 1,
+            report_type: ReportType::Input,
+        };
+        for r in cache.reports.iter_mut().filter(|r| r.instance == 1) {
+            r.report_ref = Some(touchpad);
+        }
+
+        let cache = round_trip(&cache);
+        let kind_of = |value_handle: u16| {
+            let r = cache
+                .reports
+                .iter()
+                .find(|r| r.value_handle == value_handle)
+                .unwrap();
+            input_report_kind(&cache.descriptors, r.instance, r.report_ref)
+        };
+        let keys = kind_of(0x0012).expect("keyboard report resolved");
+        let pointer = kind_of(0x0052).expect("touchpad report resolved");
+        assert_eq!(keys, ReportKind::Keyboard);
+        assert_eq!(pointer, ReportKind::Mouse);
+
+        let key_a = [0x00, 0x00, 0x04, 0, 0, 0, 0, 0];
+        let nudge = [0x00, 10, 0xF6];
+        assert!(matches!(
+            classify_known(keys, &key_a),
+            Some(HidReport::Keyboard(_))
+        ));
+        assert!(matches!(
+            classify_known(pointer, &nudge),
+            Some(HidReport::Mouse(_))
+        ));
+        // Only the report's own instance decides: the first map reads ID 1 as
+        // the keyboard, and an instance without a map resolves nothing.
+        assert_eq!(
+            input_report_kind(&cache.descriptors, 0, Some(touchpad)),
+            Some(ReportKind::Keyboard)
+        );
+        assert_eq!(
+            input_report_kind(&cache.descriptors, 2, Some(touchpad)),
+            None
+        );
+    }
+
+    #[test]
+    fn report_of_unknown_instance_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = composite().serialize(&mut buf);
+        // The last report claims a third instance.
+        buf[n - REPORT_SIZE] = MAX_HID_INSTANCES as u8;
+        assert!(GattCache::deserialize(&buf[..n]).is_none());
+    }
+
+    #[test]
+    fn oversized_instance_count_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = composite().serialize(&mut buf);
+        buf[7] = (MAX_HID_INSTANCES + 1) as u8;
+        assert!(GattCache::deserialize(&buf[..n]).is_none());
+    }
+
+    #[test]
+    fn boot_preference_round_trips() {
+        let mut cache = sample();
+        cache.prefer_boot = true;
+        cache.boot.mouse_input = Some(0x0030);
+        cache.boot.mouse_input_cccd = Some(0x0031);
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    #[test]
+    fn boot_input_needs_a_cccd() {
+        let mut boot = BootHandles::default();
+        assert!(!boot.has_input());
+        boot.keyboard_output = Some(0x0023);
+        boot.mouse_input = Some(0x0030);
+        assert!(!boot.has_input(), "no notifiable boot input yet");
+        boot.mouse_input_cccd = Some(0x0031);
+        assert!(boot.has_input());
+    }
+
+    #[test]
+    fn other_report_type_is_preserved() {
+        let mut cache = sample();
+        cache.reports[2].report_ref = Some(ReportReference {
+            report_id: 9,
+            report_type: ReportType::Other(7),
+        });
+        assert_eq!(round_trip(&cache), cache);
+    }
+
+    #[test]
+    fn serialize_refuses_short_buffer() {
+        let cache = sample();
+        let mut buf = [0u8; HEADER_SIZE];
+        assert_eq!(cache.serialize(&mut buf), 0);
+    }
+
+    #[test]
+    fn unknown_version_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = sample().serialize(&mut buf);
+        buf[0] = CACHE_VERSION + 1;
+        assert!(GattCache::deserialize(&buf[..n]).is_none());
+    }
+
+    #[test]
+    fn truncated_blob_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = sample().serialize(&mut buf);
+        assert!(GattCache::deserialize(&buf[..n - 1]).is_none());
+        assert!(GattCache::deserialize(&buf[..HEADER_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn oversized_report_count_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = sample().serialize(&mut buf);
+        buf[8] = (MAX_CACHED_REPORTS + 1) as u8;
+        assert!(GattCache::deserialize(&buf[..n]).is_none());
+    }
+
+    #[test]
+    fn zero_value_handle_is_a_miss() {
+        let mut buf = [0u8; MAX_CACHE_SIZE];
+        let n = sample().serialize(&mut buf);
+        let value = HEADER_SIZE + INSTANCE_SIZE + 1;
+        buf[value] = 0;
+        buf[value + 1] = 0;
+        assert!(GattCache::deserialize(&buf[..n]).is_none());
+    }
+}
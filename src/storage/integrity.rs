@@ -0,0 +1,363 @@
+//! Pure, hardware-free integrity layer for flash blobs: a CRC envelope and
+//! redundant A/B copies.
+//!
+//! A torn write (power lost mid-save) or a flipped bit used to read back as a
+//! silently truncated device list. Every blob the store persists is now sealed
+//! in an envelope carrying a CRC-32 and a generation counter, and saves
+//! alternate between two copies (A and B) so the previous one stays intact
+//! until the new one is fully written. On load, [`choose`] picks the newest
+//! copy whose CRC checks out and reports whether anything was damaged.
+//!
+//! Envelope layout:
+//! ```text
+//! [0]     magic (0xC3)
+//! [1..5]  generation (u32 LE)
+//! [5..9]  CRC-32 over [1..5] and the payload (u32 LE)
+//! [9..]   payload (a `framing` / settings blob)
+//! ```
+//!
+//! Blobs written before the envelope existed don't start with the magic. They
+//! were stored under copy A's key, and sealed saves start with copy B, so
+//! such a blob is loaded as generation 0 ("legacy") unless B is a valid sealed
+//! copy: the first sealed generation supersedes it, and a later one means A
+//! should have been sealed by then, so it counts as damage. A damaged B (the
+//! first sealed save torn) leaves the legacy blob as the copy to load.
+
+const MAGIC: u8 = 0xC3;
+
+/// Envelope bytes in front of the payload.
+pub const HEADER_SIZE: usize = 9;
+
+/// Fold `data` into a running (non-inverted) CRC-32 register. Bitwise rather
+/// than table-driven: blobs are small and saves rare, so flash size wins.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
+
+/// CRC-32 (IEEE 802.3, reflected, as used by zlib/Ethernet).
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+/// CRC of an envelope: the generation bytes followed by the payload.
+fn envelope_crc(generation: [u8; 4], payload: &[u8]) -> u32 {
+    !crc32_update(crc32_update(!0, &generation), payload)
+}
+
+/// Seal `payload` as `generation` into `buf`. Returns the envelope length, or
+/// 0 if it doesn't fit.
+pub fn seal(buf: &mut [u8], generation: u32, payload: &[u8]) -> usize {
+    let total = HEADER_SIZE + payload.len();
+    if buf.len() < total {
+        return 0;
+    }
+    let generation = generation.to_le_bytes();
+    buf[0] = MAGIC;
+    buf[1..5].copy_from_slice(&generation);
+    buf[5..9].copy_from_slice(&envelope_crc(generation, payload).to_le_bytes());
+    buf[HEADER_SIZE..total].copy_from_slice(payload);
+    total
+}
+
+/// What one stored copy turned out to be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stored<'a> {
+    /// An intact envelope.
+    Sealed { generation: u32, payload: &'a [u8] },
+    /// A blob from before the envelope existed (unchecked).
+    Legacy(&'a [u8]),
+    /// An envelope that fails its CRC or is cut short.
+    Corrupt,
+}
+
+/// Check one stored copy.
+pub fn open(data: &[u8]) -> Stored<'_> {
+    if data.first() != Some(&MAGIC) {
+        return if data.is_empty() {
+            Stored::Corrupt
+        } else {
+            Stored::Legacy(data)
+        };
+    }
+    if data.len() < HEADER_SIZE {
+        return Stored::Corrupt;
+    }
+    let generation = [data[1], data[2], data[3], data[4]];
+    let crc = u32::from_le_bytes([data[5], data[6], data[7], data[8]]);
+    let payload = &data[HEADER_SIZE..];
+    if envelope_crc(generation, payload) != crc {
+        return Stored::Corrupt;
+    }
+    Stored::Sealed {
+        generation: u32::from_le_bytes(generation),
+        payload,
+    }
+}
+
+/// One of the two copies of a blob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+/// State of a blob's copies as found on load, ordered from best to worst (so
+/// the state of several blobs is their `max`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Health {
+    /// Every stored copy is intact (or there was nothing stored yet).
+    Intact,
+    /// A copy was damaged, but another one was intact and got loaded. If the
+    /// damaged one was the newer, the last save is lost.
+    Recovered,
+    /// Every stored copy is damaged; the blob reads as absent.
+    Lost,
+}
+
+/// Where (and as what generation) the next save goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Next {
+    pub slot: Slot,
+    pub generation: u32,
+}
+
+impl Next {
+    /// Where the first save goes when nothing sealed is stored (copy B, so a
+    /// legacy blob in A survives it).
+    pub const FIRST: Self = Self {
+        slot: Slot::B,
+        generation: 1,
+    };
+
+    /// Move on after a successful save, to the copy it didn't overwrite.
+    pub fn advance(&mut self) {
+        self.slot = self.slot.other();
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
+
+/// Result of [`choose`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chosen<'a> {
+    /// The payload of the newest intact copy, if any.
+    pub payload: Option<&'a [u8]>,
+    pub health: Health,
+    /// The next save overwrites the copy that was *not* loaded, so the loaded
+    /// one survives a torn write.
+    pub next: Next,
+}
+
+/// Pick the newest intact copy of a blob from its stored A and B copies.
+pub fn choose<'a>(a: Option<&'a [u8]>, b: Option<&'a [u8]>) -> Chosen<'a> {
+    let b = b.map(|data| match open(data) {
+        Stored::Legacy(_) => Stored::Corrupt,
+        copy => copy,
+    });
+    let a = match (a.map(open), b) {
+        (Some(Stored::Legacy(_)), Some(Stored::Sealed { generation: 1, .. })) => None,
+        (Some(Stored::Legacy(_)), Some(Stored::Sealed { .. })) => Some(Stored::Corrupt),
+        (a, _) => a,
+    };
+
+    let mut best: Option<(Slot, u32, &'a [u8])> = None;
+    let mut damaged = false;
+    let mut newest_generation = 0;
+    for (slot, copy) in [(Slot::A, a), (Slot::B, b)] {
+        let (generation, payload) = match copy {
+            None => continue,
+            Some(Stored::Sealed {
+                generation,
+                payload,
+            }) => (generation, payload),
+            Some(Stored::Legacy(payload)) => (0, payload),
+            Some(Stored::Corrupt) => {
+                damaged = true;
+                continue;
+            }
+        };
+        newest_generation = newest_generation.max(generation);
+        if best.is_none_or(|(_, g, _)| generation > g) {
+            best = Some((slot, generation, payload));
+        }
+    }
+
+    match best {
+        Some((slot, _, payload)) => Chosen {
+            payload: Some(payload),
+            health: if damaged {
+                Health::Recovered
+            } else {
+                Health::Intact
+            },
+            next: Next {
+                slot: slot.other(),
+                generation: newest_generation.wrapping_add(1),
+            },
+        },
+        None => Chosen {
+            payload: None,
+            health: if damaged {
+                Health::Lost
+            } else {
+                Health::Intact
+            },
+            next: Next::FIRST,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed(generation: u32, payload: &[u8]) -> heapless::Vec<u8, 64> {
+        let mut buf = [0u8; 64];
+        let len = seal(&mut buf, generation, payload);
+        heapless::Vec::from_slice(&buf[..len]).unwrap()
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn sealed_copy_opens_to_its_payload() {
+        let data = sealed(7, &[0xB2, 1, 0]);
+        assert_eq!(
+            open(&data),
+            Stored::Sealed {
+                generation: 7,
+                payload: &[0xB2, 1, 0],
+            }
+        );
+        assert_eq!(seal(&mut [0u8; HEADER_SIZE + 2], 1, &[1, 2, 3]), 0);
+    }
+
+    #[test]
+    fn any_flipped_bit_or_truncation_is_detected() {
+        let data = sealed(3, &[0xB2, 1, 1, 2, 0xAA, 0xBB]);
+        for i in 1..data.len() {
+            for bit in 0..8 {
+                let mut bad = data.clone();
+                bad[i] ^= 1 << bit;
+                assert_eq!(open(&bad), Stored::Corrupt, "byte {i} bit {bit}");
+            }
+        }
+        for len in 1..data.len() {
+            assert_eq!(open(&data[..len]), Stored::Corrupt, "length {len}");
+        }
+    }
+
+    #[test]
+    fn newest_intact_copy_wins_and_next_save_spares_it() {
+        let a = sealed(4, &[1]);
+        let b = sealed(5, &[2]);
+        let chosen = choose(Some(&a), Some(&b));
+        assert_eq!(chosen.payload, Some(&[2u8][..]));
+        assert_eq!(chosen.health, Health::Intact);
+        assert_eq!(
+            chosen.next,
+            Next {
+                slot: Slot::A,
+                generation: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn torn_newer_copy_falls_back_to_the_older_one() {
+        let a = sealed(4, &[1]);
+        let mut b = sealed(5, &[2, 2, 2]);
+        b.truncate(b.len() - 1);
+        let chosen = choose(Some(&a), Some(&b));
+        assert_eq!(chosen.payload, Some(&[1u8][..]));
+        assert_eq!(chosen.health, Health::Recovered);
+        // The damaged copy is the one overwritten next.
+        assert_eq!(
+            chosen.next,
+            Next {
+                slot: Slot::B,
+                generation: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn all_copies_damaged_reads_as_lost() {
+        let mut b = sealed(1, &[1]);
+        b[HEADER_SIZE] ^= 0x10;
+        let chosen = choose(None, Some(&b));
+        assert_eq!(chosen.payload, None);
+        assert_eq!(chosen.health, Health::Lost);
+        assert_eq!(chosen.next, Next::FIRST);
+
+        let nothing = choose(None, None);
+        assert_eq!(nothing.health, Health::Intact);
+        assert_eq!(nothing.next, Next::FIRST);
+    }
+
+    #[test]
+    fn legacy_blob_loads_until_a_sealed_copy_supersedes_it() {
+        let legacy = [0xB2, 1, 0];
+        let chosen = choose(Some(&legacy), None);
+        assert_eq!(chosen.payload, Some(&legacy[..]));
+        assert_eq!(chosen.health, Health::Intact);
+        assert_eq!(chosen.next, Next::FIRST);
+
+        let b = sealed(1, &[0xB2, 1, 1, 1, 9]);
+        let chosen = choose(Some(&legacy), Some(&b));
+        assert_eq!(chosen.payload, Some(&b[HEADER_SIZE..]));
+        assert_eq!(chosen.health, Health::Intact);
+
+        // Past the first sealed generation, an unsealed A is a damaged one.
+        let b = sealed(3, &[0xB2, 1, 0]);
+        assert_eq!(choose(Some(&legacy), Some(&b)).health, Health::Recovered);
+    }
+
+    #[test]
+    fn legacy_blob_survives_a_torn_first_sealed_save() {
+        let legacy = [0xB2, 1, 0];
+        let mut b = sealed(1, &[0xB2, 1, 1, 1, 9]);
+        b.truncate(b.len() - 2);
+        let chosen = choose(Some(&legacy), Some(&b));
+        assert_eq!(chosen.payload, Some(&legacy[..]));
+        assert_eq!(chosen.health, Health::Recovered);
+        // The retry goes to B again, still sparing the legacy blob.
+        assert_eq!(chosen.next, Next::FIRST);
+    }
+
+    #[test]
+    fn saves_alternate_copies() {
+        let mut next = Next::FIRST;
+        next.advance();
+        assert_eq!(
+            next,
+            Next {
+                slot: Slot::A,
+                generation: 2,
+            }
+        );
+        next.advance();
+        assert_eq!(next.slot, Slot::B);
+    }
+}
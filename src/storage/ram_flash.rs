@@ -0,0 +1,98 @@
+//! RAM-backed `NorFlash` covering the storage region, for host tests of the
+//! store.
+//!
+//! Behaves like NOR flash (writes can only clear bits; erase sets a whole page
+//! to 0xFF) and can be told to fail the next few writes, the way SoftDevice
+//! flash operations fail while the radio is busy.
+
+use crate::storage::region::{FLASH_PAGE_SIZE, STORAGE_END, STORAGE_START};
+use embedded_hal_async::delay::DelayNs;
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+const SIZE: usize = (STORAGE_END - STORAGE_START) as usize;
+
+pub struct RamFlash {
+    data: [u8; SIZE],
+    /// Number of upcoming writes to fail.
+    pub fail_writes: u8,
+    /// Writes that reached the flash.
+    pub writes: usize,
+}
+
+impl RamFlash {
+    /// Erased flash.
+    pub fn new() -> Self {
+        Self {
+            data: [0xFF; SIZE],
+            fail_writes: 0,
+            writes: 0,
+        }
+    }
+
+    /// Byte range of `[offset, offset + len)` in `data`, if in the region.
+    fn range(offset: u32, len: usize) -> Result<core::ops::Range<usize>, NorFlashErrorKind> {
+        let start = offset
+            .checked_sub(STORAGE_START)
+            .ok_or(NorFlashErrorKind::OutOfBounds)? as usize;
+        if start + len > SIZE {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        Ok(start..start + len)
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        bytes.copy_from_slice(&self.data[Self::range(offset, bytes.len())?]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        STORAGE_END as usize
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = FLASH_PAGE_SIZE as usize;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if !from.is_multiple_of(FLASH_PAGE_SIZE) || !to.is_multiple_of(FLASH_PAGE_SIZE) || to < from
+        {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        self.data[Self::range(from, (to - from) as usize)?].fill(0xFF);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        if self.fail_writes > 0 {
+            self.fail_writes -= 1;
+            return Err(NorFlashErrorKind::Other);
+        }
+        if !(offset as usize).is_multiple_of(Self::WRITE_SIZE)
+            || !bytes.len().is_multiple_of(Self::WRITE_SIZE)
+        {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        let range = Self::range(offset, bytes.len())?;
+        for (cell, byte) in self.data[range].iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+        self.writes += 1;
+        Ok(())
+    }
+}
+
+/// Retry delay that doesn't wait.
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
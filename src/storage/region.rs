@@ -0,0 +1,176 @@
+//! Key/value access to the storage flash region, over any `NorFlash`.
+//!
+//! Wraps `sequential-storage`'s map (which handles wear levelling and GC) with
+//! the retrying writes the SoftDevice needs and the sealed A/B copies from
+//! [`integrity`]. Generic over the flash and the retry delay, so the firmware
+//! runs it on the SoftDevice flash and host tests on a RAM flash.
+
+use crate::config::{STORAGE_FLASH_PAGE_COUNT, STORAGE_FLASH_PAGE_START};
+use crate::storage::integrity::{self, Chosen, Next, Slot};
+use embedded_hal_async::delay::DelayNs;
+use embedded_storage_async::nor_flash::NorFlash;
+use sequential_storage::cache::NoCache;
+use sequential_storage::map::{MapConfig, MapStorage};
+
+/// Flash page size for nRF52840 (4 KB).
+pub const FLASH_PAGE_SIZE: u32 = 4096;
+
+/// Start address of our storage region.
+pub const STORAGE_START: u32 = STORAGE_FLASH_PAGE_START * FLASH_PAGE_SIZE;
+
+/// End address (exclusive) of our storage region.
+pub const STORAGE_END: u32 =
+    (STORAGE_FLASH_PAGE_START + STORAGE_FLASH_PAGE_COUNT) * FLASH_PAGE_SIZE;
+
+/// Key for the paired devices list in the map storage. (Settings use
+/// `storage::KEY_SETTINGS`.)
+pub const KEY_PAIRED_DEVICES: u8 = 0x01;
+
+/// Key for the paired devices' usage (recency, connect count, role, icon),
+/// kept apart from the device list so recording a connection doesn't rewrite
+/// the bonds and GATT caches.
+pub const KEY_DEVICE_USAGE: u8 = 0x03;
+
+/// Base of the device list keys of profiles after the first (`+ profile`).
+const KEY_PROFILE_DEVICES: u8 = 0x10;
+
+/// Base of the usage keys of profiles after the first (`+ profile`).
+const KEY_PROFILE_USAGE: u8 = 0x20;
+
+/// Map keys of `profile`'s device list and usage blobs. The first profile
+/// keeps the keys from before profiles existed, so an older store loads as
+/// profile 0.
+pub const fn profile_keys(profile: u8) -> (u8, u8) {
+    match profile {
+        0 => (KEY_PAIRED_DEVICES, KEY_DEVICE_USAGE),
+        n => (KEY_PROFILE_DEVICES + n, KEY_PROFILE_USAGE + n),
+    }
+}
+
+/// Flag marking the key of a blob's B copy (A uses the plain key).
+const KEY_COPY_B: u8 = 0x80;
+
+/// Largest item stored under one key, integrity envelope included.
+pub const MAX_ITEM_SIZE: usize = 1024;
+
+/// Retry budget for a flash write that races BLE radio timeslots.
+const FLASH_WRITE_ATTEMPTS: u8 = 3;
+const FLASH_RETRY_BACKOFF_MS: u32 = 20;
+
+/// Map key of `slot`'s copy of the blob under `key`.
+const fn copy_key(key: u8, slot: Slot) -> u8 {
+    match slot {
+        Slot::A => key,
+        Slot::B => key | KEY_COPY_B,
+    }
+}
+
+/// The storage region of `flash`, with `delay` pacing write retries.
+pub struct Region<F, D> {
+    flash: F,
+    delay: D,
+}
+
+impl<F: NorFlash, D: DelayNs> Region<F, D> {
+    pub fn new(flash: F, delay: D) -> Self {
+        Self { flash, delay }
+    }
+
+    /// The underlying flash, for tests to inspect or fault.
+    #[cfg(test)]
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Read the item stored under `key` into `buf`. A flash error is logged
+    /// and reads as no item.
+    pub async fn fetch<'a>(&mut self, key: u8, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+        // sequential-storage 7 exposes a stateful `MapStorage` (the standalone
+        // `map::fetch_item` free function was removed). It borrows the flash
+        // for the duration of the access and is dropped before we return.
+        let config = MapConfig::new(STORAGE_START..STORAGE_END);
+        let mut map = MapStorage::<u8, _, _>::new(&mut self.flash, config, NoCache);
+
+        let result = map.fetch_item::<&[u8]>(buf, &key).await;
+        #[cfg(feature = "defmt")]
+        if let Err(e) = &result {
+            defmt::error!("Flash read error: {:?}", defmt::Debug2Format(e));
+        }
+        result.ok().flatten()
+    }
+
+    /// Store `item` under `key`, returning whether it was written.
+    pub async fn store(&mut self, key: u8, item: &[u8]) -> bool {
+        let mut buf = [0u8; MAX_ITEM_SIZE];
+
+        let config = MapConfig::new(STORAGE_START..STORAGE_END);
+        let mut map = MapStorage::<u8, _, _>::new(&mut self.flash, config, NoCache);
+
+        // SoftDevice flash operations need radio-idle timeslots and can fail
+        // with a transient busy/timeout error while BLE links are active (a
+        // save often runs right at connect time). Retry a few times with a
+        // short backoff.
+        for attempt in 1..=FLASH_WRITE_ATTEMPTS {
+            match map.store_item::<&[u8]>(&mut buf, &key, &item).await {
+                Ok(_) => return true,
+                Err(_e) => {
+                    if attempt < FLASH_WRITE_ATTEMPTS {
+                        #[cfg(feature = "defmt")]
+                        defmt::warn!("Flash write busy (attempt {}), retrying", attempt);
+                        self.delay.delay_ms(FLASH_RETRY_BACKOFF_MS).await;
+                    } else {
+                        #[cfg(feature = "defmt")]
+                        defmt::error!(
+                            "Flash write failed after {} attempts: {:?}",
+                            FLASH_WRITE_ATTEMPTS,
+                            defmt::Debug2Format(&_e)
+                        );
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Read both copies of the blob under `key` and pick the newest intact
+    /// one. Damage is logged here; callers surface it through their
+    /// [`Health`](integrity::Health).
+    pub async fn fetch_sealed<'a>(
+        &mut self,
+        key: u8,
+        buf_a: &'a mut [u8],
+        buf_b: &'a mut [u8],
+    ) -> Chosen<'a> {
+        let a = self.fetch(copy_key(key, Slot::A), buf_a).await;
+        let b = self.fetch(copy_key(key, Slot::B), buf_b).await;
+        let chosen = integrity::choose(a, b);
+        #[cfg(feature = "defmt")]
+        match chosen.health {
+            integrity::Health::Intact => {}
+            integrity::Health::Recovered => {
+                defmt::warn!("Flash blob {=u8:#x}: damaged copy, loaded the other", key)
+            }
+            integrity::Health::Lost => {
+                defmt::error!("Flash blob {=u8:#x}: every copy damaged", key)
+            }
+        }
+        chosen
+    }
+
+    /// Seal `payload` and store it as the `next` copy of the blob under `key`,
+    /// moving `next` on once it is written. Returns whether it was written.
+    pub async fn store_sealed(&mut self, key: u8, next: &mut Next, payload: &[u8]) -> bool {
+        let mut buf = [0u8; MAX_ITEM_SIZE];
+        let len = integrity::seal(&mut buf, next.generation, payload);
+        if len == 0 {
+            #[cfg(feature = "defmt")]
+            defmt::error!("Flash blob {=u8:#x} does not fit its record", key);
+            return false;
+        }
+        let written = self.store(copy_key(key, next.slot), &buf[..len]).await;
+        if written {
+            next.advance();
+        }
+        written
+    }
+}
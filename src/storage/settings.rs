@@ -0,0 +1,562 @@
+//! Pure, hardware-free settings schema: typed fields, defaults, and the
+//! versioned wire format they are persisted in.
+//!
+//! Settings are one flash blob under their own map key, framed with
+//! [`crate::storage::framing`]. The first record holds the schema version; each
+//! further record is one field, `[field id][value]`, so fields can be added
+//! without disturbing the others. The rules that keep a firmware update (or a
+//! downgrade) from ever bricking saved state:
+//!
+//! - A field id is never reused or renumbered. A field whose meaning changes
+//!   gets a new id, and a [`Migration`] converts the old one when an older blob
+//!   is loaded.
+//! - A missing, malformed or out-of-range field falls back to its default.
+//! - Fields a *newer* firmware wrote that this one doesn't know are carried
+//!   along unchanged and written back, with the newer schema version, so
+//!   moving back to that firmware loses nothing.
+//!
+//! The flash access lives in [`crate::storage`].
+//!
+//! Wire layout (inside the framing):
+//! ```text
+//! record 0:  [0x00][schema version]
+//! record n:  [field id][value, little endian]
+//! ```
+
+use crate::ble::security::SecurityPolicy;
+use crate::config;
+use crate::hid::layout::ModifierLayout;
+use crate::storage::framing;
+use heapless::Vec;
+
+/// Current schema version. 2 added the active profile, 3 the modifier layout
+/// and the host bindings, 4 the security policy (no migrations: an older blob
+/// simply lacks the fields and gets the defaults). The automatic layout is a
+/// later value of the layout field; firmware that predates it reads the
+/// default instead.
+pub const SCHEMA_VERSION: u8 = 4;
+
+/// Largest encoded settings blob, for sizing flash buffers.
+pub const MAX_SETTINGS_SIZE: usize = 3 + MAX_FIELDS * (2 + MAX_VALUE_LEN);
+
+/// Most fields a blob can carry (known plus carried-along unknown ones).
+const MAX_FIELDS: usize = 16;
+
+/// Longest field value.
+const MAX_VALUE_LEN: usize = 8;
+
+// Field ids. Never reuse or renumber one (see the module docs).
+const FIELD_SCHEMA: u8 = 0x00;
+const FIELD_SCAN_DURATION: u8 = 0x01;
+const FIELD_SCREEN_AUTO_OFF: u8 = 0x02;
+const FIELD_SCREEN_AUTO_OFF_SECS: u8 = 0x03;
+const FIELD_BUTTON_DEBOUNCE: u8 = 0x04;
+const FIELD_RELAX_CONN: u8 = 0x05;
+const FIELD_ACTIVE_PROFILE: u8 = 0x06;
+const FIELD_MODIFIER_LAYOUT: u8 = 0x07;
+const FIELD_SECURITY_POLICY: u8 = 0x08;
+/// First of [`config::MAX_KNOWN_HOSTS`] consecutive host binding ids.
+const FIELD_HOSTS: u8 = 0x10;
+
+/// One encoded field: its id and raw value bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub id: u8,
+    pub value: Vec<u8, MAX_VALUE_LEN>,
+}
+
+/// Raw fields of a blob, as migrations see them.
+pub type Fields = Vec<Field, MAX_FIELDS>;
+
+/// An upgrade step, run on the raw fields of a blob older than `to`.
+pub struct Migration {
+    pub to: u8,
+    pub up: fn(&mut Fields),
+}
+
+/// Upgrade steps in version order. None yet: no field has changed meaning.
+const MIGRATIONS: &[Migration] = &[];
+
+/// User-adjustable tunables. The defaults are the compile-time values in
+/// [`crate::config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Settings {
+    /// BLE scan window (seconds).
+    pub scan_duration_secs: u8,
+    /// Turn the OLED off after inactivity.
+    pub screen_auto_off: bool,
+    /// Inactivity before the OLED turns off (seconds).
+    pub screen_auto_off_secs: u16,
+    /// Button debounce time (ms).
+    pub button_debounce_ms: u8,
+    /// Relax BLE connection parameters while the PC sleeps.
+    pub relax_conn_on_suspend: bool,
+    /// Device profile whose paired devices are in use.
+    pub active_profile: u8,
+    /// How modifiers are rearranged for the PC.
+    pub modifier_layout: ModifierLayout,
+    /// Whether new peripherals may pair, and whether they are bonded.
+    pub security_policy: SecurityPolicy,
+    /// Settings bound to the PCs seen upstream, most recent first.
+    pub hosts: [Option<HostBinding>; config::MAX_KNOWN_HOSTS],
+}
+
+/// The settings that follow a PC around: when the bridge finds itself
+/// enumerated by a host it has seen before, these are put back into effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HostBinding {
+    /// The host, as [`crate::usb::host_id`] identifies it.
+    pub fingerprint: u32,
+    pub active_profile: u8,
+    pub modifier_layout: ModifierLayout,
+    pub screen_auto_off: bool,
+}
+
+impl HostBinding {
+    const SIZE: usize = 7;
+
+    fn encode(&self) -> [u8; Self::SIZE] {
+        let [a, b, c, d] = self.fingerprint.to_le_bytes();
+        [
+            a,
+            b,
+            c,
+            d,
+            self.active_profile,
+            self.modifier_layout.to_byte(),
+            self.screen_auto_off as u8,
+        ]
+    }
+
+    fn decode(value: &[u8]) -> Option<Self> {
+        let [a, b, c, d, profile, layout, auto_off] = *value else {
+            return None;
+        };
+        Some(Self {
+            fingerprint: u32::from_le_bytes([a, b, c, d]),
+            active_profile: (profile < config::MAX_PROFILES as u8).then_some(profile)?,
+            modifier_layout: ModifierLayout::from_byte(layout)?,
+            screen_auto_off: match auto_off {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+        })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Settings {
+    pub const DEFAULT: Settings = Settings {
+        scan_duration_secs: config::BLE_SCAN_DURATION_SECS as u8,
+        screen_auto_off: config::SCREEN_AUTO_OFF_ENABLED,
+        screen_auto_off_secs: config::SCREEN_AUTO_OFF_TIMEOUT_SECS as u16,
+        button_debounce_ms: config::BUTTON_DEBOUNCE_MS as u8,
+        relax_conn_on_suspend: config::BLE_RELAX_CONN_ON_SUSPEND,
+        active_profile: 0,
+        modifier_layout: ModifierLayout::Auto,
+        security_policy: SecurityPolicy::Bond,
+        hosts: [None; config::MAX_KNOWN_HOSTS],
+    };
+
+    /// The settings bound to host `fingerprint`, if it was seen before.
+    pub fn host(&self, fingerprint: u32) -> Option<HostBinding> {
+        self.hosts
+            .iter()
+            .flatten()
+            .find(|h| h.fingerprint == fingerprint)
+            .copied()
+    }
+
+    /// Bind the current host-specific settings to host `fingerprint`, as the
+    /// most recent host. A new host takes the place of the least recent one
+    /// when the list is full.
+    pub fn bind_host(&mut self, fingerprint: u32) {
+        let end = self
+            .hosts
+            .iter()
+            .position(|h| h.is_some_and(|h| h.fingerprint == fingerprint))
+            .or_else(|| self.hosts.iter().position(Option::is_none))
+            .unwrap_or(self.hosts.len() - 1);
+        self.hosts.copy_within(..end, 1);
+        self.hosts[0] = Some(HostBinding {
+            fingerprint,
+            active_profile: self.active_profile,
+            modifier_layout: self.modifier_layout,
+            screen_auto_off: self.screen_auto_off,
+        });
+    }
+
+    /// Put `binding`'s settings into effect.
+    pub fn apply_host(&mut self, binding: &HostBinding) {
+        self.active_profile = binding.active_profile;
+        self.modifier_layout = binding.modifier_layout;
+        self.screen_auto_off = binding.screen_auto_off;
+    }
+
+    /// Apply one known field; `false` if `id` isn't one of ours. A bad value
+    /// leaves the field at its current (default) value.
+    fn apply(&mut self, id: u8, value: &[u8]) -> bool {
+        match id {
+            FIELD_SCAN_DURATION => set_u8(&mut self.scan_duration_secs, value, 2..=30),
+            FIELD_SCREEN_AUTO_OFF => set_bool(&mut self.screen_auto_off, value),
+            FIELD_SCREEN_AUTO_OFF_SECS => set_u16(&mut self.screen_auto_off_secs, value, 10..=3600),
+            FIELD_BUTTON_DEBOUNCE => set_u8(&mut self.button_debounce_ms, value, 5..=200),
+            FIELD_RELAX_CONN => set_bool(&mut self.relax_conn_on_suspend, value),
+            FIELD_ACTIVE_PROFILE => set_u8(
+                &mut self.active_profile,
+                value,
+                0..=config::MAX_PROFILES as u8 - 1,
+            ),
+            FIELD_MODIFIER_LAYOUT => {
+                if let Some(layout) = value.first().and_then(|&b| ModifierLayout::from_byte(b)) {
+                    self.modifier_layout = layout;
+                }
+            }
+            FIELD_SECURITY_POLICY => {
+                if let Some(policy) = value.first().and_then(|&b| SecurityPolicy::from_byte(b)) {
+                    self.security_policy = policy;
+                }
+            }
+            id if (FIELD_HOSTS..FIELD_HOSTS + config::MAX_KNOWN_HOSTS as u8).contains(&id) => {
+                // An empty or bad binding leaves the place free.
+                self.hosts[(id - FIELD_HOSTS) as usize] = HostBinding::decode(value);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Every known field, encoded.
+    fn fields(&self) -> impl Iterator<Item = (u8, Vec<u8, MAX_VALUE_LEN>)> + '_ {
+        let hosts = (FIELD_HOSTS..)
+            .zip(self.hosts.iter())
+            .filter_map(|(id, host)| Some((id, bytes(&host.as_ref()?.encode()))));
+        [
+            (FIELD_SCAN_DURATION, bytes(&[self.scan_duration_secs])),
+            (FIELD_SCREEN_AUTO_OFF, bytes(&[self.screen_auto_off as u8])),
+            (
+                FIELD_SCREEN_AUTO_OFF_SECS,
+                bytes(&self.screen_auto_off_secs.to_le_bytes()),
+            ),
+            (FIELD_BUTTON_DEBOUNCE, bytes(&[self.button_debounce_ms])),
+            (FIELD_RELAX_CONN, bytes(&[self.relax_conn_on_suspend as u8])),
+            (FIELD_ACTIVE_PROFILE, bytes(&[self.active_profile])),
+            (
+                FIELD_MODIFIER_LAYOUT,
+                bytes(&[self.modifier_layout.to_byte()]),
+            ),
+            (
+                FIELD_SECURITY_POLICY,
+                bytes(&[self.security_policy.to_byte()]),
+            ),
+        ]
+        .into_iter()
+        .chain(hosts)
+    }
+}
+
+fn bytes(value: &[u8]) -> Vec<u8, MAX_VALUE_LEN> {
+    Vec::from_slice(value).unwrap_or_default()
+}
+
+fn set_u8(field: &mut u8, value: &[u8], range: core::ops::RangeInclusive<u8>) {
+    if let [v] = value {
+        if range.contains(v) {
+            *field = *v;
+        }
+    }
+}
+
+fn set_u16(field: &mut u16, value: &[u8], range: core::ops::RangeInclusive<u16>) {
+    if let [lo, hi] = value {
+        let v = u16::from_le_bytes([*lo, *hi]);
+        if range.contains(&v) {
+            *field = v;
+        }
+    }
+}
+
+fn set_bool(field: &mut bool, value: &[u8]) {
+    match value {
+        [0] => *field = false,
+        [1] => *field = true,
+        _ => {}
+    }
+}
+
+/// A decoded settings blob.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loaded {
+    pub settings: Settings,
+    /// Fields from a newer schema, kept to be written back.
+    pub carried: Fields,
+    /// Schema version to write back: ours, or the newer one that wrote the
+    /// carried fields.
+    pub version: u8,
+}
+
+impl Default for Loaded {
+    fn default() -> Self {
+        Self {
+            settings: Settings::DEFAULT,
+            carried: Vec::new(),
+            version: SCHEMA_VERSION,
+        }
+    }
+}
+
+/// Decode a settings blob. A blank, foreign or corrupt blob yields the
+/// defaults, never an error.
+pub fn decode(data: &[u8]) -> Loaded {
+    decode_with(data, MIGRATIONS)
+}
+
+fn decode_with(data: &[u8], migrations: &[Migration]) -> Loaded {
+    let mut records = framing::records(data);
+    let version = match records.next() {
+        Some([FIELD_SCHEMA, version]) if *version > 0 => *version,
+        _ => return Loaded::default(),
+    };
+
+    let mut fields = Fields::new();
+    for record in records {
+        let Some((&id, value)) = record.split_first() else {
+            continue;
+        };
+        if id == FIELD_SCHEMA {
+            continue;
+        }
+        if let Ok(value) = Vec::from_slice(value) {
+            let _ = fields.push(Field { id, value });
+        }
+    }
+    for migration in migrations.iter().filter(|m| m.to > version) {
+        (migration.up)(&mut fields);
+    }
+
+    let mut loaded = Loaded {
+        version: version.max(SCHEMA_VERSION),
+        ..Loaded::default()
+    };
+    for field in fields {
+        let known = loaded.settings.apply(field.id, &field.value);
+        // Unknown fields of an older or our own schema were removed on
+        // purpose; only a newer schema's are worth keeping.
+        if !known && version > SCHEMA_VERSION {
+            let _ = loaded.carried.push(field);
+        }
+    }
+    loaded
+}
+
+/// Encode `loaded` into `buf`, returning the byte count (0 if it doesn't fit).
+pub fn encode(loaded: &Loaded, buf: &mut [u8]) -> usize {
+    let Some(mut writer) = framing::Writer::new(buf) else {
+        return 0;
+    };
+    let mut push = |id: u8, value: &[u8]| {
+        writer.push(|slot| {
+            let len = 1 + value.len();
+            if slot.len() < len {
+                return 0;
+            }
+            slot[0] = id;
+            slot[1..len].copy_from_slice(value);
+            len
+        })
+    };
+    let mut ok = push(FIELD_SCHEMA, &[loaded.version]);
+    for (id, value) in loaded.settings.fields() {
+        ok &= push(id, &value);
+    }
+    for field in &loaded.carried {
+        ok &= push(field.id, &field.value);
+    }
+    if !ok {
+        return 0;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom() -> Settings {
+        Settings {
+            scan_duration_secs: 12,
+            screen_auto_off: false,
+            screen_auto_off_secs: 600,
+            button_debounce_ms: 20,
+            relax_conn_on_suspend: false,
+            active_profile: 1,
+            modifier_layout: ModifierLayout::SwapCtrlGui,
+            security_policy: SecurityPolicy::BondedOnly,
+            hosts: [
+                Some(HostBinding {
+                    fingerprint: 0xDEAD_BEEF,
+                    active_profile: 2,
+                    modifier_layout: ModifierLayout::SwapAltGui,
+                    screen_auto_off: true,
+                }),
+                None,
+                None,
+                None,
+            ],
+        }
+    }
+
+    fn encoded(loaded: &Loaded) -> Vec<u8, MAX_SETTINGS_SIZE> {
+        let mut buf = [0u8; MAX_SETTINGS_SIZE];
+        let n = encode(loaded, &mut buf);
+        assert!(n > 0);
+        Vec::from_slice(&buf[..n]).unwrap()
+    }
+
+    /// A blob with the given schema version and raw field records.
+    fn blob(version: u8, fields: &[&[u8]]) -> Vec<u8, MAX_SETTINGS_SIZE> {
+        let mut buf = [0u8; MAX_SETTINGS_SIZE];
+        let mut writer = framing::Writer::new(&mut buf).unwrap();
+        for record in [&[FIELD_SCHEMA, version][..]].iter().chain(fields) {
+            assert!(writer.push(|slot| {
+                slot[..record.len()].copy_from_slice(record);
+                record.len()
+            }));
+        }
+        let n = writer.finish();
+        Vec::from_slice(&buf[..n]).unwrap()
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let loaded = Loaded {
+            settings: custom(),
+            ..Loaded::default()
+        };
+        assert_eq!(decode(&encoded(&loaded)), loaded);
+    }
+
+    #[test]
+    fn blank_or_foreign_blob_gives_defaults() {
+        assert_eq!(decode(&[]), Loaded::default());
+        assert_eq!(decode(&[0xFF; 16]), Loaded::default());
+        // Versioned framing, but no schema record first.
+        assert_eq!(decode(&blob(0, &[])), Loaded::default());
+    }
+
+    #[test]
+    fn missing_and_invalid_fields_fall_back_to_defaults() {
+        let data = blob(
+            SCHEMA_VERSION,
+            &[
+                &[FIELD_SCAN_DURATION, 200],      // out of range
+                &[FIELD_SCREEN_AUTO_OFF, 7],      // not a bool
+                &[FIELD_SCREEN_AUTO_OFF_SECS, 1], // too short
+                &[FIELD_BUTTON_DEBOUNCE, 30],
+                &[FIELD_ACTIVE_PROFILE, config::MAX_PROFILES as u8], // no such profile
+                &[FIELD_MODIFIER_LAYOUT, 9],                         // no such layout
+                &[FIELD_SECURITY_POLICY, 3],                         // no such policy
+                &[FIELD_HOSTS, 1, 2, 3, 4, 0, 0, 2],                 // not a bool
+            ],
+        );
+        let loaded = decode(&data);
+        assert_eq!(
+            loaded.settings,
+            Settings {
+                button_debounce_ms: 30,
+                ..Settings::DEFAULT
+            }
+        );
+        assert!(loaded.carried.is_empty());
+    }
+
+    #[test]
+    fn newer_schema_fields_are_carried_back() {
+        let data = blob(
+            SCHEMA_VERSION + 1,
+            &[&[FIELD_BUTTON_DEBOUNCE, 30], &[0x40, 1, 2, 3]],
+        );
+        let loaded = decode(&data);
+        assert_eq!(loaded.settings.button_debounce_ms, 30);
+        assert_eq!(loaded.version, SCHEMA_VERSION + 1);
+        assert_eq!(loaded.carried.len(), 1);
+        assert_eq!(loaded.carried[0].id, 0x40);
+        // Written back as the newer schema, unknown field intact.
+        assert_eq!(decode(&encoded(&loaded)), loaded);
+    }
+
+    #[test]
+    fn older_schema_is_migrated_forward() {
+        // A hypothetical v2 replaced a minutes field (0x30) with the seconds
+        // one; a v1 blob still has minutes.
+        fn minutes_to_secs(fields: &mut Fields) {
+            if let Some(field) = fields.iter_mut().find(|f| f.id == 0x30) {
+                let secs = field.value.first().map_or(0, |&m| m as u16 * 60);
+                field.id = FIELD_SCREEN_AUTO_OFF_SECS;
+                field.value = bytes(&secs.to_le_bytes());
+            }
+        }
+        let migrations = [Migration {
+            to: 2,
+            up: minutes_to_secs,
+        }];
+        let v1 = blob(1, &[&[0x30, 5]]);
+        assert_eq!(
+            decode_with(&v1, &migrations).settings.screen_auto_off_secs,
+            300
+        );
+        // A blob already at v2 isn't migrated again.
+        let v2 = blob(2, &[&[0x30, 5]]);
+        let loaded = decode_with(&v2, &migrations);
+        assert_eq!(
+            loaded.settings.screen_auto_off_secs,
+            Settings::DEFAULT.screen_auto_off_secs
+        );
+    }
+
+    #[test]
+    fn hosts_are_bound_most_recent_first() {
+        let mut settings = Settings::DEFAULT;
+        for fingerprint in 1..=config::MAX_KNOWN_HOSTS as u32 {
+            settings.active_profile = fingerprint as u8 % config::MAX_PROFILES as u8;
+            settings.bind_host(fingerprint);
+        }
+        assert_eq!(settings.hosts[0].unwrap().fingerprint, 4);
+        assert_eq!(settings.host(1).unwrap().active_profile, 1);
+
+        // Rebinding a known host moves it up and takes the current settings.
+        settings.modifier_layout = ModifierLayout::SwapAltGui;
+        settings.bind_host(2);
+        let fingerprints = settings.hosts.map(|h| h.unwrap().fingerprint);
+        assert_eq!(fingerprints, [2, 4, 3, 1]);
+        assert_eq!(
+            settings.host(2).unwrap().modifier_layout,
+            ModifierLayout::SwapAltGui
+        );
+
+        // A new host pushes out the least recent.
+        settings.bind_host(9);
+        assert_eq!(settings.hosts.map(|h| h.unwrap().fingerprint), [9, 2, 4, 3]);
+        assert_eq!(settings.host(1), None);
+    }
+
+    #[test]
+    fn a_known_host_brings_back_its_settings() {
+        let mut settings = custom();
+        let binding = settings.host(0xDEAD_BEEF).unwrap();
+        settings.apply_host(&binding);
+        assert_eq!(settings.active_profile, 2);
+        assert_eq!(settings.modifier_layout, ModifierLayout::SwapAltGui);
+        assert!(settings.screen_auto_off);
+        // Settings that aren't bound to a host stay.
+        assert_eq!(settings.scan_duration_secs, custom().scan_duration_secs);
+    }
+}
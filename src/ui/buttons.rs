@@ -6,25 +6,31 @@
 //!   - SELECT - context-dependent: scan / connect / disconnect
 //!
 //! Each button is handled by an async task that waits for a GPIO edge,
-//! debounces it, and sends a `ButtonEvent` to the UI channel.
+//! debounces it, and sends the press and the release as an [`Edge`].
+//! [`gesture_task`] times those edges into `ButtonEvent`s (short, long,
+//! double, repeat, chord) for the UI channel.
 
-use crate::config::BUTTON_DEBOUNCE_MS;
+use crate::ui::gesture::{Edge, Gestures, Timing};
+use crate::ui::ui_logic::Button;
 use crate::ui::ButtonEvent;
 use defmt::info;
+use embassy_futures::select::{select, Either};
 use embassy_nrf::gpio::{AnyPin, Input, Pull};
 use embassy_nrf::Peri;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::channel::Sender;
-use embassy_time::{Duration, Timer};
+use embassy_sync::channel::{Receiver, Sender};
+use embassy_time::{Duration, Instant, Timer};
 
 /// Run a single button polling loop.
 ///
-/// Waits for the pin to go low (pressed), debounces, sends the event,
-/// then waits for release before repeating.
+/// Waits for the pin to go low (pressed), debounces, sends the press, then
+/// waits for release and sends that too. `debounce_ms` is asked on every
+/// press, so a changed setting applies straight away.
 pub async fn button_task(
     pin: Peri<'static, AnyPin>,
-    event: ButtonEvent,
-    tx: &Sender<'static, CriticalSectionRawMutex, ButtonEvent, 4>,
+    button: Button,
+    tx: &Sender<'static, CriticalSectionRawMutex, Edge, 8>,
+    debounce_ms: fn() -> u64,
 ) -> ! {
     let mut btn = Input::new(pin, Pull::Up);
 
@@ -33,15 +39,63 @@ pub async fn button_task(
         btn.wait_for_falling_edge().await;
 
         // Debounce: wait and re-check.
-        Timer::after(Duration::from_millis(BUTTON_DEBOUNCE_MS)).await;
+        let debounce = Duration::from_millis(debounce_ms());
+        Timer::after(debounce).await;
 
         if btn.is_low() {
-            info!("Button: {}", event);
-            tx.send(event).await;
+            tx.send(Edge {
+                button,
+                pressed: true,
+            })
+            .await;
 
-            // Wait for release to avoid repeat triggers.
             btn.wait_for_rising_edge().await;
-            Timer::after(Duration::from_millis(BUTTON_DEBOUNCE_MS)).await;
+            Timer::after(debounce).await;
+            tx.send(Edge {
+                button,
+                pressed: false,
+            })
+            .await;
         }
     }
 }
+
+/// Turn button edges into gestures for the UI.
+///
+/// Waits for the next edge or for the recognizer's next deadline (a long
+/// press, a repeat, the end of a double-press window), whichever comes first.
+pub async fn gesture_task(
+    rx: &Receiver<'static, CriticalSectionRawMutex, Edge, 8>,
+    tx: &Sender<'static, CriticalSectionRawMutex, ButtonEvent, 4>,
+    single_button: bool,
+) -> ! {
+    let mut gestures = Gestures::new(Timing::DEFAULT, single_button);
+
+    loop {
+        let edge = match gestures.deadline() {
+            Some(at) => {
+                let wait = at.wrapping_sub(now_ms()) as i32;
+                let timeout = Timer::after_millis(wait.max(0) as u64);
+                match select(rx.receive(), timeout).await {
+                    Either::First(edge) => Some(edge),
+                    Either::Second(()) => None,
+                }
+            }
+            None => Some(rx.receive().await),
+        };
+
+        let now = now_ms();
+        let from_edge = edge.and_then(|edge| gestures.on_edge(edge, now));
+        let mut next = from_edge.or_else(|| gestures.poll(now));
+        while let Some(event) = next {
+            info!("Button: {}", event);
+            tx.send(event).await;
+            next = gestures.poll(now);
+        }
+    }
+}
+
+/// The clock the recognizer runs on (wraps after ~49 days; it copes).
+fn now_ms() -> u32 {
+    Instant::now().as_millis() as u32
+}
@@ -7,10 +7,15 @@
 //! flushes/commands are async. Redraws only happen on UI events
 //! (connect/scan/button), never on the keystroke→USB hot path.
 
+use crate::config::{MAX_PROFILES, PROFILE_NAMES};
+use crate::hid::backup::Job;
+use crate::ui::ui_logic::{self, MenuId, Setting};
+use core::fmt::Write;
 use embedded_graphics::mono_font::ascii::FONT_6X10;
 use embedded_graphics::mono_font::MonoTextStyleBuilder;
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use embedded_graphics::text::Text;
 use ssd1306::mode::BufferedGraphicsModeAsync;
 use ssd1306::prelude::*;
@@ -70,12 +75,27 @@ where
 
 /// Render the Scanning screen with a simple progress indicator.
 pub async fn draw_scanning<I2C>(display: &mut Display<I2C>, dots: u8)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    draw_busy(display, "Scanning", dots).await;
+}
+
+/// Render the Connecting screen, animated like Scanning.
+pub async fn draw_connecting<I2C>(display: &mut Display<I2C>, dots: u8)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    draw_busy(display, "Connecting", dots).await;
+}
+
+async fn draw_busy<I2C>(display: &mut Display<I2C>, title: &str, dots: u8)
 where
     I2C: embedded_hal_async::i2c::I2c,
 {
     display.clear_buffer();
 
-    let _ = Text::new("Scanning", Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new(title, Point::new(0, 10), text_style()).draw(display);
 
     // Animated dots: "." / ".." / "..."
     let dot_str = match dots % 4 {
@@ -84,52 +104,324 @@ where
         2 => "..",
         _ => "...",
     };
-    let _ = Text::new(dot_str, Point::new(54, 10), text_style()).draw(display);
+    // One space after the title (6 px per character).
+    let x = (title.len() as i32 + 1) * 6;
+    let _ = Text::new(dot_str, Point::new(x, 10), text_style()).draw(display);
 
     let _ = Text::new("Please wait...", Point::new(0, 30), text_style()).draw(display);
 
     let _ = display.flush().await;
 }
 
-/// Render the discovered-device list with current selection.
-pub async fn draw_device_list<I2C>(
+/// Render a titled list of `len` rows with a `>` marker, scrolled to keep
+/// `selected` shown (see `ui_logic::list_window`). Arrows at the right edge
+/// show there are rows above or below.
+fn draw_list<'a, I2C>(
     display: &mut Display<I2C>,
-    devices: &[heapless::String<32>],
+    title: &str,
+    items: impl Iterator<Item = &'a str>,
+    len: usize,
     selected: usize,
 ) where
     I2C: embedded_hal_async::i2c::I2c,
 {
     display.clear_buffer();
 
-    let _ = Text::new("Select device", Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new(title, Point::new(0, 10), text_style()).draw(display);
 
-    for (row, name) in devices.iter().take(4).enumerate() {
-        let marker = if row == selected { ">" } else { " " };
+    let window = ui_logic::list_window(selected, len);
+    let right = display.bounding_box().size.width as i32 - 6;
+    if window.start > 0 {
+        let _ = Text::new("^", Point::new(right, 24), text_style()).draw(display);
+    }
+    if window.end < len {
+        let y = 24 + (ui_logic::LIST_ROWS as i32 - 1) * 10;
+        let _ = Text::new("v", Point::new(right, y), text_style()).draw(display);
+    }
+    let rows = window.len();
+    for (row, (index, name)) in items.enumerate().skip(window.start).take(rows).enumerate() {
+        let marker = if index == selected { ">" } else { " " };
         let mut line: heapless::String<36> = heapless::String::new();
         let _ = line.push_str(marker);
         let _ = line.push_str(" ");
-        let _ = line.push_str(name.as_str());
+        let _ = line.push_str(name);
         let y = 24 + (row as i32 * 10);
         let _ = Text::new(line.as_str(), Point::new(0, y), text_style()).draw(display);
     }
+}
+
+/// Render the discovered-device list, then "Back", with current selection.
+pub async fn draw_device_list<I2C>(
+    display: &mut Display<I2C>,
+    devices: &[heapless::String<32>],
+    selected: usize,
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    draw_list(
+        display,
+        "Select device",
+        devices
+            .iter()
+            .map(|d| d.as_str())
+            .chain(core::iter::once("< Back")),
+        devices.len() + 1,
+        selected,
+    );
+    let _ = display.flush().await;
+}
 
+/// Render the connected slots, then "Back", for disconnecting a single link.
+pub async fn draw_slot_list<I2C>(
+    display: &mut Display<I2C>,
+    slots: &[heapless::String<32>],
+    selected: usize,
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let items = slots
+        .iter()
+        .map(|s| s.as_str())
+        .chain(core::iter::once("< Back"));
+    draw_list(display, "Disconnect", items, slots.len() + 1, selected);
     let _ = display.flush().await;
 }
 
-pub async fn draw_connected<I2C>(display: &mut Display<I2C>, device_name: &str)
+/// Render the device profiles, the active one starred, then "Back".
+pub async fn draw_profile_list<I2C>(display: &mut Display<I2C>, active: usize, selected: usize)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let mut labels: heapless::Vec<heapless::String<32>, MAX_PROFILES> = heapless::Vec::new();
+    for (index, name) in PROFILE_NAMES.iter().enumerate() {
+        let mut label = heapless::String::new();
+        let _ = label.push_str(if index == active { "* " } else { "  " });
+        let _ = label.push_str(name);
+        let _ = labels.push(label);
+    }
+    let items = labels
+        .iter()
+        .map(|l| l.as_str())
+        .chain(core::iter::once("< Back"));
+    draw_list(display, "Profile", items, labels.len() + 1, selected);
+    let _ = display.flush().await;
+}
+
+/// Render a settings menu, then "Back". `value` gives the current value of
+/// each setting shown.
+pub async fn draw_menu<I2C>(
+    display: &mut Display<I2C>,
+    menu: MenuId,
+    selected: usize,
+    value: impl Fn(Setting) -> u16,
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let mut lines: heapless::Vec<heapless::String<32>, 8> = heapless::Vec::new();
+    for item in menu.items() {
+        let _ = lines.push(ui_logic::menu_line(item, &value));
+    }
+    let items = lines
+        .iter()
+        .map(|l| l.as_str())
+        .chain(core::iter::once("< Back"));
+    draw_list(display, menu.title(), items, lines.len() + 1, selected);
+    let _ = display.flush().await;
+}
+
+/// Render the editor for `setting`, showing `value`.
+pub async fn draw_editor<I2C>(display: &mut Display<I2C>, setting: Setting, value: u16)
 where
     I2C: embedded_hal_async::i2c::I2c,
 {
     display.clear_buffer();
 
+    let text = setting.editor().format(value);
+    let _ = Text::new(setting.label(), Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new("<", Point::new(0, 32), text_style()).draw(display);
+    let _ = Text::new(text.as_str(), Point::new(12, 32), text_style()).draw(display);
+    let _ = Text::new(">", Point::new(122, 32), text_style()).draw(display);
+    let _ = Text::new("SEL:save  hold:cancel", Point::new(0, 56), text_style()).draw(display);
+
+    let _ = display.flush().await;
+}
+
+/// Render the active profile's paired devices, then "Back".
+pub async fn draw_paired_devices<I2C>(
+    display: &mut Display<I2C>,
+    devices: &[heapless::String<32>],
+    selected: usize,
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let items = devices
+        .iter()
+        .map(|d| d.as_str())
+        .chain(core::iter::once("< Back"));
+    draw_list(display, "Forget device", items, devices.len() + 1, selected);
+    let _ = display.flush().await;
+}
+
+/// Render the confirmation for forgetting a paired device.
+pub async fn draw_forget<I2C>(display: &mut Display<I2C>, device_name: &str)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    display.clear_buffer();
+
+    let _ = Text::new("FORGET?", Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new(device_name, Point::new(0, 30), text_style()).draw(display);
+    let _ = Text::new("SEL:forget  UP:keep", Point::new(0, 56), text_style()).draw(display);
+
+    let _ = display.flush().await;
+}
+
+/// Render the prompt for the USB host's export or import of the paired
+/// devices.
+pub async fn draw_confirm_backup<I2C>(display: &mut Display<I2C>, job: Job)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    display.clear_buffer();
+
+    let title = match job {
+        Job::Export => "EXPORT BONDS?",
+        Job::Import => "IMPORT BONDS?",
+    };
+    let _ = Text::new(title, Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new("Asked by USB host", Point::new(0, 30), text_style()).draw(display);
+    let _ = Text::new("SEL:allow  UP:deny", Point::new(0, 56), text_style()).draw(display);
+
+    let _ = display.flush().await;
+}
+
+/// Render the firmware version and what is stored.
+pub async fn draw_about<I2C>(
+    display: &mut Display<I2C>,
+    version: &str,
+    profile: usize,
+    paired: usize,
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    display.clear_buffer();
+
+    let mut line: heapless::String<32> = heapless::String::new();
+    let _ = Text::new("bt2usb", Point::new(0, 10), text_style()).draw(display);
+    let _ = write!(line, "Version {}", version);
+    let _ = Text::new(line.as_str(), Point::new(0, 24), text_style()).draw(display);
+    line.clear();
+    let name = PROFILE_NAMES.get(profile).copied().unwrap_or("?");
+    let _ = write!(line, "Profile {}", name);
+    let _ = Text::new(line.as_str(), Point::new(0, 38), text_style()).draw(display);
+    line.clear();
+    let _ = write!(line, "Paired {}", paired);
+    let _ = Text::new(line.as_str(), Point::new(0, 52), text_style()).draw(display);
+
+    let _ = display.flush().await;
+}
+
+/// Width of one slot's signal-strength icon, including the gap after it.
+const SIGNAL_ICON_WIDTH: i32 = 13;
+
+/// Draw a four-bar signal icon with its bottom-left corner at `origin`:
+/// filled bars for the strength, outlines for the rest.
+fn draw_signal_icon<I2C>(display: &mut Display<I2C>, origin: Point, bars: u8)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    for bar in 0..4u8 {
+        let height = 2 + 2 * bar as u32;
+        let top_left = origin + Point::new(bar as i32 * 3, 1 - height as i32);
+        let style = if bar < bars {
+            PrimitiveStyle::with_fill(BinaryColor::On)
+        } else {
+            PrimitiveStyle::with_stroke(BinaryColor::On, 1)
+        };
+        let _ = Rectangle::new(top_left, Size::new(2, height))
+            .into_styled(style)
+            .draw(display);
+    }
+}
+
+/// Render the Connected screen.
+///
+/// `battery` is the per-slot battery line (see `ui_logic::battery_line`); it
+/// replaces the "HID active" footer once any peer has reported a level.
+/// `signal` holds each slot's signal-strength bars, drawn right of the title.
+pub async fn draw_connected<I2C>(
+    display: &mut Display<I2C>,
+    device_name: &str,
+    battery: Option<&str>,
+    signal: &[Option<u8>],
+) where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    display.clear_buffer();
+
+    let footer = battery.unwrap_or("HID active");
     let _ = Text::new("Connected", Point::new(0, 10), text_style()).draw(display);
+    let right = display.bounding_box().size.width as i32;
+    for (slot, bars) in signal.iter().enumerate() {
+        if let Some(bars) = bars {
+            let x = right - (signal.len() - slot) as i32 * SIGNAL_ICON_WIDTH;
+            draw_signal_icon(display, Point::new(x, 9), *bars);
+        }
+    }
     let _ = Text::new(device_name, Point::new(0, 24), text_style()).draw(display);
-    let _ = Text::new("SEL:add  DOWN:disc", Point::new(0, 38), text_style()).draw(display);
-    let _ = Text::new("HID active", Point::new(0, 52), text_style()).draw(display);
+    let _ = Text::new("SEL:add UP:1 DN:all", Point::new(0, 38), text_style()).draw(display);
+    let _ = Text::new(footer, Point::new(0, 52), text_style()).draw(display);
 
     let _ = display.flush().await;
 }
 
+/// Render a warning about one peer.
+async fn draw_alert<I2C>(display: &mut Display<I2C>, title: &str, device_name: &str)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    display.clear_buffer();
+
+    let _ = Text::new(title, Point::new(0, 10), text_style()).draw(display);
+    let _ = Text::new(device_name, Point::new(0, 30), text_style()).draw(display);
+
+    let _ = display.flush().await;
+}
+
+/// Render the low-battery warning for a peer.
+pub async fn draw_low_battery<I2C>(display: &mut Display<I2C>, device_name: &str)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    draw_alert(display, "LOW BATTERY", device_name).await;
+}
+
+/// Render the degraded-link warning for a peer.
+pub async fn draw_weak_link<I2C>(display: &mut Display<I2C>, device_name: &str)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    draw_alert(display, "WEAK SIGNAL", device_name).await;
+}
+
+/// Render the notice that a device profile became active.
+pub async fn draw_profile_switched<I2C>(display: &mut Display<I2C>, profile: usize)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let name = PROFILE_NAMES.get(profile).copied().unwrap_or("?");
+    draw_alert(display, "PROFILE", name).await;
+}
+
+/// Render the warning that saved data was damaged in flash.
+pub async fn draw_storage_damaged<I2C>(display: &mut Display<I2C>, lost: bool)
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let detail = if lost { "Data lost" } else { "Backup restored" };
+    draw_alert(display, "STORAGE FAULT", detail).await;
+}
+
 /// Render a transient error message.
 pub async fn draw_error<I2C>(display: &mut Display<I2C>, message: &str)
 where
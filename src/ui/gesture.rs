@@ -0,0 +1,391 @@
+//! Pure button gesture recognition: turns the debounced press and release
+//! edges of the buttons into [`ButtonEvent`]s by their timing.
+//!
+//! - A press shorter than the long-press time is a short press, reported on
+//!   release. Select waits out the double-press window first, so a second
+//!   press in it makes a [`ButtonEvent::Double`] instead.
+//! - Holding Select is a [`ButtonEvent::Long`]; holding Up or Down repeats as
+//!   [`ButtonEvent::Repeat`], first after the long-press time, then every
+//!   repeat period.
+//! - Two buttons pressed within the chord window make a
+//!   [`ButtonEvent::Chord`], and nothing else until both are released.
+//! - In single-button mode (boards with one button, like the nRF52840
+//!   Dongle) the one button's short press is [`ButtonEvent::Next`] and its
+//!   long press [`ButtonEvent::Select`], with no doubles or repeats.
+//!
+//! Times are milliseconds from any wrapping clock. The shell feeds edges to
+//! [`Gestures::on_edge`] and calls [`Gestures::poll`] at
+//! [`Gestures::deadline`].
+
+use crate::config;
+use crate::ui::ui_logic::{Button, ButtonEvent};
+
+/// Gesture timing (ms).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    pub long_press_ms: u32,
+    pub double_press_ms: u32,
+    pub repeat_ms: u32,
+    pub chord_ms: u32,
+}
+
+impl Timing {
+    pub const DEFAULT: Timing = Timing {
+        long_press_ms: config::BUTTON_LONG_PRESS_MS,
+        double_press_ms: config::BUTTON_DOUBLE_PRESS_MS,
+        repeat_ms: config::BUTTON_REPEAT_MS,
+        chord_ms: config::BUTTON_CHORD_MS,
+    };
+}
+
+/// A debounced button edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Edge {
+    pub button: Button,
+    /// Pressed (`true`) or released (`false`).
+    pub pressed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Down since `since`. At `hold_at` it counts as held (again); `held` once
+    /// it did, so the release says nothing. `second` is the second press of a
+    /// double.
+    Down {
+        since: u32,
+        hold_at: Option<u32>,
+        held: bool,
+        second: bool,
+    },
+    /// Released after a short press; a press before `until` is a double.
+    Released {
+        until: u32,
+    },
+    /// Part of a chord, until released.
+    Chorded,
+}
+
+/// Gesture recognizer for the three buttons.
+#[derive(Clone, Debug)]
+pub struct Gestures {
+    timing: Timing,
+    single_button: bool,
+    states: [State; 3],
+}
+
+impl Gestures {
+    pub const fn new(timing: Timing, single_button: bool) -> Self {
+        Self {
+            timing,
+            single_button,
+            states: [State::Idle; 3],
+        }
+    }
+
+    /// Note a press or release at `now_ms`, returning the gesture it
+    /// completes, if any.
+    pub fn on_edge(&mut self, edge: Edge, now_ms: u32) -> Option<ButtonEvent> {
+        let button = edge.button;
+        if edge.pressed {
+            self.press(button, now_ms)
+        } else {
+            self.release(button, now_ms)
+        }
+    }
+
+    /// The gesture the passing of time to `now_ms` completes, if any. Call
+    /// again until `None`: several can fall due at once.
+    pub fn poll(&mut self, now_ms: u32) -> Option<ButtonEvent> {
+        for button in [Button::Up, Button::Down, Button::Select] {
+            let repeats = self.repeats(button);
+            let state = &mut self.states[button as usize];
+            match *state {
+                State::Down {
+                    hold_at: Some(at),
+                    since,
+                    second,
+                    ..
+                } if due(at, now_ms) => {
+                    *state = State::Down {
+                        since,
+                        hold_at: repeats.then(|| at.wrapping_add(self.timing.repeat_ms)),
+                        held: true,
+                        second,
+                    };
+                    return Some(if repeats {
+                        ButtonEvent::Repeat(button)
+                    } else if self.single_button {
+                        ButtonEvent::Select
+                    } else {
+                        ButtonEvent::Long(button)
+                    });
+                }
+                State::Released { until } if due(until, now_ms) => {
+                    *state = State::Idle;
+                    return Some(self.short(button));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// When [`poll`](Self::poll) next has something to do.
+    pub fn deadline(&self) -> Option<u32> {
+        self.states
+            .iter()
+            .filter_map(|state| match *state {
+                State::Down { hold_at, .. } => hold_at,
+                State::Released { until } => Some(until),
+                _ => None,
+            })
+            .min_by_key(|&at| at)
+    }
+
+    fn press(&mut self, button: Button, now_ms: u32) -> Option<ButtonEvent> {
+        if !self.single_button {
+            let chord = self.states.iter().position(|state| {
+                matches!(*state, State::Down { since, held: false, .. }
+                    if now_ms.wrapping_sub(since) <= self.timing.chord_ms)
+            });
+            if let Some(other) = chord.filter(|&other| other != button as usize) {
+                self.states[other] = State::Chorded;
+                self.states[button as usize] = State::Chorded;
+                let other = [Button::Up, Button::Down, Button::Select][other];
+                return Some(ButtonEvent::Chord(other.min(button), other.max(button)));
+            }
+        }
+        let state = &mut self.states[button as usize];
+        let second = matches!(*state, State::Released { until } if !due(until, now_ms));
+        *state = State::Down {
+            since: now_ms,
+            hold_at: Some(now_ms.wrapping_add(self.timing.long_press_ms)),
+            held: false,
+            second,
+        };
+        None
+    }
+
+    fn release(&mut self, button: Button, now_ms: u32) -> Option<ButtonEvent> {
+        let doubles = self.doubles(button);
+        let state = &mut self.states[button as usize];
+        let (next, event) = match *state {
+            State::Down {
+                held: false,
+                second: true,
+                ..
+            } => (State::Idle, Some(ButtonEvent::Double(button))),
+            State::Down { held: false, .. } if doubles => (
+                State::Released {
+                    until: now_ms.wrapping_add(self.timing.double_press_ms),
+                },
+                None,
+            ),
+            State::Down { held: false, .. } => (State::Idle, Some(self.short(button))),
+            _ => (State::Idle, None),
+        };
+        self.states[button as usize] = next;
+        event
+    }
+
+    fn short(&self, button: Button) -> ButtonEvent {
+        if self.single_button {
+            ButtonEvent::Next
+        } else {
+            button.press()
+        }
+    }
+
+    /// Holding the button repeats it (instead of a long press).
+    fn repeats(&self, button: Button) -> bool {
+        !self.single_button && button != Button::Select
+    }
+
+    /// A quick second press of the button is a double.
+    fn doubles(&self, button: Button) -> bool {
+        !self.single_button && button == Button::Select
+    }
+}
+
+/// Whether `at` has come by `now` (on a wrapping clock).
+fn due(at: u32, now: u32) -> bool {
+    (now.wrapping_sub(at) as i32) >= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: Timing = Timing {
+        long_press_ms: 500,
+        double_press_ms: 200,
+        repeat_ms: 100,
+        chord_ms: 50,
+    };
+
+    fn press(button: Button) -> Edge {
+        Edge {
+            button,
+            pressed: true,
+        }
+    }
+
+    fn release(button: Button) -> Edge {
+        Edge {
+            button,
+            pressed: false,
+        }
+    }
+
+    /// Everything `gestures` reports for `edges` (each at its time), polling
+    /// every 10 ms up to `until`.
+    fn run(gestures: &mut Gestures, edges: &[(u32, Edge)], until: u32) -> Vec<ButtonEvent> {
+        let mut events = Vec::new();
+        let mut edges = edges.iter().peekable();
+        for now in (0..=until).step_by(10) {
+            while let Some(&&(at, edge)) = edges.peek() {
+                if at > now {
+                    break;
+                }
+                events.extend(gestures.on_edge(edge, at));
+                edges.next();
+            }
+            while let Some(event) = gestures.poll(now) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn three_buttons() -> Gestures {
+        Gestures::new(TIMING, false)
+    }
+
+    #[test]
+    fn short_presses() {
+        let events = run(
+            &mut three_buttons(),
+            &[(0, press(Button::Down)), (80, release(Button::Down))],
+            1_000,
+        );
+        assert_eq!(events, [ButtonEvent::Down]);
+
+        // Select waits out the double window.
+        let mut gestures = three_buttons();
+        gestures.on_edge(press(Button::Select), 0);
+        assert_eq!(gestures.on_edge(release(Button::Select), 80), None);
+        assert_eq!(gestures.deadline(), Some(280));
+        assert_eq!(gestures.poll(279), None);
+        assert_eq!(gestures.poll(280), Some(ButtonEvent::Select));
+        assert_eq!(gestures.deadline(), None);
+    }
+
+    #[test]
+    fn double_select() {
+        let events = run(
+            &mut three_buttons(),
+            &[
+                (0, press(Button::Select)),
+                (60, release(Button::Select)),
+                (200, press(Button::Select)),
+                (260, release(Button::Select)),
+            ],
+            1_000,
+        );
+        assert_eq!(events, [ButtonEvent::Double(Button::Select)]);
+
+        // Too slow for a double: two presses.
+        let events = run(
+            &mut three_buttons(),
+            &[
+                (0, press(Button::Select)),
+                (60, release(Button::Select)),
+                (300, press(Button::Select)),
+                (360, release(Button::Select)),
+            ],
+            1_000,
+        );
+        assert_eq!(events, [ButtonEvent::Select, ButtonEvent::Select]);
+    }
+
+    #[test]
+    fn long_select_and_repeating_up() {
+        let events = run(
+            &mut three_buttons(),
+            &[(0, press(Button::Select)), (900, release(Button::Select))],
+            1_500,
+        );
+        assert_eq!(events, [ButtonEvent::Long(Button::Select)]);
+
+        let events = run(
+            &mut three_buttons(),
+            &[(0, press(Button::Up)), (750, release(Button::Up))],
+            1_500,
+        );
+        // At 500, 600 and 700; the release adds no press.
+        assert_eq!(events, [ButtonEvent::Repeat(Button::Up); 3]);
+    }
+
+    #[test]
+    fn chords_swallow_their_presses() {
+        let events = run(
+            &mut three_buttons(),
+            &[
+                (0, press(Button::Down)),
+                (30, press(Button::Up)),
+                (900, release(Button::Down)),
+                (910, release(Button::Up)),
+            ],
+            1_500,
+        );
+        assert_eq!(events, [ButtonEvent::Chord(Button::Up, Button::Down)]);
+
+        // Too far apart for a chord: Down repeats, Up is a press of its own.
+        let events = run(
+            &mut three_buttons(),
+            &[
+                (0, press(Button::Down)),
+                (100, press(Button::Up)),
+                (150, release(Button::Up)),
+                (200, release(Button::Down)),
+            ],
+            1_000,
+        );
+        assert_eq!(events, [ButtonEvent::Up, ButtonEvent::Down]);
+    }
+
+    #[test]
+    fn single_button_mode() {
+        let mut gestures = Gestures::new(TIMING, true);
+        let events = run(
+            &mut gestures,
+            &[
+                (0, press(Button::Select)),
+                (60, release(Button::Select)),
+                (100, press(Button::Select)),
+                (160, release(Button::Select)),
+                (300, press(Button::Select)),
+                (1_200, release(Button::Select)),
+            ],
+            2_000,
+        );
+        assert_eq!(
+            events,
+            [ButtonEvent::Next, ButtonEvent::Next, ButtonEvent::Select]
+        );
+    }
+
+    #[test]
+    fn timing_survives_clock_wrap() {
+        let mut gestures = three_buttons();
+        let start = u32::MAX - 100;
+        gestures.on_edge(press(Button::Select), start);
+        assert_eq!(gestures.poll(start.wrapping_add(499)), None);
+        assert_eq!(
+            gestures.poll(start.wrapping_add(500)),
+            Some(ButtonEvent::Long(Button::Select))
+        );
+    }
+}
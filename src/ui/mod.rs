@@ -6,11 +6,17 @@
 //! ## Components
 //!
 //! - **Display**: SSD1306 128×64 OLED via I²C
-//! - **Buttons**: 3 tactile switches with debouncing (UP, DOWN, SELECT)
+//! - **Buttons**: 3 tactile switches with debouncing (UP, DOWN, SELECT), or
+//!   just SELECT in single-button mode; `gesture` turns their timing into
+//!   short, long, double, repeat and chord events
 
 pub mod buttons;
 pub mod display;
+pub mod gesture;
 pub mod input_logic;
+// Maps the menu onto the stored settings, which only the real firmware has.
+#[cfg(feature = "embedded")]
+pub mod settings_menu;
 pub mod ui_logic;
 
 /// `Screen` and `ButtonEvent` live in the pure `ui_logic` core (shared with the
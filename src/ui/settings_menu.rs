@@ -0,0 +1,79 @@
+//! The settings menu's view of the stored settings: each [`Setting`] as the
+//! `u16` its [`Editor`](crate::ui::ui_logic::Editor) edits, read from and
+//! written to the [`Settings`] the store keeps.
+
+use crate::ble::security::SecurityPolicy;
+use crate::hid::layout::ModifierLayout;
+use crate::storage::settings::Settings;
+use crate::ui::ui_logic::Setting;
+
+/// The value of `setting`, as the settings menu edits it.
+pub fn value(settings: &Settings, setting: Setting) -> u16 {
+    match setting {
+        Setting::ScreenAutoOff => settings.screen_auto_off as u16,
+        Setting::ScreenAutoOffSecs => settings.screen_auto_off_secs,
+        Setting::ScanDuration => settings.scan_duration_secs as u16,
+        Setting::Security => settings.security_policy.to_byte() as u16,
+        Setting::RelaxConn => settings.relax_conn_on_suspend as u16,
+        Setting::ModifierLayout => settings.modifier_layout.to_byte() as u16,
+        Setting::ButtonDebounce => settings.button_debounce_ms as u16,
+    }
+}
+
+/// Set `setting` to a value from the settings menu, brought into the range
+/// its editor allows.
+pub fn set_value(settings: &mut Settings, setting: Setting, value: u16) {
+    let value = setting.editor().clamp(value);
+    match setting {
+        Setting::ScreenAutoOff => settings.screen_auto_off = value != 0,
+        Setting::ScreenAutoOffSecs => settings.screen_auto_off_secs = value,
+        Setting::ScanDuration => settings.scan_duration_secs = value as u8,
+        Setting::Security => {
+            if let Some(policy) = SecurityPolicy::from_byte(value as u8) {
+                settings.security_policy = policy;
+            }
+        }
+        Setting::RelaxConn => settings.relax_conn_on_suspend = value != 0,
+        Setting::ModifierLayout => {
+            if let Some(layout) = ModifierLayout::from_byte(value as u8) {
+                settings.modifier_layout = layout;
+            }
+        }
+        Setting::ButtonDebounce => settings.button_debounce_ms = value as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::settings::{decode, encode, Loaded, MAX_SETTINGS_SIZE};
+
+    #[test]
+    fn menu_edits_stay_within_what_is_stored() {
+        let every_setting = [
+            Setting::ScreenAutoOff,
+            Setting::ScreenAutoOffSecs,
+            Setting::ScanDuration,
+            Setting::Security,
+            Setting::RelaxConn,
+            Setting::ModifierLayout,
+            Setting::ButtonDebounce,
+        ];
+        for setting in every_setting {
+            for edit in [0, 1, 2, 5, 600, u16::MAX] {
+                let mut settings = Settings::DEFAULT;
+                set_value(&mut settings, setting, edit);
+                let edited = value(&settings, setting);
+                assert_eq!(edited, setting.editor().clamp(edit), "{:?}", setting);
+                // Every value the menu can set survives a save and load.
+                let loaded = Loaded {
+                    settings,
+                    ..Loaded::default()
+                };
+                let mut buf = [0u8; MAX_SETTINGS_SIZE];
+                let len = encode(&loaded, &mut buf);
+                assert_eq!(decode(&buf[..len]).settings, settings);
+            }
+        }
+    }
+}
@@ -6,6 +6,15 @@
 //! data. The `main.rs` loop is the imperative shell that applies the outcome
 //! (channel send + OLED draw). Being I/O-free, this is host-unit-tested
 //! (the orchestration layer of the README "Testing Strategy").
+//!
+//! The settings menu is data too: a static tree of [`MenuId`]s whose items
+//! open nested menus, editors for [`Setting`]s (numbers, on/off, choices), the
+//! paired-device list or the about screen. The reducer walks it like the flat
+//! screens; the shell only renders and stores the values it commits.
+
+use crate::hid::backup::Job;
+use core::fmt::Write;
+use heapless::String;
 
 /// Screens (views) the UI can be in.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -15,29 +24,313 @@ pub enum Screen {
     Home,
     /// Scanning for BLE devices - shows spinner/progress.
     Scanning,
-    /// Device list - user picks one to connect.
+    /// Device list - user picks one to connect (last entry is "Back"). The
+    /// scan may still be adding devices.
     DeviceList,
+    /// Connecting to the picked device - shows spinner.
+    Connecting,
     /// Connected - shows active device info.
     Connected,
+    /// Slot list - user picks one link to disconnect (last entry is "Back").
+    Slots,
+    /// Profile list - user picks the device profile to use (last entry is
+    /// "Back").
+    Profiles,
     /// Error - shows a transient message.
     Error,
+    /// A settings menu - user picks an item (last entry is "Back").
+    Menu(MenuId),
+    /// Editing a setting; `selected` holds the value being edited.
+    Edit(Setting),
+    /// Paired devices of the active profile - user picks one to forget (last
+    /// entry is "Back").
+    PairedDevices,
+    /// Confirm forgetting a paired device; `selected` is its index.
+    Forget,
+    /// Firmware version and build information.
+    About,
+    /// The USB host asked to export or import the paired devices: Select
+    /// allows it, any other press turns it down.
+    ConfirmBackup(Job),
+}
+
+/// The menus of the settings tree.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MenuId {
+    /// The top of the tree, opened from Home.
+    Settings,
+    Display,
+    Bluetooth,
+    Keyboard,
+}
+
+/// What picking a menu item opens.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuEntry {
+    Menu(MenuId),
+    Edit(Setting),
+    PairedDevices,
+    About,
+}
+
+/// One row of a menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MenuItem {
+    pub label: &'static str,
+    pub entry: MenuEntry,
+}
+
+const fn item(label: &'static str, entry: MenuEntry) -> MenuItem {
+    MenuItem { label, entry }
+}
+
+impl MenuId {
+    const ALL: [MenuId; 4] = [
+        MenuId::Settings,
+        MenuId::Display,
+        MenuId::Bluetooth,
+        MenuId::Keyboard,
+    ];
+
+    /// The menu's title line.
+    pub fn title(self) -> &'static str {
+        match self {
+            MenuId::Settings => "Settings",
+            MenuId::Display => "Display",
+            MenuId::Bluetooth => "Bluetooth",
+            MenuId::Keyboard => "Keyboard",
+        }
+    }
+
+    /// The menu's items ("Back" follows them).
+    pub fn items(self) -> &'static [MenuItem] {
+        match self {
+            MenuId::Settings => SETTINGS_ITEMS,
+            MenuId::Display => DISPLAY_ITEMS,
+            MenuId::Bluetooth => BLUETOOTH_ITEMS,
+            MenuId::Keyboard => KEYBOARD_ITEMS,
+        }
+    }
+}
+
+const SETTINGS_ITEMS: &[MenuItem] = &[
+    item("Display", MenuEntry::Menu(MenuId::Display)),
+    item("Bluetooth", MenuEntry::Menu(MenuId::Bluetooth)),
+    item("Keyboard", MenuEntry::Menu(MenuId::Keyboard)),
+    item("Paired devices", MenuEntry::PairedDevices),
+    item("About", MenuEntry::About),
+];
+
+const DISPLAY_ITEMS: &[MenuItem] = &[
+    item("Auto-off", MenuEntry::Edit(Setting::ScreenAutoOff)),
+    item("Off after", MenuEntry::Edit(Setting::ScreenAutoOffSecs)),
+];
+
+const BLUETOOTH_ITEMS: &[MenuItem] = &[
+    item("Scan time", MenuEntry::Edit(Setting::ScanDuration)),
+    item("Security", MenuEntry::Edit(Setting::Security)),
+    item("Relax asleep", MenuEntry::Edit(Setting::RelaxConn)),
+];
+
+const KEYBOARD_ITEMS: &[MenuItem] = &[
+    item("Modifiers", MenuEntry::Edit(Setting::ModifierLayout)),
+    item("Debounce", MenuEntry::Edit(Setting::ButtonDebounce)),
+];
+
+/// The menu holding `entry`, and its row there; `None` for the root menu.
+fn parent(entry: MenuEntry) -> Option<(MenuId, usize)> {
+    MenuId::ALL.into_iter().find_map(|menu| {
+        let row = menu.items().iter().position(|item| item.entry == entry)?;
+        Some((menu, row))
+    })
+}
+
+/// The settings the menu edits. Values are `u16`s whose meaning the
+/// setting's [`Editor`] gives; `ui::settings_menu` maps them to the stored
+/// settings' fields.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Setting {
+    ScreenAutoOff,
+    ScreenAutoOffSecs,
+    ScanDuration,
+    Security,
+    RelaxConn,
+    ModifierLayout,
+    ButtonDebounce,
+}
+
+impl Setting {
+    /// The setting's label in its menu.
+    pub fn label(self) -> &'static str {
+        parent(MenuEntry::Edit(self)).map_or("", |(menu, row)| menu.items()[row].label)
+    }
+
+    /// How the setting is edited. Number ranges match what the settings
+    /// store accepts.
+    pub fn editor(self) -> Editor {
+        match self {
+            Setting::ScreenAutoOff | Setting::RelaxConn => Editor::Toggle,
+            Setting::ScreenAutoOffSecs => Editor::Number {
+                min: 10,
+                max: 3600,
+                step: 10,
+                unit: "s",
+            },
+            Setting::ScanDuration => Editor::Number {
+                min: 2,
+                max: 30,
+                step: 1,
+                unit: "s",
+            },
+            Setting::ButtonDebounce => Editor::Number {
+                min: 5,
+                max: 200,
+                step: 5,
+                unit: "ms",
+            },
+            // In the order of the policies' and layouts' stored bytes.
+            Setting::Security => Editor::Choice(&["Bond", "Pair only", "Locked"]),
+            Setting::ModifierLayout => Editor::Choice(&["Standard", "Alt/GUI", "Ctrl/GUI", "Auto"]),
+        }
+    }
+}
+
+/// A value editor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Editor {
+    /// A number from `min` to `max` in steps of `step`.
+    Number {
+        min: u16,
+        max: u16,
+        step: u16,
+        unit: &'static str,
+    },
+    /// Off (0) or on (1).
+    Toggle,
+    /// An index into the labels.
+    Choice(&'static [&'static str]),
+}
+
+impl Editor {
+    /// The value after Up: a step more, the next choice, or flipped.
+    pub fn up(self, value: u16) -> u16 {
+        match self {
+            Editor::Number { max, step, .. } => value.saturating_add(step).min(max),
+            Editor::Toggle => (value == 0) as u16,
+            Editor::Choice(labels) => (value + 1) % labels.len() as u16,
+        }
+    }
+
+    /// The value after Down: a step less, the previous choice, or flipped.
+    pub fn down(self, value: u16) -> u16 {
+        match self {
+            Editor::Number { min, step, .. } => value.saturating_sub(step).max(min),
+            Editor::Toggle => (value == 0) as u16,
+            Editor::Choice(labels) => {
+                let len = labels.len() as u16;
+                (value + len - 1) % len
+            }
+        }
+    }
+
+    /// The value after Next (single-button mode): up, wrapping around.
+    pub fn next(self, value: u16) -> u16 {
+        match self {
+            Editor::Number { min, max, .. } if value >= max => min,
+            _ => self.up(value),
+        }
+    }
+
+    /// `value` brought into range.
+    pub fn clamp(self, value: u16) -> u16 {
+        match self {
+            Editor::Number { min, max, .. } => value.clamp(min, max),
+            Editor::Toggle => value.min(1),
+            Editor::Choice(labels) if value as usize >= labels.len() => 0,
+            Editor::Choice(_) => value,
+        }
+    }
+
+    /// `value` for display (e.g. `"120 s"`, `"On"`, `"Auto"`).
+    pub fn format(self, value: u16) -> String<16> {
+        let mut text = String::new();
+        match self {
+            Editor::Number { unit, .. } => {
+                let _ = write!(text, "{} {}", value, unit);
+            }
+            Editor::Toggle => {
+                let _ = text.push_str(if value != 0 { "On" } else { "Off" });
+            }
+            Editor::Choice(labels) => {
+                let _ = text.push_str(labels.get(value as usize).copied().unwrap_or("?"));
+            }
+        }
+        text
+    }
+}
+
+/// The physical buttons.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Button {
+    Up,
+    Down,
+    Select,
 }
 
-/// Physical button events (after debouncing).
+impl Button {
+    /// The event of a short press of this button.
+    pub fn press(self) -> ButtonEvent {
+        match self {
+            Button::Up => ButtonEvent::Up,
+            Button::Down => ButtonEvent::Down,
+            Button::Select => ButtonEvent::Select,
+        }
+    }
+}
+
+/// Button gestures (after debouncing; see [`crate::ui::gesture`]).
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ButtonEvent {
+    /// Short presses.
     Up,
     Down,
     Select,
+    /// A button held down.
+    Long(Button),
+    /// Two quick presses of a button.
+    Double(Button),
+    /// A button still held down, once per repeat period.
+    Repeat(Button),
+    /// Two buttons pressed together (in [`Button`] order).
+    Chord(Button, Button),
+    /// Single-button mode's short press: the next entry, wrapping around.
+    Next,
 }
 
-/// A BLE command the UI wants sent as a result of a button press.
+/// A command the UI wants carried out as a result of a button press (mostly
+/// BLE commands; settings are stored by the shell).
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum UiCommand {
     StartScan,
+    StopScan,
     Connect(usize),
     Disconnect,
+    /// Disconnect the link at this slot-list index.
+    DisconnectSlot(usize),
+    /// Switch to the device profile at this index.
+    SwitchProfile(usize),
+    /// Store a setting edited in the menu.
+    SetSetting(Setting, u16),
+    /// Forget the paired device at this paired-device-list index.
+    ForgetDevice(usize),
+    /// Let the host's export or import go ahead.
+    AllowBackup(Job),
+    /// Turn the host's export or import down.
+    DeclineBackup(Job),
 }
 
 /// Which view the shell should redraw after applying an outcome. The shell owns
@@ -47,7 +340,16 @@ pub enum Redraw {
     None,
     Scanning,
     DeviceList,
+    Connecting,
     Home,
+    Slots,
+    Connected,
+    Profiles,
+    Menu,
+    Edit,
+    PairedDevices,
+    Forget,
+    About,
 }
 
 /// The result of handling a button press: the new UI state plus the side
@@ -60,6 +362,9 @@ pub struct ButtonOutcome {
     pub selected: usize,
     /// Whether the shell should clear the cached device list + count.
     pub reset_devices: bool,
+    /// Setting whose current value the shell should put in `selected`
+    /// (opening its editor).
+    pub load_value: Option<Setting>,
     /// BLE command to send, if any.
     pub command: Option<UiCommand>,
     /// What to redraw.
@@ -69,7 +374,17 @@ pub struct ButtonOutcome {
 /// Decide the next UI state + side effects for a button press.
 ///
 /// Pure: `selected`/`device_count` are the current values from the shell; the
-/// returned `ButtonOutcome` tells the shell what to apply.
+/// returned `ButtonOutcome` tells the shell what to apply. On the Slots and
+/// Profiles screens `device_count` is the number of listed slots or profiles,
+/// on the PairedDevices screen the number of paired devices (the "Back" entry
+/// follows them).
+///
+/// Besides the short presses: holding Up/Down scrolls the lists, holding
+/// Select goes back a level, a double Select opens the profiles from Home or
+/// Connected, and Up+Down together abandons a scan or list for Home. Down on
+/// Home opens the settings menu. In
+/// single-button mode, [`ButtonEvent::Next`] steps through whatever can be
+/// picked and a long press is [`ButtonEvent::Select`].
 pub fn on_button(
     screen: Screen,
     btn: ButtonEvent,
@@ -80,10 +395,54 @@ pub fn on_button(
         screen,
         selected,
         reset_devices: false,
+        load_value: None,
         command: None,
         redraw: Redraw::None,
     };
 
+    // A held Up/Down steps the lists as if pressed again; elsewhere holding
+    // them does nothing.
+    let btn = match btn {
+        ButtonEvent::Repeat(button @ (Button::Up | Button::Down))
+            if matches!(
+                screen,
+                Screen::DeviceList
+                    | Screen::Slots
+                    | Screen::Profiles
+                    | Screen::Menu(_)
+                    | Screen::Edit(_)
+                    | Screen::PairedDevices
+            ) =>
+        {
+            button.press()
+        }
+        other => other,
+    };
+    if let Screen::ConfirmBackup(job) = screen {
+        // A held button is still the press that answered.
+        if !matches!(btn, ButtonEvent::Repeat(_)) {
+            out.screen = Screen::Home;
+            out.selected = 0;
+            out.redraw = Redraw::Home;
+            out.command = Some(match btn {
+                ButtonEvent::Select => UiCommand::AllowBackup(job),
+                _ => UiCommand::DeclineBackup(job),
+            });
+        }
+        return out;
+    }
+    if matches!(
+        screen,
+        Screen::Menu(_) | Screen::Edit(_) | Screen::PairedDevices | Screen::Forget | Screen::About
+    ) {
+        on_menu_button(&mut out, btn, device_count);
+        return out;
+    }
+
+    // Entries Next cycles through: the devices, slots or profiles plus
+    // "Back".
+    let entries = device_count + 1;
+
     match (screen, btn) {
         // Start a scan from Home or after an error.
         (Screen::Home, ButtonEvent::Select) | (Screen::Error, ButtonEvent::Select) => {
@@ -94,28 +453,88 @@ pub fn on_button(
             out.redraw = Redraw::Scanning;
         }
 
-        // Navigate the device list.
+        // UP (or Next) on Home lists the device profiles; so does a double
+        // Select from Home or Connected.
+        (Screen::Home, ButtonEvent::Up | ButtonEvent::Next)
+        | (Screen::Home | Screen::Connected, ButtonEvent::Double(Button::Select)) => {
+            out.screen = Screen::Profiles;
+            out.selected = 0;
+            out.redraw = Redraw::Profiles;
+        }
+
+        // DOWN on Home opens the settings.
+        (Screen::Home, ButtonEvent::Down) => {
+            out.screen = Screen::Menu(MenuId::Settings);
+            out.selected = 0;
+            out.redraw = Redraw::Menu;
+        }
+
+        // Cancel a scan that hasn't found anything yet.
+        (Screen::Scanning, ButtonEvent::Select) => {
+            out.screen = Screen::Home;
+            out.command = Some(UiCommand::StopScan);
+            out.redraw = Redraw::Home;
+        }
+
+        // Abandon a scan or a list: hold Select, or press Up+Down.
+        (
+            Screen::Scanning | Screen::DeviceList,
+            ButtonEvent::Long(Button::Select) | ButtonEvent::Chord(Button::Up, Button::Down),
+        ) => {
+            out.screen = Screen::Home;
+            out.selected = 0;
+            out.command = Some(UiCommand::StopScan);
+            out.redraw = Redraw::Home;
+        }
+        (
+            Screen::Profiles | Screen::Error,
+            ButtonEvent::Long(Button::Select) | ButtonEvent::Chord(Button::Up, Button::Down),
+        ) => {
+            out.screen = Screen::Home;
+            out.selected = 0;
+            out.redraw = Redraw::Home;
+        }
+        (
+            Screen::Slots,
+            ButtonEvent::Long(Button::Select) | ButtonEvent::Chord(Button::Up, Button::Down),
+        ) => {
+            out.screen = Screen::Connected;
+            out.selected = 0;
+            out.redraw = Redraw::Connected;
+        }
+
+        // Next steps through a list, wrapping around.
+        (Screen::DeviceList | Screen::Slots | Screen::Profiles, ButtonEvent::Next) => {
+            out.selected = (selected + 1) % entries;
+            out.redraw = match screen {
+                Screen::DeviceList => Redraw::DeviceList,
+                Screen::Slots => Redraw::Slots,
+                _ => Redraw::Profiles,
+            };
+        }
+
+        // Navigate the device list (devices, then "Back").
         (Screen::DeviceList, ButtonEvent::Up) => {
             out.selected = selected.saturating_sub(1);
             out.redraw = Redraw::DeviceList;
         }
-        (Screen::DeviceList, ButtonEvent::Down) => {
-            let next = if selected + 1 < device_count {
-                selected + 1
-            } else {
-                selected
-            };
-            if next != selected {
-                out.selected = next;
-                out.redraw = Redraw::DeviceList;
-            }
+        (Screen::DeviceList, ButtonEvent::Down) if selected < device_count => {
+            out.selected = selected + 1;
+            out.redraw = Redraw::DeviceList;
         }
 
-        // Connect to the highlighted device.
+        // "Back" abandons the list, stopping the scan if it's still running...
+        (Screen::DeviceList, ButtonEvent::Select) if selected >= device_count => {
+            out.screen = Screen::Home;
+            out.selected = 0;
+            out.command = Some(UiCommand::StopScan);
+            out.redraw = Redraw::Home;
+        }
+        // ...anything else connects to the highlighted device.
         (Screen::DeviceList, ButtonEvent::Select) => {
-            out.screen = Screen::Scanning;
+            out.screen = Screen::Connecting;
             out.command = Some(UiCommand::Connect(selected));
-            out.redraw = Redraw::Scanning;
+            out.redraw = Redraw::Connecting;
         }
 
         // From Connected: SELECT rescans (to add another device)...
@@ -126,12 +545,54 @@ pub fn on_button(
             out.command = Some(UiCommand::StartScan);
             out.redraw = Redraw::Scanning;
         }
-        // ...DOWN disconnects and returns home.
+        // ...DOWN disconnects and returns home...
         (Screen::Connected, ButtonEvent::Down) => {
             out.screen = Screen::Home;
             out.command = Some(UiCommand::Disconnect);
             out.redraw = Redraw::Home;
         }
+        // ...UP (or Next) lists the links to drop just one.
+        (Screen::Connected, ButtonEvent::Up | ButtonEvent::Next) => {
+            out.screen = Screen::Slots;
+            out.selected = 0;
+            out.redraw = Redraw::Slots;
+        }
+
+        // Navigate the slot list (slots, then "Back").
+        (Screen::Slots, ButtonEvent::Up) => {
+            out.selected = selected.saturating_sub(1);
+            out.redraw = Redraw::Slots;
+        }
+        (Screen::Slots, ButtonEvent::Down) if selected < device_count => {
+            out.selected = selected + 1;
+            out.redraw = Redraw::Slots;
+        }
+        (Screen::Slots, ButtonEvent::Select) => {
+            out.screen = Screen::Connected;
+            out.selected = 0;
+            out.redraw = Redraw::Connected;
+            if selected < device_count {
+                out.command = Some(UiCommand::DisconnectSlot(selected));
+            }
+        }
+
+        // Navigate the profile list (profiles, then "Back").
+        (Screen::Profiles, ButtonEvent::Up) => {
+            out.selected = selected.saturating_sub(1);
+            out.redraw = Redraw::Profiles;
+        }
+        (Screen::Profiles, ButtonEvent::Down) if selected < device_count => {
+            out.selected = selected + 1;
+            out.redraw = Redraw::Profiles;
+        }
+        (Screen::Profiles, ButtonEvent::Select) => {
+            out.screen = Screen::Home;
+            out.selected = 0;
+            out.redraw = Redraw::Home;
+            if selected < device_count {
+                out.command = Some(UiCommand::SwitchProfile(selected));
+            }
+        }
 
         _ => {}
     }
@@ -139,20 +600,244 @@ pub fn on_button(
     out
 }
 
-/// Decide the screen to show when a scan completes, given how many devices were
-/// found.
-pub fn on_scan_complete(device_count: usize) -> Screen {
-    if device_count > 0 {
-        Screen::DeviceList
-    } else {
-        Screen::Error
+/// The settings screens' part of [`on_button`]: `out` starts as the current
+/// state and becomes the outcome.
+fn on_menu_button(out: &mut ButtonOutcome, btn: ButtonEvent, device_count: usize) {
+    let back = matches!(
+        btn,
+        ButtonEvent::Long(Button::Select) | ButtonEvent::Chord(Button::Up, Button::Down)
+    );
+    let selected = out.selected;
+
+    match out.screen {
+        Screen::Menu(menu) => {
+            let items = menu.items();
+            match btn {
+                ButtonEvent::Select if selected < items.len() => open(out, items[selected].entry),
+                ButtonEvent::Select => close(out, MenuEntry::Menu(menu)),
+                _ if back => close(out, MenuEntry::Menu(menu)),
+                _ => scroll(out, btn, items.len() + 1, Redraw::Menu),
+            }
+        }
+        Screen::Edit(setting) => {
+            let editor = setting.editor();
+            let value = selected as u16;
+            let edited = match btn {
+                ButtonEvent::Up => editor.up(value),
+                ButtonEvent::Down => editor.down(value),
+                ButtonEvent::Next => editor.next(value),
+                ButtonEvent::Select => {
+                    out.command = Some(UiCommand::SetSetting(setting, value));
+                    close(out, MenuEntry::Edit(setting));
+                    return;
+                }
+                _ if back => {
+                    close(out, MenuEntry::Edit(setting));
+                    return;
+                }
+                _ => return,
+            };
+            out.selected = edited as usize;
+            out.redraw = Redraw::Edit;
+        }
+        Screen::PairedDevices => match btn {
+            ButtonEvent::Select if selected < device_count => {
+                out.screen = Screen::Forget;
+                out.redraw = Redraw::Forget;
+            }
+            ButtonEvent::Select => close(out, MenuEntry::PairedDevices),
+            _ if back => close(out, MenuEntry::PairedDevices),
+            _ => scroll(out, btn, device_count + 1, Redraw::PairedDevices),
+        },
+        // Select confirms; anything else keeps the device.
+        Screen::Forget => {
+            out.screen = Screen::PairedDevices;
+            out.redraw = Redraw::PairedDevices;
+            if btn == ButtonEvent::Select {
+                out.command = Some(UiCommand::ForgetDevice(selected));
+                out.selected = 0;
+            }
+        }
+        Screen::About if btn == ButtonEvent::Select || btn == ButtonEvent::Next || back => {
+            close(out, MenuEntry::About);
+        }
+        _ => {}
+    }
+}
+
+/// Open a menu item.
+fn open(out: &mut ButtonOutcome, entry: MenuEntry) {
+    out.selected = 0;
+    (out.screen, out.redraw) = match entry {
+        MenuEntry::Menu(menu) => (Screen::Menu(menu), Redraw::Menu),
+        MenuEntry::Edit(setting) => {
+            out.load_value = Some(setting);
+            (Screen::Edit(setting), Redraw::Edit)
+        }
+        MenuEntry::PairedDevices => (Screen::PairedDevices, Redraw::PairedDevices),
+        MenuEntry::About => (Screen::About, Redraw::About),
+    };
+}
+
+/// Go back from `entry` to the menu holding it, on its row; from the root
+/// menu, to Home.
+fn close(out: &mut ButtonOutcome, entry: MenuEntry) {
+    (out.screen, out.selected, out.redraw) = match parent(entry) {
+        Some((menu, row)) => (Screen::Menu(menu), row, Redraw::Menu),
+        None => (Screen::Home, 0, Redraw::Home),
+    };
+}
+
+/// Move through a list of `entries` rows: Up/Down stop at the ends, Next
+/// wraps around.
+fn scroll(out: &mut ButtonOutcome, btn: ButtonEvent, entries: usize, redraw: Redraw) {
+    let selected = out.selected;
+    out.selected = match btn {
+        ButtonEvent::Up => selected.saturating_sub(1),
+        ButtonEvent::Down => (selected + 1).min(entries - 1),
+        ButtonEvent::Next => (selected + 1) % entries,
+        _ => selected,
+    };
+    if out.selected != selected {
+        out.redraw = redraw;
+    }
+}
+
+/// Rows a list shows under its title.
+pub const LIST_ROWS: usize = 4;
+
+/// The rows of a `len`-row list on screen: scrolled just enough to show
+/// `selected`. Rows before or after the range are hinted at by the renderer.
+pub fn list_window(selected: usize, len: usize) -> core::ops::Range<usize> {
+    let first = (selected + 1).saturating_sub(LIST_ROWS);
+    first..(first + LIST_ROWS).min(len)
+}
+
+/// Characters in a list row between the selection marker and the scroll
+/// arrows.
+const ROW_CHARS: usize = 18;
+
+/// A menu row: the label, with an editable setting's value right-aligned
+/// (e.g. `"Scan time      8 s"`). `value` gives the setting's current value.
+pub fn menu_line(item: &MenuItem, value: impl Fn(Setting) -> u16) -> String<32> {
+    let mut line = String::new();
+    let _ = line.push_str(item.label);
+    if let MenuEntry::Edit(setting) = item.entry {
+        let text = setting.editor().format(value(setting));
+        let pad = ROW_CHARS.saturating_sub(line.len() + text.len()).max(1);
+        for _ in 0..pad {
+            let _ = line.push(' ');
+        }
+        let _ = line.push_str(&text);
+    }
+    line
+}
+
+/// Decide the screen to show when a scan streams in a device: the first one
+/// replaces the Scanning spinner with the (still growing) list.
+pub fn on_device_found(screen: Screen) -> Screen {
+    match screen {
+        Screen::Scanning => Screen::DeviceList,
+        other => other,
+    }
+}
+
+/// Decide the screen to show when a scan ends, given how many devices were
+/// found. Only a scan still on the Scanning spinner needs a verdict: a found
+/// device already brought up the list, and a `cancelled` scan was stopped by
+/// the user (or by a connect) and must not replace whatever followed it.
+pub fn on_scan_complete(screen: Screen, device_count: usize, cancelled: bool) -> Screen {
+    match screen {
+        Screen::Scanning if !cancelled && device_count > 0 => Screen::DeviceList,
+        Screen::Scanning if !cancelled => Screen::Error,
+        other => other,
+    }
+}
+
+/// Decide the screen to show when a link event (connected, disconnected, an
+/// error, a scan starting) brings up `next`: a backup prompt stays until the
+/// user answers it.
+pub fn on_link_event(screen: Screen, next: Screen) -> Screen {
+    match screen {
+        Screen::ConfirmBackup(_) => screen,
+        _ => next,
     }
 }
 
+/// Seconds the low-battery warning stays on screen before the Connected view
+/// comes back.
+pub const TOAST_SECS: u8 = 3;
+
+/// The Connected screen's battery line, one entry per slot (e.g.
+/// `"Bat 85% --"`). `None` when no slot has reported a level yet, so the screen
+/// can keep its default footer.
+pub fn battery_line(levels: &[Option<u8>]) -> Option<String<21>> {
+    if levels.iter().all(Option::is_none) {
+        return None;
+    }
+    let mut line = String::new();
+    let _ = line.push_str("Bat");
+    for level in levels {
+        match level {
+            Some(pct) => {
+                let _ = write!(line, " {}%", pct);
+            }
+            None => {
+                let _ = line.push_str(" --");
+            }
+        }
+    }
+    Some(line)
+}
+
+/// A device-list entry: the type glyph, `*` for a bonded device, then the name
+/// (e.g. `"K* MX Keys"`), truncated to fit.
+pub fn device_label(glyph: char, name: &str, bonded: bool) -> String<32> {
+    let mut label = String::new();
+    let _ = label.push(glyph);
+    let _ = label.push(if bonded { '*' } else { ' ' });
+    let _ = label.push(' ');
+    for c in name.chars() {
+        if label.push(c).is_err() {
+            break;
+        }
+    }
+    label
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn device_label_marks_type_and_bond() {
+        assert_eq!(device_label('K', "MX Keys", true).as_str(), "K* MX Keys");
+        assert_eq!(device_label('?', "Unknown", false).as_str(), "?  Unknown");
+    }
+
+    #[test]
+    fn device_label_truncates_long_names() {
+        let name = "X".repeat(32);
+        assert_eq!(device_label('M', &name, false).len(), 32);
+    }
+
+    #[test]
+    fn battery_line_lists_every_slot() {
+        assert_eq!(
+            battery_line(&[Some(85), None]).unwrap().as_str(),
+            "Bat 85% --"
+        );
+        assert_eq!(
+            battery_line(&[Some(100), Some(7)]).unwrap().as_str(),
+            "Bat 100% 7%"
+        );
+    }
+
+    #[test]
+    fn battery_line_absent_without_levels() {
+        assert_eq!(battery_line(&[None, None]), None);
+    }
+
     #[test]
     fn home_select_starts_scan() {
         let out = on_button(Screen::Home, ButtonEvent::Select, 0, 0);
@@ -170,6 +855,21 @@ mod tests {
         assert_eq!(out.command, Some(UiCommand::StartScan));
     }
 
+    #[test]
+    fn scanning_select_cancels_scan() {
+        let out = on_button(Screen::Scanning, ButtonEvent::Select, 0, 0);
+        assert_eq!(out.screen, Screen::Home);
+        assert_eq!(out.command, Some(UiCommand::StopScan));
+        assert_eq!(out.redraw, Redraw::Home);
+    }
+
+    #[test]
+    fn first_device_found_shows_list() {
+        assert_eq!(on_device_found(Screen::Scanning), Screen::DeviceList);
+        assert_eq!(on_device_found(Screen::DeviceList), Screen::DeviceList);
+        assert_eq!(on_device_found(Screen::Connected), Screen::Connected);
+    }
+
     #[test]
     fn device_list_up_moves_selection_and_redraws() {
         let out = on_button(Screen::DeviceList, ButtonEvent::Up, 2, 4);
@@ -195,8 +895,9 @@ mod tests {
 
     #[test]
     fn device_list_down_at_end_is_noop() {
-        let out = on_button(Screen::DeviceList, ButtonEvent::Down, 3, 4);
-        assert_eq!(out.selected, 3);
+        // The end is "Back", after the last device.
+        let out = on_button(Screen::DeviceList, ButtonEvent::Down, 4, 4);
+        assert_eq!(out.selected, 4);
         assert_eq!(
             out.redraw,
             Redraw::None,
@@ -208,9 +909,27 @@ mod tests {
     #[test]
     fn device_list_select_connects_highlighted() {
         let out = on_button(Screen::DeviceList, ButtonEvent::Select, 2, 4);
-        assert_eq!(out.screen, Screen::Scanning);
+        assert_eq!(out.screen, Screen::Connecting);
         assert_eq!(out.command, Some(UiCommand::Connect(2)));
+        assert_eq!(out.redraw, Redraw::Connecting);
         assert!(!out.reset_devices, "keep device list for the connect");
+        // Devices the scan streams in afterwards don't bring the list back.
+        assert_eq!(on_device_found(out.screen), Screen::Connecting);
+    }
+
+    #[test]
+    fn device_list_back_stops_the_scan() {
+        // Down steps past the last device onto "Back"...
+        let out = on_button(Screen::DeviceList, ButtonEvent::Down, 3, 4);
+        assert_eq!(out.selected, 4);
+        assert_eq!(out.redraw, Redraw::DeviceList);
+        // ...which cancels the still-running scan.
+        let out = on_button(Screen::DeviceList, ButtonEvent::Select, 4, 4);
+        assert_eq!(out.screen, Screen::Home);
+        assert_eq!(out.command, Some(UiCommand::StopScan));
+        assert_eq!(out.redraw, Redraw::Home);
+        // The cancelled scan's ScanComplete leaves Home alone.
+        assert_eq!(on_scan_complete(Screen::Home, 4, true), Screen::Home);
     }
 
     #[test]
@@ -230,20 +949,310 @@ mod tests {
     }
 
     #[test]
-    fn ignored_combinations_are_noops() {
-        // e.g. Up on Home, Down on Home, Select already handled elsewhere.
-        let out = on_button(Screen::Home, ButtonEvent::Up, 0, 0);
+    fn connected_up_lists_slots() {
+        let out = on_button(Screen::Connected, ButtonEvent::Up, 3, 0);
+        assert_eq!(out.screen, Screen::Slots);
+        assert_eq!(out.selected, 0);
+        assert_eq!(out.redraw, Redraw::Slots);
+        assert_eq!(out.command, None);
+    }
+
+    #[test]
+    fn slot_list_navigation_reaches_back_entry() {
+        let out = on_button(Screen::Slots, ButtonEvent::Down, 1, 2);
+        assert_eq!(out.selected, 2);
+        let out = on_button(Screen::Slots, ButtonEvent::Down, 2, 2);
+        assert_eq!(out.selected, 2);
+        assert_eq!(out.redraw, Redraw::None);
+    }
+
+    #[test]
+    fn slot_list_select_disconnects_one_or_goes_back() {
+        let out = on_button(Screen::Slots, ButtonEvent::Select, 1, 2);
+        assert_eq!(out.screen, Screen::Connected);
+        assert_eq!(out.command, Some(UiCommand::DisconnectSlot(1)));
+        let out = on_button(Screen::Slots, ButtonEvent::Select, 2, 2);
+        assert_eq!(out.screen, Screen::Connected);
+        assert_eq!(out.command, None);
+        assert_eq!(out.redraw, Redraw::Connected);
+    }
+
+    #[test]
+    fn home_up_lists_profiles() {
+        let out = on_button(Screen::Home, ButtonEvent::Up, 2, 0);
+        assert_eq!(out.screen, Screen::Profiles);
+        assert_eq!(out.selected, 0);
+        assert_eq!(out.redraw, Redraw::Profiles);
+        assert_eq!(out.command, None);
+    }
+
+    #[test]
+    fn profile_list_select_switches_or_goes_back() {
+        let out = on_button(Screen::Profiles, ButtonEvent::Down, 2, 3);
+        assert_eq!(out.selected, 3);
+        let out = on_button(Screen::Profiles, ButtonEvent::Down, 3, 3);
+        assert_eq!(out.redraw, Redraw::None);
+
+        let out = on_button(Screen::Profiles, ButtonEvent::Select, 1, 3);
+        assert_eq!(out.screen, Screen::Home);
+        assert_eq!(out.command, Some(UiCommand::SwitchProfile(1)));
+        let out = on_button(Screen::Profiles, ButtonEvent::Select, 3, 3);
         assert_eq!(out.screen, Screen::Home);
         assert_eq!(out.command, None);
+        assert_eq!(out.redraw, Redraw::Home);
+    }
+
+    #[test]
+    fn ignored_combinations_are_noops() {
+        // e.g. Down on an error, Up on Scanning.
+        let out = on_button(Screen::Error, ButtonEvent::Down, 0, 0);
+        assert_eq!(out.screen, Screen::Error);
+        assert_eq!(out.command, None);
         assert_eq!(out.redraw, Redraw::None);
 
-        let out = on_button(Screen::Home, ButtonEvent::Down, 0, 0);
+        let out = on_button(Screen::Scanning, ButtonEvent::Up, 0, 0);
+        assert_eq!(out.command, None);
+    }
+
+    #[test]
+    fn holding_up_or_down_scrolls_lists_only() {
+        let out = on_button(Screen::DeviceList, ButtonEvent::Repeat(Button::Down), 1, 4);
+        assert_eq!(out.selected, 2);
+        assert_eq!(out.redraw, Redraw::DeviceList);
+        let out = on_button(Screen::Profiles, ButtonEvent::Repeat(Button::Up), 2, 3);
+        assert_eq!(out.selected, 1);
+        // Holding Up on Home or Down on Connected is no press.
+        let out = on_button(Screen::Home, ButtonEvent::Repeat(Button::Up), 0, 0);
+        assert_eq!(out.screen, Screen::Home);
+        let out = on_button(Screen::Connected, ButtonEvent::Repeat(Button::Down), 0, 0);
+        assert_eq!(out.command, None);
+    }
+
+    #[test]
+    fn long_select_and_up_down_chord_go_back() {
+        for btn in [
+            ButtonEvent::Long(Button::Select),
+            ButtonEvent::Chord(Button::Up, Button::Down),
+        ] {
+            let out = on_button(Screen::DeviceList, btn, 2, 4);
+            assert_eq!(out.screen, Screen::Home);
+            assert_eq!(out.command, Some(UiCommand::StopScan));
+            let out = on_button(Screen::Slots, btn, 1, 2);
+            assert_eq!(out.screen, Screen::Connected);
+            assert_eq!(out.command, None);
+            let out = on_button(Screen::Profiles, btn, 1, 3);
+            assert_eq!(out.screen, Screen::Home);
+            assert_eq!(out.command, None);
+            // Nothing above Connected to go back to.
+            let out = on_button(Screen::Connected, btn, 0, 0);
+            assert_eq!(out.screen, Screen::Connected);
+            assert_eq!(out.command, None);
+        }
+    }
+
+    #[test]
+    fn double_select_opens_profiles() {
+        for screen in [Screen::Home, Screen::Connected] {
+            let out = on_button(screen, ButtonEvent::Double(Button::Select), 0, 0);
+            assert_eq!(out.screen, Screen::Profiles);
+            assert_eq!(out.redraw, Redraw::Profiles);
+        }
+    }
+
+    #[test]
+    fn single_button_next_cycles_and_select_picks() {
+        let out = on_button(Screen::Home, ButtonEvent::Next, 0, 0);
+        assert_eq!(out.screen, Screen::Profiles);
+        // Profiles 0..3 then Back, then round again.
+        let out = on_button(Screen::Profiles, ButtonEvent::Next, 2, 3);
+        assert_eq!(out.selected, 3);
+        let out = on_button(Screen::Profiles, ButtonEvent::Next, 3, 3);
+        assert_eq!(out.selected, 0);
+        // So does the device list.
+        let out = on_button(Screen::DeviceList, ButtonEvent::Next, 3, 4);
+        assert_eq!(out.selected, 4);
+        let out = on_button(Screen::DeviceList, ButtonEvent::Next, 4, 4);
+        assert_eq!(out.selected, 0);
+        assert_eq!(out.redraw, Redraw::DeviceList);
+        // Never disconnects: Next on Connected lists the slots.
+        let out = on_button(Screen::Connected, ButtonEvent::Next, 0, 0);
+        assert_eq!(out.screen, Screen::Slots);
         assert_eq!(out.command, None);
     }
 
     #[test]
     fn scan_complete_picks_list_or_error() {
-        assert_eq!(on_scan_complete(0), Screen::Error);
-        assert_eq!(on_scan_complete(3), Screen::DeviceList);
+        assert_eq!(on_scan_complete(Screen::Scanning, 0, false), Screen::Error);
+        assert_eq!(
+            on_scan_complete(Screen::Scanning, 3, false),
+            Screen::DeviceList
+        );
+        // The list is already up once something was found.
+        assert_eq!(
+            on_scan_complete(Screen::DeviceList, 3, false),
+            Screen::DeviceList
+        );
+    }
+
+    #[test]
+    fn cancelled_scan_gives_no_verdict() {
+        // A rescan started right after a cancel must not show the old scan's
+        // "No devices found", nor a connect be replaced by the list.
+        assert_eq!(
+            on_scan_complete(Screen::Scanning, 0, true),
+            Screen::Scanning
+        );
+        assert_eq!(
+            on_scan_complete(Screen::Connecting, 3, true),
+            Screen::Connecting
+        );
+    }
+
+    #[test]
+    fn settings_menu_nests_and_goes_back_to_its_row() {
+        let out = on_button(Screen::Home, ButtonEvent::Down, 0, 0);
+        assert_eq!(out.screen, Screen::Menu(MenuId::Settings));
+        assert_eq!(out.redraw, Redraw::Menu);
+        // Settings > Bluetooth (row 1), then back to that row.
+        let out = on_button(out.screen, ButtonEvent::Down, 0, 0);
+        let out = on_button(out.screen, ButtonEvent::Select, out.selected, 0);
+        assert_eq!(out.screen, Screen::Menu(MenuId::Bluetooth));
+        assert_eq!(out.selected, 0);
+        let back = MenuId::Bluetooth.items().len();
+        let out = on_button(out.screen, ButtonEvent::Select, back, 0);
+        assert_eq!(
+            (out.screen, out.selected),
+            (Screen::Menu(MenuId::Settings), 1)
+        );
+        let out = on_button(out.screen, ButtonEvent::Long(Button::Select), 1, 0);
+        assert_eq!((out.screen, out.redraw), (Screen::Home, Redraw::Home));
+    }
+
+    #[test]
+    fn menus_scroll_past_the_visible_rows() {
+        let menu = Screen::Menu(MenuId::Settings);
+        let rows = MenuId::Settings.items().len() + 1;
+        assert!(rows > LIST_ROWS);
+        let mut selected = 0;
+        for _ in 0..rows + 2 {
+            selected = on_button(menu, ButtonEvent::Repeat(Button::Down), selected, 0).selected;
+        }
+        assert_eq!(selected, rows - 1, "stops on Back");
+        assert_eq!(list_window(selected, rows), 2..6);
+        assert_eq!(list_window(1, rows), 0..4);
+        assert_eq!(list_window(0, 2), 0..2);
+        // Next wraps around to the top.
+        assert_eq!(on_button(menu, ButtonEvent::Next, selected, 0).selected, 0);
+    }
+
+    #[test]
+    fn editors_change_the_value_and_commit_or_cancel() {
+        let open = on_button(Screen::Menu(MenuId::Bluetooth), ButtonEvent::Select, 0, 0);
+        assert_eq!(open.screen, Screen::Edit(Setting::ScanDuration));
+        assert_eq!(open.load_value, Some(Setting::ScanDuration));
+
+        let edit = Screen::Edit(Setting::ScanDuration);
+        let out = on_button(edit, ButtonEvent::Up, 8, 0);
+        assert_eq!((out.selected, out.redraw), (9, Redraw::Edit));
+        assert_eq!(
+            on_button(edit, ButtonEvent::Repeat(Button::Down), 2, 0).selected,
+            2
+        );
+        assert_eq!(on_button(edit, ButtonEvent::Next, 30, 0).selected, 2);
+
+        let out = on_button(edit, ButtonEvent::Select, 9, 0);
+        assert_eq!(
+            out.command,
+            Some(UiCommand::SetSetting(Setting::ScanDuration, 9))
+        );
+        assert_eq!(
+            (out.screen, out.selected),
+            (Screen::Menu(MenuId::Bluetooth), 0)
+        );
+        let out = on_button(edit, ButtonEvent::Chord(Button::Up, Button::Down), 9, 0);
+        assert_eq!(out.command, None);
+        assert_eq!(out.screen, Screen::Menu(MenuId::Bluetooth));
+    }
+
+    #[test]
+    fn editor_kinds() {
+        let toggle = Setting::ScreenAutoOff.editor();
+        assert_eq!((toggle.up(0), toggle.down(1), toggle.clamp(5)), (1, 0, 1));
+        assert_eq!(toggle.format(1).as_str(), "On");
+
+        let choice = Setting::ModifierLayout.editor();
+        assert_eq!((choice.up(3), choice.down(0), choice.clamp(9)), (0, 3, 0));
+        assert_eq!(choice.format(3).as_str(), "Auto");
+
+        let number = Setting::ScreenAutoOffSecs.editor();
+        assert_eq!(
+            (number.up(3595), number.down(15), number.clamp(1)),
+            (3600, 10, 10)
+        );
+        assert_eq!(number.format(120).as_str(), "120 s");
+        assert_eq!(Setting::ScreenAutoOffSecs.label(), "Off after");
+    }
+
+    #[test]
+    fn menu_lines_right_align_values() {
+        let items = MenuId::Bluetooth.items();
+        let line = menu_line(&items[0], |_| 8);
+        assert_eq!(line.as_str(), "Scan time      8 s");
+        assert_eq!(menu_line(&items[1], |_| 2).as_str(), "Security    Locked");
+        let line = menu_line(&MenuId::Settings.items()[0], |_| unreachable!());
+        assert_eq!(line.as_str(), "Display");
+    }
+
+    #[test]
+    fn paired_devices_are_forgotten_after_confirming() {
+        let out = on_button(Screen::Menu(MenuId::Settings), ButtonEvent::Select, 3, 0);
+        assert_eq!(out.screen, Screen::PairedDevices);
+
+        let out = on_button(Screen::PairedDevices, ButtonEvent::Select, 1, 2);
+        assert_eq!((out.screen, out.selected), (Screen::Forget, 1));
+        let out = on_button(Screen::Forget, ButtonEvent::Down, 1, 2);
+        assert_eq!((out.screen, out.selected), (Screen::PairedDevices, 1));
+        assert_eq!(out.command, None);
+        let out = on_button(Screen::Forget, ButtonEvent::Select, 1, 2);
+        assert_eq!(out.command, Some(UiCommand::ForgetDevice(1)));
+        assert_eq!((out.screen, out.selected), (Screen::PairedDevices, 0));
+
+        // Back returns to the paired-devices row; About leaves the same way.
+        let out = on_button(Screen::PairedDevices, ButtonEvent::Select, 2, 2);
+        assert_eq!(
+            (out.screen, out.selected),
+            (Screen::Menu(MenuId::Settings), 3)
+        );
+        let out = on_button(Screen::About, ButtonEvent::Select, 0, 0);
+        assert_eq!(
+            (out.screen, out.selected),
+            (Screen::Menu(MenuId::Settings), 4)
+        );
+    }
+
+    #[test]
+    fn backups_wait_for_an_answer_on_the_device() {
+        let prompt = Screen::ConfirmBackup(Job::Import);
+        let out = on_button(prompt, ButtonEvent::Select, 0, 0);
+        assert_eq!(out.command, Some(UiCommand::AllowBackup(Job::Import)));
+        assert_eq!((out.screen, out.redraw), (Screen::Home, Redraw::Home));
+        for btn in [
+            ButtonEvent::Up,
+            ButtonEvent::Next,
+            ButtonEvent::Long(Button::Select),
+        ] {
+            let out = on_button(prompt, btn, 0, 0);
+            assert_eq!(out.command, Some(UiCommand::DeclineBackup(Job::Import)));
+            assert_eq!(out.screen, Screen::Home);
+        }
+        let out = on_button(prompt, ButtonEvent::Repeat(Button::Down), 0, 0);
+        assert_eq!((out.screen, out.command), (prompt, None));
+
+        // Links coming and going don't push the prompt away.
+        assert_eq!(on_link_event(prompt, Screen::Connected), prompt);
+        assert_eq!(
+            on_link_event(Screen::Home, Screen::Connected),
+            Screen::Connected
+        );
     }
 }
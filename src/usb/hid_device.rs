@@ -1,32 +1,50 @@
-//! USB HID composite device - keyboard + mouse + consumer control.
+//! USB HID composite device - keyboard + mouse + consumer control + battery.
 //!
 //! Initialises the Embassy USB stack on the nRF52840 hardware USB
-//! peripheral and exposes keyboard, mouse, and consumer-control HID endpoints.
+//! peripheral and exposes keyboard, mouse, and consumer-control HID endpoints,
+//! plus a battery interface reporting each BLE peer's battery level and a
+//! vendor-defined backup interface for exporting/importing the paired devices
+//! (see [`crate::hid::backup`]).
+//!
+//! The control handlers also report how the host enumerates the device, so
+//! [`host_watch_task`] can tell when a different PC took over the bus (see
+//! [`super::host_id`]).
 
+use crate::ble::coordinator::MAX_CONNECTIONS;
 use crate::config;
+use crate::hid::backup::{BackupSession, Job, BACKUP_REPORT_DESCRIPTOR};
+use crate::hid::battery::{BatteryReport, BATTERY_REPORT_DESCRIPTOR, BATTERY_REPORT_SIZE};
 use crate::hid::consumer::CONSUMER_REPORT_DESCRIPTOR;
 use crate::hid::keyboard::{KeyboardLeds, KEYBOARD_REPORT_DESCRIPTOR};
 use crate::hid::mouse::MOUSE_REPORT_DESCRIPTOR;
 use crate::hid::HidReport;
+use crate::storage::{self, backup::MAX_BACKUP_SIZE};
+use crate::usb::host_id::{BusEvent, Host, HostOs, HostTracker};
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicU8, Ordering};
 use defmt::{info, warn};
+use embassy_futures::select::{select, Either};
 use embassy_nrf::usb::vbus_detect::SoftwareVbusDetect;
 use embassy_nrf::usb::Driver;
 use embassy_nrf::{self, bind_interrupts, peripherals, Peri};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::channel::Receiver;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::channel::{Channel, Receiver};
 use embassy_sync::signal::Signal;
 use embassy_sync::watch::{Receiver as WatchReceiver, Watch};
+use embassy_time::{Instant, Timer};
 use embassy_usb::class::hid::{
     Config as HidConfig, HidBootProtocol, HidSubclass, HidWriter, ReportId, RequestHandler, State,
 };
-use embassy_usb::control::OutResponse;
+use embassy_usb::control::{InResponse, OutResponse, Recipient, Request, RequestType};
+use embassy_usb::types::StringIndex;
 use embassy_usb::{Builder, Config, UsbDevice};
 use static_cell::StaticCell;
 
-/// Number of BLE connection slots that may consume host LED updates. Must be ≥
-/// the BLE `MAX_CONNECTIONS` (a keyboard occupies one slot; mice/consumer slots
-/// simply ignore the updates).
-pub const LED_CONSUMERS: usize = 2;
+/// Number of BLE connection slots that may consume host LED updates: one per
+/// slot (a keyboard occupies one slot; mice/consumer slots simply ignore the
+/// updates).
+pub const LED_CONSUMERS: usize = MAX_CONNECTIONS;
 
 /// Latest host keyboard-LED (Caps/Num/Scroll) state, published by the USB
 /// control handler and consumed by the BLE slot tasks to drive the BLE
@@ -41,13 +59,70 @@ pub fn keyboard_led_receiver() -> Option<LedReceiver> {
     KEYBOARD_LEDS.receiver()
 }
 
+/// Interface numbers, in the order `init` adds them.
+const KEYBOARD_INTERFACE: u8 = 0;
+const MOUSE_INTERFACE: u8 = 1;
+const CONSUMER_INTERFACE: u8 = 2;
+
+/// Report descriptor length of each interface, by interface number.
+const REPORT_DESCRIPTOR_SIZES: [usize; 5] = [
+    KEYBOARD_REPORT_DESCRIPTOR.len(),
+    MOUSE_REPORT_DESCRIPTOR.len(),
+    CONSUMER_REPORT_DESCRIPTOR.len(),
+    BATTERY_REPORT_DESCRIPTOR.len(),
+    BACKUP_REPORT_DESCRIPTOR.len(),
+];
+
+/// HID descriptor type of a report descriptor (high byte of wValue).
+const HID_REPORT_DESCRIPTOR_TYPE: u8 = 0x22;
+
+/// HID class requests (GET_REPORT, GET_IDLE, GET_PROTOCOL, SET_REPORT,
+/// SET_IDLE, SET_PROTOCOL); any other class request is a probe.
+const HID_CLASS_REQUESTS: [u8; 6] = [0x01, 0x02, 0x03, 0x09, 0x0A, 0x0B];
+
+/// Bus events from the control handlers to [`host_watch_task`].
+static BUS_EVENTS: Channel<CriticalSectionRawMutex, BusEvent, 16> = Channel::new();
+
+/// Wakes the BLE task with a USB host that just took over the bus (one that
+/// differs from the last), to apply the settings bound to it.
+pub static HOST_CHANGED: Signal<CriticalSectionRawMutex, Host> = Signal::new();
+
+/// Operating system of the host upstream, as last guessed.
+static HOST_OS: BlockingMutex<CriticalSectionRawMutex, Cell<HostOs>> =
+    BlockingMutex::new(Cell::new(HostOs::Unknown));
+
+/// Operating system of the host upstream, as last guessed (`Unknown` until a
+/// host enumerated the bridge).
+pub fn host_os() -> HostOs {
+    HOST_OS.lock(Cell::get)
+}
+
+/// Hand `event` to [`host_watch_task`]. Called from the control handlers, so
+/// it must not block: an event that doesn't fit is dropped, at worst costing
+/// one enumeration's fingerprint.
+fn note_bus(event: BusEvent) {
+    if BUS_EVENTS.try_send(event).is_err() {
+        warn!("USB bus event dropped: {}", event);
+    }
+}
+
 /// USB control handler that captures the host's keyboard LED **output** report
 /// (sent via SET_REPORT on the control pipe) and republishes it for the BLE
 /// side. Installed only on the keyboard interface.
 struct LedRequestHandler;
 
 impl RequestHandler for LedRequestHandler {
+    fn set_idle_ms(&mut self, _id: Option<ReportId>, duration_ms: u32) {
+        note_bus(BusEvent::SetIdle {
+            interface: KEYBOARD_INTERFACE,
+            duration_ms,
+        });
+    }
+
     fn set_report(&mut self, _id: ReportId, data: &[u8]) -> OutResponse {
+        note_bus(BusEvent::OutputReport {
+            interface: KEYBOARD_INTERFACE,
+        });
         // Our keyboard descriptor declares no report IDs, so the output report
         // payload is the single LED bitfield byte.
         if let Some(&byte) = data.first() {
@@ -66,6 +141,117 @@ impl RequestHandler for LedRequestHandler {
 
 static LED_HANDLER: StaticCell<LedRequestHandler> = StaticCell::new();
 
+/// Notes the host's class requests on an interface that has nothing to
+/// answer them with (the mouse and consumer interfaces).
+struct ProbeRequestHandler {
+    interface: u8,
+}
+
+impl RequestHandler for ProbeRequestHandler {
+    fn set_idle_ms(&mut self, _id: Option<ReportId>, duration_ms: u32) {
+        note_bus(BusEvent::SetIdle {
+            interface: self.interface,
+            duration_ms,
+        });
+    }
+
+    fn set_report(&mut self, _id: ReportId, _data: &[u8]) -> OutResponse {
+        note_bus(BusEvent::OutputReport {
+            interface: self.interface,
+        });
+        OutResponse::Accepted
+    }
+}
+
+static MOUSE_HANDLER: StaticCell<ProbeRequestHandler> = StaticCell::new();
+static CONSUMER_HANDLER: StaticCell<ProbeRequestHandler> = StaticCell::new();
+
+/// Marks a slot whose peer hasn't reported a battery level.
+const BATTERY_UNKNOWN: u8 = u8::MAX;
+
+/// Latest battery level per BLE slot, published by the BLE coordinator. Read
+/// both by the battery writer task and by host GET_REPORT requests.
+static BATTERY_LEVELS: [AtomicU8; MAX_CONNECTIONS] =
+    [const { AtomicU8::new(BATTERY_UNKNOWN) }; MAX_CONNECTIONS];
+
+/// Wakes the battery writer task when any slot's level changes.
+static BATTERY_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Publish a BLE slot's battery level (percent) to the USB host, or withdraw
+/// it (`None`) once the slot's link is gone.
+pub fn publish_battery(slot: usize, level: Option<u8>) {
+    let Some(stored) = BATTERY_LEVELS.get(slot) else {
+        return;
+    };
+    let level = level
+        .and_then(|level| BatteryReport::new(slot, level))
+        .map_or(BATTERY_UNKNOWN, |report| report.level);
+    stored.store(level, Ordering::Relaxed);
+    BATTERY_CHANGED.signal(());
+}
+
+fn battery_report(slot: usize) -> Option<BatteryReport> {
+    match BATTERY_LEVELS.get(slot)?.load(Ordering::Relaxed) {
+        BATTERY_UNKNOWN => None,
+        level => BatteryReport::new(slot, level),
+    }
+}
+
+/// Answers the host's GET_REPORT on the battery interface (hosts typically
+/// read each report once at enumeration) from the latest published levels.
+struct BatteryRequestHandler;
+
+impl RequestHandler for BatteryRequestHandler {
+    fn get_report(&mut self, id: ReportId, buf: &mut [u8]) -> Option<usize> {
+        let slot = match id {
+            ReportId::In(id) => (id as usize).checked_sub(1)?,
+            _ => return None,
+        };
+        let n = battery_report(slot)?.serialize(buf);
+        (n > 0).then_some(n)
+    }
+}
+
+static BATTERY_HANDLER: StaticCell<BatteryRequestHandler> = StaticCell::new();
+
+/// Export/import session of the backup interface, shared by its control
+/// handler and the BLE task, which owns the device store and runs the jobs.
+pub static BACKUP_SESSION: BlockingMutex<
+    CriticalSectionRawMutex,
+    RefCell<BackupSession<MAX_BACKUP_SIZE>>,
+> = BlockingMutex::new(RefCell::new(BackupSession::new()));
+
+/// Hands the BLE task a job the host asked for, to put to the user before
+/// it runs.
+pub static BACKUP_JOB: Signal<CriticalSectionRawMutex, Job> = Signal::new();
+
+/// Feeds the host's feature reports on the backup interface to
+/// [`BACKUP_SESSION`].
+struct BackupRequestHandler;
+
+impl RequestHandler for BackupRequestHandler {
+    fn get_report(&mut self, id: ReportId, buf: &mut [u8]) -> Option<usize> {
+        let ReportId::Feature(id) = id else {
+            return None;
+        };
+        BACKUP_SESSION.lock(|session| session.borrow_mut().get_report(id, buf))
+    }
+
+    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
+        if !matches!(id, ReportId::Feature(_)) {
+            return OutResponse::Rejected;
+        }
+        // With report IDs declared, the data stage starts with the ID.
+        if let Some(job) = BACKUP_SESSION.lock(|session| session.borrow_mut().set_report(data)) {
+            info!("Backup: {} requested", job);
+            BACKUP_JOB.signal(job);
+        }
+        OutResponse::Accepted
+    }
+}
+
+static BACKUP_HANDLER: StaticCell<BackupRequestHandler> = StaticCell::new();
+
 bind_interrupts!(struct Irqs {
     USBD => embassy_nrf::usb::InterruptHandler<peripherals::USBD>;
 });
@@ -86,6 +272,8 @@ pub type UsbDriver = Driver<'static, peripherals::USBD, Vbus>;
 static KB_STATE: StaticCell<State> = StaticCell::new();
 static MOUSE_STATE: StaticCell<State> = StaticCell::new();
 static CONSUMER_STATE: StaticCell<State> = StaticCell::new();
+static BATTERY_STATE: StaticCell<State> = StaticCell::new();
+static BACKUP_STATE: StaticCell<State> = StaticCell::new();
 static USB_CONFIG_DESC: StaticCell<[u8; 256]> = StaticCell::new();
 static USB_BOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
 static USB_MSOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
@@ -96,10 +284,74 @@ static SOFTWARE_VBUS: StaticCell<SoftwareVbusDetect> = StaticCell::new();
 
 struct UsbPowerHandler;
 
+/// Note the descriptor requests and probes in `req`. Registered before the
+/// HID classes, [`UsbPowerHandler`] sees the requests the stack hands them
+/// first; it only looks and leaves the answer to them.
+fn note_request(req: &Request) {
+    let interface = req.index as u8;
+    match req.request_type {
+        RequestType::Standard
+            if req.recipient == Recipient::Interface
+                && req.request == Request::GET_DESCRIPTOR
+                && (req.value >> 8) as u8 == HID_REPORT_DESCRIPTOR_TYPE =>
+        {
+            if let Some(&size) = REPORT_DESCRIPTOR_SIZES.get(interface as usize) {
+                note_bus(BusEvent::ReportDescriptorRequest {
+                    interface,
+                    padding: req.length.saturating_sub(size as u16),
+                });
+            }
+        }
+        RequestType::Class if HID_CLASS_REQUESTS.contains(&req.request) => {}
+        RequestType::Class | RequestType::Vendor => note_bus(BusEvent::Probe {
+            request: req.request,
+            interface,
+        }),
+        _ => {}
+    }
+}
+
 impl embassy_usb::Handler for UsbPowerHandler {
+    fn enabled(&mut self, enabled: bool) {
+        note_bus(BusEvent::Power(enabled));
+    }
+
+    fn reset(&mut self) {
+        note_bus(BusEvent::Reset);
+    }
+
+    fn addressed(&mut self, _addr: u8) {
+        note_bus(BusEvent::Addressed);
+    }
+
+    fn configured(&mut self, configured: bool) {
+        note_bus(BusEvent::Configured(configured));
+    }
+
     fn suspended(&mut self, suspended: bool) {
+        note_bus(BusEvent::Suspended(suspended));
         USB_SUSPEND_SIGNAL.signal(suspended);
     }
+
+    fn control_out(&mut self, req: Request, _data: &[u8]) -> Option<OutResponse> {
+        note_request(&req);
+        None
+    }
+
+    fn control_in<'a>(&'a mut self, req: Request, _buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        note_request(&req);
+        None
+    }
+
+    fn get_string(&mut self, index: StringIndex, lang_id: u16) -> Option<&str> {
+        // Only strings the stack doesn't serve itself get here; which ones a
+        // host asks for is part of its fingerprint. There are none to give.
+        note_bus(BusEvent::StringRequest {
+            index: index.into(),
+            lang_id,
+        });
+        None
+    }
 }
 
 /// USB bus suspend/resume signal.
@@ -116,6 +368,7 @@ pub struct UsbHidDevice {
     pub keyboard_writer: HidWriter<'static, UsbDriver, 8>,
     pub mouse_writer: HidWriter<'static, UsbDriver, 8>,
     pub consumer_writer: HidWriter<'static, UsbDriver, 8>,
+    pub battery_writer: HidWriter<'static, UsbDriver, 8>,
     /// Software VBUS detector — route SoftDevice `SocEvent` power events here.
     pub vbus: Vbus,
 }
@@ -187,7 +440,9 @@ pub fn init(usbd: Peri<'static, peripherals::USBD>) -> UsbHidDevice {
     let mouse_state = MOUSE_STATE.init(State::new());
     let mouse_config = HidConfig {
         report_descriptor: MOUSE_REPORT_DESCRIPTOR,
-        request_handler: None,
+        request_handler: Some(MOUSE_HANDLER.init(ProbeRequestHandler {
+            interface: MOUSE_INTERFACE,
+        })),
         poll_ms: config::USB_HID_POLL_MS,
         max_packet_size: 8,
         // Boot mouse subclass for pre-OS use; our 3-byte report is boot compatible.
@@ -199,7 +454,9 @@ pub fn init(usbd: Peri<'static, peripherals::USBD>) -> UsbHidDevice {
     let consumer_state = CONSUMER_STATE.init(State::new());
     let consumer_config = HidConfig {
         report_descriptor: CONSUMER_REPORT_DESCRIPTOR,
-        request_handler: None,
+        request_handler: Some(CONSUMER_HANDLER.init(ProbeRequestHandler {
+            interface: CONSUMER_INTERFACE,
+        })),
         poll_ms: config::USB_HID_POLL_MS,
         max_packet_size: 8,
         // Consumer Control has no boot protocol — only keyboard/mouse do.
@@ -208,15 +465,41 @@ pub fn init(usbd: Peri<'static, peripherals::USBD>) -> UsbHidDevice {
     };
     let consumer_writer = HidWriter::new(&mut builder, consumer_state, consumer_config);
 
+    let battery_state = BATTERY_STATE.init(State::new());
+    let battery_config = HidConfig {
+        report_descriptor: BATTERY_REPORT_DESCRIPTOR,
+        request_handler: Some(BATTERY_HANDLER.init(BatteryRequestHandler)),
+        // Battery levels change slowly; no need to poll fast.
+        poll_ms: 255,
+        max_packet_size: 8,
+        hid_subclass: HidSubclass::No,
+        hid_boot_protocol: HidBootProtocol::None,
+    };
+    let battery_writer = HidWriter::new(&mut builder, battery_state, battery_config);
+
+    let backup_state = BACKUP_STATE.init(State::new());
+    let backup_config = HidConfig {
+        report_descriptor: BACKUP_REPORT_DESCRIPTOR,
+        request_handler: Some(BACKUP_HANDLER.init(BackupRequestHandler)),
+        poll_ms: 255,
+        max_packet_size: 8,
+        hid_subclass: HidSubclass::No,
+        hid_boot_protocol: HidBootProtocol::None,
+    };
+    // Only feature reports on the control pipe: the interrupt endpoint is
+    // never written, so the writer isn't kept.
+    let _ = HidWriter::<_, 8>::new(&mut builder, backup_state, backup_config);
+
     let device = builder.build();
 
-    info!("USB HID composite device initialised (keyboard + mouse + consumer)");
+    info!("USB HID composite device initialised (keyboard + mouse + consumer + battery + backup)");
 
     UsbHidDevice {
         device,
         keyboard_writer,
         mouse_writer,
         consumer_writer,
+        battery_writer,
         vbus,
     }
 }
@@ -243,7 +526,11 @@ pub async fn hid_writer_task(
     let mut buf = [0u8; 8];
 
     loop {
-        let report = report_rx.receive().await;
+        let mut report = report_rx.receive().await;
+        if let HidReport::Keyboard(keys) = &mut report {
+            let layer = storage::settings().modifier_layout.layer(host_os());
+            layer.apply(keys);
+        }
         // Count live HID traffic as activity so the OLED stays on while the user
         // is actually typing/mousing (these reports never reach the UI loop).
         crate::power::note_hid_activity();
@@ -262,3 +549,55 @@ pub async fn hid_writer_task(
         }
     }
 }
+
+/// Battery forwarding task - sends every known BLE peer battery level to the
+/// host whenever one changes.
+pub async fn battery_writer_task(mut battery: HidWriter<'static, UsbDriver, 8>) -> ! {
+    let mut buf = [0u8; BATTERY_REPORT_SIZE];
+
+    loop {
+        BATTERY_CHANGED.wait().await;
+        for slot in 0..MAX_CONNECTIONS {
+            let Some(report) = battery_report(slot) else {
+                continue;
+            };
+            let n = report.serialize(&mut buf);
+            if battery.write(&buf[..n]).await.is_err() {
+                warn!("USB battery report write failed");
+            }
+        }
+    }
+}
+
+/// How often a settling enumeration is checked for its end (ms).
+const HOST_POLL_MS: u64 = 100;
+
+/// Host watch task - fingerprints each enumeration from the bus events of the
+/// control handlers and raises [`HOST_CHANGED`] when a different host took
+/// over the bus.
+pub async fn host_watch_task() -> ! {
+    let mut tracker = HostTracker::new();
+    loop {
+        let event = if tracker.settling() {
+            match select(BUS_EVENTS.receive(), Timer::after_millis(HOST_POLL_MS)).await {
+                Either::First(event) => Some(event),
+                Either::Second(()) => None,
+            }
+        } else {
+            Some(BUS_EVENTS.receive().await)
+        };
+        let now_ms = Instant::now().as_millis() as u32;
+        let host = match event {
+            Some(event) => tracker.observe(event, now_ms),
+            None => tracker.poll(now_ms),
+        };
+        if let Some(host) = host {
+            info!(
+                "USB host {=u32:08x} ({}) took over the bus",
+                host.fingerprint, host.os
+            );
+            HOST_OS.lock(|os| os.set(host.os));
+            HOST_CHANGED.signal(host);
+        }
+    }
+}
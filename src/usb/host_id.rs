@@ -0,0 +1,445 @@
+//! Pure host identification, for a bridge behind a monitor's USB switch.
+//!
+//! When the monitor hands its hub to another PC, the bridge sees the bus go
+//! away — VBUS drops, or the bus goes quiet and suspends — and come back with
+//! a fresh enumeration by the new host. How a host enumerates (how many bus
+//! resets before it assigns an address, which descriptors and HID class
+//! requests it sends to which interface, in which order and with which
+//! lengths, and which string descriptors it asks for) depends on its operating
+//! system and drivers, and is the same each time for one host.
+//! [`HostTracker`] folds each enumeration into a [`Fingerprint`] and reports
+//! when the host on the other end differs from the last one.
+//!
+//! Values that change between enumerations by the same host (the USB address,
+//! the lock-LED state, timings) are left out, and so is the request for the
+//! Microsoft OS string descriptor: Windows stops asking once it has cached the
+//! answer for the bridge, so it only counts towards the OS guess. A resume
+//! without a bus reset is the same host waking up and starts nothing. Two
+//! hosts that enumerate alike can't be told apart; they then share one set of
+//! bound settings.
+//!
+//! The same traits give a guess at the host's operating system
+//! ([`HostOs`]), from tendencies of the common USB stacks:
+//!
+//! - Windows asks for the Microsoft OS string descriptor (index `0xEE`), and
+//!   asks for report descriptors with room to spare beyond their length.
+//! - Linux sends SET_IDLE to every HID interface and sets the keyboard LEDs
+//!   while enumerating.
+//! - macOS resets the bus once before addressing, and sends SET_IDLE to the
+//!   keyboard at most.
+//!
+//! Anything else stays [`HostOs::Unknown`]. The guess is a heuristic; the
+//! layout it selects can always be overridden in the settings.
+
+/// Identifies a host by how it enumerates the bridge.
+pub type Fingerprint = u32;
+
+/// Time after the configuration for the host's class drivers to send their
+/// requests, before the enumeration is fingerprinted (ms).
+pub const SETTLE_MS: u32 = 1500;
+
+/// String index of the Microsoft OS string descriptor.
+const MS_OS_STRING_INDEX: u8 = 0xEE;
+
+/// What the USB stack reports about the bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusEvent {
+    /// VBUS came (`true`) or went (`false`).
+    Power(bool),
+    /// Bus reset.
+    Reset,
+    /// The host assigned an address.
+    Addressed,
+    /// The host set (`true`) or cleared (`false`) the configuration.
+    Configured(bool),
+    /// The bus suspended (`true`) or resumed (`false`).
+    Suspended(bool),
+    /// HID SET_IDLE on an interface.
+    SetIdle { interface: u8, duration_ms: u32 },
+    /// HID output report (lock LEDs) on an interface; the value isn't kept.
+    OutputReport { interface: u8 },
+    /// String descriptor request the stack passed on.
+    StringRequest { index: u8, lang_id: u16 },
+    /// The host asked for an interface's report descriptor, `padding` bytes
+    /// more than it is long.
+    ReportDescriptorRequest { interface: u8, padding: u16 },
+    /// A class or vendor request the bridge doesn't implement.
+    Probe { request: u8, interface: u8 },
+}
+
+/// Operating system of a host, as guessed from its enumeration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostOs {
+    #[default]
+    Unknown,
+    Windows,
+    MacOs,
+    Linux,
+}
+
+/// A host that enumerated the bridge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Host {
+    pub fingerprint: Fingerprint,
+    pub os: HostOs,
+}
+
+/// Traits of an enumeration the OS guess goes by.
+#[derive(Clone, Copy, Debug, Default)]
+struct Traits {
+    resets: u8,
+    /// Interfaces that got SET_IDLE, as a bit mask.
+    idle_interfaces: u8,
+    leds_set: bool,
+    ms_os_string: bool,
+    padded_report_descriptor: bool,
+}
+
+impl Traits {
+    fn os(&self) -> HostOs {
+        if self.ms_os_string || self.padded_report_descriptor {
+            HostOs::Windows
+        } else if self.leds_set && self.idle_interfaces.count_ones() > 1 {
+            HostOs::Linux
+        } else if self.resets == 1 && self.idle_interfaces.count_ones() <= 1 {
+            HostOs::MacOs
+        } else {
+            HostOs::Unknown
+        }
+    }
+}
+
+/// An enumeration being fingerprinted.
+#[derive(Clone, Copy, Debug)]
+struct Enumeration {
+    hash: u32,
+    traits: Traits,
+    addressed: bool,
+    configured_at: Option<u32>,
+}
+
+impl Enumeration {
+    fn new() -> Self {
+        Self {
+            hash: FNV_OFFSET,
+            traits: Traits {
+                resets: 1,
+                ..Traits::default()
+            },
+            addressed: false,
+            configured_at: None,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash = (self.hash ^ byte as u32).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+const FNV_OFFSET: u32 = 0x811C_9DC5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Watches bus events for a change of host.
+#[derive(Clone, Debug, Default)]
+pub struct HostTracker {
+    /// The host last identified, until VBUS goes away.
+    host: Option<Host>,
+    enumeration: Option<Enumeration>,
+}
+
+impl HostTracker {
+    pub const fn new() -> Self {
+        Self {
+            host: None,
+            enumeration: None,
+        }
+    }
+
+    /// The host last identified.
+    pub fn host(&self) -> Option<Host> {
+        self.host
+    }
+
+    /// Note `event`, seen at `now_ms`. Returns the new host when an
+    /// enumeration finishes by a different host than the last one.
+    pub fn observe(&mut self, event: BusEvent, now_ms: u32) -> Option<Host> {
+        match event {
+            BusEvent::Power(false) => {
+                // Unplugged, or the hub lost its upstream: whoever enumerates
+                // next is news, even if it is the same host again.
+                self.host = None;
+                self.enumeration = None;
+            }
+            BusEvent::Power(true) | BusEvent::Suspended(_) | BusEvent::Configured(false) => {}
+            BusEvent::Reset => match &mut self.enumeration {
+                // Hosts differ in how often they reset before addressing.
+                Some(e) if !e.addressed => e.traits.resets = e.traits.resets.saturating_add(1),
+                _ => self.enumeration = Some(Enumeration::new()),
+            },
+            BusEvent::Addressed => {
+                if let Some(e) = &mut self.enumeration {
+                    if !e.addressed {
+                        e.addressed = true;
+                        let resets = e.traits.resets;
+                        e.feed(&[0x01, resets]);
+                    }
+                }
+            }
+            BusEvent::Configured(true) => {
+                if let Some(e) = &mut self.enumeration {
+                    e.configured_at.get_or_insert(now_ms);
+                    e.feed(&[0x02]);
+                }
+            }
+            BusEvent::SetIdle {
+                interface,
+                duration_ms,
+            } => {
+                let bit = 1u8.checked_shl(interface.into()).unwrap_or(0);
+                self.note(|t| t.idle_interfaces |= bit);
+                self.feed(&[
+                    0x03,
+                    interface,
+                    duration_ms.min(u8::MAX as u32 * 4).div_ceil(4) as u8,
+                ]);
+            }
+            BusEvent::OutputReport { interface } => {
+                self.note(|t| t.leds_set = true);
+                self.feed(&[0x04, interface]);
+            }
+            BusEvent::StringRequest {
+                index: MS_OS_STRING_INDEX,
+                ..
+            } => self.note(|t| t.ms_os_string = true),
+            BusEvent::StringRequest { index, lang_id } => {
+                let [lo, hi] = lang_id.to_le_bytes();
+                self.feed(&[0x05, index, lo, hi]);
+            }
+            BusEvent::ReportDescriptorRequest { interface, padding } => {
+                if padding > 0 {
+                    self.note(|t| t.padded_report_descriptor = true);
+                }
+                let [lo, hi] = padding.to_le_bytes();
+                self.feed(&[0x06, interface, lo, hi]);
+            }
+            BusEvent::Probe { request, interface } => self.feed(&[0x07, request, interface]),
+        }
+        self.poll(now_ms)
+    }
+
+    /// Finish an enumeration whose settle time is over, as of `now_ms`.
+    /// Returns the new host, as [`observe`](Self::observe).
+    pub fn poll(&mut self, now_ms: u32) -> Option<Host> {
+        let configured_at = self.enumeration?.configured_at?;
+        if now_ms.wrapping_sub(configured_at) < SETTLE_MS {
+            return None;
+        }
+        let enumeration = self.enumeration.take()?;
+        let host = Host {
+            fingerprint: enumeration.hash,
+            os: enumeration.traits.os(),
+        };
+        if self.host == Some(host) {
+            return None;
+        }
+        self.host = Some(host);
+        Some(host)
+    }
+
+    /// Whether an enumeration is waiting out its settle time.
+    pub fn settling(&self) -> bool {
+        self.enumeration.is_some_and(|e| e.configured_at.is_some())
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        if let Some(e) = &mut self.enumeration {
+            e.feed(bytes);
+        }
+    }
+
+    fn note(&mut self, f: impl FnOnce(&mut Traits)) {
+        if let Some(e) = &mut self.enumeration {
+            f(&mut e.traits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `events` as one enumeration starting at `start`, returning the
+    /// tracker's verdict once it settled.
+    fn enumerate(tracker: &mut HostTracker, start: u32, events: &[BusEvent]) -> Option<Host> {
+        for (i, &event) in events.iter().enumerate() {
+            assert_eq!(tracker.observe(event, start + i as u32), None);
+        }
+        assert!(tracker.settling());
+        assert_eq!(tracker.poll(start + SETTLE_MS - 1), None);
+        tracker.poll(start + events.len() as u32 + SETTLE_MS)
+    }
+
+    const fn idle(interface: u8) -> BusEvent {
+        BusEvent::SetIdle {
+            interface,
+            duration_ms: 0,
+        }
+    }
+
+    const fn report_descriptor(interface: u8, padding: u16) -> BusEvent {
+        BusEvent::ReportDescriptorRequest { interface, padding }
+    }
+
+    const WINDOWS_LIKE: &[BusEvent] = &[
+        BusEvent::Reset,
+        BusEvent::Reset,
+        BusEvent::Addressed,
+        BusEvent::StringRequest {
+            index: MS_OS_STRING_INDEX,
+            lang_id: 0,
+        },
+        BusEvent::Configured(true),
+        idle(0),
+        report_descriptor(0, 64),
+        BusEvent::OutputReport { interface: 0 },
+    ];
+
+    const MAC_LIKE: &[BusEvent] = &[
+        BusEvent::Reset,
+        BusEvent::Addressed,
+        BusEvent::Configured(true),
+        report_descriptor(0, 0),
+        report_descriptor(1, 0),
+        idle(0),
+        BusEvent::Probe {
+            request: 0xFE,
+            interface: 0,
+        },
+    ];
+
+    const LINUX_LIKE: &[BusEvent] = &[
+        BusEvent::Reset,
+        BusEvent::Reset,
+        BusEvent::Addressed,
+        BusEvent::Configured(true),
+        idle(0),
+        report_descriptor(0, 0),
+        BusEvent::OutputReport { interface: 0 },
+        idle(1),
+        report_descriptor(1, 0),
+        idle(2),
+        report_descriptor(2, 0),
+    ];
+
+    #[test]
+    fn a_different_host_is_reported_and_the_same_one_is_not() {
+        let mut tracker = HostTracker::new();
+        let windows = enumerate(&mut tracker, 0, WINDOWS_LIKE).unwrap();
+        assert_eq!(tracker.host(), Some(windows));
+
+        // The PC sleeps and wakes with a fresh enumeration: same host.
+        tracker.observe(BusEvent::Suspended(true), 10_000);
+        assert_eq!(enumerate(&mut tracker, 20_000, WINDOWS_LIKE), None);
+
+        // The monitor switches to another PC.
+        tracker.observe(BusEvent::Suspended(true), 30_000);
+        let mac = enumerate(&mut tracker, 31_000, MAC_LIKE).unwrap();
+        assert_ne!(mac.fingerprint, windows.fingerprint);
+        // And back.
+        assert_eq!(enumerate(&mut tracker, 40_000, WINDOWS_LIKE), Some(windows));
+    }
+
+    #[test]
+    fn changing_state_does_not_change_the_fingerprint() {
+        let mut a = HostTracker::new();
+        let mut b = HostTracker::new();
+        let first = enumerate(&mut a, 0, WINDOWS_LIKE);
+        // Power announcements and resumes along the way, or a different start
+        // time, make no other host.
+        let mut events = heapless::Vec::<_, 16>::from_slice(WINDOWS_LIKE).unwrap();
+        events.insert(0, BusEvent::Power(true)).unwrap();
+        events.insert(3, BusEvent::Suspended(false)).unwrap();
+        assert_eq!(enumerate(&mut b, 5_000, &events), first);
+    }
+
+    #[test]
+    fn windows_caching_its_os_string_makes_no_other_host() {
+        let mut tracker = HostTracker::new();
+        let first = enumerate(&mut tracker, 0, WINDOWS_LIKE).unwrap();
+        // Later enumerations skip the request for the Microsoft OS string.
+        let cached: heapless::Vec<_, 16> = WINDOWS_LIKE
+            .iter()
+            .copied()
+            .filter(|e| !matches!(e, BusEvent::StringRequest { index: 0xEE, .. }))
+            .collect();
+        assert_eq!(cached.len(), WINDOWS_LIKE.len() - 1);
+        let mut other = HostTracker::new();
+        assert_eq!(enumerate(&mut other, 0, &cached), Some(first));
+        assert_eq!(first.os, HostOs::Windows);
+    }
+
+    #[test]
+    fn losing_power_forgets_the_host() {
+        let mut tracker = HostTracker::new();
+        let host = enumerate(&mut tracker, 0, WINDOWS_LIKE);
+        assert!(host.is_some());
+        tracker.observe(BusEvent::Power(false), 5_000);
+        assert_eq!(tracker.host(), None);
+        assert_eq!(enumerate(&mut tracker, 6_000, WINDOWS_LIKE), host);
+    }
+
+    #[test]
+    fn requests_outside_an_enumeration_are_ignored() {
+        let mut tracker = HostTracker::new();
+        assert_eq!(
+            tracker.observe(BusEvent::OutputReport { interface: 0 }, 0),
+            None
+        );
+        assert!(!tracker.settling());
+        let host = enumerate(&mut tracker, 10, WINDOWS_LIKE);
+        // The host toggling Caps Lock later is no new enumeration.
+        assert_eq!(
+            tracker.observe(BusEvent::OutputReport { interface: 0 }, 9_000),
+            None
+        );
+        assert_eq!(tracker.host(), host);
+    }
+
+    #[test]
+    fn the_os_is_guessed_from_the_enumeration() {
+        fn os(events: &[BusEvent]) -> HostOs {
+            enumerate(&mut HostTracker::new(), 0, events).unwrap().os
+        }
+        assert_eq!(os(WINDOWS_LIKE), HostOs::Windows);
+        assert_eq!(os(MAC_LIKE), HostOs::MacOs);
+        assert_eq!(os(LINUX_LIKE), HostOs::Linux);
+
+        // Windows also shows by padding alone, once it cached its string.
+        assert_eq!(
+            os(&[
+                BusEvent::Reset,
+                BusEvent::Reset,
+                BusEvent::Addressed,
+                BusEvent::Configured(true),
+                report_descriptor(0, 64),
+            ]),
+            HostOs::Windows
+        );
+        // Two resets and nothing telling.
+        assert_eq!(
+            os(&[
+                BusEvent::Reset,
+                BusEvent::Reset,
+                BusEvent::Addressed,
+                BusEvent::Configured(true),
+                idle(0),
+            ]),
+            HostOs::Unknown
+        );
+    }
+}
@@ -18,3 +18,4 @@
 //! them to the correct HID endpoint.
 
 pub mod hid_device;
+pub mod host_id;